@@ -1,12 +1,18 @@
 use std::fs;
+use std::io::{self, Read as _};
 use std::path::PathBuf;
 use std::str::FromStr;
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
 use clap::{Parser, Subcommand};
+use wit_parser::{PackageId, Resolve};
 
-use spidermonkey_embedding_splicer::wit::exports::local::spidermonkey_embedding_splicer::splicer::Feature;
-use spidermonkey_embedding_splicer::{splice, stub_wasi};
+#[cfg(feature = "componentize")]
+use spidermonkey_embedding_splicer::componentize;
+use spidermonkey_embedding_splicer::wit::exports::local::spidermonkey_embedding_splicer::splicer::{
+    Feature, SpliceOptions,
+};
+use spidermonkey_embedding_splicer::{inspect, splice, stub_wasi, world_diff};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -31,16 +37,26 @@ enum Commands {
         #[arg(short, long)]
         features: Vec<String>,
 
-        /// Path to WIT file or directory
+        /// Inline WIT source, any WIT paths being its dependencies
         #[arg(long)]
-        wit_path: Option<PathBuf>,
+        wit: Option<String>,
+
+        /// Path to WIT file or directory, or `-` to read WIT source from stdin,
+        /// repeated for the packages it depends on (such as a `deps/` directory)
+        #[arg(long)]
+        wit_path: Vec<PathBuf>,
 
         /// World name to use
         #[arg(long)]
         world_name: Option<String>,
+
+        /// Path to a JSON or TOML stub policy file
+        #[arg(long)]
+        policy: Option<PathBuf>,
     },
 
-    /// Splice bindings into a WebAssembly module
+    /// Splice bindings into a WebAssembly module, writing `component.wasm`,
+    /// `initializer.js` and the `bindings.json` manifest of its exports and imports
     SpliceBindings {
         /// Input engine WebAssembly file path
         #[arg(short, long)]
@@ -54,9 +70,14 @@ enum Commands {
         #[arg(short, long)]
         features: Vec<String>,
 
-        /// Path to WIT file or directory
+        /// Inline WIT source, any WIT paths being its dependencies
         #[arg(long)]
-        wit_path: Option<PathBuf>,
+        wit: Option<String>,
+
+        /// Path to WIT file or directory, or `-` to read WIT source from stdin,
+        /// repeated for the packages it depends on (such as a `deps/` directory)
+        #[arg(long)]
+        wit_path: Vec<PathBuf>,
 
         /// World name to use
         #[arg(long)]
@@ -65,9 +86,141 @@ enum Commands {
         /// Enable debug mode
         #[arg(long)]
         debug: bool,
+
+        /// Count the calls of the JS exports, exported as
+        /// `componentize-js:diagnostics/exports#stats`
+        #[arg(long)]
+        diagnostics: bool,
+
+        /// Import to call with the message and stack of the uncaught exceptions
+        /// of the exports, by function name or as `<interface>#<function>`
+        #[arg(long)]
+        report_error: Option<String>,
+    },
+
+    /// Componentize a JS module with an engine, as `componentize.js` does,
+    /// initializing it with Wasmtime
+    #[cfg(feature = "componentize")]
+    Componentize {
+        /// Input JS module file path
+        source: PathBuf,
+
+        /// Engine WebAssembly file path
+        #[arg(short, long)]
+        engine: PathBuf,
+
+        /// Output component file path
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// Features to enable (multiple allowed)
+        #[arg(short, long)]
+        features: Vec<String>,
+
+        /// Inline WIT source, any WIT paths being its dependencies
+        #[arg(long)]
+        wit: Option<String>,
+
+        /// Path to WIT file or directory, or `-` to read WIT source from stdin,
+        /// repeated for the packages it depends on (such as a `deps/` directory)
+        #[arg(long)]
+        wit_path: Vec<PathBuf>,
+
+        /// World name to use
+        #[arg(long)]
+        world_name: Option<String>,
+
+        /// Path to a JSON or TOML stub policy file
+        #[arg(long)]
+        policy: Option<PathBuf>,
+
+        /// Arguments passed to the engine ahead of the source
+        #[arg(long, allow_hyphen_values = true)]
+        runtime_args: Option<String>,
+
+        /// Path to the `wasi_snapshot_preview1` adapter, the reactor adapter
+        /// by default
+        #[arg(long)]
+        adapter: Option<PathBuf>,
+
+        /// Enable debug mode, keeping the initialization sources
+        #[arg(long)]
+        debug: bool,
+
+        /// Count the calls of the JS exports, exported as
+        /// `componentize-js:diagnostics/exports#stats`
+        #[arg(long)]
+        diagnostics: bool,
+
+        /// Import to call with the message and stack of the uncaught exceptions
+        /// of the exports, by function name or as `<interface>#<function>`
+        #[arg(long)]
+        report_error: Option<String>,
+    },
+
+    /// Compare the JS bindings of two worlds, exiting with an error status when
+    /// JS sources written against the old world may no longer bind
+    ///
+    /// Functions are matched by name, so renamed functions are reported as
+    /// removed and added, and signature changes are not reported.
+    Diff {
+        /// Path to the WIT file or directory of the old world, repeated for the
+        /// packages it depends on
+        #[arg(long, required = true)]
+        old_wit_path: Vec<PathBuf>,
+
+        /// Path to the WIT file or directory of the new world, repeated for the
+        /// packages it depends on
+        #[arg(long, required = true)]
+        new_wit_path: Vec<PathBuf>,
+
+        /// Old world name to use
+        #[arg(long)]
+        old_world_name: Option<String>,
+
+        /// New world name to use
+        #[arg(long)]
+        new_world_name: Option<String>,
+
+        /// Features to enable (multiple allowed)
+        #[arg(short, long)]
+        features: Vec<String>,
+
+        /// Print JSON instead of human readable output
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Describe an engine or a spliced WebAssembly module
+    Inspect {
+        /// Input WebAssembly file path
+        #[arg(short, long)]
+        input: PathBuf,
+
+        /// Print JSON instead of human readable output
+        #[arg(long)]
+        json: bool,
     },
 }
 
+/// Resolve WIT given inline, from stdin or as paths, the first path being the
+/// package to use unless the source is inline
+fn resolve_wit(wit: Option<String>, mut wit_paths: Vec<PathBuf>) -> Result<(Resolve, PackageId)> {
+    let mut source = wit;
+    if let Some(index) = wit_paths.iter().position(|path| path.as_os_str() == "-") {
+        if index != 0 || source.is_some() {
+            bail!("only the first --wit-path can be read from stdin, in place of --wit");
+        }
+        wit_paths.remove(0);
+        let mut stdin = String::new();
+        io::stdin()
+            .read_to_string(&mut stdin)
+            .context("Failed to read WIT from stdin")?;
+        source = Some(stdin);
+    }
+    spidermonkey_embedding_splicer::resolve_wit(source.as_deref(), &wit_paths)
+}
+
 fn main() -> Result<()> {
     let cli = Cli::parse();
 
@@ -76,20 +229,30 @@ fn main() -> Result<()> {
             input,
             output,
             features,
+            wit,
             wit_path,
             world_name,
+            policy,
         } => {
             let wasm = fs::read(&input)
                 .with_context(|| format!("Failed to read input file: {}", input.display()))?;
 
-            let wit_path_str = wit_path.as_ref().map(|p| p.to_string_lossy().to_string());
+            let policy = policy
+                .map(|path| {
+                    fs::read_to_string(&path)
+                        .with_context(|| format!("Failed to read policy file: {}", path.display()))
+                })
+                .transpose()?;
+
+            let (resolve, id) = resolve_wit(wit, wit_path)?;
             let features = features
                 .iter()
                 .map(|v| Feature::from_str(v))
                 .collect::<Result<Vec<_>>>()?;
 
-            let result = stub_wasi::stub_wasi(wasm, features, None, wit_path_str, world_name)
-                .map_err(|e| anyhow::anyhow!(e))?;
+            let result = stub_wasi::stub_wasi_with_resolve(
+                wasm, features, &resolve, id, world_name, policy, None,
+            )?;
 
             fs::write(&output, result)
                 .with_context(|| format!("Failed to write output file: {}", output.display()))?;
@@ -104,9 +267,12 @@ fn main() -> Result<()> {
             input,
             out_dir,
             features,
+            wit,
             wit_path,
             world_name,
             debug,
+            diagnostics,
+            report_error,
         } => {
             if !out_dir.exists() {
                 fs::create_dir_all(&out_dir).with_context(|| {
@@ -116,16 +282,26 @@ fn main() -> Result<()> {
             let engine = fs::read(&input)
                 .with_context(|| format!("Failed to read input file: {}", input.display()))?;
 
-            let wit_path_str = wit_path.as_ref().map(|p| p.to_string_lossy().to_string());
+            let (resolve, id) = resolve_wit(wit, wit_path)?;
 
             let features = features
                 .iter()
                 .map(|v| Feature::from_str(v))
                 .collect::<Result<Vec<_>>>()?;
 
-            let result =
-                splice::splice_bindings(engine, features, None, wit_path_str, world_name, debug)
-                    .map_err(|e| anyhow::anyhow!(e))?;
+            let (result, manifest) = splice::splice_bindings_with_manifest(
+                engine,
+                features,
+                resolve,
+                id,
+                world_name,
+                debug,
+                SpliceOptions {
+                    diagnostics,
+                    report_error,
+                },
+            )
+            .map_err(|e| anyhow::anyhow!(e))?;
 
             fs::write(out_dir.join("component.wasm"), result.wasm).with_context(|| {
                 format!(
@@ -139,6 +315,126 @@ fn main() -> Result<()> {
                     out_dir.join("initializer.js").display()
                 )
             })?;
+            fs::write(
+                out_dir.join("bindings.json"),
+                serde_json::to_string_pretty(&manifest)?,
+            )
+            .with_context(|| {
+                format!(
+                    "Failed to write output file: {}",
+                    out_dir.join("bindings.json").display()
+                )
+            })?;
+        }
+
+        #[cfg(feature = "componentize")]
+        Commands::Componentize {
+            source,
+            engine,
+            output,
+            features,
+            wit,
+            wit_path,
+            world_name,
+            policy,
+            runtime_args,
+            adapter,
+            debug,
+            diagnostics,
+            report_error,
+        } => {
+            let engine = fs::read(&engine)
+                .with_context(|| format!("Failed to read engine file: {}", engine.display()))?;
+
+            let policy = policy
+                .map(|path| {
+                    fs::read_to_string(&path)
+                        .with_context(|| format!("Failed to read policy file: {}", path.display()))
+                })
+                .transpose()?;
+            let adapter = adapter
+                .map(|path| {
+                    fs::read(&path)
+                        .with_context(|| format!("Failed to read adapter file: {}", path.display()))
+                })
+                .transpose()?;
+
+            let (resolve, id) = resolve_wit(wit, wit_path)?;
+            let features = features
+                .iter()
+                .map(|v| Feature::from_str(v))
+                .collect::<Result<Vec<_>>>()?;
+
+            let component = componentize::componentize(
+                engine,
+                &source,
+                &resolve,
+                id,
+                world_name,
+                features,
+                policy,
+                runtime_args.as_deref(),
+                adapter.as_deref(),
+                debug,
+                SpliceOptions {
+                    diagnostics,
+                    report_error,
+                },
+            )?;
+
+            fs::write(&output, component)
+                .with_context(|| format!("Failed to write output file: {}", output.display()))?;
+
+            println!("Successfully componentized {}", output.display());
+        }
+
+        Commands::Diff {
+            old_wit_path,
+            new_wit_path,
+            old_world_name,
+            new_world_name,
+            features,
+            json,
+        } => {
+            let (old_resolve, old_id) =
+                spidermonkey_embedding_splicer::resolve_wit(None, &old_wit_path)?;
+            let (new_resolve, new_id) =
+                spidermonkey_embedding_splicer::resolve_wit(None, &new_wit_path)?;
+            let old_world = old_resolve.select_world(&[old_id], old_world_name.as_deref())?;
+            let new_world = new_resolve.select_world(&[new_id], new_world_name.as_deref())?;
+
+            let features = features
+                .iter()
+                .map(|v| Feature::from_str(v))
+                .collect::<Result<Vec<_>>>()?;
+
+            let diff = world_diff::diff_worlds(
+                &old_resolve,
+                old_world,
+                &new_resolve,
+                new_world,
+                &features,
+            )?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&diff.to_json())?);
+            } else {
+                print!("{diff}");
+            }
+            if !diff.compatible() {
+                std::process::exit(1);
+            }
+        }
+
+        Commands::Inspect { input, json } => {
+            let wasm = fs::read(&input)
+                .with_context(|| format!("Failed to read input file: {}", input.display()))?;
+
+            let inspection = inspect::inspect(&wasm)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&inspection.to_json())?);
+            } else {
+                print!("{inspection}");
+            }
         }
     }
 
@@ -1,7 +1,7 @@
 use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt::Write;
 
-use anyhow::Result;
+use anyhow::{Result, bail};
 use heck::*;
 use js_component_bindgen::TranspileOpts;
 use js_component_bindgen::function_bindgen::{
@@ -21,6 +21,7 @@ use wit_parser::Param;
 use wit_parser::abi::WasmType;
 use wit_parser::abi::{AbiVariant, WasmSignature};
 
+use crate::stub_wasi::is_wasi_interface;
 use crate::wit::exports::local::spidermonkey_embedding_splicer::splicer::Feature;
 
 use crate::{uwrite, uwriteln};
@@ -81,6 +82,17 @@ pub struct BindingItem {
     pub func: CoreFn,
 }
 
+impl BindingItem {
+    /// Name of the JS function binding this item when imported from `specifier`
+    pub fn import_binding_name(&self, specifier: &str) -> String {
+        generate_binding_name_import(
+            &self.resource.func_name(&self.name),
+            &self.iface_name,
+            specifier,
+        )
+    }
+}
+
 struct JsBindgen<'a> {
     /// The source code for the "main" file that's going to be created for the
     /// component we're generating bindings for. This is incrementally added to
@@ -111,6 +123,10 @@ struct JsBindgen<'a> {
 
     /// Features that were enabled at the time of generation
     features: &'a Vec<Feature>,
+
+    /// JS function of the import passed the uncaught exceptions of the
+    /// exports, if one was given
+    report_error: Option<String>,
 }
 
 #[derive(Debug)]
@@ -136,12 +152,44 @@ pub struct Componentization {
     pub exports: Vec<(String, BindingItem)>,
     pub imports: Vec<(String, BindingItem)>,
     pub resource_imports: Vec<(String, String, u32)>,
+    /// Names the bindings receive `resource_imports` under, after `$resource_`,
+    /// in the same order
+    pub resource_bindings: Vec<String>,
+    /// Intrinsics of the bindings, the helpers they share with the bindings of
+    /// other worlds, as included in `js_bindings`
+    pub intrinsics: String,
+    pub surface: BindingSurface,
+}
+
+/// JS-facing surface of the bindings of a world, what JS sources export and
+/// import by name
+#[derive(Debug, Default)]
+pub struct BindingSurface {
+    /// Exports the JS source must provide, functions and resources by name and
+    /// interfaces by kebab name or ID
+    pub exports: BTreeMap<String, ExportSurface>,
+    /// Members of the built-in modules the JS source can import, by specifier
+    pub modules: BTreeMap<String, BTreeSet<String>>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ExportSurface {
+    Function,
+    Resource,
+    /// Interface object, which may be exported by its alias instead
+    Interface {
+        alias: Option<String>,
+        members: BTreeSet<String>,
+    },
 }
 
+/// Generate the JS bindings of the `wid` world, reporting the uncaught
+/// exceptions of the exports to the `report_error` import when given
 pub fn componentize_bindgen(
     resolve: &Resolve,
     wid: WorldId,
     features: &Vec<Feature>,
+    report_error: Option<&str>,
 ) -> Result<Componentization> {
     let mut bindgen = JsBindgen {
         src: Source::default(),
@@ -158,6 +206,9 @@ pub fn componentize_bindgen(
         resource_directions: HashMap::new(),
         imported_resources: BTreeSet::new(),
         features,
+        report_error: report_error
+            .map(|name| report_error_import(resolve, wid, name))
+            .transpose()?,
     };
 
     bindgen.sizes.fill(resolve);
@@ -177,11 +228,7 @@ pub fn componentize_bindgen(
     let mut import_bindings = Vec::new();
     for (specifier, item) in bindgen.imports.iter() {
         // this import binding order matters
-        import_bindings.push(generate_binding_name_import(
-            &item.resource.func_name(&item.name),
-            &item.iface_name,
-            specifier,
-        ));
+        import_bindings.push(item.import_binding_name(specifier));
     }
 
     let by_specifier_by_resource = bindgen.imports.iter().fold(
@@ -202,6 +249,7 @@ pub fn componentize_bindgen(
     );
 
     let mut import_wrappers = Vec::new();
+    let mut modules = BTreeMap::new();
     for (specifier, by_resource) in by_specifier_by_resource {
         let mut specifier_list = Vec::new();
         for (resource, items) in by_resource {
@@ -214,9 +262,9 @@ pub fn componentize_bindgen(
                     &item.binding_name,
                 );
                 if item.iface {
-                    specifier_list.push(format!("{export_name}: import_{binding_name}"));
+                    specifier_list.push((export_name, binding_name));
                 } else {
-                    specifier_list.push(format!("default: import_{binding_name}"));
+                    specifier_list.push(("default".to_string(), binding_name));
                 }
             } else {
                 for BindingItem {
@@ -228,14 +276,25 @@ pub fn componentize_bindgen(
                 {
                     let export_name = name.to_lower_camel_case();
                     if *iface {
-                        specifier_list.push(format!("{export_name}: import_{binding_name}"));
+                        specifier_list.push((export_name, binding_name.clone()));
                     } else {
-                        specifier_list.push(format!("default: import_{binding_name}"));
+                        specifier_list.push(("default".to_string(), binding_name.clone()));
                     }
                 }
             }
         }
-        let joined_bindings = specifier_list.join(",\n\t");
+        let joined_bindings = specifier_list
+            .iter()
+            .map(|(member, binding_name)| format!("{member}: import_{binding_name}"))
+            .collect::<Vec<_>>()
+            .join(",\n\t");
+        modules.insert(
+            specifier.to_string(),
+            specifier_list
+                .into_iter()
+                .map(|(member, _)| member)
+                .collect(),
+        );
         import_wrappers.push((
             specifier.to_string(),
             format!("defineBuiltinModule('{specifier}', {{\n\t{joined_bindings}\n}});"),
@@ -355,6 +414,11 @@ pub fn componentize_bindgen(
 
     let finalization_registries = finalization_registries.concat();
 
+    let surface = BindingSurface {
+        exports: bindgen.esm_bindgen.surface(),
+        modules,
+    };
+
     let mut output = Source::default();
 
     uwrite!(
@@ -387,8 +451,66 @@ pub fn componentize_bindgen(
         .instantiation_occurred(true)
         .transpile_opts(&transpile_opts)
         .build();
-    let js_intrinsics = render_intrinsics(render_args);
+    let js_intrinsics = render_intrinsics(render_args).to_string();
+
+    // these should be temporary bindings fixups
+    let js_intrinsics = if js_intrinsics.contains("utf8Encode") {
+        js_intrinsics.replace(
+            "function utf8Encode(s, realloc, memory) {
+  if (typeof s !== 'string') throw new TypeError('expected a string');
+  if (s.length === 0) {
+    utf8EncodedLen = 0;
+    return 1;
+  }
+  let allocLen = 0;
+  let ptr = 0;
+  let writtenTotal = 0;
+  while (s.length > 0) {
+    ptr = realloc(ptr, allocLen, 1, allocLen + s.length);
+    allocLen += s.length;
+    const { read, written } = utf8Encoder.encodeInto(
+    s,
+    new Uint8Array(memory.buffer, ptr + writtenTotal, allocLen - writtenTotal),
+    );
+    writtenTotal += written;
+    s = s.slice(read);
+  }
+  if (allocLen > writtenTotal)
+  ptr = realloc(ptr, allocLen, 1, writtenTotal);
+  utf8EncodedLen = writtenTotal;
+  return ptr;
+}",
+            "function utf8Encode(s, realloc, memory) {
+  const buf = utf8Encoder.encode(s);
+  const ptr = realloc(0, 0, 1, buf.byteLength);
+  const out = new Uint8Array(memory.buffer, ptr, buf.byteLength);
+  for (let i = 0; i < buf.byteLength; i++) {
+    out[i] = buf[i];
+  }
+  utf8EncodedLen = buf.byteLength;
+  return ptr;
+}",
+        )
+    } else {
+        js_intrinsics
+    };
+
     output.push_str(&js_intrinsics);
+    if let Some(report_error) = &bindgen.report_error {
+        uwrite!(
+            output,
+            "
+            function $reportError(err) {{
+                let message = 'uncaught exception', stack = '';
+                try {{
+                    message = String(err);
+                    if (err instanceof Error && typeof err.stack === 'string') stack = err.stack;
+                }} catch {{}}
+                import_{report_error}(message, stack);
+            }}
+            "
+        );
+    }
     output.push_str(&bindgen.src);
 
     import_wrappers
@@ -404,6 +526,9 @@ pub fn componentize_bindgen(
         exports: bindgen.exports,
         imports: bindgen.imports,
         resource_imports,
+        resource_bindings,
+        intrinsics: js_intrinsics,
+        surface,
     })
 }
 
@@ -419,7 +544,8 @@ impl JsBindgen<'_> {
 
             // Skip bindings generation for wasi:http/incoming-handler if the fetch-event
             // feature was enabled. We expect that the built-in engine implementation will be used
-            if name.starts_with("wasi:http/incoming-handler@0.2.")
+            if (is_wasi_interface(&name, "wasi:http/incoming-handler")
+                || is_wasi_interface(&name, "wasi:http/handler"))
                 && self.features.contains(&Feature::FetchEvent)
             {
                 continue;
@@ -986,8 +1112,15 @@ impl JsBindgen<'_> {
             binding_name(&resource.func_name(fn_name), &iface_name)
         );
 
+        // with an import to report errors to, the binding is wrapped to report its
+        // uncaught exceptions before they reach the engine, which traps
+        let body_name = match &self.report_error {
+            Some(_) => format!("{binding_name}$body"),
+            None => binding_name.clone(),
+        };
+
         // all exports are supported as async functions
-        uwrite!(self.src, "\nasync function {binding_name}");
+        uwrite!(self.src, "\nasync function {body_name}");
 
         // exports are canonicalized as imports because
         // the function bindgen as currently written still makes this assumption
@@ -1013,6 +1146,20 @@ impl JsBindgen<'_> {
         );
         self.src.push_str("\n");
 
+        if self.report_error.is_some() {
+            uwriteln!(
+                self.src,
+                "async function {binding_name}(...args) {{
+                    try {{
+                        return await {body_name}(...args);
+                    }} catch (err) {{
+                        $reportError(err);
+                        throw err;
+                    }}
+                }}"
+            );
+        }
+
         // populate core function return info for splicer
         self.exports.push((
             name,
@@ -1165,6 +1312,24 @@ impl EsmBindgen {
         }
     }
 
+    /// Exports as seen by the JS source, with their aliases
+    pub fn surface(&self) -> BTreeMap<String, ExportSurface> {
+        self.exports
+            .iter()
+            .map(|(name, binding)| {
+                let surface = match binding {
+                    Binding::Interface(bindings) => ExportSurface::Interface {
+                        alias: self.export_aliases.get(name).cloned(),
+                        members: bindings.keys().cloned().collect(),
+                    },
+                    Binding::Resource(_) => ExportSurface::Resource,
+                    Binding::Local(_) => ExportSurface::Function,
+                };
+                (name.clone(), surface)
+            })
+            .collect()
+    }
+
     pub fn render_export_imports(
         &mut self,
         output: &mut Source,
@@ -1379,6 +1544,42 @@ fn generate_binding_name_import(
     }
 }
 
+/// Binding name of the function the world imports as `name`, a world function
+/// name or `<interface>#<function>`, which must take the message and stack of
+/// an uncaught exception
+fn report_error_import(resolve: &Resolve, world: WorldId, name: &str) -> Result<String> {
+    let found = resolve.worlds[world]
+        .imports
+        .iter()
+        .find_map(|(key, import)| {
+            let import_name = resolve.name_world_key(key);
+            match import {
+                WorldItem::Function(func) if import_name == name => Some((import_name, None, func)),
+                WorldItem::Interface { id, .. } => {
+                    let func = name.strip_prefix(&import_name)?.strip_prefix('#')?;
+                    let func = resolve.interfaces[*id].functions.get(func)?;
+                    Some((import_name, interface_name(resolve, *id), func))
+                }
+                _ => None,
+            }
+        });
+    let Some((import_name, iface_name, func)) = found else {
+        bail!("the world does not import a `{name}` function to report errors to");
+    };
+    if !matches!(func.kind, FunctionKind::Freestanding)
+        || func.params.len() != 2
+        || func.params.iter().any(|param| param.ty != Type::String)
+        || func.result.is_some()
+    {
+        bail!("the `{name}` import must be a `func(message: string, stack: string)`");
+    }
+    Ok(generate_binding_name_import(
+        &func.name.to_lower_camel_case(),
+        &iface_name,
+        &import_name,
+    ))
+}
+
 /// Extract success and error types from a given optional type, if it is a Result
 pub fn get_result_types(
     resolve: &Resolve,
@@ -1393,3 +1594,69 @@ pub fn get_result_types(
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIT: &str = r#"
+        package local:t;
+        interface errors {
+            report: func(message: string, stack: string);
+            log: func(message: string);
+        }
+        world w {
+            import errors;
+            import report-error: func(message: string, stack: string);
+            export run: func();
+        }
+    "#;
+
+    fn bindgen(report_error: Option<&str>) -> Result<Componentization> {
+        let mut resolve = Resolve::default();
+        let pkg = resolve.push_str("world.wit", WIT).unwrap();
+        let world = resolve.select_world(&[pkg], None).unwrap();
+        componentize_bindgen(&resolve, world, &vec![], report_error)
+    }
+
+    #[test]
+    fn report_error_is_opt_in() {
+        let componentized = bindgen(None).unwrap();
+        assert!(!componentized.js_bindings.contains("function $reportError"));
+    }
+
+    #[test]
+    fn report_error_world_function() {
+        let componentized = bindgen(Some("report-error")).unwrap();
+        assert!(
+            componentized
+                .js_bindings
+                .contains("import_report_error$reportError(message, stack)")
+        );
+        assert!(componentized.js_bindings.contains("export_run$body"));
+    }
+
+    #[test]
+    fn report_error_interface_function() {
+        let componentized = bindgen(Some("local:t/errors#report")).unwrap();
+        assert!(
+            componentized
+                .js_bindings
+                .contains("import_local_t_errors$report(message, stack)")
+        );
+    }
+
+    #[test]
+    fn report_error_errors() {
+        let err = bindgen(Some("missing")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the world does not import a `missing` function to report errors to"
+        );
+        let err = bindgen(Some("local:t/errors#log")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the `local:t/errors#log` import must be a `func(message: string, stack: string)`"
+        );
+    }
+}
@@ -0,0 +1,477 @@
+//! Native componentization, performing the steps of `componentize.js` with
+//! Wasmtime in place of the wizer binary
+//!
+//! The bindings are spliced into the engine, which is then initialized with the
+//! JS source and snapshotted, before its WASI imports are stubbed and it is
+//! encoded as a component with the WASI preview1 adapter.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context as _, Result, anyhow, bail};
+use futures_executor::block_on;
+use wasm_metadata::Producers;
+use wasmtime::{Config, Engine, Linker, Module, Store};
+use wasmtime_wasi::p1::{self, WasiP1Ctx};
+use wasmtime_wasi::p2::pipe::{MemoryInputPipe, MemoryOutputPipe};
+use wasmtime_wasi::{FsPerms, WasiCtxBuilder};
+use wasmtime_wizer::Wizer;
+use wit_component::ComponentEncoder;
+use wit_parser::{PackageId, Resolve, WorldId};
+
+use crate::stub_wasi::is_wasi_interface;
+use crate::wit::exports::local::spidermonkey_embedding_splicer::splicer::{
+    CoreFn, CoreTy, Feature, SpliceOptions, SpliceResult,
+};
+use crate::{splice, stub_wasi};
+
+/// Export of the engine initializing it with the JS source
+const INIT_FUNC: &str = "componentize.wizer";
+
+/// Response code from `check_init` that denotes success
+const CHECK_INIT_RETURN_OK: i32 = 0;
+/// Response code from `check_init` that denotes being unable to extract the
+/// exports list
+const CHECK_INIT_RETURN_FN_LIST: i32 = 1;
+/// Response code from `check_init` that denotes being unable to parse the core
+/// ABI export types
+const CHECK_INIT_RETURN_TYPE_PARSE: i32 = 2;
+
+const PACKAGE_JSON: &str = include_str!("../../../package.json");
+
+/// Componentize the JS module at `source_path` with `engine`, against the world
+/// of the `id` package of `resolve`
+///
+/// `adapter` defaults to the reactor adapter of `wasi_snapshot_preview1`, and
+/// `runtime_args` are passed to the engine ahead of the source. With `debug`,
+/// the work directory holding the initializer is kept and logging enabled
+/// during initialization. The `options` are those of splicing the bindings.
+pub fn componentize(
+    engine: Vec<u8>,
+    source_path: &Path,
+    resolve: &Resolve,
+    id: PackageId,
+    world_name: Option<String>,
+    mut features: Vec<Feature>,
+    policy: Option<String>,
+    runtime_args: Option<&str>,
+    adapter: Option<&[u8]>,
+    debug: bool,
+    options: SpliceOptions,
+) -> Result<Vec<u8>> {
+    let source = fs::read_to_string(source_path)
+        .with_context(|| format!("failed to read source {}", source_path.display()))?;
+
+    // the http handler replaces the fetch event when the source exports it
+    let world = resolve.select_world(&[id], world_name.as_deref())?;
+    if features.contains(&Feature::Http)
+        && http_handler_export(resolve, world).is_some_and(|name| source_exports(&source, name))
+    {
+        features.retain(|feature| *feature != Feature::FetchEvent);
+    }
+
+    let (spliced, _) = splice::splice_bindings_with_manifest(
+        engine,
+        features.clone(),
+        resolve.clone(),
+        id,
+        world_name.clone(),
+        false,
+        options,
+    )
+    .map_err(|e| anyhow!(e))?;
+
+    let work_dir = work_dir()?;
+    let result = initialize_in(
+        &work_dir,
+        &spliced,
+        source_path,
+        &features,
+        runtime_args,
+        debug,
+    );
+
+    if debug {
+        eprintln!(
+            "Binary and sources available for debugging at {}",
+            work_dir.display()
+        );
+    } else {
+        let _ = fs::remove_dir_all(&work_dir);
+    }
+    let wasm = result?;
+
+    let stubbed =
+        stub_wasi::stub_wasi_with_resolve(wasm, features, resolve, id, world_name, policy, None)?;
+
+    let adapter = adapter.unwrap_or(
+        wasi_preview1_component_adapter_provider::WASI_SNAPSHOT_PREVIEW1_REACTOR_ADAPTER,
+    );
+    let component = ComponentEncoder::default()
+        .module(&stubbed)?
+        .adapter("wasi_snapshot_preview1", adapter)?
+        .encode()
+        .context("failed to encode component")?;
+
+    let mut producers = Producers::empty();
+    producers.add("language", "JavaScript", "");
+    producers.add("processed-by", "ComponentizeJS", &package_version()?);
+    producers.add_to_wasm(&component)
+}
+
+/// Initialize the spliced engine with the JS source from within `work_dir`
+fn initialize_in(
+    work_dir: &Path,
+    spliced: &SpliceResult,
+    source_path: &Path,
+    features: &[Feature],
+    runtime_args: Option<&str>,
+    debug: bool,
+) -> Result<Vec<u8>> {
+    let sources_dir = work_dir.join("sources");
+    fs::create_dir_all(&sources_dir)
+        .with_context(|| format!("failed to create {}", sources_dir.display()))?;
+    let initializer_path = sources_dir.join("initializer.js");
+    fs::write(&initializer_path, &spliced.js_bindings)
+        .with_context(|| format!("failed to write {}", initializer_path.display()))?;
+
+    let source_path = source_path
+        .canonicalize()
+        .with_context(|| format!("failed to resolve {}", source_path.display()))?;
+    let env = initialization_env(&source_path, spliced, features, debug);
+
+    // the source is seen relative to the current directory when within it,
+    // and to its own directory otherwise
+    let cwd = std::env::current_dir()?.canonicalize()?;
+    let workspace_prefix = if source_path.starts_with(&cwd) {
+        cwd
+    } else {
+        source_path
+            .parent()
+            .context("source path has no parent")?
+            .to_path_buf()
+    };
+    let relative_source = source_path.strip_prefix(&workspace_prefix)?;
+    let args = format!(
+        "--initializer-script-path {} --strip-path-prefix {}/ {}",
+        initializer_path.display(),
+        workspace_prefix.display(),
+        relative_source.display()
+    );
+    let args = match runtime_args {
+        Some(runtime_args) => format!("{runtime_args} {args}"),
+        None => args,
+    };
+
+    // initialization only reads the sources
+    let mut wasi = WasiCtxBuilder::new();
+    wasi.stdin(MemoryInputPipe::new(args))
+        .inherit_stdout()
+        .inherit_stderr()
+        .envs(&env);
+    wasi.preopened_dir(
+        &sources_dir,
+        sources_dir.to_string_lossy(),
+        FsPerms::ReadOnly,
+    )
+    .and_then(|wasi| wasi.preopened_dir(&workspace_prefix, "/", FsPerms::ReadOnly))
+    .map_err(|e| anyhow!("{e:?}"))?;
+    let wasi = wasi.build_p1();
+
+    let wasm = initialize(&spliced.wasm, wasi)?;
+    check_init(&wasm, &initializer_path)?;
+    Ok(wasm)
+}
+
+/// Name the JS source exports the WASI HTTP handler of the world under,
+/// `incomingHandler` for `wasi:http/incoming-handler` of WASI 0.2 or `handler`
+/// for `wasi:http/handler` of WASI 0.3
+fn http_handler_export(resolve: &Resolve, world: WorldId) -> Option<&'static str> {
+    resolve.worlds[world].exports.keys().find_map(|key| {
+        let name = resolve.name_world_key(key);
+        if is_wasi_interface(&name, "wasi:http/incoming-handler") {
+            Some("incomingHandler")
+        } else if is_wasi_interface(&name, "wasi:http/handler") {
+            Some("handler")
+        } else {
+            None
+        }
+    })
+}
+
+/// Whether the JS source exports `name` from an `export` declaration or list
+///
+/// Unlike `componentize.js` the source is not parsed, only tokenized to skip
+/// its comments and strings.
+fn source_exports(source: &str, name: &str) -> bool {
+    let tokens = tokenize(source);
+    let mut tokens = tokens.iter().map(String::as_str);
+    while let Some(token) = tokens.next() {
+        if token != "export" {
+            continue;
+        }
+        match tokens.next() {
+            Some("async") => {
+                if tokens.next() == Some("function") && tokens.next() == Some(name) {
+                    return true;
+                }
+            }
+            Some("function" | "class" | "const" | "let" | "var") => {
+                if tokens.next() == Some(name) {
+                    return true;
+                }
+            }
+            // each entry of an export list is exported under its last name
+            Some("{") => {
+                let mut exported = None;
+                for token in tokens.by_ref() {
+                    match token {
+                        "," | "}" if exported == Some(name) => return true,
+                        "," => exported = None,
+                        "}" => break,
+                        token => exported = Some(token),
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Identifiers, string contents and export list punctuation of a JS source,
+/// without its comments
+fn tokenize(source: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '/' if chars.peek() == Some(&'/') => {
+                chars.find(|&c| c == '\n');
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut prev = ' ';
+                chars.find(|&c| std::mem::replace(&mut prev, c) == '*' && c == '/');
+            }
+            '\'' | '"' | '`' => {
+                let mut string = String::new();
+                while let Some(next) = chars.next() {
+                    match next {
+                        '\\' => string.extend(chars.next()),
+                        next if next == c => break,
+                        next => string.push(next),
+                    }
+                }
+                tokens.push(string);
+            }
+            '{' | '}' | ',' => tokens.push(c.to_string()),
+            c if c.is_alphanumeric() || c == '_' || c == '$' => {
+                let mut ident = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if !(next.is_alphanumeric() || next == '_' || next == '$') {
+                        break;
+                    }
+                    ident.push(next);
+                    chars.next();
+                }
+                tokens.push(ident);
+            }
+            _ => {}
+        }
+    }
+    tokens
+}
+
+/// Unique directory for the initializer, removed once componentized
+fn work_dir() -> Result<PathBuf> {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos())
+        .unwrap_or_default();
+    let dir = std::env::temp_dir().join(format!(
+        "spidermonkey-embedding-splicer-{}-{nanos}",
+        std::process::id()
+    ));
+    fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    Ok(dir)
+}
+
+/// Environment the engine reads its exports and imports from while initializing
+fn initialization_env(
+    source_path: &Path,
+    spliced: &SpliceResult,
+    features: &[Feature],
+    debug: bool,
+) -> Vec<(String, String)> {
+    let flag = |enabled: bool| if enabled { "1" } else { "" }.to_string();
+    let source_name = source_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut env = vec![
+        ("DEBUG".to_string(), flag(debug)),
+        ("SOURCE_NAME".to_string(), source_name),
+        ("EXPORT_CNT".to_string(), spliced.exports.len().to_string()),
+        (
+            "FEATURE_CLOCKS".to_string(),
+            flag(features.contains(&Feature::Clocks)),
+        ),
+    ];
+    for (idx, (name, core_fn)) in spliced.exports.iter().enumerate() {
+        let CoreFn {
+            params,
+            ret,
+            retptr,
+            retsize,
+            paramptr,
+        } = core_fn;
+        let ty = |ty: &CoreTy| format!("{ty:?}").to_lowercase();
+        env.push((format!("EXPORT{idx}_NAME"), name.clone()));
+        env.push((
+            format!("EXPORT{idx}_ARGS"),
+            format!(
+                "{}{}",
+                if *paramptr { "*" } else { "" },
+                params.iter().map(ty).collect::<Vec<_>>().join(",")
+            ),
+        ));
+        env.push((
+            format!("EXPORT{idx}_RET"),
+            format!(
+                "{}{}",
+                if *retptr { "*" } else { "" },
+                ret.as_ref().map(ty).unwrap_or_default()
+            ),
+        ));
+        env.push((format!("EXPORT{idx}_RETSIZE"), retsize.to_string()));
+    }
+    for (idx, (_, name, argcnt)) in spliced.imports.iter().enumerate() {
+        env.push((format!("IMPORT{idx}_NAME"), name.clone()));
+        env.push((format!("IMPORT{idx}_ARGCNT"), argcnt.to_string()));
+    }
+    env.push(("IMPORT_CNT".to_string(), spliced.imports.len().to_string()));
+    env
+}
+
+/// Run the initialization function of the spliced engine and snapshot it
+fn initialize(wasm: &[u8], wasi: WasiP1Ctx) -> Result<Vec<u8>> {
+    let mut config = Config::new();
+    config.wasm_bulk_memory(true);
+    let engine = Engine::new(&config).map_err(|e| anyhow!("{e:?}"))?;
+    let mut store = Store::new(&engine, wasi);
+    let mut linker = Linker::new(&engine);
+    p1::add_to_linker_sync(&mut linker, |wasi| wasi).map_err(|e| anyhow!("{e:?}"))?;
+
+    block_on(
+        Wizer::new()
+            .init_func(INIT_FUNC)
+            .run(&mut store, wasm, async |store, module| {
+                linker.instantiate(store, module)
+            }),
+    )
+    .map_err(|e| anyhow!("Failed to initialize component:\n{e:?}"))
+}
+
+/// Check for initialization errors by running `check_init` of the snapshot
+fn check_init(wasm: &[u8], initializer_path: &Path) -> Result<()> {
+    let engine = Engine::default();
+    let stderr = MemoryOutputPipe::new(usize::MAX);
+    let wasi = WasiCtxBuilder::new().stderr(stderr.clone()).build_p1();
+    let mut store = Store::new(&engine, wasi);
+    let mut linker = Linker::new(&engine);
+    p1::add_to_linker_sync(&mut linker, |wasi| wasi).map_err(|e| anyhow!("{e:?}"))?;
+
+    let status = (|| {
+        let module = Module::new(&engine, wasm)?;
+        let instance = linker.instantiate(&mut store, &module)?;
+        instance
+            .get_typed_func::<(), i32>(&mut store, "check_init")?
+            .call(&mut store, ())
+    })()
+    .map_err(|e| anyhow!("{e:?}"))?;
+
+    let err = match status {
+        CHECK_INIT_RETURN_OK => return Ok(()),
+        CHECK_INIT_RETURN_FN_LIST => "Unable to extract expected exports list".to_string(),
+        CHECK_INIT_RETURN_TYPE_PARSE => "Unable to parse the core ABI export types".to_string(),
+        status => format!("Unknown error during initialization: {status}"),
+    };
+    let stderr = String::from_utf8_lossy(&stderr.contents())
+        .replace(&initializer_path.display().to_string(), "initializer.js");
+    if stderr.is_empty() {
+        bail!("{err}");
+    }
+    bail!("{err}\n{stderr}");
+}
+
+/// Version of ComponentizeJS recorded in the producers section
+fn package_version() -> Result<String> {
+    let package: serde_json::Value = serde_json::from_str(PACKAGE_JSON)?;
+    package["version"]
+        .as_str()
+        .map(str::to_string)
+        .context("missing package version")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn source_exports_declarations() {
+        let source = "export function incomingHandler() {}\nexport async function handler() {}";
+        assert!(source_exports(source, "incomingHandler"));
+        assert!(source_exports(source, "handler"));
+        assert!(source_exports("export const handler = {};", "handler"));
+        assert!(!source_exports("export function handlerFn() {}", "handler"));
+    }
+
+    #[test]
+    fn source_exports_lists() {
+        assert!(source_exports("export { a, b as handler };", "handler"));
+        assert!(source_exports(
+            "export { incomingHandler } from './http.js';",
+            "incomingHandler"
+        ));
+        assert!(!source_exports("export { handler as fetch };", "handler"));
+    }
+
+    #[test]
+    fn source_exports_skips_comments_and_strings() {
+        let source = r#"
+            // export function handler() {}
+            /* export { handler } */
+            const text = "export { handler }";
+            export function run() { return 'handler'; }
+        "#;
+        assert!(!source_exports(source, "handler"));
+    }
+
+    #[test]
+    fn http_handler_export_from_world() {
+        let mut resolve = Resolve::default();
+        let wit = r#"
+            package wasi:http@0.2.3;
+            interface incoming-handler {
+                handle: func();
+            }
+            world proxy {
+                export incoming-handler;
+            }
+            world other {
+                export handler: func();
+            }
+        "#;
+        let pkg = resolve.push_str("http.wit", wit).unwrap();
+        let proxy = resolve.select_world(&[pkg], Some("proxy")).unwrap();
+        let other = resolve.select_world(&[pkg], Some("other")).unwrap();
+        assert_eq!(
+            http_handler_export(&resolve, proxy),
+            Some("incomingHandler")
+        );
+        assert_eq!(http_handler_export(&resolve, other), None);
+    }
+}
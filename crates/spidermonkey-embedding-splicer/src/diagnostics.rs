@@ -0,0 +1,201 @@
+//! Instrumentation of the JS exports, enabled by the `diagnostics` splice option
+//!
+//! The export wrappers synthesized by the splicer count their calls, along with
+//! the monotonic clock time spent in them when the `clocks` feature is enabled.
+//! The counters are read through the `componentize-js:diagnostics/exports`
+//! export.
+//!
+//! Traps are not counted: a trap unwinds the wrapper before it can record
+//! anything, and the component cannot be called again afterwards, so only the
+//! host can observe them.
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+use wasm_encoder::{Encode, Section};
+use wirm::ir::function::FunctionBuilder;
+use wirm::ir::id::{FunctionID, GlobalID, LocalID};
+use wirm::ir::types::{InitExpr, Value};
+use wirm::module_builder::AddLocal;
+use wirm::{DataType, InitInstr, Module, Opcode};
+use wit_component::StringEncoding;
+use wit_parser::Resolve;
+
+use crate::stub_wasi::{build_image, is_wasi_interface, mem};
+
+/// Export exposing the counters to the host
+const DIAGNOSTICS_WIT: &str = r#"
+package componentize-js:diagnostics;
+
+interface exports {
+  /// Counters of a JS export since the component was instantiated
+  record export-stats {
+    /// Core name of the export
+    name: string,
+    /// Calls of the export, including a call in progress
+    calls: u64,
+    /// Nanoseconds of the monotonic clock spent in the export, zero without
+    /// the `clocks` feature
+    ticks: u64,
+  }
+
+  /// Counters of each JS export, in the order of the world exports
+  stats: func() -> list<export-stats>;
+}
+
+world diagnostics {
+  export exports;
+}
+"#;
+
+const STATS_EXPORT: &str = "componentize-js:diagnostics/exports#stats";
+
+/// Size of an `export-stats` record, aligned to its `u64` fields
+const STATS_SIZE: i32 = 24;
+
+/// Globals counting the calls of an export
+struct Counters {
+    calls: GlobalID,
+    ticks: GlobalID,
+}
+
+/// Counters of the JS exports, and the clock timing them
+pub(crate) struct Diagnostics {
+    now: Option<FunctionID>,
+    counters: Vec<Counters>,
+}
+
+impl Diagnostics {
+    /// Add the counters of `exports` JS exports, timed with the engine import
+    /// of `wasi:clocks/monotonic-clock#now` when `clocks` is set
+    pub(crate) fn new(module: &mut Module, exports: usize, clocks: bool) -> Self {
+        let now = clocks
+            .then(|| {
+                module
+                    .imports
+                    .iter()
+                    .find(|import| {
+                        is_wasi_interface(&import.module, "wasi:clocks/monotonic-clock")
+                            && import.name == "now"
+                    })
+                    .map(|import| (import.module.to_string(), import.name.to_string()))
+            })
+            .flatten()
+            .and_then(|(module_name, name)| module.imports.get_func(module_name, name));
+        let mut global = || {
+            module.add_global(
+                InitExpr::new(vec![InitInstr::Value(Value::I64(0))]),
+                DataType::I64,
+                true,
+                false,
+            )
+        };
+        let counters = (0..exports)
+            .map(|_| Counters {
+                calls: global(),
+                ticks: global(),
+            })
+            .collect();
+        Diagnostics { now, counters }
+    }
+
+    /// Count a call of the export `export_num`, returning the local holding
+    /// its start time when timed
+    pub(crate) fn enter(&self, func: &mut FunctionBuilder, export_num: usize) -> Option<LocalID> {
+        let counters = &self.counters[export_num];
+        increment(func, counters.calls);
+        self.now.map(|now| {
+            let start = func.add_local(DataType::I64);
+            func.call(now);
+            func.local_set(start);
+            start
+        })
+    }
+
+    /// Add the time spent in the export `export_num` since `start`
+    pub(crate) fn exit(&self, func: &mut FunctionBuilder, export_num: usize, start: LocalID) {
+        let counters = &self.counters[export_num];
+        if let Some(now) = self.now {
+            func.global_get(counters.ticks);
+            func.call(now);
+            func.local_get(start);
+            func.i64_sub();
+            func.i64_add();
+            func.global_set(counters.ticks);
+        }
+    }
+
+    /// Export the `stats` function, reporting the counters under the core
+    /// names of the exports
+    pub(crate) fn synthesize_stats(&self, module: &mut Module, names: &[&str]) {
+        let memory = module.get_memory_id().unwrap();
+        // the records are followed by the return area and the names
+        let retarea_offset = STATS_SIZE * names.len() as i32;
+        let names_offset = retarea_offset + 8;
+        let mut image = vec![0; names_offset as usize];
+        for name in names {
+            image.extend_from_slice(name.as_bytes());
+        }
+        let image = build_image(module, image);
+
+        let mut body = FunctionBuilder::new(&[], &[DataType::I32]);
+        let base = body.add_local(DataType::I32);
+        body.call(image);
+        body.local_set(base);
+        let mut name_offset = names_offset;
+        for (idx, (name, counters)) in names.iter().zip(&self.counters).enumerate() {
+            let offset = (STATS_SIZE * idx as i32) as u64;
+            body.local_get(base);
+            body.local_get(base);
+            body.i32_const(name_offset);
+            body.i32_add();
+            body.i32_store(mem(memory, 2, offset));
+            body.local_get(base);
+            body.i32_const(name.len() as i32);
+            body.i32_store(mem(memory, 2, offset + 4));
+            body.local_get(base);
+            body.global_get(counters.calls);
+            body.i64_store(mem(memory, 3, offset + 8));
+            body.local_get(base);
+            body.global_get(counters.ticks);
+            body.i64_store(mem(memory, 3, offset + 16));
+            name_offset += name.len() as i32;
+        }
+        body.local_get(base);
+        body.local_get(base);
+        body.i32_store(mem(memory, 2, retarea_offset as u64));
+        body.local_get(base);
+        body.i32_const(names.len() as i32);
+        body.i32_store(mem(memory, 2, retarea_offset as u64 + 4));
+        body.local_get(base);
+        body.i32_const(retarea_offset);
+        body.i32_add();
+        let stats = body.finish_module(module);
+        module
+            .exports
+            .add_export_func(STATS_EXPORT.to_string(), *stats);
+    }
+}
+
+fn increment(func: &mut FunctionBuilder, counter: GlobalID) {
+    func.global_get(counter);
+    func.i64_const(1);
+    func.i64_add();
+    func.global_set(counter);
+}
+
+/// Encode the `component-type` custom section adding the diagnostics export to
+/// the world
+pub(crate) fn diagnostics_component_type() -> Result<Vec<u8>> {
+    let mut resolve = Resolve::default();
+    let pkg = resolve.push_str(PathBuf::from("diagnostics.wit"), DIAGNOSTICS_WIT)?;
+    let world = resolve.select_world(&[pkg], None)?;
+    let encoded = wit_component::metadata::encode(&resolve, world, StringEncoding::UTF8, None)?;
+    let section = wasm_encoder::CustomSection {
+        name: "component-type:diagnostics".into(),
+        data: encoded.into(),
+    };
+    let mut bytes = vec![section.id()];
+    section.encode(&mut bytes);
+    Ok(bytes)
+}
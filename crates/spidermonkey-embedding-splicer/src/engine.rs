@@ -0,0 +1,52 @@
+//! A JS engine parsed once, to splice bindings for many worlds
+//!
+//! Parsing a multi-megabyte engine and decoding its `component-type` world is
+//! most of the cost of splicing, so the parsed module is kept alongside the
+//! bytes it borrows from and cloned for each splice.
+
+use anyhow::{Context as _, Result};
+use self_cell::self_cell;
+use wirm::ir::module::Module;
+use wit_component::metadata::{Bindgen, decode};
+use wit_parser::{Resolve, WorldId};
+
+self_cell!(
+    struct ParsedModule {
+        owner: Vec<u8>,
+
+        #[covariant]
+        dependent: Module,
+    }
+);
+
+pub struct Engine {
+    module: ParsedModule,
+    /// Decoded `component-type` metadata of the engine
+    bindgen: Bindgen,
+}
+
+impl Engine {
+    /// Parse the engine and decode its `component-type` metadata
+    pub fn parse(engine: Vec<u8>) -> Result<Self> {
+        let (_, bindgen) = decode(&engine).context("failed to decode engine")?;
+        let module = ParsedModule::try_new(engine, |engine| {
+            Module::parse(engine, false, false).context("failed to parse engine")
+        })?;
+        Ok(Self { module, bindgen })
+    }
+
+    /// Copy of the parsed engine module, to be spliced
+    pub(crate) fn module(&self) -> Module<'_> {
+        self.module.borrow_dependent().clone()
+    }
+
+    /// World the engine was built against
+    pub(crate) fn world(&self) -> (Resolve, WorldId) {
+        (self.bindgen.resolve.clone(), self.bindgen.world)
+    }
+
+    /// Decoded `component-type` metadata, retaining the engine producers
+    pub(crate) fn bindgen(&self) -> &Bindgen {
+        &self.bindgen
+    }
+}
@@ -0,0 +1,318 @@
+//! Environment variables, arguments and working directory baked in at build time
+//!
+//! The lowered values are stored in a single passive data segment. Each call to
+//! `wasi:cli/environment` or to the preview1 `environ_*` and `args_*` functions
+//! copies the relevant part of the segment into memory, then patches in the
+//! absolute addresses of the strings.
+
+use anyhow::{Result, bail};
+use serde_json::Value;
+use wirm::ir::function::FunctionBuilder;
+use wirm::ir::id::{DataSegmentID, FunctionID, LocalID, MemoryID};
+use wirm::ir::types::{DataSegment, DataSegmentKind};
+use wirm::module_builder::AddLocal;
+use wirm::{DataType, Module, Opcode};
+
+use crate::stub_policy::StubPolicy;
+use crate::stub_wasi::{PREVIEW1, mem, stub_import, stub_wasi_imports};
+
+/// Environment configuration, from the `environment` key of a stub policy
+#[derive(Debug, Default)]
+pub struct Environment {
+    pub vars: Vec<(String, String)>,
+    pub args: Vec<String>,
+    pub cwd: Option<String>,
+}
+
+impl Environment {
+    pub fn from_json(value: &Value) -> Result<Self> {
+        let Value::Object(config) = value else {
+            bail!("stub policy \"environment\" must be an object");
+        };
+        let mut env = Environment::default();
+        for (key, value) in config {
+            match (key.as_str(), value) {
+                ("vars", Value::Object(vars)) => {
+                    for (name, value) in vars {
+                        let Value::String(value) = value else {
+                            bail!("stub policy environment variable [{name}] must be a string");
+                        };
+                        env.vars.push((name.clone(), value.clone()));
+                    }
+                }
+                ("args", Value::Array(args)) => {
+                    for arg in args {
+                        let Value::String(arg) = arg else {
+                            bail!(
+                                "stub policy environment arguments must be strings, found [{arg}]"
+                            );
+                        };
+                        env.args.push(arg.clone());
+                    }
+                }
+                ("cwd", Value::String(cwd)) => env.cwd = Some(cwd.clone()),
+                ("vars" | "args" | "cwd", _) => {
+                    bail!("invalid stub policy value for [environment.{key}]")
+                }
+                _ => bail!("unrecognized stub policy key [environment.{key}]"),
+            }
+        }
+        Ok(env)
+    }
+}
+
+/// A region of the data segment, with the offsets of the pointers in it that are
+/// relative to its start
+#[derive(Default)]
+struct Region {
+    offset: u32,
+    len: u32,
+    relocs: Vec<u32>,
+}
+
+/// Lowered values, laid out in a data segment
+#[derive(Default)]
+struct Layout {
+    data: Vec<u8>,
+}
+
+impl Layout {
+    fn begin(&mut self) -> Region {
+        // keep regions aligned for the pointer tables
+        self.data.resize(self.data.len().next_multiple_of(8), 0);
+        Region {
+            offset: self.data.len() as u32,
+            ..Default::default()
+        }
+    }
+
+    fn end(&mut self, mut region: Region) -> Region {
+        region.len = self.data.len() as u32 - region.offset;
+        region
+    }
+
+    /// Lay out a canonical ABI `list<string>`, or `list<tuple<string, string>>`
+    /// when `strings` holds pairs
+    fn string_list(&mut self, strings: &[Vec<&str>]) -> Region {
+        let mut region = self.begin();
+        let table = self.data.len();
+        let width: usize = strings.first().map_or(0, |s| s.len());
+        self.data.resize(table + strings.len() * width * 8, 0);
+        for (idx, tuple) in strings.iter().enumerate() {
+            for (field, s) in tuple.iter().enumerate() {
+                let entry = table + (idx * width + field) * 8;
+                let rel = (self.data.len() - region.offset as usize) as u32;
+                self.data[entry..entry + 4].copy_from_slice(&rel.to_le_bytes());
+                self.data[entry + 4..entry + 8].copy_from_slice(&(s.len() as u32).to_le_bytes());
+                region.relocs.push((entry - region.offset as usize) as u32);
+                self.data.extend_from_slice(s.as_bytes());
+            }
+        }
+        self.end(region)
+    }
+
+    /// Lay out preview1 style NUL-terminated strings, recording their offsets
+    /// in the region relocations
+    fn c_strings(&mut self, strings: &[String]) -> Region {
+        let mut region = self.begin();
+        for s in strings {
+            region.relocs.push(self.data.len() as u32 - region.offset);
+            self.data.extend_from_slice(s.as_bytes());
+            self.data.push(0);
+        }
+        self.end(region)
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) -> Region {
+        let region = self.begin();
+        self.data.extend_from_slice(bytes);
+        self.end(region)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Segment {
+    memory: MemoryID,
+    realloc: FunctionID,
+    data: DataSegmentID,
+}
+
+impl Segment {
+    /// Copy a region into `dst`
+    fn init(self, body: &mut FunctionBuilder, dst: LocalID, region: &Region) {
+        body.local_get(dst);
+        body.i32_const(region.offset as i32);
+        body.i32_const(region.len as i32);
+        body.memory_init(self.data, self.memory);
+    }
+
+    /// Allocate and copy a region, leaving its address in `dst`
+    fn alloc(self, body: &mut FunctionBuilder, dst: LocalID, region: &Region) {
+        body.i32_const(0);
+        body.i32_const(0);
+        body.i32_const(4);
+        body.i32_const(region.len as i32);
+        body.call(self.realloc);
+        body.local_set(dst);
+        self.init(body, dst, region);
+    }
+
+    /// Turn the relative pointers of a copied region into absolute ones
+    fn relocate(self, body: &mut FunctionBuilder, dst: LocalID, region: &Region) {
+        for reloc in &region.relocs {
+            body.local_get(dst);
+            body.local_get(dst);
+            body.i32_load(mem(self.memory, 2, *reloc as u64));
+            body.local_get(dst);
+            body.i32_add();
+            body.i32_store(mem(self.memory, 2, *reloc as u64));
+        }
+    }
+
+    /// Store `(ptr, len)` of a copied list at `retptr + offset`
+    fn store_list(
+        self,
+        body: &mut FunctionBuilder,
+        retptr: LocalID,
+        offset: u64,
+        ptr: LocalID,
+        len: usize,
+    ) {
+        body.local_get(retptr);
+        body.local_get(ptr);
+        body.i32_store(mem(self.memory, 2, offset));
+        body.local_get(retptr);
+        body.i32_const(len as i32);
+        body.i32_store(mem(self.memory, 2, offset + 4));
+    }
+}
+
+/// Replace the `wasi:cli/environment` and preview1 environment imports with
+/// functions returning the given values
+pub fn synthesize_environment(
+    module: &mut Module,
+    policy: &StubPolicy,
+    env: &Environment,
+) -> Result<()> {
+    let memory = module.get_memory_id().unwrap();
+    let realloc = module
+        .exports
+        .get_func_by_name("cabi_realloc".to_string())
+        .unwrap();
+
+    let mut layout = Layout::default();
+    let vars = layout.string_list(
+        &env.vars
+            .iter()
+            .map(|(name, value)| vec![name.as_str(), value.as_str()])
+            .collect::<Vec<_>>(),
+    );
+    let args = layout.string_list(
+        &env.args
+            .iter()
+            .map(|arg| vec![arg.as_str()])
+            .collect::<Vec<_>>(),
+    );
+    let cwd = layout.bytes(env.cwd.as_deref().unwrap_or_default().as_bytes());
+    let environ = layout.c_strings(
+        &env.vars
+            .iter()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect::<Vec<_>>(),
+    );
+    let argv = layout.c_strings(&env.args);
+
+    let segment = Segment {
+        memory,
+        realloc,
+        data: module.add_data(DataSegment {
+            kind: DataSegmentKind::Passive,
+            data: layout.data,
+            tag: None,
+        }),
+    };
+
+    stub_wasi_imports(
+        module,
+        policy,
+        "wasi:cli/environment",
+        "get-environment",
+        |body| {
+            let retptr = LocalID(0);
+            let ptr = body.add_local(DataType::I32);
+            segment.alloc(body, ptr, &vars);
+            segment.relocate(body, ptr, &vars);
+            segment.store_list(body, retptr, 0, ptr, env.vars.len());
+            Ok(vec![])
+        },
+    )?;
+
+    stub_wasi_imports(
+        module,
+        policy,
+        "wasi:cli/environment",
+        "get-arguments",
+        |body| {
+            let retptr = LocalID(0);
+            let ptr = body.add_local(DataType::I32);
+            segment.alloc(body, ptr, &args);
+            segment.relocate(body, ptr, &args);
+            segment.store_list(body, retptr, 0, ptr, env.args.len());
+            Ok(vec![])
+        },
+    )?;
+
+    stub_wasi_imports(
+        module,
+        policy,
+        "wasi:cli/environment",
+        "initial-cwd",
+        |body| {
+            let retptr = LocalID(0);
+            body.local_get(retptr);
+            body.i32_const(env.cwd.is_some() as i32);
+            body.i32_store8(mem(memory, 0, 0));
+            if env.cwd.is_some() {
+                let ptr = body.add_local(DataType::I32);
+                segment.alloc(body, ptr, &cwd);
+                segment.store_list(body, retptr, 4, ptr, cwd.len as usize);
+            }
+            Ok(vec![])
+        },
+    )?;
+
+    // preview1 writes the strings to a caller provided buffer, and their
+    // addresses to a caller provided array
+    for (sizes_get, get, strings) in [
+        ("environ_sizes_get", "environ_get", &environ),
+        ("args_sizes_get", "args_get", &argv),
+    ] {
+        stub_import(module, policy, PREVIEW1, sizes_get, |body| {
+            let (count_ptr, size_ptr) = (LocalID(0), LocalID(1));
+            body.local_get(count_ptr);
+            body.i32_const(strings.relocs.len() as i32);
+            body.i32_store(mem(memory, 2, 0));
+            body.local_get(size_ptr);
+            body.i32_const(strings.len as i32);
+            body.i32_store(mem(memory, 2, 0));
+            body.i32_const(0);
+            Ok(vec![])
+        })?;
+
+        stub_import(module, policy, PREVIEW1, get, |body| {
+            let (ptrs, buf) = (LocalID(0), LocalID(1));
+            segment.init(body, buf, strings);
+            for (idx, offset) in strings.relocs.iter().enumerate() {
+                body.local_get(ptrs);
+                body.local_get(buf);
+                body.i32_const(*offset as i32);
+                body.i32_add();
+                body.i32_store(mem(memory, 2, idx as u64 * 4));
+            }
+            body.i32_const(0);
+            Ok(vec![])
+        })?;
+    }
+
+    Ok(())
+}
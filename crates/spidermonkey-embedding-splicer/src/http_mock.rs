@@ -0,0 +1,1601 @@
+//! Offline HTTP responses for `wasi:http/outgoing-handler`
+//!
+//! When neither the `http` nor the `fetch-event` feature is enabled, the requests
+//! made by `fetch` are answered from a table of responses embedded at build time
+//! instead of trapping. `wasi:http/types` is synthesized in full: resources are
+//! the addresses of their state in linear memory, except for body streams, which
+//! are tagged with [`HTTP_TAG`], and the pollables of futures and streams, which
+//! are always ready.
+//!
+//! Requests are matched in the order of the table, on their method and on their
+//! URL, assembled as `<scheme>://<authority><path-with-query>`. The scheme
+//! defaults to `https`. A request matching no response fails with
+//! `HTTP-request-denied`, and its body is discarded.
+//!
+//! The embedded image starts with the `(offset, len)` pairs of the names of the
+//! `method` and `scheme` cases, followed by the strings of the table.
+
+use anyhow::{Result, bail};
+use serde_json::Value;
+use wirm::ir::function::FunctionBuilder;
+use wirm::ir::id::{FunctionID, LocalID, MemoryID};
+use wirm::ir::types::BlockType;
+use wirm::module_builder::AddLocal;
+use wirm::{DataType, Module, Opcode};
+
+use crate::stdio_capture::dispatch_output_streams;
+use crate::stub_policy::StubPolicy;
+use crate::stub_wasi::{
+    Dispatcher, HTTP_TAG, READY_TAG, build_image, build_memeq, emit_tag_address,
+    emit_untag_address, mem, stub_wasi_imports,
+};
+
+/// Names of the `method` cases, `other` being the last case
+const METHODS: [&str; 9] = [
+    "GET", "HEAD", "POST", "PUT", "DELETE", "CONNECT", "OPTIONS", "TRACE", "PATCH",
+];
+const METHOD_OTHER: i32 = METHODS.len() as i32;
+/// Names of the `scheme` cases, `other` being the last case
+const SCHEMES: [&str; 2] = ["http", "https"];
+const SCHEME_OTHER: i32 = SCHEMES.len() as i32;
+const SCHEME_HTTPS: i32 = 1;
+
+// image layout
+const METHODS_OFFSET: i32 = 0;
+const SCHEMES_OFFSET: i32 = METHODS_OFFSET + 8 * METHODS.len() as i32;
+const SEPARATOR_OFFSET: i32 = SCHEMES_OFFSET + 8 * SCHEMES.len() as i32;
+const SEPARATOR: &str = "://";
+
+// `error-code` case for requests matching no response
+const ERROR_HTTP_REQUEST_DENIED: i32 = 15;
+
+// `fields` state: an array of 16-byte entries, each the address and length of
+// a lowercase name and of a value
+const FIELDS_ENTRIES: u64 = 0;
+const FIELDS_COUNT: u64 = 4;
+const FIELDS_CAPACITY: u64 = 8;
+const FIELDS_SIZE: i32 = 12;
+const ENTRY_SIZE: i32 = 16;
+
+// `outgoing-request` state, which is also its `outgoing-body`. The method and
+// scheme strings point to the image for the named cases, and the scheme string
+// is `https` when there is no scheme.
+const REQUEST_HEADERS: u64 = 0;
+const REQUEST_METHOD: u64 = 4;
+const REQUEST_SCHEME: u64 = 16;
+const REQUEST_AUTHORITY: u64 = 32;
+const REQUEST_PATH: u64 = 44;
+const REQUEST_SIZE: i32 = 56;
+
+// `incoming-response` state, which is also its `future-incoming-response` and
+// its `incoming-body`
+const RESPONSE_STATUS: u64 = 0;
+const RESPONSE_HEADERS: u64 = 4;
+const RESPONSE_BODY: u64 = 8;
+const RESPONSE_SIZE: i32 = 16;
+
+// body `input-stream` state: the address and length of the body, and the read
+// position
+const STREAM_SIZE: i32 = 12;
+
+/// A mocked response, from an entry of the `http-mock` key of a stub policy
+#[derive(Debug)]
+pub struct MockResponse {
+    /// Method to match, any method when absent
+    pub method: Option<String>,
+    /// URL to match, or URL prefix when `prefix` is set
+    pub url: String,
+    pub prefix: bool,
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+/// HTTP mock configuration, from the `http-mock` key of a stub policy
+#[derive(Debug)]
+pub struct HttpMock {
+    pub responses: Vec<MockResponse>,
+}
+
+impl HttpMock {
+    pub fn from_json(value: &Value) -> Result<Self> {
+        let Value::Array(entries) = value else {
+            bail!("stub policy \"http-mock\" must be an array of responses");
+        };
+        let mut responses = vec![];
+        for (idx, entry) in entries.iter().enumerate() {
+            let Value::Object(config) = entry else {
+                bail!("stub policy \"http-mock[{idx}]\" must be an object");
+            };
+            let mut response = MockResponse {
+                method: None,
+                url: String::new(),
+                prefix: false,
+                status: 200,
+                headers: vec![],
+                body: String::new(),
+            };
+            let mut url = None;
+            for (key, value) in config {
+                match (key.as_str(), value) {
+                    ("method", Value::String(method)) => response.method = Some(method.clone()),
+                    ("url", Value::String(pattern)) => url = Some(pattern.clone()),
+                    ("status", status) => match status.as_u64() {
+                        Some(status @ 100..=599) => response.status = status as u16,
+                        _ => {
+                            bail!("stub policy \"http-mock[{idx}].status\" must be an HTTP status")
+                        }
+                    },
+                    ("headers", Value::Object(headers)) => {
+                        for (name, value) in headers {
+                            let Value::String(value) = value else {
+                                bail!(
+                                    "stub policy \"http-mock[{idx}].headers.{name}\" must be a string"
+                                );
+                            };
+                            response
+                                .headers
+                                .push((name.to_ascii_lowercase(), value.clone()));
+                        }
+                    }
+                    ("body", Value::String(body)) => response.body = body.clone(),
+                    ("method" | "url" | "body", _) => {
+                        bail!("stub policy \"http-mock[{idx}].{key}\" must be a string")
+                    }
+                    ("headers", _) => {
+                        bail!("stub policy \"http-mock[{idx}].headers\" must be an object")
+                    }
+                    _ => bail!("unrecognized stub policy key [http-mock[{idx}].{key}]"),
+                }
+            }
+            let Some(url) = url else {
+                bail!("stub policy \"http-mock[{idx}]\" requires a \"url\"");
+            };
+            let (url, prefix) = match url.strip_suffix('*') {
+                Some(prefix) => (prefix.to_string(), true),
+                None => (url, false),
+            };
+            if url.contains('*') {
+                bail!("stub policy \"http-mock[{idx}].url\" may only end with a `*`");
+            }
+            response.url = url;
+            response.prefix = prefix;
+            responses.push(response);
+        }
+        Ok(HttpMock { responses })
+    }
+
+    /// Lay out the image, returning it along with the location of the strings
+    /// of each response
+    fn image(&self) -> (Vec<u8>, Vec<ResponseImage>) {
+        let mut image = vec![0; SEPARATOR_OFFSET as usize];
+        let push = |image: &mut Vec<u8>, bytes: &[u8]| -> (i32, i32) {
+            let offset = image.len() as i32;
+            image.extend_from_slice(bytes);
+            (offset, bytes.len() as i32)
+        };
+        push(&mut image, SEPARATOR.as_bytes());
+        for (idx, name) in METHODS.iter().chain(SCHEMES.iter()).enumerate() {
+            let (offset, len) = push(&mut image, name.as_bytes());
+            let entry = METHODS_OFFSET as usize + 8 * idx;
+            image[entry..entry + 4].copy_from_slice(&offset.to_le_bytes());
+            image[entry + 4..entry + 8].copy_from_slice(&len.to_le_bytes());
+        }
+        let responses = self
+            .responses
+            .iter()
+            .map(|response| ResponseImage {
+                method: response
+                    .method
+                    .as_ref()
+                    .map(|method| push(&mut image, method.as_bytes())),
+                url: push(&mut image, response.url.as_bytes()),
+                headers: response
+                    .headers
+                    .iter()
+                    .map(|(name, value)| {
+                        (
+                            push(&mut image, name.as_bytes()),
+                            push(&mut image, value.as_bytes()),
+                        )
+                    })
+                    .collect(),
+                body: push(&mut image, response.body.as_bytes()),
+            })
+            .collect();
+        (image, responses)
+    }
+}
+
+/// `(offset, len)` of the strings of a response in the image
+struct ResponseImage {
+    method: Option<(i32, i32)>,
+    url: (i32, i32),
+    headers: Vec<((i32, i32), (i32, i32))>,
+    body: (i32, i32),
+}
+
+/// Loop while `i` is below the value of `n`, incrementing it after `each`, which
+/// can leave the loop with `br 1` at its top level
+fn emit_while_below(
+    body: &mut FunctionBuilder,
+    i: LocalID,
+    n: LocalID,
+    each: impl FnOnce(&mut FunctionBuilder),
+) {
+    body.block(BlockType::Empty);
+    body.loop_stmt(BlockType::Empty);
+    body.local_get(i);
+    body.local_get(n);
+    body.i32_ge_u();
+    body.br_if(1);
+    each(body);
+    body.local_get(i);
+    body.i32_const(1);
+    body.i32_add();
+    body.local_set(i);
+    body.br(0);
+    body.end();
+    body.end();
+}
+
+/// Helper functions shared by the synthesized imports
+#[derive(Clone, Copy)]
+struct Http {
+    memory: MemoryID,
+    realloc: FunctionID,
+    /// `() -> i32`: address of the image
+    base: FunctionID,
+    /// `(ptr, len, lower) -> i32`: copy a string into a new allocation,
+    /// lowercasing its ASCII letters when `lower` is set
+    dup: FunctionID,
+    memeq: FunctionID,
+    /// `() -> i32`: create an empty `fields`
+    fields_new: FunctionID,
+    /// `(fields, name_ptr, name_len, value_ptr, value_len)`: append a copy of an
+    /// entry
+    fields_append: FunctionID,
+    /// `(fields, name_ptr, name_len, start) -> i32`: index of the first entry
+    /// from `start` with a lowercase name, or -1
+    fields_find: FunctionID,
+    /// `(fields, index)`: remove an entry, keeping the order of the others
+    fields_remove: FunctionID,
+}
+
+impl Http {
+    fn new(module: &mut Module, image: Vec<u8>) -> Http {
+        let memory = module.get_memory_id().unwrap();
+        let realloc = module
+            .exports
+            .get_func_by_name("cabi_realloc".to_string())
+            .unwrap();
+        let base = build_image(module, image);
+        let memeq = build_memeq(module, memory);
+        let dup = Self::build_dup(module, memory, realloc);
+
+        let mut body = FunctionBuilder::new(&[], &[DataType::I32]);
+        let fields = body.add_local(DataType::I32);
+        body.i32_const(0);
+        body.i32_const(0);
+        body.i32_const(4);
+        body.i32_const(FIELDS_SIZE);
+        body.call(realloc);
+        body.local_tee(fields);
+        body.i32_const(0);
+        body.i32_const(FIELDS_SIZE);
+        body.memory_fill(memory);
+        body.local_get(fields);
+        let fields_new = body.finish_module(module);
+
+        let mut http = Http {
+            memory,
+            realloc,
+            base,
+            dup,
+            memeq,
+            fields_new,
+            fields_append: fields_new,
+            fields_find: fields_new,
+            fields_remove: fields_new,
+        };
+        http.fields_append = http.build_fields_append(module);
+        http.fields_find = http.build_fields_find(module);
+        http.fields_remove = http.build_fields_remove(module);
+        http
+    }
+
+    fn build_dup(module: &mut Module, memory: MemoryID, realloc: FunctionID) -> FunctionID {
+        let (ptr, len, lower) = (LocalID(0), LocalID(1), LocalID(2));
+        let mut body = FunctionBuilder::new(
+            &[DataType::I32, DataType::I32, DataType::I32],
+            &[DataType::I32],
+        );
+        let out = body.add_local(DataType::I32);
+        let i = body.add_local(DataType::I32);
+        let c = body.add_local(DataType::I32);
+        body.i32_const(0);
+        body.i32_const(0);
+        body.i32_const(1);
+        body.local_get(len);
+        body.call(realloc);
+        body.local_tee(out);
+        body.local_get(ptr);
+        body.local_get(len);
+        body.memory_copy(memory, memory);
+        body.local_get(lower);
+        body.if_stmt(BlockType::Empty);
+        emit_while_below(&mut body, i, len, |body| {
+            body.local_get(out);
+            body.local_get(i);
+            body.i32_add();
+            body.i32_load8_u(mem(memory, 0, 0));
+            body.local_tee(c);
+            body.i32_const(b'A' as i32);
+            body.i32_sub();
+            body.i32_const(26);
+            body.i32_lt_u();
+            body.if_stmt(BlockType::Empty);
+            body.local_get(out);
+            body.local_get(i);
+            body.i32_add();
+            body.local_get(c);
+            body.i32_const(0x20);
+            body.i32_or();
+            body.i32_store8(mem(memory, 0, 0));
+            body.end();
+        });
+        body.end();
+        body.local_get(out);
+        body.finish_module(module)
+    }
+
+    /// Push the address of the entry `i` of `fields`
+    fn entry(self, body: &mut FunctionBuilder, fields: LocalID, i: LocalID) {
+        body.local_get(fields);
+        body.i32_load(mem(self.memory, 2, FIELDS_ENTRIES));
+        body.local_get(i);
+        body.i32_const(ENTRY_SIZE);
+        body.i32_mul();
+        body.i32_add();
+    }
+
+    fn build_fields_append(self, module: &mut Module) -> FunctionID {
+        let memory = self.memory;
+        let (fields, name, name_len, value, value_len) =
+            (LocalID(0), LocalID(1), LocalID(2), LocalID(3), LocalID(4));
+        let mut body = FunctionBuilder::new(&[DataType::I32; 5], &[]);
+        let count = body.add_local(DataType::I32);
+        let entry = body.add_local(DataType::I32);
+        body.local_get(fields);
+        body.i32_load(mem(memory, 2, FIELDS_COUNT));
+        body.local_tee(count);
+        body.local_get(fields);
+        body.i32_load(mem(memory, 2, FIELDS_CAPACITY));
+        body.i32_eq();
+        body.if_stmt(BlockType::Empty);
+        // grow to twice the capacity plus 8 entries
+        body.local_get(fields);
+        body.local_get(fields);
+        body.i32_load(mem(memory, 2, FIELDS_ENTRIES));
+        body.local_get(count);
+        body.i32_const(ENTRY_SIZE);
+        body.i32_mul();
+        body.i32_const(4);
+        body.local_get(count);
+        body.i32_const(2 * ENTRY_SIZE);
+        body.i32_mul();
+        body.i32_const(8 * ENTRY_SIZE);
+        body.i32_add();
+        body.call(self.realloc);
+        body.i32_store(mem(memory, 2, FIELDS_ENTRIES));
+        body.local_get(fields);
+        body.local_get(count);
+        body.i32_const(1);
+        body.i32_shl();
+        body.i32_const(8);
+        body.i32_add();
+        body.i32_store(mem(memory, 2, FIELDS_CAPACITY));
+        body.end();
+
+        self.entry(&mut body, fields, count);
+        body.local_tee(entry);
+        body.local_get(name);
+        body.local_get(name_len);
+        body.i32_const(1);
+        body.call(self.dup);
+        body.i32_store(mem(memory, 2, 0));
+        body.local_get(entry);
+        body.local_get(name_len);
+        body.i32_store(mem(memory, 2, 4));
+        body.local_get(entry);
+        body.local_get(value);
+        body.local_get(value_len);
+        body.i32_const(0);
+        body.call(self.dup);
+        body.i32_store(mem(memory, 2, 8));
+        body.local_get(entry);
+        body.local_get(value_len);
+        body.i32_store(mem(memory, 2, 12));
+        body.local_get(fields);
+        body.local_get(count);
+        body.i32_const(1);
+        body.i32_add();
+        body.i32_store(mem(memory, 2, FIELDS_COUNT));
+        body.finish_module(module)
+    }
+
+    fn build_fields_find(self, module: &mut Module) -> FunctionID {
+        let memory = self.memory;
+        let (fields, name, name_len, i) = (LocalID(0), LocalID(1), LocalID(2), LocalID(3));
+        let mut body = FunctionBuilder::new(&[DataType::I32; 4], &[DataType::I32]);
+        let count = body.add_local(DataType::I32);
+        let entry = body.add_local(DataType::I32);
+        body.local_get(fields);
+        body.i32_load(mem(memory, 2, FIELDS_COUNT));
+        body.local_set(count);
+        emit_while_below(&mut body, i, count, |body| {
+            self.entry(body, fields, i);
+            body.local_tee(entry);
+            body.i32_load(mem(memory, 2, 4));
+            body.local_get(name_len);
+            body.i32_eq();
+            body.if_stmt(BlockType::Empty);
+            body.local_get(entry);
+            body.i32_load(mem(memory, 2, 0));
+            body.local_get(name);
+            body.local_get(name_len);
+            body.call(self.memeq);
+            body.if_stmt(BlockType::Empty);
+            body.local_get(i);
+            body.return_stmt();
+            body.end();
+            body.end();
+        });
+        body.i32_const(-1);
+        body.finish_module(module)
+    }
+
+    fn build_fields_remove(self, module: &mut Module) -> FunctionID {
+        let memory = self.memory;
+        let (fields, i) = (LocalID(0), LocalID(1));
+        let mut body = FunctionBuilder::new(&[DataType::I32, DataType::I32], &[]);
+        let count = body.add_local(DataType::I32);
+        body.local_get(fields);
+        body.i32_load(mem(memory, 2, FIELDS_COUNT));
+        body.i32_const(1);
+        body.i32_sub();
+        body.local_set(count);
+        self.entry(&mut body, fields, i);
+        self.entry(&mut body, fields, i);
+        body.i32_const(ENTRY_SIZE);
+        body.i32_add();
+        body.local_get(count);
+        body.local_get(i);
+        body.i32_sub();
+        body.i32_const(ENTRY_SIZE);
+        body.i32_mul();
+        body.memory_copy(memory, memory);
+        body.local_get(fields);
+        body.local_get(count);
+        body.i32_store(mem(memory, 2, FIELDS_COUNT));
+        body.finish_module(module)
+    }
+
+    /// Push `realloc(0, 0, align, size)`
+    fn alloc(
+        self,
+        body: &mut FunctionBuilder,
+        align: i32,
+        size: impl FnOnce(&mut FunctionBuilder),
+    ) {
+        body.i32_const(0);
+        body.i32_const(0);
+        body.i32_const(align);
+        size(body);
+        body.call(self.realloc);
+    }
+
+    /// Push the address of the image plus `offset`
+    fn image_addr(self, body: &mut FunctionBuilder, offset: i32) {
+        body.call(self.base);
+        body.i32_const(offset);
+        body.i32_add();
+    }
+
+    fn store_u8(self, body: &mut FunctionBuilder, ptr: LocalID, offset: u64, value: i32) {
+        body.local_get(ptr);
+        body.i32_const(value);
+        body.i32_store8(mem(self.memory, 0, offset));
+    }
+
+    fn store_local(self, body: &mut FunctionBuilder, ptr: LocalID, offset: u64, value: LocalID) {
+        body.local_get(ptr);
+        body.local_get(value);
+        body.i32_store(mem(self.memory, 2, offset));
+    }
+
+    /// Store a copy of the string at `(ptr, len)` as a `string` at `offset`
+    fn store_string(
+        self,
+        body: &mut FunctionBuilder,
+        dst: LocalID,
+        offset: u64,
+        ptr: impl Fn(&mut FunctionBuilder),
+        len: impl Fn(&mut FunctionBuilder),
+    ) {
+        body.local_get(dst);
+        ptr(body);
+        len(body);
+        body.i32_const(0);
+        body.call(self.dup);
+        body.i32_store(mem(self.memory, 2, offset));
+        body.local_get(dst);
+        len(body);
+        body.i32_store(mem(self.memory, 2, offset + 4));
+    }
+
+    /// Store at `offset` of a request the `(ptr, len)` of the name of the case
+    /// `case` of the table at `table` in the image
+    fn store_case_name(
+        self,
+        body: &mut FunctionBuilder,
+        request: LocalID,
+        offset: u64,
+        table: i32,
+        case: LocalID,
+    ) {
+        let memory = self.memory;
+        for field in [0, 4] {
+            body.local_get(request);
+            self.image_addr(body, 0);
+            body.local_get(case);
+            body.i32_const(3);
+            body.i32_shl();
+            body.i32_add();
+            body.i32_load(mem(memory, 2, (table + field) as u64));
+            if field == 0 {
+                body.call(self.base);
+                body.i32_add();
+            }
+            body.i32_store(mem(memory, 2, offset + field as u64));
+        }
+    }
+
+    /// Store at `offset` of a request a copy of the string of the `other` case
+    /// `other` of a variant, or the name of its named case `case`
+    fn store_case_string(
+        self,
+        body: &mut FunctionBuilder,
+        request: LocalID,
+        offset: u64,
+        table: i32,
+        (case, other): (LocalID, i32),
+        (ptr, len): (LocalID, LocalID),
+    ) {
+        body.local_get(case);
+        body.i32_const(other);
+        body.i32_eq();
+        body.if_stmt(BlockType::Empty);
+        self.store_string(
+            body,
+            request,
+            offset,
+            |body| {
+                body.local_get(ptr);
+            },
+            |body| {
+                body.local_get(len);
+            },
+        );
+        body.else_stmt();
+        self.store_case_name(body, request, offset, table, case);
+        body.end();
+    }
+}
+
+/// Replace `wasi:http/outgoing-handler` and `wasi:http/types` with functions
+/// answering requests from the mocked responses
+pub(crate) fn synthesize_http_mock(
+    module: &mut Module,
+    dispatcher: &mut Dispatcher,
+    policy: &StubPolicy,
+    mock: &HttpMock,
+    has_io: bool,
+) -> Result<()> {
+    // imports must be added before any local function is synthesized
+    dispatcher.ready_pollables(module, has_io)?;
+    let mut streams = vec![];
+    for (name, stream_fn) in [
+        ("[method]input-stream.read", StreamFn::Read),
+        ("[method]input-stream.blocking-read", StreamFn::Read),
+        ("[method]input-stream.skip", StreamFn::Skip),
+        ("[method]input-stream.blocking-skip", StreamFn::Skip),
+        ("[method]input-stream.subscribe", StreamFn::Subscribe),
+        ("[resource-drop]input-stream", StreamFn::Drop),
+    ] {
+        for dispatch in
+            dispatcher.dispatch_handles(module, HTTP_TAG, "wasi:io/streams", name, has_io)?
+        {
+            streams.push((stream_fn, dispatch));
+        }
+    }
+
+    let (image, responses) = mock.image();
+    let http = Http::new(module, image);
+    let memory = http.memory;
+
+    for (stream_fn, dispatch) in streams {
+        dispatcher.handle(module, dispatch, |body| stream_fn.build(http, body))?;
+    }
+
+    // request bodies are discarded
+    let discard = FunctionBuilder::new(&[DataType::I32; 3], &[]).finish_module(module);
+    dispatch_output_streams(module, dispatcher, HTTP_TAG, has_io, discard)?;
+
+    synthesize_fields(module, policy, http)?;
+    synthesize_outgoing_request(module, policy, http)?;
+
+    let types = "wasi:http/types";
+
+    // (func (param i32 i32 i32 i32))
+    stub_wasi_imports(
+        module,
+        policy,
+        "wasi:http/outgoing-handler",
+        "handle",
+        |body| {
+            let (request, retptr) = (LocalID(0), LocalID(3));
+            let url = body.add_local(DataType::I32);
+            let url_len = body.add_local(DataType::I32);
+            let len = body.add_local(DataType::I32);
+            let response = body.add_local(DataType::I32);
+            let headers = body.add_local(DataType::I32);
+
+            // assemble the URL
+            let field = |body: &mut FunctionBuilder, offset: u64| {
+                body.local_get(request);
+                body.i32_load(mem(memory, 2, offset));
+            };
+            field(body, REQUEST_SCHEME + 12);
+            body.i32_const(SEPARATOR.len() as i32);
+            body.i32_add();
+            field(body, REQUEST_AUTHORITY + 8);
+            body.i32_add();
+            field(body, REQUEST_PATH + 8);
+            body.i32_add();
+            body.local_set(url_len);
+            http.alloc(body, 1, |body| {
+                body.local_get(url_len);
+            });
+            body.local_tee(url);
+            body.local_set(len);
+            // `len` is the end of the URL assembled so far
+            let copy = |body: &mut FunctionBuilder,
+                        ptr: &dyn Fn(&mut FunctionBuilder),
+                        part_len: &dyn Fn(&mut FunctionBuilder)| {
+                body.local_get(len);
+                ptr(body);
+                part_len(body);
+                body.memory_copy(memory, memory);
+                body.local_get(len);
+                part_len(body);
+                body.i32_add();
+                body.local_set(len);
+            };
+            copy(body, &|body| field(body, REQUEST_SCHEME + 8), &|body| {
+                field(body, REQUEST_SCHEME + 12)
+            });
+            copy(
+                body,
+                &|body| http.image_addr(body, SEPARATOR_OFFSET),
+                &|body| {
+                    body.i32_const(SEPARATOR.len() as i32);
+                },
+            );
+            for offset in [REQUEST_AUTHORITY, REQUEST_PATH] {
+                copy(body, &|body| field(body, offset + 4), &|body| {
+                    field(body, offset + 8)
+                });
+            }
+
+            for (response_mock, response_image) in mock.responses.iter().zip(&responses) {
+                body.block(BlockType::Empty);
+                if let Some((offset, method_len)) = response_image.method {
+                    field(body, REQUEST_METHOD + 8);
+                    body.i32_const(method_len);
+                    body.i32_ne();
+                    body.br_if(0);
+                    field(body, REQUEST_METHOD + 4);
+                    http.image_addr(body, offset);
+                    body.i32_const(method_len);
+                    body.call(http.memeq);
+                    body.i32_eqz();
+                    body.br_if(0);
+                }
+                let (offset, pattern_len) = response_image.url;
+                body.local_get(url_len);
+                body.i32_const(pattern_len);
+                if response_mock.prefix {
+                    body.i32_lt_u();
+                } else {
+                    body.i32_ne();
+                }
+                body.br_if(0);
+                body.local_get(url);
+                http.image_addr(body, offset);
+                body.i32_const(pattern_len);
+                body.call(http.memeq);
+                body.i32_eqz();
+                body.br_if(0);
+
+                body.call(http.fields_new);
+                body.local_set(headers);
+                for ((name, name_len), (value, value_len)) in &response_image.headers {
+                    body.local_get(headers);
+                    http.image_addr(body, *name);
+                    body.i32_const(*name_len);
+                    http.image_addr(body, *value);
+                    body.i32_const(*value_len);
+                    body.call(http.fields_append);
+                }
+                http.alloc(body, 4, |body| {
+                    body.i32_const(RESPONSE_SIZE);
+                });
+                body.local_tee(response);
+                body.i32_const(response_mock.status as i32);
+                body.i32_store(mem(memory, 2, RESPONSE_STATUS));
+                http.store_local(body, response, RESPONSE_HEADERS, headers);
+                body.local_get(response);
+                http.image_addr(body, response_image.body.0);
+                body.i32_store(mem(memory, 2, RESPONSE_BODY));
+                body.local_get(response);
+                body.i32_const(response_image.body.1);
+                body.i32_store(mem(memory, 2, RESPONSE_BODY + 4));
+                http.store_u8(body, retptr, 0, 0);
+                http.store_local(body, retptr, 8, response);
+                body.return_stmt();
+                body.end();
+            }
+            http.store_u8(body, retptr, 0, 1);
+            http.store_u8(body, retptr, 8, ERROR_HTTP_REQUEST_DENIED);
+            Ok(vec![])
+        },
+    )?;
+
+    // the response is ready as soon as the request is handled
+    for name in [
+        "[method]future-incoming-response.subscribe",
+        "[method]future-trailers.subscribe",
+    ] {
+        stub_wasi_imports(module, policy, types, name, |body| {
+            body.i32_const(READY_TAG);
+            Ok(vec![])
+        })?;
+    }
+
+    // (func (param i32 i32))
+    stub_wasi_imports(
+        module,
+        policy,
+        types,
+        "[method]future-incoming-response.get",
+        |body| {
+            let (response, retptr) = (LocalID(0), LocalID(1));
+            http.store_u8(body, retptr, 0, 1);
+            http.store_u8(body, retptr, 8, 0);
+            http.store_u8(body, retptr, 16, 0);
+            http.store_local(body, retptr, 24, response);
+            Ok(vec![])
+        },
+    )?;
+
+    // (func (param i32 i32))
+    stub_wasi_imports(
+        module,
+        policy,
+        types,
+        "[method]future-trailers.get",
+        |body| {
+            let retptr = LocalID(1);
+            http.store_u8(body, retptr, 0, 1);
+            http.store_u8(body, retptr, 8, 0);
+            http.store_u8(body, retptr, 16, 0);
+            http.store_u8(body, retptr, 24, 0);
+            Ok(vec![])
+        },
+    )?;
+
+    for (name, offset) in [
+        ("[method]incoming-response.status", RESPONSE_STATUS),
+        ("[method]incoming-response.headers", RESPONSE_HEADERS),
+    ] {
+        // (func (param i32) (result i32))
+        stub_wasi_imports(module, policy, types, name, |body| {
+            body.local_get(LocalID(0));
+            body.i32_load(mem(memory, 2, offset));
+            Ok(vec![])
+        })?;
+    }
+
+    // (func (param i32 i32))
+    stub_wasi_imports(
+        module,
+        policy,
+        types,
+        "[method]incoming-response.consume",
+        |body| {
+            let (response, retptr) = (LocalID(0), LocalID(1));
+            http.store_u8(body, retptr, 0, 0);
+            http.store_local(body, retptr, 4, response);
+            Ok(vec![])
+        },
+    )?;
+
+    // (func (param i32 i32))
+    stub_wasi_imports(
+        module,
+        policy,
+        types,
+        "[method]incoming-body.stream",
+        |body| {
+            let (response, retptr) = (LocalID(0), LocalID(1));
+            let stream = body.add_local(DataType::I32);
+            http.alloc(body, 4, |body| {
+                body.i32_const(STREAM_SIZE);
+            });
+            body.local_tee(stream);
+            body.local_get(response);
+            body.i64_load(mem(memory, 2, RESPONSE_BODY));
+            body.i64_store(mem(memory, 2, 0));
+            body.local_get(stream);
+            body.i32_const(0);
+            body.i32_store(mem(memory, 2, 8));
+            http.store_u8(body, retptr, 0, 0);
+            body.local_get(retptr);
+            body.local_get(stream);
+            emit_tag_address(body, HTTP_TAG);
+            body.i32_store(mem(memory, 2, 4));
+            Ok(vec![])
+        },
+    )?;
+
+    // (func (param i32) (result i32))
+    stub_wasi_imports(
+        module,
+        policy,
+        types,
+        "[static]incoming-body.finish",
+        |body| {
+            // trailers are never provided, so the future has no state
+            body.i32_const(1);
+            Ok(vec![])
+        },
+    )?;
+
+    // (func (param i32 i32))
+    stub_wasi_imports(module, policy, types, "http-error-code", |body| {
+        http.store_u8(body, LocalID(1), 0, 0);
+        Ok(vec![])
+    })?;
+
+    // state is not reclaimed
+    for name in [
+        "[resource-drop]fields",
+        "[resource-drop]outgoing-request",
+        "[resource-drop]request-options",
+        "[resource-drop]outgoing-body",
+        "[resource-drop]future-incoming-response",
+        "[resource-drop]incoming-response",
+        "[resource-drop]incoming-body",
+        "[resource-drop]future-trailers",
+    ] {
+        stub_wasi_imports(module, policy, types, name, |_| Ok(vec![]))?;
+    }
+
+    Ok(())
+}
+
+/// Synthesize the `fields` resource
+fn synthesize_fields(module: &mut Module, policy: &StubPolicy, http: Http) -> Result<()> {
+    let memory = http.memory;
+    let types = "wasi:http/types";
+
+    // (func (result i32))
+    stub_wasi_imports(module, policy, types, "[constructor]fields", |body| {
+        body.call(http.fields_new);
+        Ok(vec![])
+    })?;
+
+    // (func (param i32 i32 i32))
+    stub_wasi_imports(module, policy, types, "[static]fields.from-list", |body| {
+        let (list, len, retptr) = (LocalID(0), LocalID(1), LocalID(2));
+        let fields = body.add_local(DataType::I32);
+        let i = body.add_local(DataType::I32);
+        let entry = body.add_local(DataType::I32);
+        body.call(http.fields_new);
+        body.local_set(fields);
+        emit_while_below(body, i, len, |body| {
+            body.local_get(list);
+            body.local_get(i);
+            body.i32_const(ENTRY_SIZE);
+            body.i32_mul();
+            body.i32_add();
+            body.local_set(entry);
+            body.local_get(fields);
+            for offset in [0, 4, 8, 12] {
+                body.local_get(entry);
+                body.i32_load(mem(memory, 2, offset));
+            }
+            body.call(http.fields_append);
+        });
+        http.store_u8(body, retptr, 0, 0);
+        http.store_local(body, retptr, 4, fields);
+        Ok(vec![])
+    })?;
+
+    // (func (param i32) (result i32))
+    stub_wasi_imports(module, policy, types, "[method]fields.clone", |body| {
+        let fields = LocalID(0);
+        let clone = body.add_local(DataType::I32);
+        let i = body.add_local(DataType::I32);
+        let count = body.add_local(DataType::I32);
+        let entry = body.add_local(DataType::I32);
+        body.call(http.fields_new);
+        body.local_set(clone);
+        body.local_get(fields);
+        body.i32_load(mem(memory, 2, FIELDS_COUNT));
+        body.local_set(count);
+        emit_while_below(body, i, count, |body| {
+            http.entry(body, fields, i);
+            body.local_set(entry);
+            body.local_get(clone);
+            for offset in [0, 4, 8, 12] {
+                body.local_get(entry);
+                body.i32_load(mem(memory, 2, offset));
+            }
+            body.call(http.fields_append);
+        });
+        body.local_get(clone);
+        Ok(vec![])
+    })?;
+
+    // (func (param i32 i32 i32) (result i32))
+    stub_wasi_imports(module, policy, types, "[method]fields.has", |body| {
+        let (fields, name, len) = (LocalID(0), LocalID(1), LocalID(2));
+        body.local_get(fields);
+        body.local_get(name);
+        body.local_get(len);
+        body.i32_const(1);
+        body.call(http.dup);
+        body.local_get(len);
+        body.i32_const(0);
+        body.call(http.fields_find);
+        body.i32_const(-1);
+        body.i32_ne();
+        Ok(vec![])
+    })?;
+
+    // (func (param i32 i32 i32 i32))
+    stub_wasi_imports(module, policy, types, "[method]fields.get", |body| {
+        let (fields, name, len, retptr) = (LocalID(0), LocalID(1), LocalID(2), LocalID(3));
+        let lower = body.add_local(DataType::I32);
+        let list = body.add_local(DataType::I32);
+        let count = body.add_local(DataType::I32);
+        let i = body.add_local(DataType::I32);
+        let entry = body.add_local(DataType::I32);
+        let item = body.add_local(DataType::I32);
+        body.local_get(name);
+        body.local_get(len);
+        body.i32_const(1);
+        body.call(http.dup);
+        body.local_set(lower);
+        http.alloc(body, 4, |body| {
+            body.local_get(fields);
+            body.i32_load(mem(memory, 2, FIELDS_COUNT));
+            body.i32_const(3);
+            body.i32_shl();
+        });
+        body.local_set(list);
+        body.block(BlockType::Empty);
+        body.loop_stmt(BlockType::Empty);
+        body.local_get(fields);
+        body.local_get(lower);
+        body.local_get(len);
+        body.local_get(i);
+        body.call(http.fields_find);
+        body.local_tee(i);
+        body.i32_const(0);
+        body.i32_lt_s();
+        body.br_if(1);
+        http.entry(body, fields, i);
+        body.local_set(entry);
+        body.local_get(list);
+        body.local_get(count);
+        body.i32_const(3);
+        body.i32_shl();
+        body.i32_add();
+        body.local_set(item);
+        http.store_string(
+            body,
+            item,
+            0,
+            |body| {
+                body.local_get(entry);
+                body.i32_load(mem(memory, 2, 8));
+            },
+            |body| {
+                body.local_get(entry);
+                body.i32_load(mem(memory, 2, 12));
+            },
+        );
+        body.local_get(count);
+        body.i32_const(1);
+        body.i32_add();
+        body.local_set(count);
+        body.local_get(i);
+        body.i32_const(1);
+        body.i32_add();
+        body.local_set(i);
+        body.br(0);
+        body.end();
+        body.end();
+        http.store_local(body, retptr, 0, list);
+        http.store_local(body, retptr, 4, count);
+        Ok(vec![])
+    })?;
+
+    // (func (param i32 i32))
+    stub_wasi_imports(module, policy, types, "[method]fields.entries", |body| {
+        let (fields, retptr) = (LocalID(0), LocalID(1));
+        let list = body.add_local(DataType::I32);
+        let count = body.add_local(DataType::I32);
+        let i = body.add_local(DataType::I32);
+        let entry = body.add_local(DataType::I32);
+        let item = body.add_local(DataType::I32);
+        body.local_get(fields);
+        body.i32_load(mem(memory, 2, FIELDS_COUNT));
+        body.local_set(count);
+        http.alloc(body, 4, |body| {
+            body.local_get(count);
+            body.i32_const(ENTRY_SIZE);
+            body.i32_mul();
+        });
+        body.local_set(list);
+        emit_while_below(body, i, count, |body| {
+            http.entry(body, fields, i);
+            body.local_set(entry);
+            body.local_get(list);
+            body.local_get(i);
+            body.i32_const(ENTRY_SIZE);
+            body.i32_mul();
+            body.i32_add();
+            body.local_set(item);
+            for offset in [0, 8] {
+                http.store_string(
+                    body,
+                    item,
+                    offset,
+                    |body| {
+                        body.local_get(entry);
+                        body.i32_load(mem(memory, 2, offset));
+                    },
+                    |body| {
+                        body.local_get(entry);
+                        body.i32_load(mem(memory, 2, offset + 4));
+                    },
+                );
+            }
+        });
+        http.store_local(body, retptr, 0, list);
+        http.store_local(body, retptr, 4, count);
+        Ok(vec![])
+    })?;
+
+    // remove the entries named `lower`, returning `ok` in `retptr`
+    let delete = |body: &mut FunctionBuilder, fields: LocalID, lower: LocalID, len: LocalID| {
+        let i = body.add_local(DataType::I32);
+        body.block(BlockType::Empty);
+        body.loop_stmt(BlockType::Empty);
+        body.local_get(fields);
+        body.local_get(lower);
+        body.local_get(len);
+        body.i32_const(0);
+        body.call(http.fields_find);
+        body.local_tee(i);
+        body.i32_const(0);
+        body.i32_lt_s();
+        body.br_if(1);
+        body.local_get(fields);
+        body.local_get(i);
+        body.call(http.fields_remove);
+        body.br(0);
+        body.end();
+        body.end();
+    };
+    let lowercase = |body: &mut FunctionBuilder, name: LocalID, len: LocalID| -> LocalID {
+        let lower = body.add_local(DataType::I32);
+        body.local_get(name);
+        body.local_get(len);
+        body.i32_const(1);
+        body.call(http.dup);
+        body.local_set(lower);
+        lower
+    };
+
+    // header errors are never reported, as all fields are mutable
+    // (func (param i32 i32 i32 i32))
+    stub_wasi_imports(module, policy, types, "[method]fields.delete", |body| {
+        let (fields, name, len, retptr) = (LocalID(0), LocalID(1), LocalID(2), LocalID(3));
+        let lower = lowercase(body, name, len);
+        delete(body, fields, lower, len);
+        http.store_u8(body, retptr, 0, 0);
+        Ok(vec![])
+    })?;
+
+    // (func (param i32 i32 i32 i32 i32 i32))
+    stub_wasi_imports(module, policy, types, "[method]fields.set", |body| {
+        let (fields, name, len, values, count, retptr) = (
+            LocalID(0),
+            LocalID(1),
+            LocalID(2),
+            LocalID(3),
+            LocalID(4),
+            LocalID(5),
+        );
+        let i = body.add_local(DataType::I32);
+        let value = body.add_local(DataType::I32);
+        let lower = lowercase(body, name, len);
+        delete(body, fields, lower, len);
+        emit_while_below(body, i, count, |body| {
+            body.local_get(values);
+            body.local_get(i);
+            body.i32_const(3);
+            body.i32_shl();
+            body.i32_add();
+            body.local_set(value);
+            body.local_get(fields);
+            body.local_get(lower);
+            body.local_get(len);
+            body.local_get(value);
+            body.i32_load(mem(memory, 2, 0));
+            body.local_get(value);
+            body.i32_load(mem(memory, 2, 4));
+            body.call(http.fields_append);
+        });
+        http.store_u8(body, retptr, 0, 0);
+        Ok(vec![])
+    })?;
+
+    // (func (param i32 i32 i32 i32 i32 i32))
+    stub_wasi_imports(module, policy, types, "[method]fields.append", |body| {
+        let retptr = LocalID(5);
+        for idx in 0..5 {
+            body.local_get(LocalID(idx));
+        }
+        body.call(http.fields_append);
+        http.store_u8(body, retptr, 0, 0);
+        Ok(vec![])
+    })?;
+
+    Ok(())
+}
+
+/// Synthesize the `outgoing-request`, `outgoing-body` and `request-options`
+/// resources
+fn synthesize_outgoing_request(module: &mut Module, policy: &StubPolicy, http: Http) -> Result<()> {
+    let memory = http.memory;
+    let types = "wasi:http/types";
+
+    // (func (param i32) (result i32))
+    stub_wasi_imports(
+        module,
+        policy,
+        types,
+        "[constructor]outgoing-request",
+        |body| {
+            let headers = LocalID(0);
+            let request = body.add_local(DataType::I32);
+            let case = body.add_local(DataType::I32);
+            http.alloc(body, 4, |body| {
+                body.i32_const(REQUEST_SIZE);
+            });
+            body.local_tee(request);
+            body.i32_const(0);
+            body.i32_const(REQUEST_SIZE);
+            body.memory_fill(memory);
+            http.store_local(body, request, REQUEST_HEADERS, headers);
+            // GET, without scheme
+            http.store_case_name(body, request, REQUEST_METHOD + 4, METHODS_OFFSET, case);
+            body.i32_const(SCHEME_HTTPS);
+            body.local_set(case);
+            http.store_case_name(body, request, REQUEST_SCHEME + 8, SCHEMES_OFFSET, case);
+            body.local_get(request);
+            Ok(vec![])
+        },
+    )?;
+
+    // (func (param i32) (result i32))
+    stub_wasi_imports(
+        module,
+        policy,
+        types,
+        "[method]outgoing-request.headers",
+        |body| {
+            body.local_get(LocalID(0));
+            body.i32_load(mem(memory, 2, REQUEST_HEADERS));
+            Ok(vec![])
+        },
+    )?;
+
+    // (func (param i32 i32 i32 i32) (result i32))
+    stub_wasi_imports(
+        module,
+        policy,
+        types,
+        "[method]outgoing-request.set-method",
+        |body| {
+            let (request, case, ptr, len) = (LocalID(0), LocalID(1), LocalID(2), LocalID(3));
+            http.store_local(body, request, REQUEST_METHOD, case);
+            http.store_case_string(
+                body,
+                request,
+                REQUEST_METHOD + 4,
+                METHODS_OFFSET,
+                (case, METHOD_OTHER),
+                (ptr, len),
+            );
+            body.i32_const(0);
+            Ok(vec![])
+        },
+    )?;
+
+    // (func (param i32 i32))
+    stub_wasi_imports(
+        module,
+        policy,
+        types,
+        "[method]outgoing-request.method",
+        |body| {
+            let (request, retptr) = (LocalID(0), LocalID(1));
+            let case = body.add_local(DataType::I32);
+            body.local_get(retptr);
+            body.local_get(request);
+            body.i32_load(mem(memory, 2, REQUEST_METHOD));
+            body.local_tee(case);
+            body.i32_store8(mem(memory, 0, 0));
+            body.local_get(case);
+            body.i32_const(METHOD_OTHER);
+            body.i32_eq();
+            body.if_stmt(BlockType::Empty);
+            http.store_string(
+                body,
+                retptr,
+                4,
+                |body| {
+                    body.local_get(request);
+                    body.i32_load(mem(memory, 2, REQUEST_METHOD + 4));
+                },
+                |body| {
+                    body.local_get(request);
+                    body.i32_load(mem(memory, 2, REQUEST_METHOD + 8));
+                },
+            );
+            body.end();
+            Ok(vec![])
+        },
+    )?;
+
+    // (func (param i32 i32 i32 i32 i32) (result i32))
+    stub_wasi_imports(
+        module,
+        policy,
+        types,
+        "[method]outgoing-request.set-scheme",
+        |body| {
+            let (request, is_some, case, ptr, len) =
+                (LocalID(0), LocalID(1), LocalID(2), LocalID(3), LocalID(4));
+            http.store_local(body, request, REQUEST_SCHEME, is_some);
+            body.local_get(is_some);
+            body.i32_eqz();
+            body.if_stmt(BlockType::Empty);
+            body.i32_const(SCHEME_HTTPS);
+            body.local_set(case);
+            body.end();
+            http.store_local(body, request, REQUEST_SCHEME + 4, case);
+            http.store_case_string(
+                body,
+                request,
+                REQUEST_SCHEME + 8,
+                SCHEMES_OFFSET,
+                (case, SCHEME_OTHER),
+                (ptr, len),
+            );
+            body.i32_const(0);
+            Ok(vec![])
+        },
+    )?;
+
+    // (func (param i32 i32))
+    stub_wasi_imports(
+        module,
+        policy,
+        types,
+        "[method]outgoing-request.scheme",
+        |body| {
+            let (request, retptr) = (LocalID(0), LocalID(1));
+            body.local_get(retptr);
+            body.local_get(request);
+            body.i32_load(mem(memory, 2, REQUEST_SCHEME));
+            body.i32_store8(mem(memory, 0, 0));
+            body.local_get(retptr);
+            body.local_get(request);
+            body.i32_load(mem(memory, 2, REQUEST_SCHEME + 4));
+            body.i32_store8(mem(memory, 0, 4));
+            body.local_get(request);
+            body.i32_load(mem(memory, 2, REQUEST_SCHEME + 4));
+            body.i32_const(SCHEME_OTHER);
+            body.i32_eq();
+            body.if_stmt(BlockType::Empty);
+            http.store_string(
+                body,
+                retptr,
+                8,
+                |body| {
+                    body.local_get(request);
+                    body.i32_load(mem(memory, 2, REQUEST_SCHEME + 8));
+                },
+                |body| {
+                    body.local_get(request);
+                    body.i32_load(mem(memory, 2, REQUEST_SCHEME + 12));
+                },
+            );
+            body.end();
+            Ok(vec![])
+        },
+    )?;
+
+    for (name, offset) in [
+        ("authority", REQUEST_AUTHORITY),
+        ("path-with-query", REQUEST_PATH),
+    ] {
+        // (func (param i32 i32 i32 i32) (result i32))
+        stub_wasi_imports(
+            module,
+            policy,
+            types,
+            &format!("[method]outgoing-request.set-{name}"),
+            |body| {
+                let (request, is_some, ptr, len) = (LocalID(0), LocalID(1), LocalID(2), LocalID(3));
+                http.store_local(body, request, offset, is_some);
+                // an absent string is empty in the URL
+                body.local_get(request);
+                body.i32_const(0);
+                body.i32_store(mem(memory, 2, offset + 8));
+                body.local_get(is_some);
+                body.if_stmt(BlockType::Empty);
+                http.store_string(
+                    body,
+                    request,
+                    offset + 4,
+                    |body| {
+                        body.local_get(ptr);
+                    },
+                    |body| {
+                        body.local_get(len);
+                    },
+                );
+                body.end();
+                body.i32_const(0);
+                Ok(vec![])
+            },
+        )?;
+
+        // (func (param i32 i32))
+        stub_wasi_imports(
+            module,
+            policy,
+            types,
+            &format!("[method]outgoing-request.{name}"),
+            |body| {
+                let (request, retptr) = (LocalID(0), LocalID(1));
+                body.local_get(retptr);
+                body.local_get(request);
+                body.i32_load(mem(memory, 2, offset));
+                body.i32_store8(mem(memory, 0, 0));
+                body.local_get(request);
+                body.i32_load(mem(memory, 2, offset));
+                body.if_stmt(BlockType::Empty);
+                http.store_string(
+                    body,
+                    retptr,
+                    4,
+                    |body| {
+                        body.local_get(request);
+                        body.i32_load(mem(memory, 2, offset + 4));
+                    },
+                    |body| {
+                        body.local_get(request);
+                        body.i32_load(mem(memory, 2, offset + 8));
+                    },
+                );
+                body.end();
+                Ok(vec![])
+            },
+        )?;
+    }
+
+    // the request is its own body
+    // (func (param i32 i32))
+    stub_wasi_imports(
+        module,
+        policy,
+        types,
+        "[method]outgoing-request.body",
+        |body| {
+            let (request, retptr) = (LocalID(0), LocalID(1));
+            http.store_u8(body, retptr, 0, 0);
+            http.store_local(body, retptr, 4, request);
+            Ok(vec![])
+        },
+    )?;
+
+    // (func (param i32 i32))
+    stub_wasi_imports(
+        module,
+        policy,
+        types,
+        "[method]outgoing-body.write",
+        |body| {
+            let retptr = LocalID(1);
+            http.store_u8(body, retptr, 0, 0);
+            body.local_get(retptr);
+            body.i32_const(HTTP_TAG);
+            body.i32_store(mem(memory, 2, 4));
+            Ok(vec![])
+        },
+    )?;
+
+    // (func (param i32 i32 i32 i32))
+    stub_wasi_imports(
+        module,
+        policy,
+        types,
+        "[static]outgoing-body.finish",
+        |body| {
+            http.store_u8(body, LocalID(3), 0, 0);
+            Ok(vec![])
+        },
+    )?;
+
+    // request options are accepted and ignored
+    // (func (result i32))
+    stub_wasi_imports(
+        module,
+        policy,
+        types,
+        "[constructor]request-options",
+        |body| {
+            body.i32_const(1);
+            Ok(vec![])
+        },
+    )?;
+    for timeout in [
+        "connect-timeout",
+        "first-byte-timeout",
+        "between-bytes-timeout",
+    ] {
+        // (func (param i32 i32))
+        stub_wasi_imports(
+            module,
+            policy,
+            types,
+            &format!("[method]request-options.{timeout}"),
+            |body| {
+                http.store_u8(body, LocalID(1), 0, 0);
+                Ok(vec![])
+            },
+        )?;
+        // (func (param i32 i32 i64) (result i32))
+        stub_wasi_imports(
+            module,
+            policy,
+            types,
+            &format!("[method]request-options.set-{timeout}"),
+            |body| {
+                body.i32_const(0);
+                Ok(vec![])
+            },
+        )?;
+    }
+
+    Ok(())
+}
+
+/// `wasi:io/streams` functions that need to handle response body streams
+#[derive(Clone, Copy)]
+enum StreamFn {
+    Read,
+    Skip,
+    Subscribe,
+    Drop,
+}
+
+impl StreamFn {
+    /// Build the handling of a tagged stream handle
+    fn build(self, http: Http, body: &mut FunctionBuilder) {
+        let memory = http.memory;
+        let (handle, len, retptr) = (LocalID(0), LocalID(1), LocalID(2));
+        let offset = match self {
+            StreamFn::Read => 4,
+            StreamFn::Skip => 8,
+            StreamFn::Subscribe => {
+                body.i32_const(READY_TAG);
+                return;
+            }
+            // stream state is not reclaimed
+            StreamFn::Drop => return,
+        };
+        let state = body.add_local(DataType::I32);
+        let pos = body.add_local(DataType::I32);
+        let n = body.add_local(DataType::I32);
+        let out = body.add_local(DataType::I32);
+        body.local_get(handle);
+        emit_untag_address(body);
+        body.local_tee(state);
+        body.i32_load(mem(memory, 2, 8));
+        body.local_set(pos);
+
+        // err(closed) at the end of the body
+        body.local_get(pos);
+        body.local_get(state);
+        body.i32_load(mem(memory, 2, 4));
+        body.i32_ge_u();
+        body.if_stmt(BlockType::Empty);
+        http.store_u8(body, retptr, 0, 1);
+        http.store_u8(body, retptr, offset, 1);
+        body.else_stmt();
+        // n = min(len, size - pos)
+        body.local_get(state);
+        body.i32_load(mem(memory, 2, 4));
+        body.local_get(pos);
+        body.i32_sub();
+        body.local_tee(n);
+        body.local_get(len);
+        body.i32_wrap_i64();
+        body.local_get(len);
+        body.local_get(n);
+        body.i64_extend_i32_u();
+        body.i64_gt_u();
+        body.select();
+        body.local_set(n);
+        http.store_u8(body, retptr, 0, 0);
+        match self {
+            StreamFn::Read => {
+                http.alloc(body, 1, |body| {
+                    body.local_get(n);
+                });
+                body.local_tee(out);
+                body.local_get(state);
+                body.i32_load(mem(memory, 2, 0));
+                body.local_get(pos);
+                body.i32_add();
+                body.local_get(n);
+                body.memory_copy(memory, memory);
+                http.store_local(body, retptr, 4, out);
+                http.store_local(body, retptr, 8, n);
+            }
+            _ => {
+                body.local_get(retptr);
+                body.local_get(n);
+                body.i64_extend_i32_u();
+                body.i64_store(mem(memory, 3, 8));
+            }
+        }
+        body.local_get(state);
+        body.local_get(pos);
+        body.local_get(n);
+        body.i32_add();
+        body.i32_store(mem(memory, 2, 8));
+        body.end();
+    }
+}
@@ -0,0 +1,273 @@
+//! Inspection of an engine or of a spliced module, for the `inspect` command of
+//! the splicer CLI
+//!
+//! An engine carries the `component-type` world it was built against and the
+//! exports the splicer generates bindings from, which splicing replaces with the
+//! exports of the target world.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use anyhow::{Context as _, Result};
+use serde_json::{Value, json};
+use wirm::ir::id::FunctionID;
+use wirm::{DataType, Module};
+use wit_component::metadata::{Bindgen, decode};
+use wit_parser::WorldItem;
+
+use crate::stub_wasi::PREVIEW1;
+
+/// Engine exports the splicer depends on
+const ENGINE_SYMBOLS: [&str; 10] = [
+    "cabi_realloc",
+    "call",
+    "post_call",
+    "coreabi_get_import",
+    "coreabi_sample_i32",
+    "coreabi_sample_i64",
+    "coreabi_sample_f32",
+    "coreabi_sample_f64",
+    "coreabi_from_bigint64",
+    "coreabi_to_bigint64",
+];
+
+/// World described by the `component-type` custom sections
+#[derive(Debug)]
+pub struct WorldSummary {
+    /// Name of the world, qualified by its package
+    pub name: String,
+    pub imports: Vec<String>,
+    pub exports: Vec<String>,
+}
+
+/// Export of a spliced module along with its core signature
+#[derive(Debug)]
+pub struct CoreExport {
+    pub name: String,
+    pub params: Vec<String>,
+    pub results: Vec<String>,
+}
+
+/// What an engine or a spliced module contains
+#[derive(Debug)]
+pub struct Inspection {
+    pub world: Option<WorldSummary>,
+    /// Engine exports the splicer depends on, and whether they are present
+    pub symbols: Vec<(&'static str, bool)>,
+    /// Function imports of WASI interfaces, by interface and version
+    pub wasi_imports: BTreeMap<String, BTreeMap<String, Vec<String>>>,
+    /// Whether the module has been spliced, its `call` and `post_call` exports
+    /// having been replaced by the exports of the world
+    pub spliced: bool,
+    /// Exports of the world found in a spliced module
+    pub exports: Vec<CoreExport>,
+}
+
+/// Inspect an engine or a spliced module
+pub fn inspect(wasm: &[u8]) -> Result<Inspection> {
+    let module = Module::parse(wasm, false, false).context("failed to parse module")?;
+
+    let symbols = ENGINE_SYMBOLS
+        .iter()
+        .map(|name| {
+            (
+                *name,
+                module.exports.get_by_name(name.to_string()).is_some(),
+            )
+        })
+        .collect::<Vec<_>>();
+    let spliced = !symbols
+        .iter()
+        .any(|(name, present)| *present && matches!(*name, "call" | "post_call"));
+
+    let mut wasi_imports: BTreeMap<String, BTreeMap<String, Vec<String>>> = BTreeMap::new();
+    for import in module.imports.iter() {
+        if !import.is_function() {
+            continue;
+        }
+        let (iface, version) = match import.module.split_once('@') {
+            Some((iface, version)) if iface.starts_with("wasi:") => (iface, version),
+            _ if import.module == PREVIEW1 => (PREVIEW1, ""),
+            _ => continue,
+        };
+        wasi_imports
+            .entry(iface.to_string())
+            .or_default()
+            .entry(version.to_string())
+            .or_default()
+            .push(import.name.to_string());
+    }
+
+    // engines without bindings metadata are still worth inspecting
+    let mut world = None;
+    let mut exports = vec![];
+    if let Ok((
+        _,
+        Bindgen {
+            resolve, world: id, ..
+        },
+    )) = decode(wasm)
+    {
+        let target = &resolve.worlds[id];
+        let mut export_names = vec![];
+        for (key, item) in &target.exports {
+            let key = resolve.name_world_key(key);
+            match item {
+                WorldItem::Function(func) => export_names.push(func.name.clone()),
+                WorldItem::Interface { id, .. } => export_names.extend(
+                    resolve.interfaces[*id]
+                        .functions
+                        .keys()
+                        .map(|name| format!("{key}#{name}")),
+                ),
+                WorldItem::Type { .. } => {}
+            }
+        }
+        if spliced {
+            for name in export_names {
+                let Some(fid) = module.exports.get_func_by_name(name.clone()) else {
+                    continue;
+                };
+                let (params, results) = core_signature(&module, fid)?;
+                exports.push(CoreExport {
+                    name,
+                    params,
+                    results,
+                });
+            }
+        }
+
+        let package = target
+            .package
+            .map(|package| format!("{}/", resolve.packages[package].name))
+            .unwrap_or_default();
+        world = Some(WorldSummary {
+            name: format!("{package}{}", target.name),
+            imports: target
+                .imports
+                .keys()
+                .map(|key| resolve.name_world_key(key))
+                .collect(),
+            exports: target
+                .exports
+                .keys()
+                .map(|key| resolve.name_world_key(key))
+                .collect(),
+        });
+    }
+
+    Ok(Inspection {
+        world,
+        symbols,
+        wasi_imports,
+        spliced,
+        exports,
+    })
+}
+
+fn core_signature(module: &Module, fid: FunctionID) -> Result<(Vec<String>, Vec<String>)> {
+    let ty = module
+        .types
+        .get(module.functions.get_type_id(fid))
+        .context("missing function type")?;
+    let names = |types: Vec<DataType>| {
+        types
+            .iter()
+            .map(|ty| format!("{ty:?}").to_lowercase())
+            .collect::<Vec<_>>()
+    };
+    Ok((
+        names(ty.params().context("failed to retrieve params")?),
+        names(ty.results().context("failed to retrieve results")?),
+    ))
+}
+
+impl Inspection {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "world": self.world.as_ref().map(|world| json!({
+                "name": world.name,
+                "imports": world.imports,
+                "exports": world.exports,
+            })),
+            "symbols": self
+                .symbols
+                .iter()
+                .map(|(name, present)| (name.to_string(), json!(present)))
+                .collect::<serde_json::Map<_, _>>(),
+            "wasi-imports": self.wasi_imports,
+            "spliced": self.spliced,
+            "exports": self
+                .exports
+                .iter()
+                .map(|export| json!({
+                    "name": export.name,
+                    "params": export.params,
+                    "results": export.results,
+                }))
+                .collect::<Vec<_>>(),
+        })
+    }
+}
+
+impl fmt::Display for Inspection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.world {
+            Some(world) => {
+                writeln!(f, "world {}", world.name)?;
+                for import in &world.imports {
+                    writeln!(f, "  import {import}")?;
+                }
+                for export in &world.exports {
+                    writeln!(f, "  export {export}")?;
+                }
+            }
+            None => writeln!(f, "no component-type world")?,
+        }
+
+        writeln!(f)?;
+        if self.spliced {
+            writeln!(f, "spliced module")?;
+        } else {
+            writeln!(f, "engine symbols")?;
+            for (name, present) in &self.symbols {
+                let status = if *present { "present" } else { "missing" };
+                writeln!(f, "  {name}: {status}")?;
+            }
+        }
+
+        writeln!(f)?;
+        if self.wasi_imports.is_empty() {
+            writeln!(f, "no WASI imports")?;
+        } else {
+            writeln!(f, "WASI imports")?;
+            for (iface, versions) in &self.wasi_imports {
+                for (version, names) in versions {
+                    if version.is_empty() {
+                        writeln!(f, "  {iface}")?;
+                    } else {
+                        writeln!(f, "  {iface}@{version}")?;
+                    }
+                    for name in names {
+                        writeln!(f, "    {name}")?;
+                    }
+                }
+            }
+        }
+
+        if self.spliced {
+            writeln!(f)?;
+            writeln!(f, "exports")?;
+            for export in &self.exports {
+                writeln!(
+                    f,
+                    "  {}: ({}) -> ({})",
+                    export.name,
+                    export.params.join(", "),
+                    export.results.join(", ")
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,12 +1,27 @@
-use std::path::Path;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result, bail};
-use wit_parser::{PackageId, Resolve};
+use wit_parser::{PackageId, Resolve, UnresolvedPackageGroup};
 
 pub mod bindgen;
+#[cfg(feature = "componentize")]
+pub mod componentize;
+pub mod diagnostics;
+pub mod engine;
+pub mod environment;
+pub mod http_mock;
+pub mod inspect;
+pub mod preview1;
 pub mod splice;
+pub mod stdio_capture;
+pub mod stub_policy;
 pub mod stub_wasi;
+mod validate;
+pub mod verify;
+pub mod virtual_fs;
 pub mod wit;
+pub mod world_diff;
 
 use wit::exports::local::spidermonkey_embedding_splicer::splicer::{CoreFn, CoreTy};
 
@@ -81,3 +96,214 @@ fn parse_wit(path: impl AsRef<Path>) -> Result<(Resolve, PackageId)> {
     };
     Ok((resolve, id))
 }
+
+/// Resolve inline WIT `source`, or else the WIT file or directory at the first
+/// of `paths`, after the packages of the remaining `paths` it depends on
+///
+/// Each dependency path is a WIT file, a package directory or a directory of
+/// packages like `deps/`.
+pub fn resolve_wit(source: Option<&str>, paths: &[PathBuf]) -> Result<(Resolve, PackageId)> {
+    let (main, deps) = match (source, paths) {
+        (Some(_), deps) => (None, deps),
+        (None, [main, deps @ ..]) => (Some(main), deps),
+        (None, []) => bail!("neither wit source nor path have been specified"),
+    };
+
+    let mut groups = vec![];
+    for path in deps {
+        if path.is_dir() {
+            let mut entries = std::fs::read_dir(path)
+                .and_then(|entries| entries.collect::<std::io::Result<Vec<_>>>())
+                .with_context(|| format!("reading directory {}", path.display()))?;
+            entries.sort_by_key(|entry| entry.file_name());
+            // a package directory only nests its own `deps`, so a directory with
+            // package directories, or whose files declare different packages, is
+            // a directory of packages
+            let nests_packages = entries
+                .iter()
+                .any(|entry| entry.path().is_dir() && entry.file_name() != "deps");
+            let mut headers = HashSet::new();
+            for entry in &entries {
+                let path = entry.path();
+                if !path.is_dir() && path.extension().is_some_and(|ext| ext == "wit") {
+                    let source = std::fs::read_to_string(&path)
+                        .with_context(|| format!("reading {}", path.display()))?;
+                    headers.extend(package_header(&source).map(str::to_string));
+                }
+            }
+            if !nests_packages && headers.len() <= 1 {
+                groups.push(
+                    UnresolvedPackageGroup::parse_dir(path)
+                        .with_context(|| format!("failed to parse package: {}", path.display()))?,
+                );
+                continue;
+            }
+            for entry in entries {
+                let path = entry.path();
+                if path.is_dir() || path.extension().is_some_and(|ext| ext == "wit") {
+                    groups.push(
+                        UnresolvedPackageGroup::parse_path(&path).with_context(|| {
+                            format!("failed to parse package: {}", path.display())
+                        })?,
+                    );
+                }
+            }
+        } else {
+            groups.push(
+                UnresolvedPackageGroup::parse_path(path)
+                    .with_context(|| format!("failed to parse package: {}", path.display()))?,
+            );
+        }
+    }
+
+    // push the dependencies once the packages they depend on are known
+    let mut resolve = Resolve::default();
+    while !groups.is_empty() {
+        let ready = groups
+            .iter()
+            .position(|group| {
+                std::iter::once(&group.main)
+                    .chain(&group.nested)
+                    .flat_map(|package| package.foreign_deps.keys())
+                    .all(|dep| {
+                        resolve.package_names.contains_key(dep)
+                            || group.nested.iter().any(|package| package.name == *dep)
+                    })
+            })
+            .unwrap_or(0);
+        resolve.push_group(groups.remove(ready))?;
+    }
+
+    let id = match (source, main) {
+        (Some(source), _) => resolve.push_str(Path::new("component.wit"), source)?,
+        (None, Some(main)) if main.is_dir() => {
+            resolve
+                .push_dir(main)
+                .with_context(|| format!("resolving WIT in {}", main.display()))?
+                .0
+        }
+        (None, Some(main)) => resolve
+            .push_file(main)
+            .with_context(|| format!("resolving WIT in {}", main.display()))?,
+        (None, None) => unreachable!("checked above"),
+    };
+    Ok((resolve, id))
+}
+
+/// The name of the package a WIT source declares, if any
+fn package_header(source: &str) -> Option<&str> {
+    source
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with("//"))?
+        .strip_prefix("package ")
+        .map(|line| line.split([';', '{']).next().unwrap_or(line).trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const APP: &str = "package local:app;\n\
+        interface api { use local:types/types.{id}; get: func() -> id; }";
+    const TYPES: &str = "package local:types;\ninterface types { type id = u32; }";
+    const WORLD: &str = "package local:main;\nworld main { import local:app/api; }";
+
+    /// A fresh directory with the WIT `files`, as `(path, source)`
+    fn fixture(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("splicer-resolve-wit-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for (path, source) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, source).unwrap();
+        }
+        dir
+    }
+
+    fn world_name(resolve: &Resolve, id: PackageId) -> &str {
+        let (name, _) = resolve.packages[id].worlds.first().unwrap();
+        name
+    }
+
+    #[test]
+    fn dependencies_in_any_order() {
+        let dir = fixture("order", &[("app.wit", APP), ("types.wit", TYPES)]);
+        let deps = [dir.join("app.wit"), dir.join("types.wit")];
+        let (resolve, id) = resolve_wit(Some(WORLD), &deps).unwrap();
+        assert_eq!(world_name(&resolve, id), "main");
+
+        let (resolve, _) = resolve_wit(Some(WORLD), &[deps[1].clone(), deps[0].clone()]).unwrap();
+        assert_eq!(resolve.packages.len(), 3);
+    }
+
+    #[test]
+    fn directory_of_packages() {
+        let dir = fixture(
+            "deps",
+            &[
+                ("deps/app/app.wit", APP),
+                ("deps/types.wit", TYPES),
+                ("main.wit", WORLD),
+            ],
+        );
+        let (resolve, id) = resolve_wit(None, &[dir.join("main.wit"), dir.join("deps")]).unwrap();
+        assert_eq!(world_name(&resolve, id), "main");
+        assert_eq!(resolve.packages.len(), 3);
+    }
+
+    #[test]
+    fn directory_of_package_files() {
+        let dir = fixture("files", &[("deps/app.wit", APP), ("deps/types.wit", TYPES)]);
+        let (resolve, id) = resolve_wit(Some(WORLD), &[dir.join("deps")]).unwrap();
+        assert_eq!(world_name(&resolve, id), "main");
+        assert_eq!(resolve.packages.len(), 3);
+    }
+
+    #[test]
+    fn package_directory_dependency() {
+        let dir = fixture(
+            "package",
+            &[
+                ("app/app.wit", APP),
+                ("types/types.wit", TYPES),
+                ("main.wit", WORLD),
+            ],
+        );
+        let paths = [dir.join("main.wit"), dir.join("app"), dir.join("types")];
+        let (resolve, id) = resolve_wit(None, &paths).unwrap();
+        assert_eq!(world_name(&resolve, id), "main");
+    }
+
+    #[test]
+    fn package_directory_error() {
+        let dir = fixture(
+            "syntax",
+            &[
+                ("app/api.wit", APP),
+                ("app/extra.wit", "interface extra { get: func( }"),
+                ("types.wit", TYPES),
+            ],
+        );
+        let paths = [dir.join("types.wit"), dir.join("app")];
+        let err = resolve_wit(Some(WORLD), &paths).unwrap_err();
+        assert!(
+            err.to_string().starts_with("failed to parse package")
+                && err.to_string().ends_with("app"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn missing_dependency() {
+        let dir = fixture("missing", &[("app.wit", APP)]);
+        assert!(resolve_wit(Some(WORLD), &[dir.join("app.wit")]).is_err());
+    }
+
+    #[test]
+    fn no_source_or_path() {
+        let err = resolve_wit(None, &[]).unwrap_err();
+        assert!(err.to_string().contains("neither"), "{err}");
+    }
+}
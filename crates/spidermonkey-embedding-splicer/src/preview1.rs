@@ -0,0 +1,136 @@
+//! Ownership of the `wasi_snapshot_preview1` functions
+//!
+//! Every preview1 function is attributed to the feature or the world interface
+//! the preview2 adapter implements it with. Functions whose owner is not enabled
+//! are stubbed, so that a build without any feature does not need the adapter.
+
+use std::collections::HashSet;
+
+use crate::stub_policy::StubBehavior;
+use crate::stub_wasi::interface_enabled;
+use crate::wit::exports::local::spidermonkey_embedding_splicer::splicer::Feature;
+
+/// What an import depends on to be kept
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum ImportOwner {
+    Feature(Feature),
+    /// Either the `http` or the `fetch-event` feature
+    HttpTypes,
+    /// Any feature depending on `wasi:io`
+    Io,
+    /// A WASI interface imported by the target world or kept by its feature,
+    /// without its version
+    Interface(&'static str),
+    /// Always stubbed
+    Never,
+}
+
+impl ImportOwner {
+    pub(crate) fn enabled(
+        self,
+        features: &[Feature],
+        has_io: bool,
+        world_imports: &HashSet<String>,
+    ) -> bool {
+        match self {
+            ImportOwner::Feature(feature) => features.contains(&feature),
+            ImportOwner::HttpTypes => {
+                features.contains(&Feature::Http) || features.contains(&Feature::FetchEvent)
+            }
+            ImportOwner::Io => has_io,
+            ImportOwner::Interface(iface) => interface_enabled(features, world_imports, iface),
+            ImportOwner::Never => false,
+        }
+    }
+}
+
+const FS: ImportOwner = ImportOwner::Interface("wasi:filesystem/types");
+const ENV: ImportOwner = ImportOwner::Interface("wasi:cli/environment");
+const SOCKETS: ImportOwner = ImportOwner::Interface("wasi:sockets/tcp");
+
+/// All the `wasi_snapshot_preview1` functions, with the stub preset for when
+/// their owner is not enabled
+pub(crate) const PREVIEW1_FUNCS: [(&str, ImportOwner, StubBehavior); 46] = [
+    ("args_get", ENV, StubBehavior::Trap),
+    ("args_sizes_get", ENV, StubBehavior::Trap),
+    ("environ_get", ENV, StubBehavior::Trap),
+    ("environ_sizes_get", ENV, StubBehavior::Trap),
+    (
+        "clock_res_get",
+        ImportOwner::Feature(Feature::Clocks),
+        StubBehavior::Trap,
+    ),
+    (
+        "clock_time_get",
+        ImportOwner::Feature(Feature::Clocks),
+        StubBehavior::Trap,
+    ),
+    ("fd_advise", FS, StubBehavior::Trap),
+    ("fd_allocate", FS, StubBehavior::Trap),
+    ("fd_close", FS, StubBehavior::Trap),
+    ("fd_datasync", FS, StubBehavior::Trap),
+    (
+        "fd_fdstat_get",
+        ImportOwner::Feature(Feature::Stdio),
+        StubBehavior::Trap,
+    ),
+    ("fd_fdstat_set_flags", FS, StubBehavior::Trap),
+    ("fd_fdstat_set_rights", FS, StubBehavior::Trap),
+    ("fd_filestat_get", FS, StubBehavior::Trap),
+    ("fd_filestat_set_size", FS, StubBehavior::Trap),
+    ("fd_filestat_set_times", FS, StubBehavior::Trap),
+    ("fd_pread", FS, StubBehavior::Trap),
+    ("fd_prestat_get", FS, StubBehavior::Trap),
+    ("fd_prestat_dir_name", FS, StubBehavior::Trap),
+    ("fd_pwrite", FS, StubBehavior::Trap),
+    ("fd_read", FS, StubBehavior::Trap),
+    ("fd_readdir", FS, StubBehavior::Trap),
+    ("fd_renumber", FS, StubBehavior::Trap),
+    ("fd_seek", FS, StubBehavior::Trap),
+    ("fd_sync", FS, StubBehavior::Trap),
+    ("fd_tell", FS, StubBehavior::Trap),
+    (
+        "fd_write",
+        ImportOwner::Feature(Feature::Stdio),
+        StubBehavior::Trap,
+    ),
+    ("path_create_directory", FS, StubBehavior::Trap),
+    ("path_filestat_get", FS, StubBehavior::Trap),
+    ("path_filestat_set_times", FS, StubBehavior::Trap),
+    ("path_link", FS, StubBehavior::Trap),
+    ("path_open", FS, StubBehavior::Trap),
+    ("path_readlink", FS, StubBehavior::Trap),
+    ("path_remove_directory", FS, StubBehavior::Trap),
+    ("path_rename", FS, StubBehavior::Trap),
+    ("path_symlink", FS, StubBehavior::Trap),
+    ("path_unlink_file", FS, StubBehavior::Trap),
+    ("poll_oneoff", ImportOwner::Io, StubBehavior::Trap),
+    (
+        "proc_exit",
+        ImportOwner::Interface("wasi:cli/exit"),
+        StubBehavior::Trap,
+    ),
+    ("proc_raise", ImportOwner::Never, StubBehavior::Trap),
+    // random comes from prevew2 only in StarlingMonkey
+    ("random_get", ImportOwner::Never, StubBehavior::Trap),
+    ("sched_yield", ImportOwner::Never, StubBehavior::Zero),
+    ("sock_accept", SOCKETS, StubBehavior::Trap),
+    ("sock_recv", SOCKETS, StubBehavior::Trap),
+    ("sock_send", SOCKETS, StubBehavior::Trap),
+    ("sock_shutdown", SOCKETS, StubBehavior::Trap),
+];
+
+/// Sizes of the cells a `wasi_snapshot_preview1` function writes its results
+/// to, passed as its last parameters
+pub(crate) fn preview1_results(name: &str) -> &'static [u32] {
+    match name {
+        "args_sizes_get" | "environ_sizes_get" => &[4, 4],
+        "clock_res_get" | "clock_time_get" | "fd_prestat_get" | "fd_seek" | "fd_tell" => &[8],
+        "fd_fdstat_get" => &[24],
+        "fd_filestat_get" | "path_filestat_get" => &[64],
+        "fd_pread" | "fd_pwrite" | "fd_read" | "fd_readdir" | "fd_write" | "path_open"
+        | "path_readlink" | "poll_oneoff" | "sock_accept" | "sock_send" => &[4],
+        "sock_recv" => &[4, 2],
+        _ => &[],
+    }
+}
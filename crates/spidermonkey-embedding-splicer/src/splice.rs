@@ -1,6 +1,9 @@
+use std::collections::HashMap;
 use std::path::PathBuf;
 
 use anyhow::{Context as _, Result};
+use heck::{ToLowerCamelCase, ToUpperCamelCase};
+use serde_json::json;
 use wasm_encoder::{Encode, Section};
 use wirm::ir::function::{FunctionBuilder, FunctionModifier};
 use wirm::ir::id::{ExportsID, FunctionID, GlobalID, LocalID};
@@ -12,14 +15,18 @@ use wirm::opcode::{Inject, InjectAt};
 use wirm::wasmparser::{ExternalKind, MemArg, Operator};
 use wirm::{DataType, Opcode};
 use wit_component::StringEncoding;
-use wit_component::metadata::{Bindgen, decode};
-use wit_parser::Resolve;
+use wit_parser::{PackageId, Resolve, WorldId, WorldItem};
 
-use crate::bindgen::BindingItem;
+use crate::bindgen::{BindingItem, Resource};
+use crate::diagnostics::{Diagnostics, diagnostics_component_type};
+use crate::engine::Engine;
+use crate::stub_wasi::{ensure_data_count, is_wasi_interface};
+use crate::validate::validate;
 use crate::wit::exports::local::spidermonkey_embedding_splicer::splicer::{
-    CoreFn, CoreTy, Feature, SpliceResult,
+    Binding, BindingsOptions, BindingsResult, CoreFn, CoreTy, Feature, ResourceFunction,
+    SpliceOptions, SpliceResult,
 };
-use crate::{bindgen, map_core_fn, parse_wit, splice};
+use crate::{bindgen, map_core_fn, parse_wit};
 
 /// Splice bindings into a given JS engine WebAssembly binary
 pub fn splice_bindings(
@@ -29,22 +36,134 @@ pub fn splice_bindings(
     wit_path: Option<String>,
     world_name: Option<String>,
     debug: bool,
+    options: SpliceOptions,
 ) -> Result<SpliceResult, String> {
-    let (mut resolve, id) = match (wit_source, wit_path) {
+    let engine = Engine::parse(engine).map_err(|e| format!("{e:?}"))?;
+    splice_engine_bindings(
+        &engine, features, wit_source, wit_path, world_name, debug, options,
+    )
+}
+
+/// Splice bindings into an already parsed JS engine, which can be spliced
+/// again for other worlds
+pub fn splice_engine_bindings(
+    engine: &Engine,
+    features: Vec<Feature>,
+    wit_source: Option<String>,
+    wit_path: Option<String>,
+    world_name: Option<String>,
+    debug: bool,
+    options: SpliceOptions,
+) -> Result<SpliceResult, String> {
+    let (resolve, id) = resolve_wit_source(wit_source, wit_path)?;
+    splice_engine_bindings_with_manifest(engine, features, resolve, id, world_name, debug, options)
+        .map(|(result, _)| result)
+}
+
+/// Generate the JS bindings for a given WIT world without splicing them into
+/// an engine
+pub fn generate_bindings(
+    wit_source: Option<String>,
+    wit_path: Option<String>,
+    world_name: Option<String>,
+    features: Vec<Feature>,
+    options: BindingsOptions,
+) -> Result<BindingsResult, String> {
+    let (resolve, id) = resolve_wit_source(wit_source, wit_path)?;
+    generate_world_bindings(&resolve, id, world_name, features, options)
+}
+
+/// [`generate_bindings`] for a world of an already resolved WIT package
+///
+/// The exports and imports are the core functions a spliced engine exports
+/// and imports for the bindings, the manifest being the one returned by
+/// [`splice_bindings_with_manifest`].
+pub fn generate_world_bindings(
+    resolve: &Resolve,
+    id: PackageId,
+    world_name: Option<String>,
+    features: Vec<Feature>,
+    options: BindingsOptions,
+) -> Result<BindingsResult, String> {
+    let world = resolve
+        .select_world(&[id], world_name.as_deref())
+        .map_err(|e| e.to_string())?;
+
+    let WorldBindings {
+        componentized,
+        exports,
+        imports,
+    } = world_bindings(resolve, world, &features, options.report_error.as_deref())?;
+
+    let manifest = options
+        .manifest
+        .then(|| serde_json::to_string_pretty(&bindings_manifest(&componentized)))
+        .transpose()
+        .map_err(|e| e.to_string())?;
+    let (export_bindings, import_bindings, _) = binding_records(resolve, world, &componentized);
+
+    Ok(BindingsResult {
+        js_bindings: componentized.js_bindings,
+        exports,
+        imports: imports
+            .into_iter()
+            .map(|(module, name, func, _)| (module, name, func))
+            .collect(),
+        resource_imports: componentized.resource_imports,
+        manifest,
+        export_bindings,
+        import_bindings,
+    })
+}
+
+fn resolve_wit_source(
+    wit_source: Option<String>,
+    wit_path: Option<String>,
+) -> Result<(Resolve, PackageId), String> {
+    match (wit_source, wit_path) {
         (Some(wit_source), _) => {
             let mut resolve = Resolve::default();
             let path = PathBuf::from("component.wit");
             let id = resolve
                 .push_str(&path, &wit_source)
                 .map_err(|e| e.to_string())?;
-            (resolve, id)
-        }
-        (_, Some(wit_path)) => parse_wit(&wit_path).map_err(|e| format!("{e:?}"))?,
-        (None, None) => {
-            return Err("neither wit source nor path have been specified".into());
+            Ok((resolve, id))
         }
-    };
+        (_, Some(wit_path)) => parse_wit(&wit_path).map_err(|e| format!("{e:?}")),
+        (None, None) => Err("neither wit source nor path have been specified".into()),
+    }
+}
 
+/// Splice bindings for a world of an already resolved WIT package into a given
+/// JS engine WebAssembly binary, also returning a JSON manifest of the spliced
+/// exports and imports
+///
+/// The manifest lists the entries of [`SpliceResult::exports`] and
+/// [`SpliceResult::imports`] in the same order, along with the WIT function,
+/// interface and resource kind they originate from and their core signature.
+pub fn splice_bindings_with_manifest(
+    engine: Vec<u8>,
+    features: Vec<Feature>,
+    resolve: Resolve,
+    id: PackageId,
+    world_name: Option<String>,
+    debug: bool,
+    options: SpliceOptions,
+) -> Result<(SpliceResult, serde_json::Value), String> {
+    let engine = Engine::parse(engine).map_err(|e| format!("{e:?}"))?;
+    splice_engine_bindings_with_manifest(&engine, features, resolve, id, world_name, debug, options)
+}
+
+/// [`splice_bindings_with_manifest`] for an already parsed JS engine
+pub fn splice_engine_bindings_with_manifest(
+    engine: &Engine,
+    features: Vec<Feature>,
+    mut resolve: Resolve,
+    id: PackageId,
+    world_name: Option<String>,
+    debug: bool,
+    options: SpliceOptions,
+) -> Result<(SpliceResult, serde_json::Value), String> {
     let world = resolve
         .select_world(&[id], world_name.as_deref())
         .map_err(|e| e.to_string())?;
@@ -53,50 +172,26 @@ pub fn splice_bindings(
         wit_component::dummy_module(&resolve, world, wit_parser::ManglingAndAbi::Standard32);
 
     // Merge the engine world with the target world, retaining the engine producers
-    let Ok((
-        _,
-        Bindgen {
-            resolve: mut engine_resolve,
-            world: engine_world_id,
-            metadata: _,
-            producers,
-        },
-    )) = decode(&engine)
-    else {
-        unreachable!("failed to decode engine");
-    };
+    let (mut engine_resolve, engine_world_id) = engine.world();
+    let producers = engine.bindgen().producers.as_ref();
 
     // we disable the engine run and incoming handler as we recreate these exports
     // when needed, so remove these from the world before initiating the merge
-    let maybe_run = engine_resolve.worlds[engine_world_id]
-        .exports
-        .iter()
-        .find(|(key, _)| {
-            engine_resolve
-                .name_world_key(key)
-                .starts_with("wasi:cli/run@0.2")
-        })
-        .map(|(key, _)| key.clone());
-    if let Some(run) = maybe_run {
-        engine_resolve.worlds[engine_world_id]
-            .exports
-            .shift_remove(&run)
-            .unwrap();
-    }
-    let maybe_serve = engine_resolve.worlds[engine_world_id]
+    let removed = engine_resolve.worlds[engine_world_id]
         .exports
-        .iter()
-        .find(|(key, _)| {
-            engine_resolve
-                .name_world_key(key)
-                .starts_with("wasi:http/incoming-handler@0.2.")
+        .keys()
+        .filter(|key| {
+            let name = engine_resolve.name_world_key(key);
+            is_wasi_interface(&name, "wasi:cli/run")
+                || is_wasi_interface(&name, "wasi:http/incoming-handler")
+                || is_wasi_interface(&name, "wasi:http/handler")
         })
-        .map(|(key, _)| key.clone());
-
-    if let Some(serve) = maybe_serve {
+        .cloned()
+        .collect::<Vec<_>>();
+    for key in removed {
         engine_resolve.worlds[engine_world_id]
             .exports
-            .shift_remove(&serve)
+            .shift_remove(&key)
             .unwrap();
     }
 
@@ -108,17 +203,19 @@ pub fn splice_bindings(
 
     let engine_world_id = map.map_world(engine_world_id, engine_world_span).unwrap();
 
-    let componentized =
-        bindgen::componentize_bindgen(&resolve, world, &features).map_err(|err| err.to_string())?;
+    let WorldBindings {
+        componentized,
+        exports,
+        mut imports,
+    } = world_bindings(&resolve, world, &features, options.report_error.as_deref())?;
 
     let mut clone_maps = wit_parser::CloneMaps::default();
     resolve
         .merge_worlds(engine_world_id, world, &mut clone_maps)
         .expect("unable to merge with engine world");
 
-    let encoded =
-        wit_component::metadata::encode(&resolve, world, StringEncoding::UTF8, producers.as_ref())
-            .map_err(|e| e.to_string())?;
+    let encoded = wit_component::metadata::encode(&resolve, world, StringEncoding::UTF8, producers)
+        .map_err(|e| e.to_string())?;
 
     let section = wasm_encoder::CustomSection {
         name: "component-type".into(),
@@ -128,53 +225,122 @@ pub fn splice_bindings(
     wasm_bytes.push(section.id());
     section.encode(&mut wasm_bytes);
 
-    let mut generated_bindings = componentized.js_bindings;
+    let manifest = bindings_manifest(&componentized);
+    let (export_bindings, mut import_bindings, resource_bindings) =
+        binding_records(&resolve, world, &componentized);
+    import_bindings.extend(resource_bindings);
+
+    for (key, name, return_count) in &componentized.resource_imports {
+        imports.push((
+            key.clone(),
+            name.clone(),
+            CoreFn {
+                params: vec![CoreTy::I32],
+                ret: if *return_count == 0 {
+                    None
+                } else {
+                    Some(CoreTy::I32)
+                },
+                retptr: false,
+                retsize: 0,
+                paramptr: false,
+            },
+            Some(i32::try_from(*return_count).unwrap()),
+        ));
+    }
+
+    let mut wasm = splice_module(
+        engine.module(),
+        imports,
+        exports,
+        features,
+        debug,
+        options.diagnostics,
+    )
+    .map_err(|e| format!("{e:?}"))?;
+
+    // add the world section to the spliced wasm
+    wasm.push(section.id());
+    section.encode(&mut wasm);
+
+    let result = SpliceResult {
+        wasm,
+        exports: componentized
+            .exports
+            .iter()
+            .map(
+                |(
+                    _,
+                    BindingItem {
+                        binding_name, func, ..
+                    },
+                )| { (binding_name.to_string(), map_core_fn(func)) },
+            )
+            .collect(),
+        imports: componentized
+            .imports
+            .iter()
+            .map(
+                |(
+                    specifier,
+                    BindingItem {
+                        name,
+                        iface,
+                        func,
+                        resource,
+                        ..
+                    },
+                )| {
+                    (
+                        if *iface {
+                            specifier.to_string()
+                        } else {
+                            "$root".into()
+                        },
+                        if *iface {
+                            resource.canon_string(name)
+                        } else {
+                            specifier.to_string()
+                        },
+                        func.params.len() as u32,
+                    )
+                },
+            )
+            .chain(componentized.resource_imports)
+            .collect(),
+        js_bindings: componentized.js_bindings,
+        export_bindings,
+        import_bindings,
+    };
+    Ok((result, manifest))
+}
+
+/// JS bindings generated for a world, along with the engine exports and
+/// imports to splice for them
+struct WorldBindings {
+    componentized: bindgen::Componentization,
+    /// Core functions to export, named by export and function
+    exports: Vec<(String, CoreFn)>,
+    /// Core functions to import by module and name, with their return size,
+    /// excluding the resource imports
+    imports: Vec<(String, String, CoreFn, Option<i32>)>,
+}
+
+/// Generate the JS bindings for `world` and the core functions they bind to
+fn world_bindings(
+    resolve: &Resolve,
+    world: WorldId,
+    features: &Vec<Feature>,
+    report_error: Option<&str>,
+) -> Result<WorldBindings, String> {
+    let componentized = bindgen::componentize_bindgen(resolve, world, features, report_error)
+        .map_err(|err| err.to_string())?;
 
     // let mut imports_mapped = Vec::new();
     // for impt in componentized.imports {
 
     // }
 
-    // these should be temporary bindings fixups
-    if generated_bindings.contains("utf8Encode") {
-        generated_bindings = generated_bindings.replace(
-            "function utf8Encode(s, realloc, memory) {
-  if (typeof s !== 'string') throw new TypeError('expected a string');
-  if (s.length === 0) {
-    utf8EncodedLen = 0;
-    return 1;
-  }
-  let allocLen = 0;
-  let ptr = 0;
-  let writtenTotal = 0;
-  while (s.length > 0) {
-    ptr = realloc(ptr, allocLen, 1, allocLen + s.length);
-    allocLen += s.length;
-    const { read, written } = utf8Encoder.encodeInto(
-    s,
-    new Uint8Array(memory.buffer, ptr + writtenTotal, allocLen - writtenTotal),
-    );
-    writtenTotal += written;
-    s = s.slice(read);
-  }
-  if (allocLen > writtenTotal)
-  ptr = realloc(ptr, allocLen, 1, writtenTotal);
-  utf8EncodedLen = writtenTotal;
-  return ptr;
-}",
-            "function utf8Encode(s, realloc, memory) {
-  const buf = utf8Encoder.encode(s);
-  const ptr = realloc(0, 0, 1, buf.byteLength);
-  const out = new Uint8Array(memory.buffer, ptr, buf.byteLength);
-  for (let i = 0; i < buf.byteLength; i++) {
-    out[i] = buf[i];
-  }
-  utf8EncodedLen = buf.byteLength;
-  return ptr;
-}",
-        );
-    }
-
     let mut exports = Vec::new();
     for (
         export_name,
@@ -233,81 +399,226 @@ pub fn splice_bindings(
         }
     }
 
-    for (key, name, return_count) in &componentized.resource_imports {
-        imports.push((
-            key.clone(),
-            name.clone(),
-            CoreFn {
-                params: vec![CoreTy::I32],
-                ret: if *return_count == 0 {
-                    None
+    Ok(WorldBindings {
+        componentized,
+        exports,
+        imports,
+    })
+}
+
+/// Records of the WIT functions of the exports, imports and resource intrinsic
+/// imports of `componentized`, in their order
+fn binding_records(
+    resolve: &Resolve,
+    world: WorldId,
+    componentized: &bindgen::Componentization,
+) -> (Vec<Binding>, Vec<Binding>, Vec<Binding>) {
+    // only interfaces with an ID are keyed by it, inline ones being named
+    let world = &resolve.worlds[world];
+    let interface_ids = world
+        .imports
+        .iter()
+        .chain(&world.exports)
+        .filter_map(|(key, item)| match item {
+            WorldItem::Interface { id, .. } => {
+                Some((resolve.name_world_key(key), resolve.id_of(*id)?))
+            }
+            _ => None,
+        })
+        .collect::<HashMap<_, _>>();
+
+    let record = |key: &String, item: &BindingItem, export: bool| {
+        let member = match &item.resource {
+            Resource::None if item.iface => Some(item.name.to_lower_camel_case()),
+            Resource::None if export => None,
+            Resource::None => Some("default".to_string()),
+            Resource::Constructor(name) | Resource::Static(name) | Resource::Method(name) => {
+                Some(if item.iface {
+                    name.to_upper_camel_case()
                 } else {
-                    Some(CoreTy::I32)
-                },
-                retptr: false,
-                retsize: 0,
-                paramptr: false,
+                    "default".to_string()
+                })
+            }
+        };
+        Binding {
+            world_key: key.clone(),
+            interface: interface_ids.get(key).cloned(),
+            function: item.resource.canon_string(&item.name),
+            resource: match &item.resource {
+                Resource::None => None,
+                Resource::Constructor(name) => Some(ResourceFunction::Constructor(name.clone())),
+                Resource::Static(name) => Some(ResourceFunction::Static(name.clone())),
+                Resource::Method(name) => Some(ResourceFunction::Method(name.clone())),
             },
-            Some(i32::try_from(*return_count).unwrap()),
-        ));
-    }
-
-    let mut wasm =
-        splice::splice(engine, imports, exports, features, debug).map_err(|e| format!("{e:?}"))?;
-
-    // add the world section to the spliced wasm
-    wasm.push(section.id());
-    section.encode(&mut wasm);
+            binding_name: if export {
+                item.binding_name.clone()
+            } else {
+                format!("import_{}", item.import_binding_name(key))
+            },
+            specifier: if export && !item.iface {
+                key.to_lower_camel_case()
+            } else {
+                key.clone()
+            },
+            member,
+            core_fn: map_core_fn(&item.func),
+        }
+    };
 
-    Ok(SpliceResult {
-        wasm,
-        exports: componentized
+    (
+        componentized
             .exports
             .iter()
-            .map(
-                |(
-                    _,
-                    BindingItem {
-                        binding_name, func, ..
-                    },
-                )| { (binding_name.to_string(), map_core_fn(func)) },
-            )
+            .map(|(key, item)| record(key, item, true))
             .collect(),
-        imports: componentized
+        componentized
             .imports
             .iter()
-            .map(
-                |(
-                    specifier,
-                    BindingItem {
-                        name,
-                        iface,
-                        func,
-                        resource,
-                        ..
+            .map(|(key, item)| record(key, item, false))
+            .collect(),
+        componentized
+            .resource_imports
+            .iter()
+            .zip(&componentized.resource_bindings)
+            .map(|((specifier, name, return_count), binding)| {
+                let (kind, resource) = name
+                    .strip_prefix("[resource-")
+                    .and_then(|name| name.split_once(']'))
+                    .expect("resource intrinsic name");
+                // resources of the world itself are imported from `$root`
+                let world_key = match specifier.trim_start_matches("[export]") {
+                    "$root" => resource.to_string(),
+                    key => key.to_string(),
+                };
+                let resource = resource.to_string();
+                Binding {
+                    interface: interface_ids.get(&world_key).cloned(),
+                    world_key,
+                    function: name.clone(),
+                    resource: Some(match kind {
+                        "new" => ResourceFunction::New(resource),
+                        "rep" => ResourceFunction::Rep(resource),
+                        _ => ResourceFunction::Drop(resource),
+                    }),
+                    binding_name: format!("resource_{binding}"),
+                    specifier: specifier.clone(),
+                    member: None,
+                    core_fn: CoreFn {
+                        params: vec![CoreTy::I32],
+                        ret: (*return_count > 0).then_some(CoreTy::I32),
+                        retptr: false,
+                        retsize: 0,
+                        paramptr: false,
                     },
-                )| {
-                    (
-                        if *iface {
-                            specifier.to_string()
-                        } else {
-                            "$root".into()
-                        },
-                        if *iface {
-                            resource.canon_string(name)
-                        } else {
-                            specifier.to_string()
-                        },
-                        func.params.len() as u32,
-                    )
-                },
-            )
-            .chain(componentized.resource_imports)
+                }
+            })
             .collect(),
-        js_bindings: generated_bindings,
+    )
+}
+
+fn core_ty_json(ty: &bindgen::CoreTy) -> serde_json::Value {
+    json!(match ty {
+        bindgen::CoreTy::I32 => "i32",
+        bindgen::CoreTy::I64 => "i64",
+        bindgen::CoreTy::F32 => "f32",
+        bindgen::CoreTy::F64 => "f64",
     })
 }
 
+fn resource_json(resource: &Resource) -> serde_json::Value {
+    match resource {
+        Resource::None => serde_json::Value::Null,
+        Resource::Constructor(name) => json!({ "kind": "constructor", "name": name }),
+        Resource::Static(name) => json!({ "kind": "static", "name": name }),
+        Resource::Method(name) => json!({ "kind": "method", "name": name }),
+    }
+}
+
+/// Manifest entry of a binding, `name` being its name in the splice result and
+/// `interface` the WIT interface it belongs to, if any
+fn binding_json(name: String, interface: Option<&str>, item: &BindingItem) -> serde_json::Value {
+    let bindgen::CoreFn {
+        params,
+        ret,
+        retptr,
+        retsize,
+        paramptr,
+    } = &item.func;
+    json!({
+        "name": name,
+        "function": item.resource.canon_string(&item.name),
+        "interface": interface,
+        "resource": resource_json(&item.resource),
+        "params": params.iter().map(core_ty_json).collect::<Vec<_>>(),
+        "ret": ret.as_ref().map(core_ty_json),
+        "retptr": retptr,
+        "retsize": retsize,
+        "paramptr": paramptr,
+    })
+}
+
+/// JSON manifest of the exports and imports of a splice result, in the order of
+/// [`SpliceResult::exports`] and [`SpliceResult::imports`]
+fn bindings_manifest(componentized: &bindgen::Componentization) -> serde_json::Value {
+    let exports = componentized
+        .exports
+        .iter()
+        .map(|(export_name, item)| {
+            let interface = item.iface.then_some(export_name.as_str());
+            let mut entry = binding_json(item.binding_name.clone(), interface, item);
+            entry["export"] = json!(if item.iface {
+                format!("{export_name}#{}", item.resource.canon_string(&item.name))
+            } else {
+                export_name.clone()
+            });
+            entry
+        })
+        .collect::<Vec<_>>();
+
+    let mut imports = componentized
+        .imports
+        .iter()
+        .map(|(specifier, item)| {
+            let (specifier, name, interface) = if item.iface {
+                let name = item.resource.canon_string(&item.name);
+                (specifier.clone(), name, Some(specifier.as_str()))
+            } else {
+                ("$root".to_string(), specifier.clone(), None)
+            };
+            let mut entry = binding_json(name, interface, item);
+            entry["specifier"] = json!(specifier);
+            entry["arity"] = json!(item.func.params.len());
+            entry
+        })
+        .collect::<Vec<_>>();
+    // resource intrinsics take a handle, returning a handle or representation
+    // unless they drop it, and report their result count as arity like the
+    // splice result does
+    for (specifier, name, return_count) in &componentized.resource_imports {
+        let resource = name
+            .strip_prefix('[')
+            .and_then(|name| name.split_once(']'))
+            .map(|(kind, name)| {
+                json!({ "kind": kind.trim_start_matches("resource-"), "name": name })
+            });
+        imports.push(json!({
+            "name": name,
+            "function": name,
+            "interface": specifier.trim_start_matches("[export]"),
+            "resource": resource,
+            "params": ["i32"],
+            "ret": (*return_count != 0).then_some("i32"),
+            "retptr": false,
+            "retsize": 0,
+            "paramptr": false,
+            "specifier": specifier,
+            "arity": return_count,
+        }));
+    }
+
+    json!({ "exports": exports, "imports": imports })
+}
+
 //
 // Parses the Spidermonkey binary into section data for reserialization
 // into an output binary, and in the process:
@@ -329,6 +640,8 @@ pub fn splice_bindings(
 //    for all provided exported functions ("[name]" and "cabi_post_[name]").
 //    These are created simply by calling the "call" and "post_call" generic
 //    core wasm functions which take a list of core type variants.
+//    With the "diagnostics" option, they also count their calls, which are
+//    exported through "componentize-js:diagnostics/exports#stats".
 //
 //
 pub fn splice(
@@ -337,12 +650,23 @@ pub fn splice(
     exports: Vec<(String, CoreFn)>,
     features: Vec<Feature>,
     debug: bool,
+    diagnostics: bool,
 ) -> Result<Vec<u8>> {
-    let mut module = Module::parse(&engine, false, false).unwrap();
+    let module = Module::parse(&engine, false, false).unwrap();
+    splice_module(module, imports, exports, features, debug, diagnostics)
+}
 
+fn splice_module(
+    mut module: Module,
+    imports: Vec<(String, String, CoreFn, Option<i32>)>,
+    exports: Vec<(String, CoreFn)>,
+    features: Vec<Feature>,
+    debug: bool,
+    diagnostics: bool,
+) -> Result<Vec<u8>> {
     // since StarlingMonkey implements CLI Run and incoming handler,
     // we override them only if the guest content exports those functions
-    remove_if_exported_by_js(&mut module, &exports, "wasi:cli/run@0.2.", "#run");
+    remove_if_exported_by_js(&mut module, &exports, "wasi:cli/run", "run");
 
     // if 'fetch-event' feature is disabled (default being default-enabled),
     // remove the built-in incoming-handler which is built around it's use.
@@ -350,9 +674,10 @@ pub fn splice(
         remove_if_exported_by_js(
             &mut module,
             &exports,
-            "wasi:http/incoming-handler@0.2.",
-            "#handle",
+            "wasi:http/incoming-handler",
+            "handle",
         );
+        remove_if_exported_by_js(&mut module, &exports, "wasi:http/handler", "handle");
     }
 
     // we reencode the WASI world component data, so strip it out from the
@@ -369,41 +694,80 @@ pub fn splice(
     // (erasing sample functions in the process)
     synthesize_import_functions(&mut module, &imports, debug)?;
 
+    // count the calls of the exports with the 'diagnostics' option
+    let diagnostics = diagnostics.then(|| {
+        Diagnostics::new(
+            &mut module,
+            exports.len(),
+            features.contains(&Feature::Clocks),
+        )
+    });
+
     // create the exported functions as wrappers around the "cabi_call" function
-    synthesize_export_functions(&mut module, &exports)?;
+    synthesize_export_functions(&mut module, &exports, diagnostics.as_ref())?;
 
-    let encoded = module
+    if let Some(diagnostics) = &diagnostics {
+        let names = exports
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        diagnostics.synthesize_stats(&mut module, &names);
+    }
+
+    let mut encoded = module
         .encode()
         .context("failed to encode module during splice")?;
+    if diagnostics.is_some() {
+        encoded = ensure_data_count(encoded)?;
+        encoded.extend(diagnostics_component_type()?);
+    }
+    validate(&encoded, "splice")?;
 
     Ok(encoded)
 }
 
+/// Prefixes of the core export names of async lifted functions and their
+/// callbacks
+const ASYNC_EXPORT_PREFIXES: [&str; 3] = [
+    "[callback][async-lift]",
+    "[async-lift-stackful]",
+    "[async-lift]",
+];
+
+/// Whether the core export `name` lifts the function `func` of the WASI
+/// interface `iface`, synchronously or not
+fn is_wasi_export(name: &str, iface: &str, func: &str) -> bool {
+    let name = ASYNC_EXPORT_PREFIXES
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(name);
+    name.split_once('#')
+        .is_some_and(|(name, name_func)| name_func == func && is_wasi_interface(name, iface))
+}
+
 fn remove_if_exported_by_js(
     module: &mut Module,
     content_exports: &[(String, CoreFn)],
-    name_start: &str,
-    name_end: &str,
+    iface: &str,
+    func: &str,
 ) {
     let content_exports_run = content_exports
         .iter()
-        .any(|(name, _)| name.starts_with(name_start) && name.ends_with(name_end));
+        .any(|(name, _)| is_wasi_export(name, iface, func));
     if content_exports_run {
-        let exported_run_fn = module
+        // remove the engine export along with its async callback, if any
+        let exported_fns = module
             .exports
             .iter()
-            .find(|export| export.name.starts_with(name_start) && export.name.ends_with(name_end))
-            .unwrap();
-        let export_id = module
-            .exports
-            .get_export_id_by_name(String::from(&exported_run_fn.name))
-            .unwrap();
-        let function_id = module
-            .exports
-            .get_func_by_name(String::from(&exported_run_fn.name))
-            .unwrap();
-        module.exports.delete(export_id);
-        module.delete_func(function_id);
+            .filter(|export| is_wasi_export(&export.name, iface, func))
+            .map(|export| export.name.clone())
+            .collect::<Vec<_>>();
+        for name in exported_fns {
+            let export_id = module.exports.get_export_id_by_name(name.clone()).unwrap();
+            let function_id = module.exports.get_func_by_name(name).unwrap();
+            module.exports.delete(export_id);
+            module.delete_func(function_id);
+        }
     }
 }
 
@@ -566,6 +930,7 @@ fn synthesize_import_functions(
                 &[DataType::I32, DataType::I32, DataType::I32],
                 &[DataType::I32],
             );
+            func.set_name(format!("{impt_specifier}#{impt_name}"));
 
             let retptr_local = func.add_local(DataType::I32);
             let tmp_local = func.add_local(DataType::I64);
@@ -859,7 +1224,11 @@ fn synthesize_import_functions(
     Ok(())
 }
 
-fn synthesize_export_functions(module: &mut Module, exports: &[(String, CoreFn)]) -> Result<()> {
+fn synthesize_export_functions(
+    module: &mut Module,
+    exports: &[(String, CoreFn)],
+    diagnostics: Option<&Diagnostics>,
+) -> Result<()> {
     let cabi_realloc = get_export_fid(
         module,
         &module
@@ -917,6 +1286,9 @@ fn synthesize_export_functions(module: &mut Module, exports: &[(String, CoreFn)]
             let arg_ptr = func.add_local(DataType::I32);
             let ret_ptr = func.add_local(DataType::I32);
 
+            let start =
+                diagnostics.and_then(|diagnostics| diagnostics.enter(&mut func, export_num));
+
             // Stack "call" arg1 - export number to call
             func.i32_const(export_num as i32);
 
@@ -1000,6 +1372,12 @@ fn synthesize_export_functions(module: &mut Module, exports: &[(String, CoreFn)]
             // Call "call" (returns retptr)
             func.call(call);
 
+            if let (Some(diagnostics), Some(start)) = (diagnostics, start) {
+                func.local_set(ret_ptr);
+                diagnostics.exit(&mut func, export_num, start);
+                func.local_get(ret_ptr);
+            }
+
             if expt_sig.ret.is_none() {
                 func.drop();
             } else if !expt_sig.retptr {
@@ -1081,3 +1459,81 @@ fn synthesize_export_functions(module: &mut Module, exports: &[(String, CoreFn)]
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binding_records_follow_the_spliced_imports() {
+        let wit = r#"
+            package local:t;
+            interface things {
+                resource thing {
+                    constructor();
+                    name: func() -> string;
+                }
+                log: func(msg: string);
+            }
+            world w {
+                import things;
+                import now: func() -> u64;
+                export things;
+            }
+        "#;
+        let mut resolve = Resolve::default();
+        let pkg = resolve.push_str("world.wit", wit).unwrap();
+        let world = resolve.select_world(&[pkg], None).unwrap();
+        let componentized = bindgen::componentize_bindgen(&resolve, world, &vec![], None).unwrap();
+        let (_, imports, intrinsics) = binding_records(&resolve, world, &componentized);
+
+        assert_eq!(imports.len(), componentized.imports.len());
+        for binding in &imports {
+            assert!(binding.binding_name.starts_with("import_"));
+            let bound = format!("${}", binding.binding_name);
+            assert!(
+                componentized.js_bindings.contains(&bound),
+                "{bound} is not bound"
+            );
+        }
+        let now = imports.iter().find(|b| b.function == "now").unwrap();
+        assert!(
+            componentized
+                .js_bindings
+                .contains(&format!("function {}(", now.binding_name))
+        );
+
+        let functions = intrinsics
+            .iter()
+            .map(|binding| (binding.specifier.as_str(), binding.function.as_str()))
+            .collect::<Vec<_>>();
+        let spliced = componentized
+            .resource_imports
+            .iter()
+            .map(|(specifier, name, _)| (specifier.as_str(), name.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(functions, spliced);
+        let kinds = intrinsics
+            .iter()
+            .map(|binding| match &binding.resource {
+                Some(ResourceFunction::New(name)) => format!("new {name}"),
+                Some(ResourceFunction::Rep(name)) => format!("rep {name}"),
+                Some(ResourceFunction::Drop(name)) => format!("drop {name}"),
+                resource => panic!("unexpected intrinsic {resource:?}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            ["new thing", "rep thing", "drop thing", "drop thing"]
+        );
+        for binding in &intrinsics {
+            assert_eq!(binding.world_key, "local:t/things");
+            assert_eq!(binding.interface.as_deref(), Some("local:t/things"));
+            let bound = format!("${}", binding.binding_name);
+            assert!(
+                componentized.js_bindings.contains(&bound),
+                "{bound} is not bound"
+            );
+        }
+    }
+}
@@ -0,0 +1,809 @@
+//! Capture of console output when the `stdio` feature is disabled
+//!
+//! `wasi:cli/stdout` and `wasi:cli/stderr` return output streams tagged with
+//! [`STDIO_TAG`], which the wrapped `wasi:io/streams` output functions and the
+//! preview1 `fd_write` pass to a sink function. The sink is either a ring buffer
+//! drained through the `componentize-js:stdio/buffer` export, or a line splitter
+//! calling a logging function imported by the target world.
+
+use std::path::PathBuf;
+
+use anyhow::{Context as _, Result, bail};
+use serde_json::Value;
+use wasm_encoder::{Encode, Section};
+use wirm::ir::function::FunctionBuilder;
+use wirm::ir::id::{FunctionID, GlobalID, LocalID, MemoryID};
+use wirm::ir::types::{BlockType, InitExpr, Value as InitValue};
+use wirm::module_builder::AddLocal;
+use wirm::{DataType, InitInstr, Module, Opcode};
+use wit_component::StringEncoding;
+use wit_parser::{Enum, Function, Resolve, Type, TypeDefKind, WorldId, WorldItem};
+
+use crate::stub_policy::StubPolicy;
+use crate::stub_wasi::{
+    Dispatcher, HANDLE_TAG_MASK, PREVIEW1, READY_TAG, STDIO_TAG, mem, stub_import,
+    stub_wasi_imports,
+};
+
+const DEFAULT_CAPACITY: u32 = 64 * 1024;
+
+/// Export exposing the captured output to the host
+const STDIO_BUFFER_WIT: &str = r#"
+package componentize-js:stdio;
+
+interface buffer {
+  /// Take the console output captured since the last call
+  drain: func() -> list<u8>;
+}
+
+world stdio-buffer {
+  export buffer;
+}
+"#;
+
+const DRAIN_EXPORT: &str = "componentize-js:stdio/buffer#drain";
+
+/// Stdio buffer configuration, from the `stdio-buffer` key of a stub policy
+#[derive(Debug)]
+pub struct StdioBuffer {
+    /// Size of the ring buffer in bytes, beyond which the oldest output is dropped
+    pub capacity: u32,
+}
+
+impl StdioBuffer {
+    pub fn from_json(value: &Value) -> Result<Self> {
+        match value {
+            Value::Bool(true) => Ok(StdioBuffer {
+                capacity: DEFAULT_CAPACITY,
+            }),
+            Value::Object(config) => {
+                let mut capacity = DEFAULT_CAPACITY;
+                for (key, value) in config {
+                    match (key.as_str(), value.as_u64()) {
+                        ("capacity", Some(value)) if value > 0 && value <= i32::MAX as u64 => {
+                            capacity = value as u32
+                        }
+                        ("capacity", _) => {
+                            bail!("stub policy \"stdio-buffer.capacity\" must be a positive size")
+                        }
+                        _ => bail!("unrecognized stub policy key [stdio-buffer.{key}]"),
+                    }
+                }
+                Ok(StdioBuffer { capacity })
+            }
+            _ => bail!("stub policy \"stdio-buffer\" must be true or an object"),
+        }
+    }
+}
+
+/// Stdio log configuration, from the `stdio-log` key of a stub policy
+#[derive(Debug)]
+pub struct StdioLog {
+    /// Function of the target world receiving the output, as
+    /// `<interface>#<name>`, or `<name>` for a function imported by the world itself
+    pub function: String,
+    /// Level case used for stdout lines
+    pub stdout: String,
+    /// Level case used for stderr lines
+    pub stderr: String,
+}
+
+impl StdioLog {
+    pub fn from_json(value: &Value) -> Result<Self> {
+        let mut log = StdioLog {
+            function: String::new(),
+            stdout: "info".into(),
+            stderr: "error".into(),
+        };
+        match value {
+            Value::String(function) => log.function = function.clone(),
+            Value::Object(config) => {
+                for (key, value) in config {
+                    let Value::String(value) = value else {
+                        bail!("stub policy \"stdio-log.{key}\" must be a string");
+                    };
+                    match key.as_str() {
+                        "function" => log.function = value.clone(),
+                        "stdout" => log.stdout = value.clone(),
+                        "stderr" => log.stderr = value.clone(),
+                        _ => bail!("unrecognized stub policy key [stdio-log.{key}]"),
+                    }
+                }
+                if log.function.is_empty() {
+                    bail!("stub policy \"stdio-log\" must name a function");
+                }
+            }
+            _ => bail!("stub policy \"stdio-log\" must be a function name or an object"),
+        }
+        Ok(log)
+    }
+}
+
+/// Output-stream functions to wrap for synthesized output streams
+const OUTPUT_STREAM_FUNCS: [(&str, OutputFn); 9] = [
+    ("[method]output-stream.check-write", OutputFn::CheckWrite),
+    ("[method]output-stream.write", OutputFn::Write),
+    (
+        "[method]output-stream.blocking-write-and-flush",
+        OutputFn::Write,
+    ),
+    ("[method]output-stream.flush", OutputFn::Ok { retptr: 1 }),
+    (
+        "[method]output-stream.blocking-flush",
+        OutputFn::Ok { retptr: 1 },
+    ),
+    (
+        "[method]output-stream.write-zeroes",
+        OutputFn::Ok { retptr: 2 },
+    ),
+    (
+        "[method]output-stream.blocking-write-zeroes-and-flush",
+        OutputFn::Ok { retptr: 2 },
+    ),
+    ("[method]output-stream.subscribe", OutputFn::Subscribe),
+    ("[resource-drop]output-stream", OutputFn::Drop),
+];
+
+#[derive(Clone, Copy)]
+enum OutputFn {
+    /// Always ready for a write of any size
+    CheckWrite,
+    /// Pass the written bytes to the sink
+    Write,
+    /// Succeed without doing anything
+    Ok {
+        retptr: u32,
+    },
+    /// Return a pollable that is always ready
+    Subscribe,
+    Drop,
+}
+
+/// Wrap the `wasi:io/streams` output functions so that writes to streams tagged
+/// with `tag` are passed to a sink function taking `(payload, ptr, len)`, where
+/// `payload` is the handle without its tag
+///
+/// `has_io` indicates that `wasi:io/streams` remains available, in which case
+/// other output streams are forwarded to it.
+pub(crate) fn dispatch_output_streams(
+    module: &mut Module,
+    dispatcher: &mut Dispatcher,
+    tag: i32,
+    has_io: bool,
+    sink: FunctionID,
+) -> Result<()> {
+    let memory = module.get_memory_id().unwrap();
+    dispatcher.ready_pollables(module, has_io)?;
+
+    for (name, output_fn) in OUTPUT_STREAM_FUNCS {
+        for dispatch in dispatcher.dispatch_handles(module, tag, "wasi:io/streams", name, has_io)? {
+            dispatcher.handle(module, dispatch, |body| {
+                let handle = LocalID(0);
+                match output_fn {
+                    OutputFn::CheckWrite => {
+                        let retptr = LocalID(1);
+                        body.local_get(retptr);
+                        body.i32_const(0);
+                        body.i32_store8(mem(memory, 0, 0));
+                        body.local_get(retptr);
+                        body.i64_const(4096);
+                        body.i64_store(mem(memory, 3, 8));
+                    }
+                    OutputFn::Write => {
+                        let retptr = LocalID(3);
+                        body.local_get(handle);
+                        body.i32_const(!HANDLE_TAG_MASK);
+                        body.i32_and();
+                        body.local_get(LocalID(1));
+                        body.local_get(LocalID(2));
+                        body.call(sink);
+                        body.local_get(retptr);
+                        body.i32_const(0);
+                        body.i32_store8(mem(memory, 0, 0));
+                    }
+                    OutputFn::Ok { retptr } => {
+                        body.local_get(LocalID(retptr));
+                        body.i32_const(0);
+                        body.i32_store8(mem(memory, 0, 0));
+                    }
+                    OutputFn::Subscribe => {
+                        body.i32_const(READY_TAG);
+                    }
+                    OutputFn::Drop => {}
+                }
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Route the stdout and stderr output of the engine to a sink function taking
+/// `(stream, ptr, len)`, where `stream` is 1 for stdout and 2 for stderr
+///
+/// `has_io` indicates that `wasi:io/streams` remains available, in which case
+/// other output streams are forwarded to it.
+pub(crate) fn capture_stdio(
+    module: &mut Module,
+    dispatcher: &mut Dispatcher,
+    policy: &StubPolicy,
+    has_io: bool,
+    sink: FunctionID,
+) -> Result<()> {
+    let memory = module.get_memory_id().unwrap();
+    dispatch_output_streams(module, dispatcher, STDIO_TAG, has_io, sink)?;
+
+    for (name, stream) in [("wasi:cli/stdout", 1), ("wasi:cli/stderr", 2)] {
+        let getter = name.replace("wasi:cli/", "get-");
+        stub_wasi_imports(module, policy, name, &getter, |body| {
+            body.i32_const(STDIO_TAG | stream);
+            Ok(vec![])
+        })?;
+    }
+
+    // (func (param i32 i32 i32 i32) (result i32)))
+    stub_import(module, policy, PREVIEW1, "fd_write", |body| {
+        let (fd, iovs, iovs_len, nwritten) = (LocalID(0), LocalID(1), LocalID(2), LocalID(3));
+        let iov = body.add_local(DataType::I32);
+        let total = body.add_local(DataType::I32);
+        body.local_get(iovs);
+        body.local_set(iov);
+        body.block(BlockType::Empty);
+        body.loop_stmt(BlockType::Empty);
+        body.local_get(iov);
+        body.local_get(iovs);
+        body.local_get(iovs_len);
+        body.i32_const(8);
+        body.i32_mul();
+        body.i32_add();
+        body.i32_ge_u();
+        body.br_if(1);
+        body.local_get(fd);
+        body.local_get(iov);
+        body.i32_load(mem(memory, 2, 0));
+        body.local_get(iov);
+        body.i32_load(mem(memory, 2, 4));
+        body.call(sink);
+        body.local_get(total);
+        body.local_get(iov);
+        body.i32_load(mem(memory, 2, 4));
+        body.i32_add();
+        body.local_set(total);
+        body.local_get(iov);
+        body.i32_const(8);
+        body.i32_add();
+        body.local_set(iov);
+        body.br(0);
+        body.end();
+        body.end();
+        body.local_get(nwritten);
+        body.local_get(total);
+        body.i32_store(mem(memory, 2, 0));
+        body.i32_const(0);
+        Ok(vec![])
+    })?;
+
+    Ok(())
+}
+
+/// Capture stdout and stderr into a ring buffer, and export the function to
+/// drain it
+pub(crate) fn synthesize_stdio_buffer(
+    module: &mut Module,
+    dispatcher: &mut Dispatcher,
+    policy: &StubPolicy,
+    buffer: &StdioBuffer,
+    has_io: bool,
+) -> Result<()> {
+    let memory = module.get_memory_id().unwrap();
+    let realloc = module
+        .exports
+        .get_func_by_name("cabi_realloc".to_string())
+        .unwrap();
+    let cap = buffer.capacity as i32;
+    // the drain return area follows the buffer
+    let retarea_offset = (cap + 3) & !3;
+    let mut global = || -> GlobalID {
+        module.add_global(
+            InitExpr::new(vec![InitInstr::Value(InitValue::I32(0))]),
+            DataType::I32,
+            true,
+            false,
+        )
+    };
+    let (start, len) = (global(), global());
+
+    // the buffer is followed by the return area of the drain export
+    let buffer_fn = build_lazy_alloc(module, retarea_offset + 8, |_, _| {});
+
+    let sink = build_ring_write(module, memory, buffer_fn, cap, start, len);
+    capture_stdio(module, dispatcher, policy, has_io, sink)?;
+
+    let mut body = FunctionBuilder::new(&[], &[DataType::I32]);
+    let base = body.add_local(DataType::I32);
+    let out = body.add_local(DataType::I32);
+    let first = body.add_local(DataType::I32);
+    let retarea = body.add_local(DataType::I32);
+    body.call(buffer_fn);
+    body.local_set(base);
+    body.i32_const(0);
+    body.i32_const(0);
+    body.i32_const(1);
+    body.global_get(len);
+    body.call(realloc);
+    body.local_set(out);
+    // first = min(len, cap - start)
+    emit_min(
+        &mut body,
+        first,
+        |body| {
+            body.i32_const(cap);
+            body.global_get(start);
+            body.i32_sub();
+        },
+        |body| {
+            body.global_get(len);
+        },
+    );
+    body.local_get(out);
+    body.local_get(base);
+    body.global_get(start);
+    body.i32_add();
+    body.local_get(first);
+    body.memory_copy(memory, memory);
+    body.local_get(out);
+    body.local_get(first);
+    body.i32_add();
+    body.local_get(base);
+    body.global_get(len);
+    body.local_get(first);
+    body.i32_sub();
+    body.memory_copy(memory, memory);
+    body.local_get(base);
+    body.i32_const(retarea_offset);
+    body.i32_add();
+    body.local_tee(retarea);
+    body.local_get(out);
+    body.i32_store(mem(memory, 2, 0));
+    body.local_get(retarea);
+    body.global_get(len);
+    body.i32_store(mem(memory, 2, 4));
+    body.i32_const(0);
+    body.global_set(start);
+    body.i32_const(0);
+    body.global_set(len);
+    body.local_get(retarea);
+    let drain = body.finish_module(module);
+    module
+        .exports
+        .add_export_func(DRAIN_EXPORT.to_string(), *drain);
+
+    Ok(())
+}
+
+/// Longest line passed to the logging function, longer lines are split
+const LINE_CAPACITY: i32 = 4096;
+
+/// Contexts passed to `wasi:logging`, with the same length
+const LOG_CONTEXTS: [&[u8]; 2] = [b"stdout", b"stderr"];
+
+/// Send stdout and stderr to a logging function of the target world, one call
+/// per line
+///
+/// The function takes the message as its last `string` parameter, optionally
+/// preceded by an `enum` level, and a `string` context between the two as in
+/// `wasi:logging/logging#log`, which receives the stream name.
+pub(crate) fn synthesize_stdio_log(
+    module: &mut Module,
+    dispatcher: &mut Dispatcher,
+    policy: &StubPolicy,
+    log: &StdioLog,
+    resolve: &Resolve,
+    world: WorldId,
+    has_io: bool,
+) -> Result<()> {
+    let memory = module.get_memory_id().unwrap();
+    let (import, func) = find_world_function(resolve, world, &log.function)?;
+    if func.result.is_some() {
+        bail!(
+            "stdio log function [{}] must not return a value",
+            log.function
+        );
+    }
+    let (level, context) = match func.params.as_slice() {
+        [message] if message.ty == Type::String => (None, false),
+        [level, message] if message.ty == Type::String => (Some(&level.ty), false),
+        [level, context, message] if context.ty == Type::String && message.ty == Type::String => {
+            (Some(&level.ty), true)
+        }
+        _ => bail!(
+            "stdio log function [{}] must take a message string, optionally preceded by a level and a context",
+            log.function
+        ),
+    };
+    let levels = match level {
+        Some(level) => {
+            let Some(cases) = enum_cases(resolve, level) else {
+                bail!(
+                    "stdio log function [{}] level must be an enum",
+                    log.function
+                );
+            };
+            let case = |name: &str| {
+                cases
+                    .cases
+                    .iter()
+                    .position(|case| case.name == name)
+                    .map(|idx| idx as i32)
+                    .with_context(|| {
+                        format!(
+                            "stdio log function [{}] has no level [{name}]",
+                            log.function
+                        )
+                    })
+            };
+            Some([case(&log.stdout)?, case(&log.stderr)?])
+        }
+        None => None,
+    };
+
+    // (level, context ptr, context len, message ptr, message len)
+    let params = vec![DataType::I32; levels.is_some() as usize + 2 * context as usize + 2];
+    let log_fn = match module.imports.get_func(import.clone(), func.name.clone()) {
+        Some(fid) => fid,
+        None => {
+            let ty = module.types.add_func_type(&params, &[]);
+            module.add_import_func(import, func.name.clone(), ty).0
+        }
+    };
+
+    // [stdout len, stderr len, contexts, stdout line, stderr line]
+    let lines_offset = 8 + LOG_CONTEXTS.iter().map(|c| c.len() as i32).sum::<i32>();
+    let buffer_fn = build_lazy_alloc(module, lines_offset + 2 * LINE_CAPACITY, |body, area| {
+        body.global_get(area);
+        body.i64_const(0);
+        body.i64_store(mem(memory, 2, 0));
+        for (offset, byte) in (8..).zip(LOG_CONTEXTS.concat()) {
+            body.global_get(area);
+            body.i32_const(byte as i32);
+            body.i32_store8(mem(memory, 0, offset));
+        }
+    });
+
+    // emit the pending line of a stream (0 for stdout, 1 for stderr)
+    let mut body = FunctionBuilder::new(&[DataType::I32], &[]);
+    let stream = LocalID(0);
+    let base = body.add_local(DataType::I32);
+    body.call(buffer_fn);
+    body.local_set(base);
+    if let Some([stdout, stderr]) = levels {
+        body.i32_const(stderr);
+        body.i32_const(stdout);
+        body.local_get(stream);
+        body.select();
+    }
+    if context {
+        body.local_get(base);
+        body.i32_const(8);
+        body.i32_add();
+        body.local_get(stream);
+        body.i32_const(LOG_CONTEXTS[0].len() as i32);
+        body.i32_mul();
+        body.i32_add();
+        body.i32_const(LOG_CONTEXTS[0].len() as i32);
+    }
+    body.local_get(base);
+    body.i32_const(lines_offset);
+    body.i32_add();
+    body.local_get(stream);
+    body.i32_const(LINE_CAPACITY);
+    body.i32_mul();
+    body.i32_add();
+    body.local_get(base);
+    body.local_get(stream);
+    body.i32_const(4);
+    body.i32_mul();
+    body.i32_add();
+    body.i32_load(mem(memory, 2, 0));
+    body.call(log_fn);
+    body.local_get(base);
+    body.local_get(stream);
+    body.i32_const(4);
+    body.i32_mul();
+    body.i32_add();
+    body.i32_const(0);
+    body.i32_store(mem(memory, 2, 0));
+    let emit_fn = body.finish_module(module);
+
+    let sink = build_line_split(module, memory, buffer_fn, emit_fn, lines_offset);
+    capture_stdio(module, dispatcher, policy, has_io, sink)
+}
+
+/// Find a function imported by a world, from `<interface>#<name>` (where the
+/// interface version may be omitted) or `<name>`, along with its import module
+fn find_world_function<'a>(
+    resolve: &'a Resolve,
+    world: WorldId,
+    spec: &str,
+) -> Result<(String, &'a Function)> {
+    let (iface, name) = match spec.rsplit_once('#') {
+        Some((iface, name)) => (Some(iface), name),
+        None => (None, spec),
+    };
+    for (key, item) in &resolve.worlds[world].imports {
+        match (iface, item) {
+            (None, WorldItem::Function(func)) if func.name == name => {
+                return Ok(("$root".into(), func));
+            }
+            (Some(iface), WorldItem::Interface { id, .. }) => {
+                let key_name = resolve.name_world_key(key);
+                if key_name != iface && key_name.split('@').next() != Some(iface) {
+                    continue;
+                }
+                if let Some(func) = resolve.interfaces[*id].functions.get(name) {
+                    return Ok((key_name, func));
+                }
+            }
+            _ => {}
+        }
+    }
+    bail!("stdio log function [{spec}] is not imported by the target world")
+}
+
+/// Resolve a type to an enum, through type aliases
+fn enum_cases<'a>(resolve: &'a Resolve, ty: &Type) -> Option<&'a Enum> {
+    let Type::Id(id) = ty else {
+        return None;
+    };
+    match &resolve.types[*id].kind {
+        TypeDefKind::Enum(cases) => Some(cases),
+        TypeDefKind::Type(ty) => enum_cases(resolve, ty),
+        _ => None,
+    }
+}
+
+/// Build the `(stream, ptr, len)` sink accumulating the output of each stream
+/// into lines, emitted on newlines or when the line buffer is full
+fn build_line_split(
+    module: &mut Module,
+    memory: MemoryID,
+    buffer_fn: FunctionID,
+    emit_fn: FunctionID,
+    lines_offset: i32,
+) -> FunctionID {
+    let (stream, ptr, n) = (LocalID(0), LocalID(1), LocalID(2));
+    let mut body = FunctionBuilder::new(&[DataType::I32, DataType::I32, DataType::I32], &[]);
+    let idx = body.add_local(DataType::I32);
+    let len_ptr = body.add_local(DataType::I32);
+    let line = body.add_local(DataType::I32);
+    let byte = body.add_local(DataType::I32);
+    let i = body.add_local(DataType::I32);
+
+    // preview1 file descriptors other than stderr are treated as stdout
+    body.local_get(stream);
+    body.i32_const(2);
+    body.i32_eq();
+    body.local_set(idx);
+    body.call(buffer_fn);
+    body.local_get(idx);
+    body.i32_const(4);
+    body.i32_mul();
+    body.i32_add();
+    body.local_set(len_ptr);
+    body.call(buffer_fn);
+    body.i32_const(lines_offset);
+    body.i32_add();
+    body.local_get(idx);
+    body.i32_const(LINE_CAPACITY);
+    body.i32_mul();
+    body.i32_add();
+    body.local_set(line);
+
+    body.block(BlockType::Empty);
+    body.loop_stmt(BlockType::Empty);
+    body.local_get(i);
+    body.local_get(n);
+    body.i32_ge_u();
+    body.br_if(1);
+    body.local_get(ptr);
+    body.local_get(i);
+    body.i32_add();
+    body.i32_load8_u(mem(memory, 0, 0));
+    body.local_tee(byte);
+    body.i32_const(b'\n' as i32);
+    body.i32_eq();
+    body.if_stmt(BlockType::Empty);
+    body.local_get(idx);
+    body.call(emit_fn);
+    body.else_stmt();
+    body.local_get(len_ptr);
+    body.i32_load(mem(memory, 2, 0));
+    body.i32_const(LINE_CAPACITY);
+    body.i32_eq();
+    body.if_stmt(BlockType::Empty);
+    body.local_get(idx);
+    body.call(emit_fn);
+    body.end();
+    body.local_get(line);
+    body.local_get(len_ptr);
+    body.i32_load(mem(memory, 2, 0));
+    body.i32_add();
+    body.local_get(byte);
+    body.i32_store8(mem(memory, 0, 0));
+    body.local_get(len_ptr);
+    body.local_get(len_ptr);
+    body.i32_load(mem(memory, 2, 0));
+    body.i32_const(1);
+    body.i32_add();
+    body.i32_store(mem(memory, 2, 0));
+    body.end();
+    body.local_get(i);
+    body.i32_const(1);
+    body.i32_add();
+    body.local_set(i);
+    body.br(0);
+    body.end();
+    body.end();
+    body.finish_module(module)
+}
+
+/// Build a function returning the address of a `size` bytes area, allocated and
+/// initialized by `init` on first use
+fn build_lazy_alloc(
+    module: &mut Module,
+    size: i32,
+    init: impl FnOnce(&mut FunctionBuilder, GlobalID),
+) -> FunctionID {
+    let realloc = module
+        .exports
+        .get_func_by_name("cabi_realloc".to_string())
+        .unwrap();
+    let area = module.add_global(
+        InitExpr::new(vec![InitInstr::Value(InitValue::I32(0))]),
+        DataType::I32,
+        true,
+        false,
+    );
+    let mut body = FunctionBuilder::new(&[], &[DataType::I32]);
+    body.global_get(area);
+    body.i32_eqz();
+    body.if_stmt(BlockType::Empty);
+    body.i32_const(0);
+    body.i32_const(0);
+    body.i32_const(4);
+    body.i32_const(size);
+    body.call(realloc);
+    body.global_set(area);
+    init(&mut body, area);
+    body.end();
+    body.global_get(area);
+    body.finish_module(module)
+}
+
+/// Set `dst` to the minimum of two unsigned values
+fn emit_min(
+    body: &mut FunctionBuilder,
+    dst: LocalID,
+    a: impl Fn(&mut FunctionBuilder),
+    b: impl Fn(&mut FunctionBuilder),
+) {
+    a(body);
+    b(body);
+    a(body);
+    b(body);
+    body.i32_lt_u();
+    body.select();
+    body.local_set(dst);
+}
+
+/// Build the `(stream, ptr, len)` sink appending to the ring buffer, dropping
+/// the oldest output when it is full
+fn build_ring_write(
+    module: &mut Module,
+    memory: MemoryID,
+    buffer_fn: FunctionID,
+    cap: i32,
+    start: GlobalID,
+    len: GlobalID,
+) -> FunctionID {
+    let (ptr, n) = (LocalID(1), LocalID(2));
+    let mut body = FunctionBuilder::new(&[DataType::I32, DataType::I32, DataType::I32], &[]);
+    let base = body.add_local(DataType::I32);
+    let end = body.add_local(DataType::I32);
+    let overflow = body.add_local(DataType::I32);
+    let first = body.add_local(DataType::I32);
+    body.call(buffer_fn);
+    body.local_set(base);
+
+    // only the last `cap` bytes of a larger write are kept
+    body.local_get(n);
+    body.i32_const(cap);
+    body.i32_gt_u();
+    body.if_stmt(BlockType::Empty);
+    body.local_get(ptr);
+    body.local_get(n);
+    body.i32_add();
+    body.i32_const(cap);
+    body.i32_sub();
+    body.local_set(ptr);
+    body.i32_const(cap);
+    body.local_set(n);
+    body.end();
+
+    // end = (start + len) % cap
+    body.global_get(start);
+    body.global_get(len);
+    body.i32_add();
+    body.i32_const(cap);
+    body.i32_rem_u();
+    body.local_set(end);
+
+    // drop the oldest bytes that don't fit
+    body.global_get(len);
+    body.local_get(n);
+    body.i32_add();
+    body.i32_const(cap);
+    body.i32_sub();
+    body.local_tee(overflow);
+    body.i32_const(0);
+    body.i32_gt_s();
+    body.if_stmt(BlockType::Empty);
+    body.global_get(start);
+    body.local_get(overflow);
+    body.i32_add();
+    body.i32_const(cap);
+    body.i32_rem_u();
+    body.global_set(start);
+    body.global_get(len);
+    body.local_get(overflow);
+    body.i32_sub();
+    body.global_set(len);
+    body.end();
+
+    // copy up to the end of the buffer, then wrap around
+    emit_min(
+        &mut body,
+        first,
+        |body| {
+            body.i32_const(cap);
+            body.local_get(end);
+            body.i32_sub();
+        },
+        |body| {
+            body.local_get(n);
+        },
+    );
+    body.local_get(base);
+    body.local_get(end);
+    body.i32_add();
+    body.local_get(ptr);
+    body.local_get(first);
+    body.memory_copy(memory, memory);
+    body.local_get(base);
+    body.local_get(ptr);
+    body.local_get(first);
+    body.i32_add();
+    body.local_get(n);
+    body.local_get(first);
+    body.i32_sub();
+    body.memory_copy(memory, memory);
+    body.global_get(len);
+    body.local_get(n);
+    body.i32_add();
+    body.global_set(len);
+    body.finish_module(module)
+}
+
+/// Encode the `component-type` custom section adding the drain export to the world
+pub(crate) fn stdio_buffer_component_type() -> Result<Vec<u8>> {
+    let mut resolve = Resolve::default();
+    let pkg = resolve.push_str(PathBuf::from("stdio-buffer.wit"), STDIO_BUFFER_WIT)?;
+    let world = resolve.select_world(&[pkg], None)?;
+    let encoded = wit_component::metadata::encode(&resolve, world, StringEncoding::UTF8, None)?;
+    let section = wasm_encoder::CustomSection {
+        name: "component-type:stdio-buffer".into(),
+        data: encoded.into(),
+    };
+    let mut bytes = vec![section.id()];
+    section.encode(&mut bytes);
+    Ok(bytes)
+}
@@ -0,0 +1,626 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use anyhow::{Context as _, Result, bail};
+use serde_json::Value;
+use wirm::ir::function::FunctionBuilder;
+use wirm::ir::id::{FunctionID, ImportsID, LocalID};
+use wirm::ir::module::module_functions::FuncKind;
+use wirm::wasmparser::{MemArg, TypeRef};
+use wirm::{DataType, Module, Opcode};
+use wit_component::metadata::{Bindgen, decode};
+use wit_parser::abi::AbiVariant;
+use wit_parser::{SizeAlign, Type, TypeDefKind, WorldItem};
+
+use crate::environment::Environment;
+use crate::http_mock::HttpMock;
+use crate::preview1::preview1_results;
+use crate::stdio_capture::{StdioBuffer, StdioLog};
+use crate::verify::VerifyMode;
+use crate::virtual_fs::VirtualFs;
+use crate::wit::exports::local::spidermonkey_embedding_splicer::splicer::Feature;
+
+/// Errno returned by preview1 imports that are stubbed to produce an error
+/// (`ERRNO_NOTCAPABLE`)
+const PREVIEW1_ERRNO_NOTCAPABLE: i32 = 76;
+
+/// Behavior to apply to an import matched by a stub policy rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StubBehavior {
+    /// Leave the import in place, even if a feature preset would stub it
+    Keep,
+    /// Replace the import with a function that traps
+    Trap,
+    /// Replace the import with a function that returns zero values, which
+    /// lifts as zero numbers, empty lists and strings, `none` and `ok`, or
+    /// which succeeds with zeroed results for preview1 functions
+    ///
+    /// Imports that are neither preview1 functions nor described by the
+    /// `component-type` metadata can't be zeroed if their results may be
+    /// written to a return area.
+    Zero,
+    /// Replace the import with a function that returns the first error case
+    /// of its `result` return type
+    Error,
+}
+
+impl FromStr for StubBehavior {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "keep" => Ok(StubBehavior::Keep),
+            "trap" => Ok(StubBehavior::Trap),
+            "zero" => Ok(StubBehavior::Zero),
+            "error" => Ok(StubBehavior::Error),
+            _ => bail!("unrecognized stub behavior [{s}]"),
+        }
+    }
+}
+
+/// A declarative policy describing how `stub_wasi` treats engine imports
+///
+/// The policy document is JSON or TOML, of the form:
+///
+/// ```json
+/// {
+///   "features": ["stdio", "random"],
+///   "imports": {
+///     "wasi:filesystem/*": "trap",
+///     "wasi:cli/environment#get-arguments": "zero"
+///   },
+///   "virtual-fs": { "dir": "./assets", "preopen": "/" },
+///   "environment": { "vars": { "MODE": "production" }, "args": ["app"], "cwd": "/" },
+///   "stdio-buffer": { "capacity": 65536 },
+///   "stdio-log": { "function": "wasi:logging/logging#log", "stdout": "info", "stderr": "error" },
+///   "http-mock": [{ "method": "GET", "url": "https://api.example.com/*", "body": "{}" }],
+///   "virtual-time": true,
+///   "verify": "imports"
+/// }
+/// ```
+///
+/// or equivalently:
+///
+/// ```toml
+/// features = ["stdio", "random"]
+/// virtual-time = true
+/// verify = "imports"
+///
+/// [imports]
+/// "wasi:filesystem/*" = "trap"
+/// "wasi:cli/environment#get-arguments" = "zero"
+///
+/// [virtual-fs]
+/// dir = "./assets"
+/// preopen = "/"
+///
+/// [[http-mock]]
+/// method = "GET"
+/// url = "https://api.example.com/*"
+/// body = "{}"
+/// ```
+///
+/// `features`, when present, replaces the features passed to `stub_wasi`, which
+/// act as presets that the `imports` rules refine. Every feature that is not
+/// enabled is a preset of rules trapping the imports of its interfaces (or
+/// returning zero for some preview1 functions), which applies under the `imports`
+/// rules to the imports that no implementation is synthesized for. Implementations
+/// are synthesized by `virtual-fs`, `environment`, `stdio-buffer`, `stdio-log` and
+/// `http-mock`, and by default for the `random`, `clocks` and `stdio` features.
+///
+/// Import patterns match `<module>` or `<module>#<name>`, where `*` matches any
+/// sequence of characters. Patterns without a version (`@...`) match all versions
+/// of an interface. When several patterns match, function patterns (containing `#`)
+/// take precedence over interface patterns, then the pattern with the most literal
+/// characters wins.
+///
+/// `virtual-fs`, when present, embeds a read-only image of a host directory that
+/// is served in place of `wasi:filesystem` (see [`crate::virtual_fs`]).
+///
+/// `environment`, when present, provides the environment variables, arguments and
+/// working directory returned in place of `wasi:cli/environment` (see
+/// [`crate::environment`]).
+///
+/// `stdio-buffer`, when present and the `stdio` feature is disabled, captures the
+/// console output into a ring buffer instead of trapping (see
+/// [`crate::stdio_capture`]).
+///
+/// `stdio-log`, when present and the `stdio` feature is disabled, sends each line of
+/// console output to a logging function imported by the target world instead. It
+/// is either the function name or an object also giving the level cases to use for
+/// stdout and stderr.
+///
+/// `http-mock`, when present and both the `http` and `fetch-event` features are
+/// disabled, answers outgoing requests from a table of responses instead of
+/// trapping. Each response matches a URL, or a URL prefix ending with `*`, and
+/// optionally a method, and gives a status, headers and body (see
+/// [`crate::http_mock`]).
+///
+/// `virtual-time`, when true and the `clocks` feature is disabled, runs timers in
+/// virtual time instead of trapping: the monotonic clock starts at the build time,
+/// and jumps to the earliest timer deadline whenever a poll would block.
+///
+/// `verify`, when present, fails stubbing if imports remain beyond those of the
+/// target world, of the enabled features and kept by the policy (`"imports"`), or
+/// beyond those of the target world alone with every feature disabled (`"pure"`,
+/// see [`crate::verify`]).
+#[derive(Debug, Default)]
+pub struct StubPolicy {
+    pub features: Option<Vec<Feature>>,
+    pub virtual_fs: Option<VirtualFs>,
+    pub environment: Option<Environment>,
+    pub stdio_buffer: Option<StdioBuffer>,
+    pub stdio_log: Option<StdioLog>,
+    pub http_mock: Option<HttpMock>,
+    pub virtual_time: bool,
+    pub verify: Option<VerifyMode>,
+    rules: Vec<(String, StubBehavior)>,
+    /// Rules of the features that are not enabled, which apply when no rule of
+    /// `imports` matches
+    pub(crate) presets: Vec<(String, StubBehavior)>,
+}
+
+impl StubPolicy {
+    pub fn parse(source: &str) -> Result<Self> {
+        // a TOML document cannot start with an inline table
+        let doc: Value = if source.trim_start().starts_with('{') {
+            serde_json::from_str(source).context("failed to parse JSON stub policy")?
+        } else {
+            toml::from_str(source).context("failed to parse TOML stub policy")?
+        };
+        let Value::Object(doc) = doc else {
+            bail!("stub policy must be an object");
+        };
+
+        let mut policy = StubPolicy::default();
+        for (key, value) in doc {
+            match key.as_str() {
+                "features" => {
+                    let Value::Array(features) = value else {
+                        bail!("stub policy \"features\" must be an array of feature strings");
+                    };
+                    policy.features = Some(
+                        features
+                            .iter()
+                            .map(|f| match f {
+                                Value::String(f) => Feature::from_str(f),
+                                _ => bail!("stub policy features must be strings, found [{f}]"),
+                            })
+                            .collect::<Result<_>>()?,
+                    );
+                }
+                "imports" => {
+                    let Value::Object(imports) = value else {
+                        bail!("stub policy \"imports\" must be an object of pattern to behavior");
+                    };
+                    for (pattern, behavior) in imports {
+                        let Value::String(behavior) = behavior else {
+                            bail!("stub policy behavior for [{pattern}] must be a string");
+                        };
+                        let behavior = StubBehavior::from_str(&behavior)
+                            .with_context(|| format!("invalid stub policy for [{pattern}]"))?;
+                        policy.rules.push((pattern, behavior));
+                    }
+                }
+                "virtual-fs" => policy.virtual_fs = Some(VirtualFs::from_json(&value)?),
+                "environment" => policy.environment = Some(Environment::from_json(&value)?),
+                "stdio-buffer" => policy.stdio_buffer = Some(StdioBuffer::from_json(&value)?),
+                "stdio-log" => policy.stdio_log = Some(StdioLog::from_json(&value)?),
+                "http-mock" => policy.http_mock = Some(HttpMock::from_json(&value)?),
+                "virtual-time" => {
+                    let Value::Bool(virtual_time) = value else {
+                        bail!("stub policy \"virtual-time\" must be a boolean");
+                    };
+                    policy.virtual_time = virtual_time;
+                }
+                "verify" => policy.verify = Some(VerifyMode::from_json(&value)?),
+                _ => bail!("unrecognized stub policy key [{key}]"),
+            }
+        }
+        Ok(policy)
+    }
+
+    /// Determine the behavior for a given import, if any rule or preset matches it
+    pub fn behavior(&self, import: &str, name: &str) -> Option<StubBehavior> {
+        matching_rule(&self.rules, import, name)
+            .or_else(|| matching_rule(&self.presets, import, name))
+    }
+
+    /// Whether the policy requires the given import to be left in place
+    pub fn keeps(&self, import: &str, name: &str) -> bool {
+        self.behavior(import, name) == Some(StubBehavior::Keep)
+    }
+
+    /// Whether a rule of the policy replaces the given import
+    pub fn replaces(&self, import: &str, name: &str) -> bool {
+        matching_rule(&self.rules, import, name)
+            .is_some_and(|behavior| behavior != StubBehavior::Keep)
+    }
+}
+
+/// Behavior of the most specific of the `rules` matching an import
+fn matching_rule(
+    rules: &[(String, StubBehavior)],
+    import: &str,
+    name: &str,
+) -> Option<StubBehavior> {
+    let unversioned = import.split_once('@').map_or(import, |(iface, _)| iface);
+    rules
+        .iter()
+        .filter(|(pattern, _)| {
+            let (iface_pattern, name_pattern) = match pattern.split_once('#') {
+                Some((iface, name)) => (iface, Some(name)),
+                None => (pattern.as_str(), None),
+            };
+            (glob_match(iface_pattern, import) || glob_match(iface_pattern, unversioned))
+                && name_pattern.is_none_or(|name_pattern| glob_match(name_pattern, name))
+        })
+        .max_by_key(|(pattern, _)| (pattern.contains('#'), pattern.replace('*', "").len()))
+        .map(|(_, behavior)| *behavior)
+}
+
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let (pattern, text) = (pattern.as_bytes(), text.as_bytes());
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((star_p, star_t)) = backtrack {
+            p = star_p + 1;
+            t = star_t + 1;
+            backtrack = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+/// Lowered return information for a WIT-level import
+struct ImportReturn {
+    /// Size of the return area passed as the last parameter, if any
+    retarea: Option<u32>,
+    /// Whether the function returns a `result`
+    is_result: bool,
+}
+
+/// Collect the return information of all imports described by the
+/// `component-type` metadata of the given module
+fn import_returns(wasm: &[u8]) -> HashMap<(String, String), ImportReturn> {
+    let mut returns = HashMap::new();
+    let Ok((_, Bindgen { resolve, world, .. })) = decode(wasm) else {
+        return returns;
+    };
+    let mut sizes = SizeAlign::default();
+    sizes.fill(&resolve);
+
+    for (key, item) in &resolve.worlds[world].imports {
+        let (module, funcs) = match item {
+            WorldItem::Interface { id, .. } => (
+                resolve.name_world_key(key),
+                resolve.interfaces[*id]
+                    .functions
+                    .values()
+                    .collect::<Vec<_>>(),
+            ),
+            WorldItem::Function(func) => ("$root".to_string(), vec![func]),
+            WorldItem::Type { .. } => continue,
+        };
+        for func in funcs {
+            let sig = resolve.wasm_signature(AbiVariant::GuestImport, func);
+            let retarea = sig.retptr.then(|| {
+                func.result
+                    .map(|ty| sizes.size(&ty).size_wasm32() as u32)
+                    .unwrap_or(0)
+            });
+            let is_result = matches!(
+                func.result,
+                Some(Type::Id(id)) if matches!(resolve.types[id].kind, TypeDefKind::Result(_))
+            );
+            returns.insert(
+                (module.clone(), func.name.clone()),
+                ImportReturn { retarea, is_result },
+            );
+        }
+    }
+    returns
+}
+
+/// Replace every import matched by a trapping, zero or error rule of the policy
+///
+/// This runs once with the rules of the policy, before any implementation is
+/// synthesized, then once more with the feature presets, which skips the imports
+/// already replaced.
+pub fn apply_stub_policy(module: &mut Module, policy: &StubPolicy, wasm: &[u8]) -> Result<()> {
+    if policy.rules.is_empty() && policy.presets.is_empty() {
+        return Ok(());
+    }
+    let returns = import_returns(wasm);
+    let memory = module
+        .get_memory_id()
+        .context("the engine must define a memory to apply the stub policy")?;
+
+    let matched = module
+        .imports
+        .iter()
+        .enumerate()
+        .filter(|(_, impt)| matches!(impt.ty, TypeRef::Func(_)))
+        .filter_map(|(idx, impt)| {
+            let behavior = policy.behavior(&impt.module, &impt.name)?;
+            (behavior != StubBehavior::Keep).then(|| {
+                (
+                    ImportsID(idx as u32),
+                    impt.module.to_string(),
+                    impt.name.to_string(),
+                    behavior,
+                )
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for (iid, import, name, behavior) in matched {
+        let fid = FunctionID(*iid);
+        let ty_id = match module.functions.get(fid).kind() {
+            FuncKind::Local(_) => continue,
+            FuncKind::Import(i) => i.ty_id,
+        };
+        let ty = module.types.get(ty_id).unwrap();
+        let (params, results) = (
+            ty.params()
+                .with_context(|| format!("failed to retrieve params for '{import}#{name}'"))?
+                .to_vec(),
+            ty.results()
+                .with_context(|| format!("failed to retrieve results for '{import}#{name}'"))?
+                .to_vec(),
+        );
+        let ret = returns.get(&(import.clone(), name.clone()));
+        let is_preview1 = import == "wasi_snapshot_preview1";
+
+        let mut builder = FunctionBuilder::new(params.as_slice(), results.as_slice());
+        match behavior {
+            StubBehavior::Keep => unreachable!(),
+            StubBehavior::Trap => {
+                builder.unreachable();
+            }
+            StubBehavior::Zero | StubBehavior::Error => {
+                if behavior == StubBehavior::Error
+                    && !is_preview1
+                    && !ret.is_some_and(|r| r.is_result)
+                {
+                    bail!(
+                        "stub policy requires an error for '{import}#{name}', but it does not return a result"
+                    );
+                }
+                if behavior == StubBehavior::Zero
+                    && !is_preview1
+                    && ret.is_none()
+                    && results.is_empty()
+                    && !params.is_empty()
+                    && !name.starts_with("[resource-drop]")
+                {
+                    bail!(
+                        "stub policy requires zero values for '{import}#{name}', but its return area is unknown"
+                    );
+                }
+                let err = behavior == StubBehavior::Error;
+                if is_preview1 {
+                    // zero the cells the results are written to, so that a
+                    // successful call never leaves them unset
+                    let cells = preview1_results(&name);
+                    let first = params
+                        .len()
+                        .checked_sub(cells.len())
+                        .with_context(|| format!("unexpected signature for '{import}#{name}'"))?;
+                    for (idx, size) in cells.iter().enumerate() {
+                        builder.local_get(LocalID((first + idx) as u32));
+                        builder.i32_const(0);
+                        builder.i32_const(*size as i32);
+                        builder.memory_fill(memory);
+                    }
+                    if name == "random_get" && params.len() == 2 {
+                        builder.local_get(LocalID(0));
+                        builder.i32_const(0);
+                        builder.local_get(LocalID(1));
+                        builder.memory_fill(memory);
+                    }
+                }
+                if let Some(size) = ret.and_then(|r| r.retarea) {
+                    let retptr = LocalID(params.len() as u32 - 1);
+                    builder.local_get(retptr);
+                    builder.i32_const(0);
+                    builder.i32_const(size as i32);
+                    builder.memory_fill(memory);
+                    if err {
+                        // set the result discriminant to the error case
+                        builder.local_get(retptr);
+                        builder.i32_const(1);
+                        builder.i32_store8(MemArg {
+                            align: 0,
+                            max_align: 0,
+                            offset: 0,
+                            memory: *memory,
+                        });
+                    }
+                }
+                for (idx, result) in results.iter().enumerate() {
+                    match result {
+                        DataType::I32 if err && idx == 0 => {
+                            builder.i32_const(if is_preview1 {
+                                PREVIEW1_ERRNO_NOTCAPABLE
+                            } else {
+                                1
+                            });
+                        }
+                        DataType::I32 => {
+                            builder.i32_const(0);
+                        }
+                        DataType::I64 => {
+                            builder.i64_const(0);
+                        }
+                        DataType::F32 => {
+                            builder.f32_const(0.0);
+                        }
+                        DataType::F64 => {
+                            builder.f64_const(0.0);
+                        }
+                        _ => bail!("unsupported result type for '{import}#{name}'"),
+                    }
+                }
+            }
+        }
+        builder.replace_import_in_module(module, iid)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_match_patterns() {
+        assert!(glob_match("wasi:cli/environment", "wasi:cli/environment"));
+        assert!(glob_match("wasi:filesystem/*", "wasi:filesystem/types"));
+        assert!(glob_match("wasi:*/types", "wasi:http/types"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("get-*-u64", "get-random-u64"));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(!glob_match("wasi:filesystem/*", "wasi:cli/environment"));
+        assert!(!glob_match("get-*-u64", "get-random-bytes"));
+        assert!(!glob_match("wasi:cli", "wasi:cli/environment"));
+    }
+
+    #[test]
+    fn parse_json_and_toml() {
+        let json = StubPolicy::parse(
+            r#"{
+                "features": ["stdio", "random"],
+                "imports": { "wasi:filesystem/*": "trap", "wasi:cli/environment#get-arguments": "zero" },
+                "verify": "imports"
+            }"#,
+        )
+        .unwrap();
+        let toml = StubPolicy::parse(
+            r#"
+            features = ["stdio", "random"]
+            verify = "imports"
+
+            [imports]
+            "wasi:filesystem/*" = "trap"
+            "wasi:cli/environment#get-arguments" = "zero"
+            "#,
+        )
+        .unwrap();
+        for policy in [json, toml] {
+            assert_eq!(policy.features, Some(vec![Feature::Stdio, Feature::Random]));
+            assert_eq!(policy.verify, Some(VerifyMode::Imports));
+            assert_eq!(
+                policy.behavior("wasi:filesystem/types@0.2.3", "[method]descriptor.stat"),
+                Some(StubBehavior::Trap)
+            );
+            assert_eq!(
+                policy.behavior("wasi:cli/environment@0.2.3", "get-arguments"),
+                Some(StubBehavior::Zero)
+            );
+            assert_eq!(
+                policy.behavior("wasi:cli/environment@0.2.3", "get-environment"),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(StubPolicy::parse("[]").is_err());
+        assert!(StubPolicy::parse(r#"{ "unknown": 1 }"#).is_err());
+        assert!(StubPolicy::parse(r#"{ "imports": { "wasi:cli/*": "skip" } }"#).is_err());
+        assert!(StubPolicy::parse(r#"{ "features": ["teleport"] }"#).is_err());
+        assert!(StubPolicy::parse("imports = 1").is_err());
+    }
+
+    #[test]
+    fn behavior_precedence() {
+        let policy = StubPolicy::parse(
+            r#"{
+                "imports": {
+                    "wasi:*": "trap",
+                    "wasi:random/*": "zero",
+                    "wasi:random/random@0.2.3": "error",
+                    "wasi:random/*#get-random-u64": "keep"
+                }
+            }"#,
+        )
+        .unwrap();
+        // function patterns win over interface patterns
+        assert!(policy.keeps("wasi:random/random@0.2.3", "get-random-u64"));
+        // then the most literal pattern, matching the versioned name
+        assert_eq!(
+            policy.behavior("wasi:random/random@0.2.3", "get-random-bytes"),
+            Some(StubBehavior::Error)
+        );
+        // unversioned patterns match every version
+        assert_eq!(
+            policy.behavior("wasi:random/random@0.3.0", "get-random-bytes"),
+            Some(StubBehavior::Zero)
+        );
+        assert_eq!(
+            policy.behavior("wasi:clocks/monotonic-clock@0.2.3", "now"),
+            Some(StubBehavior::Trap)
+        );
+        assert_eq!(policy.behavior("env", "abort"), None);
+    }
+
+    #[test]
+    fn apply_without_memory() {
+        let mut types = wasm_encoder::TypeSection::new();
+        types.ty().function([], [wasm_encoder::ValType::I64]);
+        let mut imports = wasm_encoder::ImportSection::new();
+        imports.import(
+            "wasi:random/random@0.2.3",
+            "get-random-u64",
+            wasm_encoder::EntityType::Function(0),
+        );
+        let mut wasm = wasm_encoder::Module::new();
+        wasm.section(&types).section(&imports);
+        let wasm = wasm.finish();
+
+        let policy = StubPolicy::parse(r#"{ "imports": { "wasi:random/*": "zero" } }"#).unwrap();
+        let mut module = Module::parse(&wasm, false, false).unwrap();
+        let err = apply_stub_policy(&mut module, &policy, &wasm).unwrap_err();
+        assert!(err.to_string().contains("memory"), "{err}");
+    }
+
+    #[test]
+    fn zero_requires_known_return_area() {
+        let mut types = wasm_encoder::TypeSection::new();
+        types.ty().function([wasm_encoder::ValType::I32], []);
+        let mut imports = wasm_encoder::ImportSection::new();
+        imports.import(
+            "wasi:cli/environment@0.2.3",
+            "get-arguments",
+            wasm_encoder::EntityType::Function(0),
+        );
+        let mut memories = wasm_encoder::MemorySection::new();
+        memories.memory(wasm_encoder::MemoryType {
+            minimum: 1,
+            maximum: None,
+            memory64: false,
+            shared: false,
+            page_size_log2: None,
+        });
+        let mut wasm = wasm_encoder::Module::new();
+        wasm.section(&types).section(&imports).section(&memories);
+        let wasm = wasm.finish();
+
+        let policy = StubPolicy::parse(r#"{ "imports": { "wasi:cli/*": "zero" } }"#).unwrap();
+        let mut module = Module::parse(&wasm, false, false).unwrap();
+        let err = apply_stub_policy(&mut module, &policy, &wasm).unwrap_err();
+        assert!(err.to_string().contains("return area"), "{err}");
+    }
+}
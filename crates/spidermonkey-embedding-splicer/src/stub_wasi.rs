@@ -1,24 +1,104 @@
 use std::collections::HashSet;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use anyhow::{Context as _, Result, bail};
 use wirm::ir::function::FunctionBuilder;
-use wirm::ir::id::{FunctionID, LocalID};
+use wirm::ir::id::{DataSegmentID, FunctionID, GlobalID, LocalID, MemoryID};
 use wirm::ir::module::module_functions::FuncKind;
-use wirm::ir::types::{BlockType, InitExpr, Value};
+use wirm::ir::types::{BlockType, DataSegment, DataSegmentKind, InitExpr, Value};
 use wirm::module_builder::AddLocal;
-use wirm::wasmparser::{MemArg, TypeRef};
+use wirm::wasmparser::{MemArg, Parser, Payload, TypeRef};
 use wirm::{DataType, InitInstr, Module, Opcode};
-use wit_parser::Resolve;
+use wit_parser::{PackageId, Resolve};
 
+use crate::environment::synthesize_environment;
+use crate::http_mock::synthesize_http_mock;
 use crate::parse_wit;
+use crate::preview1::{ImportOwner, PREVIEW1_FUNCS};
+use crate::stdio_capture::{
+    stdio_buffer_component_type, synthesize_stdio_buffer, synthesize_stdio_log,
+};
+use crate::stub_policy::{StubBehavior, StubPolicy, apply_stub_policy};
+use crate::validate::{name_stubbed_imports, validate};
+use crate::verify::{FEATURE_INTERFACES, verify_imports};
+use crate::virtual_fs::synthesize_virtual_fs;
 use crate::wit::exports::local::spidermonkey_embedding_splicer::splicer::Feature;
 
-const WASI_VERSIONS: [&str; 5] = ["0.2.0", "0.2.1", "0.2.2", "0.2.3", "0.2.10"];
+/// Prefixes of the WASI versions understood by the stubs, including their
+/// release candidates
+pub(crate) const WASI_VERSION_PREFIXES: [&str; 2] = ["0.2.", "0.3."];
+
+/// Whether `name`, an import module or a world key, is the interface `iface`
+/// at a supported WASI version
+pub(crate) fn is_wasi_interface(name: &str, iface: &str) -> bool {
+    name.split_once('@').is_some_and(|(name, version)| {
+        name == iface
+            && WASI_VERSION_PREFIXES
+                .iter()
+                .any(|prefix| version.starts_with(prefix))
+    })
+}
+
+/// Whether the target world imports `iface`, at any version
+pub(crate) fn world_imports_interface(world_imports: &HashSet<String>, iface: &str) -> bool {
+    world_imports
+        .iter()
+        .any(|import| import.split('@').next() == Some(iface))
+}
+
+/// Feature keeping the WASI interface `iface` whether or not the target world
+/// imports it
+pub(crate) fn interface_feature(iface: &str) -> Option<Feature> {
+    match iface {
+        "wasi:cli/environment" => Some(Feature::Environment),
+        "wasi:cli/exit" => Some(Feature::Exit),
+        _ if iface.starts_with("wasi:cli/terminal-") => Some(Feature::Terminal),
+        _ if iface.starts_with("wasi:filesystem/") => Some(Feature::Filesystem),
+        _ if iface.starts_with("wasi:sockets/") => Some(Feature::Sockets),
+        _ => None,
+    }
+}
+
+/// Whether `iface` is kept, being imported by the target world or owned by an
+/// enabled feature
+pub(crate) fn interface_enabled(
+    features: &[Feature],
+    world_imports: &HashSet<String>,
+    iface: &str,
+) -> bool {
+    world_imports_interface(world_imports, iface)
+        || interface_feature(iface).is_some_and(|feature| features.contains(&feature))
+}
 
-fn stub_wasi_imports<StubFn>(
+/// Whether the module imports a WASI 0.3 interface of `package`, such as
+/// `wasi:filesystem`, which the synthesized implementations do not cover yet
+fn imports_wasi_p3_package(module: &Module, package: &str) -> bool {
+    module.imports.iter().any(|import| {
+        import
+            .module
+            .split_once('@')
+            .is_some_and(|(iface, version)| {
+                iface.split('/').next() == Some(package) && version.starts_with("0.3.")
+            })
+    })
+}
+
+/// Versioned import modules of `iface` in the module, in import order
+fn wasi_import_modules(module: &Module, iface: &str) -> Vec<String> {
+    let mut modules: Vec<String> = vec![];
+    for import in module.imports.iter() {
+        if is_wasi_interface(&import.module, iface) && !modules.iter().any(|m| *m == import.module)
+        {
+            modules.push(import.module.to_string());
+        }
+    }
+    modules
+}
+
+pub(crate) fn stub_wasi_imports<StubFn>(
     module: &mut Module,
+    policy: &StubPolicy,
     import: &str,
     name: &str,
     stub: StubFn,
@@ -26,9 +106,8 @@ fn stub_wasi_imports<StubFn>(
 where
     StubFn: Fn(&mut FunctionBuilder) -> Result<Vec<LocalID>>,
 {
-    for wasi_version in WASI_VERSIONS {
-        let full_import = format!("{import}@{wasi_version}");
-        let Some(iid) = module.imports.find(full_import.parse()?, name.parse()?) else {
+    for full_import in wasi_import_modules(module, import) {
+        let Some(iid) = module.imports.find(full_import.clone(), name.to_string()) else {
             continue;
         };
 
@@ -37,9 +116,12 @@ where
         };
         let fid: FunctionID = FunctionID(*iid);
 
+        // imports kept by the policy or already stubbed by it are left as-is
         let f = module.functions.get(fid);
         let ty_id = match f.kind() {
+            FuncKind::Local(_) if policy.replaces(&full_import, name) => return Ok(Some(fid)),
             FuncKind::Local(_) => bail!("Can't find type of '{full_import}#{name}'"),
+            FuncKind::Import(_) if policy.keeps(&full_import, name) => return Ok(Some(fid)),
             FuncKind::Import(i) => i.ty_id,
         };
 
@@ -62,8 +144,9 @@ where
     Ok(None)
 }
 
-fn stub_import<StubFn>(
+pub(crate) fn stub_import<StubFn>(
     module: &mut Module,
+    policy: &StubPolicy,
     import: &str,
     name: &str,
     stub: StubFn,
@@ -80,9 +163,12 @@ where
     };
     let fid: FunctionID = FunctionID(*iid);
 
+    // imports kept by the policy or already stubbed by it are left as-is
     let f = module.functions.get(fid);
     let ty_id = match f.kind() {
+        FuncKind::Local(_) if policy.replaces(import, name) => return Ok(Some(fid)),
         FuncKind::Local(_) => bail!("Can't find type of '{import}#{name}'"),
+        FuncKind::Import(_) if policy.keeps(import, name) => return Ok(Some(fid)),
         FuncKind::Import(i) => i.ty_id,
     };
 
@@ -103,6 +189,662 @@ where
     Ok(Some(fid))
 }
 
+pub(crate) fn mem(memory: MemoryID, align: u8, offset: u64) -> MemArg {
+    MemArg {
+        align,
+        max_align: 0,
+        offset,
+        memory: *memory,
+    }
+}
+
+/// `() -> i32`: address of `image`, which is stored in a passive data segment
+/// and copied into memory on first use
+pub(crate) fn build_image(module: &mut Module, image: Vec<u8>) -> FunctionID {
+    let memory = module.get_memory_id().unwrap();
+    let realloc = module
+        .exports
+        .get_func_by_name("cabi_realloc".to_string())
+        .unwrap();
+    let image_len = image.len() as i32;
+    let segment: DataSegmentID = module.add_data(DataSegment {
+        kind: DataSegmentKind::Passive,
+        data: image,
+        tag: None,
+    });
+    let base_global: GlobalID = module.add_global(
+        InitExpr::new(vec![InitInstr::Value(Value::I32(0))]),
+        DataType::I32,
+        true,
+        false,
+    );
+
+    let mut body = FunctionBuilder::new(&[], &[DataType::I32]);
+    body.global_get(base_global);
+    body.i32_eqz();
+    body.if_stmt(BlockType::Empty);
+    body.i32_const(0);
+    body.i32_const(0);
+    body.i32_const(8);
+    body.i32_const(image_len);
+    body.call(realloc);
+    body.global_set(base_global);
+    body.global_get(base_global);
+    body.i32_const(0);
+    body.i32_const(image_len);
+    body.memory_init(segment, memory);
+    body.end();
+    body.global_get(base_global);
+    body.finish_module(module)
+}
+
+/// `(a, b, len) -> i32`: compare two byte ranges
+pub(crate) fn build_memeq(module: &mut Module, memory: MemoryID) -> FunctionID {
+    let (a, b, len) = (LocalID(0), LocalID(1), LocalID(2));
+    let mut body = FunctionBuilder::new(
+        &[DataType::I32, DataType::I32, DataType::I32],
+        &[DataType::I32],
+    );
+    let i = body.add_local(DataType::I32);
+    body.loop_stmt(BlockType::Empty);
+    body.local_get(i);
+    body.local_get(len);
+    body.i32_ge_u();
+    body.if_stmt(BlockType::Empty);
+    body.i32_const(1);
+    body.return_stmt();
+    body.end();
+    body.local_get(a);
+    body.local_get(i);
+    body.i32_add();
+    body.i32_load8_u(mem(memory, 0, 0));
+    body.local_get(b);
+    body.local_get(i);
+    body.i32_add();
+    body.i32_load8_u(mem(memory, 0, 0));
+    body.i32_ne();
+    body.if_stmt(BlockType::Empty);
+    body.i32_const(0);
+    body.return_stmt();
+    body.end();
+    body.local_get(i);
+    body.i32_const(1);
+    body.i32_add();
+    body.local_set(i);
+    body.br(0);
+    body.end();
+    body.unreachable();
+    body.finish_module(module)
+}
+
+/// High bits of resource handles created by synthesized functions, which tell
+/// them apart from host handles and from each other
+pub(crate) const HANDLE_TAG_MASK: i32 = 0xF000_0000_u32 as i32;
+/// Tag of the file input streams of the virtual filesystem
+pub(crate) const VFS_TAG: i32 = 0x8000_0000_u32 as i32;
+/// Tag of the captured stdout and stderr output streams
+pub(crate) const STDIO_TAG: i32 = 0x9000_0000_u32 as i32;
+/// Tag of the body streams of mocked HTTP requests and responses
+pub(crate) const HTTP_TAG: i32 = 0xA000_0000_u32 as i32;
+/// Tag of pollables that are always ready
+pub(crate) const READY_TAG: i32 = 0xB000_0000_u32 as i32;
+/// Tag of the pollables of virtual timers, whose state is their `u64` deadline
+pub(crate) const TIMER_TAG: i32 = 0xC000_0000_u32 as i32;
+
+/// Turn the 4-byte aligned address on the stack into a handle with `tag`
+pub(crate) fn emit_tag_address(body: &mut FunctionBuilder, tag: i32) {
+    body.i32_const(2);
+    body.i32_shr_u();
+    body.i32_const(tag);
+    body.i32_or();
+}
+
+/// Turn the tagged handle on the stack back into the address it was made from
+pub(crate) fn emit_untag_address(body: &mut FunctionBuilder) {
+    body.i32_const(!HANDLE_TAG_MASK);
+    body.i32_and();
+    body.i32_const(2);
+    body.i32_shl();
+}
+
+fn import_signature(
+    module: &Module,
+    import: &str,
+    name: &str,
+) -> Result<(Vec<DataType>, Vec<DataType>)> {
+    let iid = module
+        .imports
+        .find(import.to_string(), name.to_string())
+        .with_context(|| format!("missing import '{import}#{name}'"))?;
+    let FuncKind::Import(i) = module.functions.get(FunctionID(*iid)).kind() else {
+        bail!("'{import}#{name}' is not an imported function.")
+    };
+    let ty = module.types.get(i.ty_id).unwrap();
+    Ok((
+        ty.params()
+            .with_context(|| format!("failed to retrieve params for '{import}#{name}'"))?
+            .to_vec(),
+        ty.results()
+            .with_context(|| format!("failed to retrieve results for '{import}#{name}'"))?
+            .to_vec(),
+    ))
+}
+
+/// Imports taking a resource handle as their first parameter, replaced with
+/// functions passing tagged handles to the handler registered for their tag,
+/// and other handles to the host
+///
+/// Several synthesized resources can then share an interface, such as the
+/// `wasi:io/streams` of files and of stdio.
+#[derive(Default)]
+pub(crate) struct Dispatcher {
+    routes: Vec<Route>,
+    /// Whether [`READY_TAG`] pollables are set up
+    ready: bool,
+    /// Versions of `wasi:io/poll#poll` to wrap, with their re-added host import
+    polls: Vec<(String, Option<FunctionID>)>,
+    /// Virtual time in nanoseconds, when [`TIMER_TAG`] pollables are set up
+    clock: Option<GlobalID>,
+}
+
+struct Route {
+    import: String,
+    name: String,
+    /// The re-added host import for untagged handles, if the host provides it
+    host: Option<FunctionID>,
+    handlers: Vec<(i32, FunctionID)>,
+}
+
+/// A versioned import to register a handler of tagged handles for
+pub(crate) struct HandleDispatch {
+    route: usize,
+    tag: i32,
+}
+
+impl Dispatcher {
+    /// Prepare the dispatch of all versions of a WASI import on handles with `tag`
+    ///
+    /// When `keep_host` is set, the host import is re-added to handle untagged
+    /// handles, otherwise they trap. This must happen before the handlers are
+    /// synthesized, as imports must be added before local functions.
+    pub(crate) fn dispatch_handles(
+        &mut self,
+        module: &mut Module,
+        tag: i32,
+        import: &str,
+        name: &str,
+        keep_host: bool,
+    ) -> Result<Vec<HandleDispatch>> {
+        let mut dispatches = vec![];
+        for full_import in wasi_import_modules(module, import) {
+            let Some(iid) = module.imports.find(full_import.clone(), name.to_string()) else {
+                continue;
+            };
+            let TypeRef::Func(_) = module.imports.get(iid).ty else {
+                bail!("'{full_import}#{name}' is not a function.")
+            };
+            let FuncKind::Import(i) = module.functions.get(FunctionID(*iid)).kind() else {
+                // already stubbed by the policy
+                continue;
+            };
+            let ty_id = i.ty_id;
+            let route = match self
+                .routes
+                .iter()
+                .position(|route| route.import == full_import && route.name == name)
+            {
+                Some(route) => route,
+                None => {
+                    self.routes.push(Route {
+                        import: full_import.clone(),
+                        name: name.to_string(),
+                        host: None,
+                        handlers: vec![],
+                    });
+                    self.routes.len() - 1
+                }
+            };
+            if keep_host && self.routes[route].host.is_none() {
+                self.routes[route].host = Some(
+                    module
+                        .add_import_func(full_import, name.to_string(), ty_id)
+                        .0,
+                );
+            }
+            dispatches.push(HandleDispatch { route, tag });
+        }
+        Ok(dispatches)
+    }
+
+    /// Synthesize the handler of a dispatch with `build`, which receives the
+    /// parameters of the import
+    pub(crate) fn handle(
+        &mut self,
+        module: &mut Module,
+        dispatch: HandleDispatch,
+        build: impl FnOnce(&mut FunctionBuilder),
+    ) -> Result<()> {
+        let route = &mut self.routes[dispatch.route];
+        let (params, results) = import_signature(module, &route.import, &route.name)?;
+        let mut body = FunctionBuilder::new(params.as_slice(), results.as_slice());
+        build(&mut body);
+        route
+            .handlers
+            .push((dispatch.tag, body.finish_module(module)));
+        Ok(())
+    }
+
+    /// Make [`READY_TAG`] handles pollables that are always ready, which
+    /// `wasi:io/poll#poll` reports ready along with any other tagged pollable
+    pub(crate) fn ready_pollables(&mut self, module: &mut Module, keep_host: bool) -> Result<()> {
+        if self.ready {
+            return Ok(());
+        }
+        self.ready = true;
+        let mut dispatches = vec![];
+        for name in [
+            "[method]pollable.ready",
+            "[method]pollable.block",
+            "[resource-drop]pollable",
+        ] {
+            for dispatch in
+                self.dispatch_handles(module, READY_TAG, "wasi:io/poll", name, keep_host)?
+            {
+                dispatches.push((name, dispatch));
+            }
+        }
+        for import in wasi_import_modules(module, "wasi:io/poll") {
+            let Some(iid) = module.imports.find(import.clone(), "poll".to_string()) else {
+                continue;
+            };
+            let FuncKind::Import(i) = module.functions.get(FunctionID(*iid)).kind() else {
+                continue;
+            };
+            let ty_id = i.ty_id;
+            let host = keep_host.then(|| {
+                module
+                    .add_import_func(import.clone(), "poll".to_string(), ty_id)
+                    .0
+            });
+            self.polls.push((import, host));
+        }
+
+        for (name, dispatch) in dispatches {
+            self.handle(module, dispatch, |body| {
+                if name == "[method]pollable.ready" {
+                    body.i32_const(1);
+                }
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Make [`TIMER_TAG`] handles pollables ready once the virtual time,
+    /// starting at `start`, reaches their deadline, returning the global holding
+    /// the virtual time
+    ///
+    /// Rather than blocking, `wasi:io/poll#poll` advances the virtual time to the
+    /// earliest deadline of the list when none of its pollables is ready, so that
+    /// timers fire in order without real time passing.
+    pub(crate) fn virtual_clock(
+        &mut self,
+        module: &mut Module,
+        keep_host: bool,
+        start: i64,
+    ) -> Result<GlobalID> {
+        if let Some(clock) = self.clock {
+            return Ok(clock);
+        }
+        self.ready_pollables(module, keep_host)?;
+        let memory = module.get_memory_id().unwrap();
+        let mut dispatches = vec![];
+        for name in [
+            "[method]pollable.ready",
+            "[method]pollable.block",
+            "[resource-drop]pollable",
+        ] {
+            for dispatch in
+                self.dispatch_handles(module, TIMER_TAG, "wasi:io/poll", name, keep_host)?
+            {
+                dispatches.push((name, dispatch));
+            }
+        }
+        let clock = module.add_global(
+            InitExpr::new(vec![InitInstr::Value(Value::I64(start))]),
+            DataType::I64,
+            true,
+            false,
+        );
+        self.clock = Some(clock);
+
+        for (name, dispatch) in dispatches {
+            self.handle(module, dispatch, |body| match name {
+                "[method]pollable.ready" => {
+                    body.local_get(LocalID(0));
+                    emit_untag_address(body);
+                    body.i64_load(mem(memory, 3, 0));
+                    body.global_get(clock);
+                    body.i64_le_u();
+                }
+                "[method]pollable.block" => {
+                    emit_advance_clock(body, clock, |body| {
+                        body.local_get(LocalID(0));
+                        emit_untag_address(body);
+                        body.i64_load(mem(memory, 3, 0));
+                    });
+                }
+                _ => {}
+            })?;
+        }
+        Ok(clock)
+    }
+
+    /// Replace the dispatched imports with functions routing to their handlers
+    pub(crate) fn finish(self, module: &mut Module) -> Result<()> {
+        for (import, host) in self.polls {
+            wrap_poll(module, &import, host, self.clock)?;
+        }
+        for Route {
+            import,
+            name,
+            host,
+            handlers,
+        } in self.routes
+        {
+            let (params, results) = import_signature(module, &import, &name)?;
+            let iid = module
+                .imports
+                .find(import.clone(), name.clone())
+                .expect("dispatched import");
+            let mut body = FunctionBuilder::new(params.as_slice(), results.as_slice());
+            let block_ty = match results.as_slice() {
+                [] => BlockType::Empty,
+                [result] => BlockType::Type(*result),
+                _ => bail!("unsupported results for '{import}#{name}'"),
+            };
+            let forward = |body: &mut FunctionBuilder, target: FunctionID| {
+                for idx in 0..params.len() {
+                    body.local_get(LocalID(idx as u32));
+                }
+                body.call(target);
+            };
+            for (tag, handler) in &handlers {
+                body.local_get(LocalID(0));
+                body.i32_const(HANDLE_TAG_MASK);
+                body.i32_and();
+                body.i32_const(*tag);
+                body.i32_eq();
+                body.if_stmt(block_ty);
+                forward(&mut body, *handler);
+                body.else_stmt();
+            }
+            match host {
+                Some(host) => forward(&mut body, host),
+                None => {
+                    body.unreachable();
+                }
+            }
+            for _ in &handlers {
+                body.end();
+            }
+            body.replace_import_in_module(module, iid)?;
+        }
+        Ok(())
+    }
+}
+
+/// Set the virtual time to the deadline pushed by `deadline` if it is later
+fn emit_advance_clock(
+    body: &mut FunctionBuilder,
+    clock: GlobalID,
+    deadline: impl Fn(&mut FunctionBuilder),
+) {
+    deadline(body);
+    body.global_get(clock);
+    deadline(body);
+    body.global_get(clock);
+    body.i64_gt_u();
+    body.select();
+    body.global_set(clock);
+}
+
+/// Replace `wasi:io/poll#poll` with a function returning the indices of the
+/// ready tagged pollables of the list when there are any, and forwarding the
+/// list to the host otherwise
+///
+/// With a virtual `clock`, the virtual time first advances to the earliest
+/// deadline of the timers of the list when none of its pollables is ready.
+fn wrap_poll(
+    module: &mut Module,
+    import: &str,
+    host: Option<FunctionID>,
+    clock: Option<GlobalID>,
+) -> Result<()> {
+    let memory = module.get_memory_id().unwrap();
+    let realloc = module
+        .exports
+        .get_func_by_name("cabi_realloc".to_string())
+        .unwrap();
+    let iid = module
+        .imports
+        .find(import.to_string(), "poll".to_string())
+        .expect("wrapped poll import");
+
+    // (func (param i32 i32 i32))
+    let (list, len, retptr) = (LocalID(0), LocalID(1), LocalID(2));
+    let mut body = FunctionBuilder::new(&[DataType::I32, DataType::I32, DataType::I32], &[]);
+    let i = body.add_local(DataType::I32);
+    let count = body.add_local(DataType::I32);
+    let out = body.add_local(DataType::I32);
+    let handle = body.add_local(DataType::I32);
+    let earliest = body.add_local(DataType::I64);
+    // run `each` for the pollables of the list whose handle `is` selects, with
+    // their index in `i` and their handle in `handle`
+    let for_each = |body: &mut FunctionBuilder,
+                    is: &dyn Fn(&mut FunctionBuilder),
+                    each: &dyn Fn(&mut FunctionBuilder)| {
+        body.i32_const(0);
+        body.local_set(i);
+        body.block(BlockType::Empty);
+        body.loop_stmt(BlockType::Empty);
+        body.local_get(i);
+        body.local_get(len);
+        body.i32_ge_u();
+        body.br_if(1);
+        body.local_get(list);
+        body.local_get(i);
+        body.i32_const(2);
+        body.i32_shl();
+        body.i32_add();
+        body.i32_load(mem(memory, 2, 0));
+        body.local_set(handle);
+        is(body);
+        body.if_stmt(BlockType::Empty);
+        each(body);
+        body.end();
+        body.local_get(i);
+        body.i32_const(1);
+        body.i32_add();
+        body.local_set(i);
+        body.br(0);
+        body.end();
+        body.end();
+    };
+    let deadline = |body: &mut FunctionBuilder| {
+        body.local_get(handle);
+        emit_untag_address(body);
+        body.i64_load(mem(memory, 3, 0));
+    };
+    let is_timer = |body: &mut FunctionBuilder| {
+        body.local_get(handle);
+        body.i32_const(HANDLE_TAG_MASK);
+        body.i32_and();
+        body.i32_const(TIMER_TAG);
+        body.i32_eq();
+    };
+    // tagged, and past its deadline for a timer
+    let is_ready = |body: &mut FunctionBuilder| {
+        body.local_get(handle);
+        body.i32_const(HANDLE_TAG_MASK);
+        body.i32_and();
+        if let Some(clock) = clock {
+            body.if_stmt(BlockType::Type(DataType::I32));
+            is_timer(body);
+            body.if_stmt(BlockType::Type(DataType::I32));
+            deadline(body);
+            body.global_get(clock);
+            body.i64_le_u();
+            body.else_stmt();
+            body.i32_const(1);
+            body.end();
+            body.else_stmt();
+            body.i32_const(0);
+            body.end();
+        }
+    };
+    let count_ready = |body: &mut FunctionBuilder| {
+        body.i32_const(0);
+        body.local_set(count);
+        for_each(body, &is_ready, &|body| {
+            body.local_get(count);
+            body.i32_const(1);
+            body.i32_add();
+            body.local_set(count);
+        });
+    };
+
+    count_ready(&mut body);
+    if let Some(clock) = clock {
+        body.local_get(count);
+        body.i32_eqz();
+        body.if_stmt(BlockType::Empty);
+        body.i64_const(-1);
+        body.local_set(earliest);
+        for_each(&mut body, &is_timer, &|body| {
+            deadline(body);
+            body.local_get(earliest);
+            deadline(body);
+            body.local_get(earliest);
+            body.i64_lt_u();
+            body.select();
+            body.local_set(earliest);
+        });
+        body.local_get(earliest);
+        body.i64_const(-1);
+        body.i64_ne();
+        body.if_stmt(BlockType::Empty);
+        emit_advance_clock(&mut body, clock, |body| {
+            body.local_get(earliest);
+        });
+        count_ready(&mut body);
+        body.end();
+        body.end();
+    }
+    body.local_get(count);
+    body.i32_eqz();
+    body.if_stmt(BlockType::Empty);
+    match host {
+        Some(host) => {
+            body.local_get(list);
+            body.local_get(len);
+            body.local_get(retptr);
+            body.call(host);
+        }
+        None => {
+            body.unreachable();
+        }
+    }
+    body.return_stmt();
+    body.end();
+
+    body.i32_const(0);
+    body.i32_const(0);
+    body.i32_const(4);
+    body.local_get(count);
+    body.i32_const(2);
+    body.i32_shl();
+    body.call(realloc);
+    body.local_set(out);
+    body.local_get(retptr);
+    body.local_get(out);
+    body.i32_store(mem(memory, 2, 0));
+    body.local_get(retptr);
+    body.local_get(count);
+    body.i32_store(mem(memory, 2, 4));
+    body.i32_const(0);
+    body.local_set(count);
+    for_each(&mut body, &is_ready, &|body| {
+        body.local_get(out);
+        body.local_get(count);
+        body.i32_const(2);
+        body.i32_shl();
+        body.i32_add();
+        body.local_get(i);
+        body.i32_store(mem(memory, 2, 0));
+        body.local_get(count);
+        body.i32_const(1);
+        body.i32_add();
+        body.local_set(count);
+    });
+    body.replace_import_in_module(module, iid)?;
+    Ok(())
+}
+
+/// Rules stubbing the imports of the features that are not enabled, except for
+/// the interfaces the target world imports
+///
+/// WASI interfaces trap, and the preview1 functions trap or succeed as listed in
+/// [`PREVIEW1_FUNCS`]. Only `wasi:http/outgoing-handler` belongs to the `http`
+/// feature alone: `wasi:http/types` is left in place when `fetch-event` is
+/// enabled, for StarlingMonkey's implementation of `FetchEvent` to use.
+fn feature_presets(
+    module: &Module,
+    features: &[Feature],
+    has_io: bool,
+    world_imports: &HashSet<String>,
+) -> Vec<(String, StubBehavior)> {
+    let mut presets = vec![];
+    for (name, owner, behavior) in PREVIEW1_FUNCS {
+        if !owner.enabled(features, has_io, world_imports) {
+            presets.push((format!("{PREVIEW1}#{name}"), behavior));
+        }
+    }
+
+    let mut modules: Vec<&str> = vec![];
+    for import in module.imports.iter() {
+        if !modules.contains(&&*import.module) {
+            modules.push(&import.module);
+        }
+    }
+    for module_name in modules {
+        let Some((iface, version)) = module_name.split_once('@') else {
+            continue;
+        };
+        if !iface.starts_with("wasi:")
+            || !WASI_VERSION_PREFIXES
+                .iter()
+                .any(|prefix| version.starts_with(prefix))
+        {
+            continue;
+        }
+        let owner = FEATURE_INTERFACES
+            .iter()
+            .find(|(known, _)| *known == iface)
+            .map(|(_, owner)| *owner)
+            .or(interface_feature(iface).map(ImportOwner::Feature));
+        let enabled = world_imports_interface(world_imports, iface)
+            || match owner {
+                Some(owner) => owner.enabled(features, has_io, world_imports),
+                // unknown WASI 0.2 interfaces are left for verification to report
+                None => !version.starts_with("0.3."),
+            };
+        if !enabled {
+            presets.push((module_name.to_string(), StubBehavior::Trap));
+        }
+    }
+    presets
+}
+
 fn unreachable_stub(body: &mut FunctionBuilder) -> Result<Vec<LocalID>> {
     body.unreachable();
     Ok(vec![])
@@ -114,6 +856,8 @@ pub fn stub_wasi(
     wit_source: Option<String>,
     wit_path: Option<String>,
     world_name: Option<String>,
+    policy: Option<String>,
+    policy_dir: Option<String>,
 ) -> Result<Vec<u8>> {
     let (resolve, ids) = if let Some(wit_source) = wit_source {
         let mut resolve = Resolve::default();
@@ -125,6 +869,42 @@ pub fn stub_wasi(
         parse_wit(PathBuf::from(wit_path.unwrap()))?
     };
 
+    stub_wasi_with_resolve(
+        wasm,
+        features,
+        &resolve,
+        ids,
+        world_name,
+        policy,
+        policy_dir.as_deref().map(Path::new),
+    )
+}
+
+/// Stub the WASI imports of an engine against a world of an already resolved
+/// WIT package
+///
+/// The relative paths of the policy are resolved against `policy_dir`, or
+/// against the current directory without it.
+pub fn stub_wasi_with_resolve(
+    wasm: Vec<u8>,
+    features: Vec<Feature>,
+    resolve: &Resolve,
+    ids: PackageId,
+    world_name: Option<String>,
+    policy: Option<String>,
+    policy_dir: Option<&Path>,
+) -> Result<Vec<u8>> {
+    let mut policy = policy
+        .as_deref()
+        .map(StubPolicy::parse)
+        .transpose()?
+        .unwrap_or_default();
+    if let (Some(virtual_fs), Some(policy_dir)) = (&mut policy.virtual_fs, policy_dir) {
+        virtual_fs.dir = policy_dir.join(&virtual_fs.dir);
+    }
+    // a policy that lists features replaces the features presets
+    let features = policy.features.clone().unwrap_or(features);
+
     let world = resolve.select_world(&[ids], world_name.as_deref())?;
 
     let target_world = &resolve.worlds[world];
@@ -136,59 +916,176 @@ pub fn stub_wasi(
 
     let mut module = Module::parse(wasm.as_slice(), false, false).unwrap();
 
-    stub_preview1(&mut module)?;
+    apply_stub_policy(&mut module, &policy, &wasm)?;
+
+    let has_io = features.contains(&Feature::Clocks)
+        || features.contains(&Feature::Stdio)
+        || features.contains(&Feature::Http)
+        || features.contains(&Feature::Filesystem)
+        || features.contains(&Feature::Sockets)
+        || features.contains(&Feature::Terminal)
+        || target_world_requires_io(&target_world_imports);
+
+    let mut dispatcher = Dispatcher::default();
+
+    if let Some(vfs) = &policy.virtual_fs {
+        if features.contains(&Feature::Filesystem) {
+            bail!("a virtual filesystem requires the filesystem feature to be disabled");
+        }
+        if world_imports_interface(&target_world_imports, "wasi:filesystem/types")
+            || world_imports_interface(&target_world_imports, "wasi:filesystem/preopens")
+        {
+            bail!(
+                "a virtual filesystem cannot be embedded when the target world imports wasi:filesystem"
+            );
+        }
+        if imports_wasi_p3_package(&module, "wasi:filesystem") {
+            bail!("a virtual filesystem can only be embedded in engines importing WASI 0.2");
+        }
+        synthesize_virtual_fs(&mut module, &mut dispatcher, &policy, vfs, has_io)?;
+    }
 
-    stub_filesystem(&mut module, &target_world_imports)?;
-    stub_cli(&mut module, &target_world_imports)?;
+    if let Some(env) = &policy.environment {
+        if features.contains(&Feature::Environment) {
+            bail!("an environment can only be baked in with the environment feature disabled");
+        }
+        if world_imports_interface(&target_world_imports, "wasi:cli/environment") {
+            bail!(
+                "an environment cannot be baked in when the target world imports wasi:cli/environment"
+            );
+        }
+        synthesize_environment(&mut module, &policy, env)?;
+    }
 
     if !features.contains(&Feature::Random) {
-        stub_random(&mut module)?;
+        stub_random(&mut module, &policy)?;
     }
 
     if !features.contains(&Feature::Clocks) {
-        stub_clocks(&mut module)?;
+        stub_clocks(&mut module, &mut dispatcher, &policy, has_io)?;
     }
 
     if !features.contains(&Feature::Stdio) {
-        stub_stdio(&mut module)?;
+        if (policy.stdio_buffer.is_some() || policy.stdio_log.is_some())
+            && imports_wasi_p3_package(&module, "wasi:cli")
+        {
+            bail!("console output can only be captured in engines importing WASI 0.2");
+        }
+        match (&policy.stdio_buffer, &policy.stdio_log) {
+            (Some(_), Some(_)) => bail!("a stdio buffer and a stdio log cannot be combined"),
+            (Some(buffer), None) => {
+                synthesize_stdio_buffer(&mut module, &mut dispatcher, &policy, buffer, has_io)?;
+            }
+            (None, Some(log)) => {
+                synthesize_stdio_log(
+                    &mut module,
+                    &mut dispatcher,
+                    &policy,
+                    log,
+                    resolve,
+                    world,
+                    has_io,
+                )?;
+            }
+            (None, None) => {}
+        }
+        let captured = policy.stdio_buffer.is_some() || policy.stdio_log.is_some();
+        stub_stdio(&mut module, &policy, captured)?;
+    } else if policy.stdio_buffer.is_some() || policy.stdio_log.is_some() {
+        bail!("capturing console output requires the stdio feature to be disabled");
     }
 
     match (
         features.contains(&Feature::Http),
         features.contains(&Feature::FetchEvent),
     ) {
-        // If both are disabled, then disable all HTTP related imports
+        // If both are disabled, then all HTTP related imports can be mocked
         (false, false) => {
-            stub_http_types(&mut module)?;
-            stub_http_outgoing(&mut module)?;
+            if let Some(mock) = &policy.http_mock {
+                if imports_wasi_p3_package(&module, "wasi:http") {
+                    bail!("HTTP responses can only be mocked in engines importing WASI 0.2");
+                }
+                synthesize_http_mock(&mut module, &mut dispatcher, &policy, mock, has_io)?;
+            }
         }
-        // If HTTP is disabled but fetch-event is enabled we want to stub only the `wasi:http/outgoing-handler`
-        // and leave the rest of `wasi:http/types` in place for StarlingMonkey's implementation of `FetchEvent` to use.
-        //
-        // Note that we cannot *know* that the user will make use of fetch-event, but we must be prepared
-        // for it, as the feature is enabled.
-        (false, true) => {
-            stub_http_outgoing(&mut module)?;
+        _ if policy.http_mock.is_some() => {
+            bail!(
+                "mocking HTTP responses requires the http and fetch-event features to be disabled"
+            )
         }
-        // For all other cases we can avoid stubbing
         _ => {}
     }
 
-    let has_io = features.contains(&Feature::Clocks)
-        || features.contains(&Feature::Stdio)
-        || features.contains(&Feature::Http)
-        || target_world_requires_io(&target_world_imports);
-    if !has_io {
-        stub_io(&mut module)?;
-    }
+    dispatcher.finish(&mut module)?;
+
+    // the presets of the disabled features stub what remains, under the rules
+    // of the policy
+    policy.presets = feature_presets(&module, &features, has_io, &target_world_imports);
+    apply_stub_policy(&mut module, &policy, &wasm)?;
 
-    stub_sockets(&mut module, &target_world_imports)?;
-    let encoded = module
+    name_stubbed_imports(&mut module);
+    let mut encoded = module
         .encode()
         .context("failed to encode module during stub")?;
+    if policy.virtual_fs.is_some() || policy.environment.is_some() || policy.http_mock.is_some() {
+        encoded = ensure_data_count(encoded)?;
+    }
+    if let Some(mode) = policy.verify {
+        verify_imports(
+            &encoded,
+            mode,
+            &policy,
+            &features,
+            has_io,
+            &target_world_imports,
+        )?;
+    }
+    if policy.stdio_buffer.is_some() {
+        encoded.extend(stdio_buffer_component_type()?);
+    }
+    validate(&encoded, "stub_wasi")?;
     Ok(encoded)
 }
 
+/// Insert a data count section if the module lacks one, as required to validate
+/// `memory.init` instructions on synthesized passive data segments
+pub(crate) fn ensure_data_count(wasm: Vec<u8>) -> Result<Vec<u8>> {
+    let mut sections = vec![];
+    let mut data_count = None;
+    for payload in Parser::new(0).parse_all(&wasm) {
+        let payload = payload?;
+        match &payload {
+            Payload::DataCountSection { .. } => {
+                data_count = None;
+                break;
+            }
+            Payload::DataSection(reader) => data_count = Some(reader.count()),
+            _ => {}
+        }
+        if let Some((id, range)) = payload.as_section() {
+            sections.push((id, range));
+        }
+    }
+    let Some(count) = data_count else {
+        return Ok(wasm);
+    };
+
+    let mut out = wasm_encoder::Module::new();
+    for (id, range) in sections {
+        // the data count section goes right before the code section
+        if id == wasm_encoder::SectionId::Code as u8 {
+            out.section(&wasm_encoder::DataCountSection { count });
+        }
+        out.section(&wasm_encoder::RawSection {
+            id,
+            data: &wasm[range],
+        });
+    }
+    Ok(out.finish())
+}
+
+// wasi:io is gone from WASI 0.3, so only the 0.2 interfaces built on its
+// streams and pollables require it
 fn target_world_requires_io(target_world_imports: &HashSet<String>) -> bool {
     target_world_imports.contains("wasi:sockets/instance-network@0.2")
         || target_world_imports.contains("wasi:sockets/udp@0.2")
@@ -206,14 +1103,8 @@ fn target_world_requires_io(target_world_imports: &HashSet<String>) -> bool {
         || target_world_imports.contains("wasi:cli/terminal-output@0.2")
 }
 
-const PREVIEW1: &str = "wasi_snapshot_preview1";
-fn stub_preview1(module: &mut Module) -> Result<()> {
-    // random comes from prevew2 only in StarlingMonkey
-    stub_import(module, PREVIEW1, "random_get", unreachable_stub)?;
-    Ok(())
-}
-
-fn stub_random(module: &mut Module) -> Result<()> {
+pub(crate) const PREVIEW1: &str = "wasi_snapshot_preview1";
+fn stub_random(module: &mut Module, policy: &StubPolicy) -> Result<()> {
     let memory = module.get_memory_id().unwrap();
     let realloc = module
         .exports
@@ -229,106 +1120,121 @@ fn stub_random(module: &mut Module) -> Result<()> {
         false,
     );
 
-    let random_u64 = stub_wasi_imports(module, "wasi:random/random", "get-random-u64", |func| {
-        func.global_get(seed_global);
-        func.i64_const(-0x5F89E29B87429BD1);
-        func.i64_add();
-        func.global_set(seed_global);
-        func.global_get(seed_global);
-        func.global_get(seed_global);
-        func.i64_const(-0x18FC812E5F4BD725);
-        func.i64_xor();
-        func.i64_mul();
-        Ok(vec![])
-    })?
+    let random_u64 = stub_wasi_imports(
+        module,
+        policy,
+        "wasi:random/random",
+        "get-random-u64",
+        |func| {
+            func.global_get(seed_global);
+            func.i64_const(-0x5F89E29B87429BD1);
+            func.i64_add();
+            func.global_set(seed_global);
+            func.global_get(seed_global);
+            func.global_get(seed_global);
+            func.i64_const(-0x18FC812E5F4BD725);
+            func.i64_xor();
+            func.i64_mul();
+            Ok(vec![])
+        },
+    )?
     .expect("get-random-u64 not found");
 
-    stub_wasi_imports(module, "wasi:random/random", "get-random-bytes", |body| {
-        // let num_bytes = body.add_local(DataType::I64);
-        let num_bytes: LocalID = LocalID(0); // First parameter
-        let retptr: LocalID = LocalID(1); // Second parametr
-        let outptr = body.add_local(DataType::I32);
-        let curptr = body.add_local(DataType::I32);
-        // carries through to *retptr = outptr
-        body.local_get(retptr);
-
-        // outptr = realloc(0, 0, 1, len rounded up to 8 bytes)
-        body.i32_const(0);
-        body.i32_const(0);
-        body.i32_const(1);
-        body.local_get(num_bytes);
-        body.i32_wrap_i64();
-        body.i32_const(3);
-        body.i32_shr_u();
-        body.i32_const(3);
-        body.i32_shl();
-        body.i32_const(8);
-        body.i32_add();
-        body.call(realloc);
-
-        body.local_tee(outptr);
-
-        // *retptr = outptr
-        // *retptr + 1 = len
-        body.i32_store(MemArg {
-            align: 2,
-            max_align: 0,
-            offset: 0,
-            memory: *memory,
-        });
-
-        body.local_get(retptr);
-        body.local_get(num_bytes);
-        body.i32_wrap_i64();
-        body.i32_store(MemArg {
-            align: 2,
-            max_align: 0,
-            offset: 4,
-            memory: *memory,
-        });
-        body.local_get(outptr);
-        body.local_set(curptr);
-
-        // store random bytes, we allocated a multiple of 8 bytes at the
-        // start, so we do that exact multiple, while returning a shorter
-        // list
-        body.loop_stmt(BlockType::Empty);
-        body.local_get(curptr);
-        body.call(random_u64);
-        body.i64_store(MemArg {
-            align: 3,
-            max_align: 0,
-            offset: 0,
-            memory: *memory,
-        });
-        body.local_get(curptr);
-        body.i32_const(8);
-        body.i32_add();
-        body.local_tee(curptr);
-        body.local_get(outptr);
-        body.i32_sub();
-        body.local_get(num_bytes);
-        body.i32_wrap_i64();
-        body.i32_lt_u();
-        body.br_if(0);
-        body.end(); // This is for the loop
-        Ok(vec![num_bytes, retptr])
-    })?;
-
     stub_wasi_imports(
         module,
+        policy,
+        "wasi:random/random",
+        "get-random-bytes",
+        |body| {
+            // let num_bytes = body.add_local(DataType::I64);
+            let num_bytes: LocalID = LocalID(0); // First parameter
+            let retptr: LocalID = LocalID(1); // Second parametr
+            let outptr = body.add_local(DataType::I32);
+            let curptr = body.add_local(DataType::I32);
+            // carries through to *retptr = outptr
+            body.local_get(retptr);
+
+            // outptr = realloc(0, 0, 1, len rounded up to 8 bytes)
+            body.i32_const(0);
+            body.i32_const(0);
+            body.i32_const(1);
+            body.local_get(num_bytes);
+            body.i32_wrap_i64();
+            body.i32_const(3);
+            body.i32_shr_u();
+            body.i32_const(3);
+            body.i32_shl();
+            body.i32_const(8);
+            body.i32_add();
+            body.call(realloc);
+
+            body.local_tee(outptr);
+
+            // *retptr = outptr
+            // *retptr + 1 = len
+            body.i32_store(MemArg {
+                align: 2,
+                max_align: 0,
+                offset: 0,
+                memory: *memory,
+            });
+
+            body.local_get(retptr);
+            body.local_get(num_bytes);
+            body.i32_wrap_i64();
+            body.i32_store(MemArg {
+                align: 2,
+                max_align: 0,
+                offset: 4,
+                memory: *memory,
+            });
+            body.local_get(outptr);
+            body.local_set(curptr);
+
+            // store random bytes, we allocated a multiple of 8 bytes at the
+            // start, so we do that exact multiple, while returning a shorter
+            // list
+            body.loop_stmt(BlockType::Empty);
+            body.local_get(curptr);
+            body.call(random_u64);
+            body.i64_store(MemArg {
+                align: 3,
+                max_align: 0,
+                offset: 0,
+                memory: *memory,
+            });
+            body.local_get(curptr);
+            body.i32_const(8);
+            body.i32_add();
+            body.local_tee(curptr);
+            body.local_get(outptr);
+            body.i32_sub();
+            body.local_get(num_bytes);
+            body.i32_wrap_i64();
+            body.i32_lt_u();
+            body.br_if(0);
+            body.end(); // This is for the loop
+            Ok(vec![num_bytes, retptr])
+        },
+    )?;
+
+    stub_wasi_imports(
+        module,
+        policy,
         "wasi:random/insecure",
         "get-insecure-random-bytes",
         unreachable_stub,
     )?;
     stub_wasi_imports(
         module,
+        policy,
         "wasi:random/insecure",
         "get-insecure-random-u64",
         unreachable_stub,
     )?;
     stub_wasi_imports(
         module,
+        policy,
         "wasi:random/insecure-seed",
         "insecure-seed",
         unreachable_stub,
@@ -336,16 +1242,28 @@ fn stub_random(module: &mut Module) -> Result<()> {
     Ok(())
 }
 
-fn stub_clocks(module: &mut Module) -> Result<()> {
+/// Stub the clocks with the time at build time, and timers with traps, or with
+/// virtual timers that become ready without real time passing when the policy
+/// enables virtual time
+fn stub_clocks(
+    module: &mut Module,
+    dispatcher: &mut Dispatcher,
+    policy: &StubPolicy,
+    has_io: bool,
+) -> Result<()> {
     let memory = module.get_memory_id().unwrap();
-    stub_import(module, PREVIEW1, "clock_res_get", unreachable_stub)?;
+    let realloc = module
+        .exports
+        .get_func_by_name("cabi_realloc".to_string())
+        .unwrap();
+    stub_import(module, policy, PREVIEW1, "clock_res_get", unreachable_stub)?;
 
     // stub the time with the current time at build time
     let time = SystemTime::now();
     let unix_time = time.duration_since(UNIX_EPOCH)?;
 
     // (func (param i32 i64 i32) (result i32)))
-    stub_import(module, PREVIEW1, "clock_time_get", |body| {
+    stub_import(module, policy, PREVIEW1, "clock_time_get", |body| {
         let clock_id: LocalID = LocalID(0); // First Parameter
         let precision: LocalID = LocalID(1); // Second Parameter
         let time_ptr: LocalID = LocalID(2); // Third Parameter
@@ -361,1248 +1279,120 @@ fn stub_clocks(module: &mut Module) -> Result<()> {
         Ok(vec![clock_id, precision, time_ptr])
     })?;
 
-    stub_wasi_imports(module, "wasi:clocks/monotonic-clock", "now", |body| {
-        body.i64_const(i64::try_from(unix_time.as_nanos())?);
-        Ok(vec![])
-    })?;
-    stub_wasi_imports(
-        module,
-        "wasi:clocks/monotonic-clock",
-        "subscribe-instant",
-        unreachable_stub,
-    )?;
+    if policy.virtual_time {
+        // the monotonic clock starts at the build time and advances with timers
+        let clock =
+            dispatcher.virtual_clock(module, has_io, i64::try_from(unix_time.as_nanos())?)?;
+        stub_wasi_imports(
+            module,
+            policy,
+            "wasi:clocks/monotonic-clock",
+            "now",
+            |body| {
+                body.global_get(clock);
+                Ok(vec![])
+            },
+        )?;
+        for (name, relative) in [("subscribe-instant", false), ("subscribe-duration", true)] {
+            // (func (param i64) (result i32))
+            stub_wasi_imports(
+                module,
+                policy,
+                "wasi:clocks/monotonic-clock",
+                name,
+                |body| {
+                    let deadline = body.add_local(DataType::I32);
+                    body.i32_const(0);
+                    body.i32_const(0);
+                    body.i32_const(8);
+                    body.i32_const(8);
+                    body.call(realloc);
+                    body.local_tee(deadline);
+                    body.local_get(LocalID(0));
+                    if relative {
+                        body.global_get(clock);
+                        body.i64_add();
+                    }
+                    body.i64_store(mem(memory, 3, 0));
+                    body.local_get(deadline);
+                    emit_tag_address(body, TIMER_TAG);
+                    Ok(vec![])
+                },
+            )?;
+        }
+    } else {
+        // the monotonic clock is fixed at the build time, and timers trap
+        stub_wasi_imports(
+            module,
+            policy,
+            "wasi:clocks/monotonic-clock",
+            "now",
+            |body| {
+                body.i64_const(i64::try_from(unix_time.as_nanos())?);
+                Ok(vec![])
+            },
+        )?;
+        for name in ["subscribe-instant", "subscribe-duration"] {
+            stub_wasi_imports(
+                module,
+                policy,
+                "wasi:clocks/monotonic-clock",
+                name,
+                unreachable_stub,
+            )?;
+        }
+    }
     stub_wasi_imports(
         module,
+        policy,
         "wasi:clocks/monotonic-clock",
         "resolution",
         unreachable_stub,
     )?;
     stub_wasi_imports(
         module,
-        "wasi:clocks/monotonic-clock",
-        "subscribe-duration",
+        policy,
+        "wasi:clocks/wall-clock",
+        "now",
         unreachable_stub,
     )?;
-    stub_wasi_imports(module, "wasi:clocks/wall-clock", "now", unreachable_stub)?;
     stub_wasi_imports(
         module,
+        policy,
         "wasi:clocks/wall-clock",
         "resolution",
         unreachable_stub,
     )?;
+    // WASI 0.3 renames the wall clock
+    for name in ["now", "get-resolution"] {
+        stub_wasi_imports(
+            module,
+            policy,
+            "wasi:clocks/system-clock",
+            name,
+            unreachable_stub,
+        )?;
+    }
 
     Ok(())
 }
 
-fn stub_stdio(module: &mut Module) -> Result<()> {
+/// Stub the preview1 stdio functions, `fd_write` being implemented by the
+/// capture of the console output when there is one
+fn stub_stdio(module: &mut Module, policy: &StubPolicy, captured: bool) -> Result<()> {
     // (func (param i32 i32) (result i32)))
-    stub_import(module, PREVIEW1, "fd_fdstat_get", |body| {
+    stub_import(module, policy, PREVIEW1, "fd_fdstat_get", |body| {
         body.i32_const(0);
         Ok(vec![])
     })?;
 
-    // (func (param i32 i32 i32 i32) (result i32)))
-    stub_import(module, PREVIEW1, "fd_write", |body| {
-        let len_local: LocalID = LocalID(3); // Index of the last local
-        body.local_get(len_local);
-        Ok(vec![len_local])
-    })?;
-
-    stub_wasi_imports(module, "wasi:cli/stdin", "get-stdin", unreachable_stub)?;
-    stub_wasi_imports(module, "wasi:cli/stdout", "get-stdout", unreachable_stub)?;
-    stub_wasi_imports(module, "wasi:cli/stderr", "get-stderr", unreachable_stub)?;
-    Ok(())
-}
-
-fn stub_http_outgoing(module: &mut Module) -> Result<()> {
-    stub_wasi_imports(
-        module,
-        "wasi:http/outgoing-handler",
-        "handle",
-        unreachable_stub,
-    )?;
-    Ok(())
-}
-
-fn stub_http_types(module: &mut Module) -> Result<()> {
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "http-error-code",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[static]fields.from-list",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]fields.has",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]outgoing-request.method",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]outgoing-request.path-with-query",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]outgoing-request.scheme",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]outgoing-request.authority",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]outgoing-request.headers",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[constructor]request-options",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]request-options.connect-timeout",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]request-options.set-connect-timeout",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]request-options.first-byte-timeout",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]request-options.set-first-byte-timeout",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]request-options.between-bytes-timeout",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]request-options.set-between-bytes-timeout",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[static]incoming-body.finish",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]future-trailers.subscribe",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]future-trailers.get",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]outgoing-response.status-code",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[resource-drop]incoming-request",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[resource-drop]outgoing-request",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[resource-drop]request-options",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[resource-drop]response-outparam",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[resource-drop]incoming-response",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[resource-drop]incoming-body",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[resource-drop]future-trailers",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[resource-drop]outgoing-response",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[resource-drop]outgoing-body",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[resource-drop]future-incoming-response",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[resource-drop]fields",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[constructor]fields",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]fields.get",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]fields.set",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]fields.delete",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]fields.append",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]fields.entries",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]fields.clone",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]incoming-request.method",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]incoming-request.path-with-query",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]incoming-request.scheme",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]incoming-request.authority",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]incoming-request.headers",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]incoming-request.consume",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[constructor]outgoing-request",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]outgoing-request.body",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]outgoing-request.set-method",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]outgoing-request.set-path-with-query",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]outgoing-request.set-scheme",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]outgoing-request.set-authority",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[static]response-outparam.set",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]incoming-response.status",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]incoming-response.headers",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]incoming-response.consume",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]incoming-body.stream",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[constructor]outgoing-response",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]outgoing-response.set-status-code",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]outgoing-response.headers",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]outgoing-response.body",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]outgoing-body.write",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[static]outgoing-body.finish",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]future-incoming-response.subscribe",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:http/types",
-        "[method]future-incoming-response.get",
-        unreachable_stub,
-    )?;
-    Ok(())
-}
-
-fn stub_io(module: &mut Module) -> Result<()> {
-    stub_wasi_imports(
-        module,
-        "wasi:io/poll",
-        "[method]pollable.ready",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:io/poll",
-        "[method]pollable.block",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:io/error",
-        "[method]error.to-debug-string",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:io/streams",
-        "[method]input-stream.blocking-read",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:io/streams",
-        "[method]input-stream.skip",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:io/streams",
-        "[method]input-stream.blocking-skip",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:io/streams",
-        "[method]output-stream.blocking-write-and-flush",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:io/streams",
-        "[method]output-stream.flush",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:io/streams",
-        "[method]output-stream.write-zeroes",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:io/streams",
-        "[method]output-stream.blocking-write-zeroes-and-flush",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:io/streams",
-        "[method]output-stream.splice",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:io/streams",
-        "[method]output-stream.blocking-splice",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:io/error",
-        "[resource-drop]error",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:io/poll",
-        "[resource-drop]pollable",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:io/streams",
-        "[resource-drop]input-stream",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:io/streams",
-        "[resource-drop]output-stream",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(module, "wasi:io/poll", "poll", unreachable_stub)?;
-    stub_wasi_imports(
-        module,
-        "wasi:io/streams",
-        "[method]input-stream.read",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:io/streams",
-        "[method]input-stream.subscribe",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:io/streams",
-        "[method]output-stream.check-write",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:io/streams",
-        "[method]output-stream.write",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:io/streams",
-        "[method]output-stream.blocking-flush",
-        unreachable_stub,
-    )?;
-    stub_wasi_imports(
-        module,
-        "wasi:io/streams",
-        "[method]output-stream.subscribe",
-        unreachable_stub,
-    )?;
-    Ok(())
-}
-
-fn stub_sockets(module: &mut Module, world_imports: &HashSet<String>) -> Result<()> {
-    if !world_imports.contains("wasi:sockets/instance-network@0.2") {
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/instance-network",
-            "instance-network",
-            unreachable_stub,
-        )?;
-    }
-
-    if !world_imports.contains("wasi:sockets/udp@0.2") {
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/udp",
-            "[method]udp-socket.start-bind",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/udp",
-            "[method]udp-socket.finish-bind",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/udp",
-            "[method]udp-socket.stream",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/udp",
-            "[method]udp-socket.local-address",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/udp",
-            "[method]udp-socket.remote-address",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/udp",
-            "[method]udp-socket.address-family",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/udp",
-            "[method]udp-socket.unicast-hop-limit",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/udp",
-            "[method]udp-socket.set-unicast-hop-limit",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/udp",
-            "[method]udp-socket.receive-buffer-size",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/udp",
-            "[method]udp-socket.set-receive-buffer-size",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/udp",
-            "[method]udp-socket.send-buffer-size",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/udp",
-            "[method]udp-socket.set-send-buffer-size",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/udp",
-            "[method]udp-socket.subscribe",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/udp",
-            "[method]incoming-datagram-stream.receive",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/udp",
-            "[method]incoming-datagram-stream.subscribe",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/udp",
-            "[method]outgoing-datagram-stream.check-send",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/udp",
-            "[method]outgoing-datagram-stream.send",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/udp",
-            "[method]outgoing-datagram-stream.subscribe",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/udp",
-            "[resource-drop]udp-socket",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/udp",
-            "[resource-drop]incoming-datagram-stream",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/udp",
-            "[resource-drop]outgoing-datagram-stream",
-            unreachable_stub,
-        )?;
-    }
-
-    if !world_imports.contains("wasi:sockets/udp-create-socket@0.2") {
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/udp-create-socket",
-            "create-udp-socket",
-            unreachable_stub,
-        )?;
-    }
-
-    if !world_imports.contains("wasi:sockets/tcp@0.2") {
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/tcp",
-            "[method]tcp-socket.start-bind",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/tcp",
-            "[method]tcp-socket.finish-bind",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/tcp",
-            "[method]tcp-socket.start-connect",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/tcp",
-            "[method]tcp-socket.finish-connect",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/tcp",
-            "[method]tcp-socket.start-listen",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/tcp",
-            "[method]tcp-socket.finish-listen",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/tcp",
-            "[method]tcp-socket.accept",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/tcp",
-            "[method]tcp-socket.local-address",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/tcp",
-            "[method]tcp-socket.remote-address",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/tcp",
-            "[method]tcp-socket.is-listening",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/tcp",
-            "[method]tcp-socket.address-family",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/tcp",
-            "[method]tcp-socket.set-listen-backlog-size",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/tcp",
-            "[method]tcp-socket.keep-alive-enabled",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/tcp",
-            "[method]tcp-socket.set-keep-alive-enabled",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/tcp",
-            "[method]tcp-socket.keep-alive-idle-time",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/tcp",
-            "[method]tcp-socket.set-keep-alive-idle-time",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/tcp",
-            "[method]tcp-socket.keep-alive-interval",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/tcp",
-            "[method]tcp-socket.set-keep-alive-interval",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/tcp",
-            "[method]tcp-socket.keep-alive-count",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/tcp",
-            "[method]tcp-socket.set-keep-alive-count",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/tcp",
-            "[method]tcp-socket.hop-limit",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/tcp",
-            "[method]tcp-socket.set-hop-limit",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/tcp",
-            "[method]tcp-socket.receive-buffer-size",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/tcp",
-            "[method]tcp-socket.set-receive-buffer-size",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/tcp",
-            "[method]tcp-socket.send-buffer-size",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/tcp",
-            "[method]tcp-socket.set-send-buffer-size",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/tcp",
-            "[method]tcp-socket.subscribe",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/tcp",
-            "[method]tcp-socket.shutdown",
-            unreachable_stub,
-        )?;
-
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/tcp",
-            "[resource-drop]tcp-socket",
-            unreachable_stub,
-        )?;
-    }
-
-    if !world_imports.contains("wasi:sockets/tcp-create-socket@0.2") {
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/tcp-create-socket",
-            "create-tcp-socket",
-            unreachable_stub,
-        )?;
-    }
-
-    if !world_imports.contains("wasi:sockets/ip-name-lookup@0.2") {
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/ip-name-lookup",
-            "resolve-addresses",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/ip-name-lookup",
-            "[method]resolve-address-stream.resolve-next-address",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/ip-name-lookup",
-            "[method]resolve-address-stream.subscribe",
-            unreachable_stub,
-        )?;
-
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/ip-name-lookup",
-            "[resource-drop]resolve-address-stream",
-            unreachable_stub,
-        )?;
-    }
-
-    if !world_imports.contains("wasi:sockets/network@0.2") {
-        stub_wasi_imports(
-            module,
-            "wasi:sockets/network",
-            "[resource-drop]network",
-            unreachable_stub,
-        )?;
-    }
-
-    Ok(())
-}
-
-fn stub_filesystem(module: &mut Module, world_imports: &HashSet<String>) -> Result<()> {
-    if !world_imports.contains("wasi:filesystem/types@0.2") {
-        stub_wasi_imports(
-            module,
-            "wasi:filesystem/types",
-            "filesystem-error-code",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:filesystem/types",
-            "[method]descriptor.read-via-stream",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:filesystem/types",
-            "[method]descriptor.write-via-stream",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:filesystem/types",
-            "[method]descriptor.append-via-stream",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:filesystem/types",
-            "[method]descriptor.advise",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:filesystem/types",
-            "[method]descriptor.sync-data",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:filesystem/types",
-            "[method]descriptor.get-flags",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:filesystem/types",
-            "[method]descriptor.get-type",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:filesystem/types",
-            "[method]descriptor.set-size",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:filesystem/types",
-            "[method]descriptor.set-times",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:filesystem/types",
-            "[method]descriptor.read",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:filesystem/types",
-            "[method]descriptor.write",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:filesystem/types",
-            "[method]descriptor.sync",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:filesystem/types",
-            "[method]descriptor.create-directory-at",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:filesystem/types",
-            "[method]descriptor.stat",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:filesystem/types",
-            "[method]descriptor.stat-at",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:filesystem/types",
-            "[method]descriptor.set-times-at",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:filesystem/types",
-            "[method]descriptor.link-at",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:filesystem/types",
-            "[method]descriptor.open-at",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:filesystem/types",
-            "[method]descriptor.readlink-at",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:filesystem/types",
-            "[method]descriptor.remove-directory-at",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:filesystem/types",
-            "[method]descriptor.rename-at",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:filesystem/types",
-            "[method]descriptor.symlink-at",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:filesystem/types",
-            "[method]descriptor.unlink-file-at",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:filesystem/types",
-            "[method]descriptor.is-same-object",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:filesystem/types",
-            "[method]descriptor.metadata-hash",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:filesystem/types",
-            "[method]descriptor.metadata-hash-at",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:filesystem/types",
-            "[method]directory-entry-stream.read-directory-entry",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:filesystem/types",
-            "[method]descriptor.read-directory",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:filesystem/types",
-            "[resource-drop]descriptor",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:filesystem/types",
-            "[resource-drop]directory-entry-stream",
-            unreachable_stub,
-        )?;
-
-        stub_import(module, PREVIEW1, "fd_close", unreachable_stub)?;
-        stub_import(module, PREVIEW1, "fd_fdstat_set_flags", unreachable_stub)?;
-        stub_import(module, PREVIEW1, "fd_prestat_get", unreachable_stub)?;
-        stub_import(module, PREVIEW1, "fd_readdir", unreachable_stub)?;
-        stub_import(module, PREVIEW1, "fd_prestat_dir_name", unreachable_stub)?;
-        stub_import(module, PREVIEW1, "fd_read", unreachable_stub)?;
-        stub_import(module, PREVIEW1, "fd_seek", unreachable_stub)?;
-        stub_import(module, PREVIEW1, "path_open", unreachable_stub)?;
-        stub_import(module, PREVIEW1, "path_filestat_get", unreachable_stub)?;
-        stub_import(module, PREVIEW1, "path_remove_directory", unreachable_stub)?;
-        stub_import(module, PREVIEW1, "path_unlink_file", unreachable_stub)?;
-    }
-
-    if !world_imports.contains("wasi:filesystem/preopens@0.2") {
-        stub_wasi_imports(
-            module,
-            "wasi:filesystem/preopens",
-            "get-directories",
-            unreachable_stub,
-        )?;
-    }
-
-    Ok(())
-}
-
-fn stub_cli(module: &mut Module, world_imports: &HashSet<String>) -> Result<()> {
-    if !world_imports.contains("wasi:cli/environment@0.2") {
-        stub_wasi_imports(
-            module,
-            "wasi:cli/environment",
-            "get-environment",
-            unreachable_stub,
-        )?;
-
-        stub_wasi_imports(
-            module,
-            "wasi:cli/environment",
-            "get-arguments",
-            unreachable_stub,
-        )?;
-        stub_wasi_imports(
-            module,
-            "wasi:cli/environment",
-            "initial-cwd",
-            unreachable_stub,
-        )?;
-
-        stub_import(module, PREVIEW1, "args_get", unreachable_stub)?;
-        stub_import(module, PREVIEW1, "args_sizes_get", unreachable_stub)?;
-        stub_import(module, PREVIEW1, "environ_get", unreachable_stub)?;
-        stub_import(module, PREVIEW1, "environ_sizes_get", unreachable_stub)?;
-    }
-
-    if !world_imports.contains("wasi:cli/exit@0.2") {
-        stub_wasi_imports(module, "wasi:cli/exit", "exit", unreachable_stub)?;
-        stub_import(module, PREVIEW1, "proc_exit", unreachable_stub)?;
-    }
-
-    if !world_imports.contains("wasi:cli/terminal-stdin@0.2") {
-        stub_wasi_imports(
-            module,
-            "wasi:cli/terminal-stdin",
-            "get-terminal-stdin",
-            unreachable_stub,
-        )?;
-    }
-
-    if !world_imports.contains("wasi:cli/terminal-stdout@0.2") {
-        stub_wasi_imports(
-            module,
-            "wasi:cli/terminal-stdout",
-            "get-terminal-stdout",
-            unreachable_stub,
-        )?;
-    }
-
-    if !world_imports.contains("wasi:cli/terminal-stderr@0.2") {
-        stub_wasi_imports(
-            module,
-            "wasi:cli/terminal-stderr",
-            "get-terminal-stderr",
-            unreachable_stub,
-        )?;
-    }
-
-    if !world_imports.contains("wasi:cli/terminal-input@0.2") {
-        stub_wasi_imports(
-            module,
-            "wasi:cli/terminal-input",
-            "[resource-drop]terminal-input",
-            unreachable_stub,
-        )?;
-    }
-
-    if !world_imports.contains("wasi:cli/terminal-output@0.2") {
-        stub_wasi_imports(
-            module,
-            "wasi:cli/terminal-output",
-            "[resource-drop]terminal-output",
-            unreachable_stub,
-        )?;
+    if !captured {
+        // (func (param i32 i32 i32 i32) (result i32)))
+        stub_import(module, policy, PREVIEW1, "fd_write", |body| {
+            let len_local: LocalID = LocalID(3); // Index of the last local
+            body.local_get(len_local);
+            Ok(vec![len_local])
+        })?;
     }
-
     Ok(())
 }
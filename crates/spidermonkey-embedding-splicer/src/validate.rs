@@ -0,0 +1,228 @@
+//! Validation of the modules encoded by `splice` and `stub_wasi`
+//!
+//! Mistakes in the functions the splicer synthesizes would otherwise only
+//! surface when wizer or `componentNew` reject the module, so failures are
+//! attributed to the export or import the invalid function was synthesized for.
+
+use std::collections::HashMap;
+
+use anyhow::{Result, bail};
+use wasmparser::{
+    BinaryReader, ExternalKind, KnownCustom, Name, Parser, Payload, TypeRef, Validator,
+    WasmFeatures,
+};
+use wirm::Module;
+use wirm::ir::id::FunctionID;
+
+/// Features the functions synthesized by the splicer use, on top of those of
+/// the engine
+const SPLICER_FEATURES: WasmFeatures = WasmFeatures::WASM1.union(WasmFeatures::BULK_MEMORY);
+
+/// Validate `wasm` with the features the engine is built with
+pub(crate) fn validate(wasm: &[u8], stage: &str) -> Result<()> {
+    let mut validator = Validator::new_with_features(engine_features(wasm));
+    let Err(err) = validator.validate_all(wasm) else {
+        return Ok(());
+    };
+    match function_at(wasm, err.offset()) {
+        Some(name) => bail!("invalid function `{name}` synthesized during {stage}: {err}"),
+        None => bail!("invalid module produced during {stage}: {err}"),
+    }
+}
+
+/// Features of the `target_features` section the engine is compiled with,
+/// those Wasmtime enables by default for engines without the section
+fn engine_features(wasm: &[u8]) -> WasmFeatures {
+    let section = Parser::new(0)
+        .parse_all(wasm)
+        .filter_map(|payload| match payload {
+            Ok(Payload::CustomSection(section)) if section.name() == "target_features" => {
+                Some(section)
+            }
+            _ => None,
+        })
+        .next();
+    let Some(section) = section else {
+        return WasmFeatures::default();
+    };
+    let mut features = SPLICER_FEATURES;
+    let mut reader = BinaryReader::new(section.data(), section.data_offset());
+    let Ok(count) = reader.read_var_u32() else {
+        return WasmFeatures::default();
+    };
+    for _ in 0..count {
+        let (Ok(prefix), Ok(name)) = (reader.read_u8(), reader.read_string()) else {
+            return WasmFeatures::default();
+        };
+        // `-` marks features the engine must not be linked with
+        if prefix == b'-' {
+            continue;
+        }
+        features |= match name {
+            "atomics" | "shared-mem" => WasmFeatures::THREADS,
+            "bulk-memory" | "bulk-memory-opt" => WasmFeatures::BULK_MEMORY,
+            "call-indirect-overlong" | "reference-types" => WasmFeatures::REFERENCE_TYPES,
+            "exception-handling" => WasmFeatures::EXCEPTIONS | WasmFeatures::LEGACY_EXCEPTIONS,
+            "extended-const" => WasmFeatures::EXTENDED_CONST,
+            "gc" => WasmFeatures::GC | WasmFeatures::FUNCTION_REFERENCES,
+            "memory64" => WasmFeatures::MEMORY64,
+            "multimemory" => WasmFeatures::MULTI_MEMORY,
+            "multivalue" => WasmFeatures::MULTI_VALUE,
+            "mutable-globals" => WasmFeatures::MUTABLE_GLOBAL,
+            "nontrapping-fptoint" => WasmFeatures::SATURATING_FLOAT_TO_INT,
+            "relaxed-simd" => WasmFeatures::SIMD | WasmFeatures::RELAXED_SIMD,
+            "sign-ext" => WasmFeatures::SIGN_EXTENSION,
+            "simd128" => WasmFeatures::SIMD,
+            "tail-call" => WasmFeatures::TAIL_CALL,
+            "wide-arithmetic" => WasmFeatures::WIDE_ARITHMETIC,
+            _ => WasmFeatures::empty(),
+        };
+    }
+    features
+}
+
+/// Name the functions stubbing imports after the import, so that validation
+/// errors point at its specifier
+pub(crate) fn name_stubbed_imports(module: &mut Module) {
+    let stubbed = module
+        .imports
+        .iter()
+        .enumerate()
+        .filter(|(idx, import)| {
+            import.is_function() && module.functions.is_local(FunctionID(*idx as u32))
+        })
+        .map(|(idx, import)| {
+            (
+                FunctionID(idx as u32),
+                format!("{}#{}", import.module, import.name),
+            )
+        })
+        .collect::<Vec<_>>();
+    for (fid, name) in stubbed {
+        module.functions.set_local_fn_name(fid, name);
+    }
+}
+
+/// Name of the function whose body contains `offset`, from the name section,
+/// which holds the export name of synthesized exports and the import specifier
+/// of synthesized and stubbed imports, or else from its export
+fn function_at(wasm: &[u8], offset: usize) -> Option<String> {
+    let mut imported_funcs = 0;
+    let mut body_idx = 0;
+    let mut func_idx = None;
+    let mut exports = HashMap::new();
+    let mut names = HashMap::new();
+    for payload in Parser::new(0).parse_all(wasm) {
+        match payload.ok()? {
+            Payload::ImportSection(reader) => {
+                for import in reader.into_imports() {
+                    if matches!(import.ok()?.ty, TypeRef::Func(_) | TypeRef::FuncExact(_)) {
+                        imported_funcs += 1;
+                    }
+                }
+            }
+            Payload::ExportSection(reader) => {
+                for export in reader {
+                    let export = export.ok()?;
+                    if export.kind == ExternalKind::Func {
+                        exports
+                            .entry(export.index)
+                            .or_insert_with(|| export.name.to_string());
+                    }
+                }
+            }
+            Payload::CodeSectionEntry(body) => {
+                if body.range().contains(&offset) {
+                    func_idx = Some(imported_funcs + body_idx);
+                }
+                body_idx += 1;
+            }
+            Payload::CustomSection(section) => {
+                let KnownCustom::Name(reader) = section.as_known() else {
+                    continue;
+                };
+                for name in reader {
+                    let Ok(Name::Function(map)) = name else {
+                        continue;
+                    };
+                    for naming in map {
+                        let naming = naming.ok()?;
+                        names.insert(naming.index, naming.name.to_string());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    let func_idx = func_idx?;
+    names
+        .remove(&func_idx)
+        .or_else(|| exports.remove(&func_idx))
+        .or_else(|| Some(format!("function {func_idx}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn module_with_target_features(features: &[(u8, &str)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        wasm_encoder::Encode::encode(&(features.len() as u32), &mut data);
+        for (prefix, name) in features {
+            data.push(*prefix);
+            wasm_encoder::Encode::encode(*name, &mut data);
+        }
+        let mut module = wasm_encoder::Module::new();
+        module.section(&wasm_encoder::CustomSection {
+            name: "target_features".into(),
+            data: data.into(),
+        });
+        module.finish()
+    }
+
+    #[test]
+    fn engine_features_from_target_features() {
+        let wasm = module_with_target_features(&[
+            (b'+', "sign-ext"),
+            (b'+', "multivalue"),
+            (b'-', "simd128"),
+            (b'+', "unknown-feature"),
+        ]);
+        let features = engine_features(&wasm);
+        assert!(features.contains(SPLICER_FEATURES));
+        assert!(features.contains(WasmFeatures::SIGN_EXTENSION | WasmFeatures::MULTI_VALUE));
+        assert!(!features.contains(WasmFeatures::SIMD));
+        assert!(!features.contains(WasmFeatures::REFERENCE_TYPES));
+    }
+
+    #[test]
+    fn engine_features_default() {
+        let wasm = wasm_encoder::Module::new().finish();
+        assert_eq!(engine_features(&wasm), WasmFeatures::default());
+    }
+
+    #[test]
+    fn validate_rejects_features_outside_the_engine() {
+        let mut wasm = module_with_target_features(&[(b'+', "bulk-memory")]);
+        // (func (result i32) i32.const 0 i32.extend8_s)
+        let mut types = wasm_encoder::TypeSection::new();
+        types.ty().function([], [wasm_encoder::ValType::I32]);
+        let mut funcs = wasm_encoder::FunctionSection::new();
+        funcs.function(0);
+        let mut code = wasm_encoder::CodeSection::new();
+        let mut body = wasm_encoder::Function::new([]);
+        body.instructions().i32_const(0).i32_extend8_s().end();
+        code.function(&body);
+        let mut module = wasm_encoder::Module::new();
+        module.section(&types).section(&funcs).section(&code);
+        let sections = module.finish()[8..].to_vec();
+        wasm.extend_from_slice(&sections);
+
+        let err = validate(&wasm, "test").unwrap_err();
+        assert!(err.to_string().contains("function 0"), "{err}");
+
+        let mut wasm = module_with_target_features(&[(b'+', "sign-ext")]);
+        wasm.extend_from_slice(&sections);
+        validate(&wasm, "test").unwrap();
+    }
+}
@@ -0,0 +1,286 @@
+//! Verification of the imports left in a module after stubbing
+//!
+//! A module may only import the interfaces of the target world, those the enabled
+//! features depend on, and imports kept by the stub policy. Anything else is an
+//! import none of the `stub_*` functions know about, which would otherwise leak
+//! into the component.
+
+use std::collections::{BTreeSet, HashSet};
+
+use anyhow::{Result, bail};
+use serde_json::Value;
+use wasmparser::{Parser, Payload, TypeRef};
+
+use crate::preview1::{ImportOwner, PREVIEW1_FUNCS};
+use crate::stub_policy::StubPolicy;
+use crate::stub_wasi::{PREVIEW1, interface_feature};
+use crate::wit::exports::local::spidermonkey_embedding_splicer::splicer::Feature;
+
+/// Import verification, from the `verify` key of a stub policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyMode {
+    /// Only allow the imports of the target world and of the enabled features
+    Imports,
+    /// Only allow the imports of the target world, with every feature disabled
+    Pure,
+}
+
+impl VerifyMode {
+    pub fn from_json(value: &Value) -> Result<Self> {
+        match value.as_str() {
+            Some("imports") => Ok(VerifyMode::Imports),
+            Some("pure") => Ok(VerifyMode::Pure),
+            _ => bail!("stub policy \"verify\" must be \"imports\" or \"pure\""),
+        }
+    }
+}
+
+/// Interfaces the engine imports for each feature, without their version
+pub(crate) const FEATURE_INTERFACES: [(&str, ImportOwner); 16] = [
+    ("wasi:cli/stdin", ImportOwner::Feature(Feature::Stdio)),
+    ("wasi:cli/stdout", ImportOwner::Feature(Feature::Stdio)),
+    ("wasi:cli/stderr", ImportOwner::Feature(Feature::Stdio)),
+    (
+        "wasi:clocks/monotonic-clock",
+        ImportOwner::Feature(Feature::Clocks),
+    ),
+    (
+        "wasi:clocks/wall-clock",
+        ImportOwner::Feature(Feature::Clocks),
+    ),
+    (
+        "wasi:clocks/system-clock",
+        ImportOwner::Feature(Feature::Clocks),
+    ),
+    ("wasi:random/random", ImportOwner::Feature(Feature::Random)),
+    (
+        "wasi:random/insecure",
+        ImportOwner::Feature(Feature::Random),
+    ),
+    (
+        "wasi:random/insecure-seed",
+        ImportOwner::Feature(Feature::Random),
+    ),
+    (
+        "wasi:http/outgoing-handler",
+        ImportOwner::Feature(Feature::Http),
+    ),
+    ("wasi:http/handler", ImportOwner::Feature(Feature::Http)),
+    ("wasi:http/client", ImportOwner::Feature(Feature::Http)),
+    ("wasi:http/types", ImportOwner::HttpTypes),
+    ("wasi:io/poll", ImportOwner::Io),
+    ("wasi:io/streams", ImportOwner::Io),
+    ("wasi:io/error", ImportOwner::Io),
+];
+
+/// Canonicalize an interface name the way `name_canonicalized_world_key` does,
+/// keeping only the major version (or `0.<minor>` for unstable versions) of
+/// release versions
+fn canonical_interface(module: &str) -> String {
+    let Some((name, version)) = module.split_once('@') else {
+        return module.to_string();
+    };
+    let version = version.split('+').next().unwrap_or(version);
+    if version.contains('-') {
+        return format!("{name}@{version}");
+    }
+    match version.split('.').collect::<Vec<_>>().as_slice() {
+        ["0", "0", _] => format!("{name}@{version}"),
+        ["0", minor, _] => format!("{name}@0.{minor}"),
+        [major, ..] => format!("{name}@{major}"),
+        [] => module.to_string(),
+    }
+}
+
+/// Fail with the list of function imports of `wasm` that neither the target
+/// world, the enabled features nor the policy allow
+pub(crate) fn verify_imports(
+    wasm: &[u8],
+    mode: VerifyMode,
+    policy: &StubPolicy,
+    features: &[Feature],
+    has_io: bool,
+    world_imports: &HashSet<String>,
+) -> Result<()> {
+    if mode == VerifyMode::Pure && !features.is_empty() {
+        bail!("pure mode requires all features to be disabled, found {features:?}");
+    }
+
+    // pure mode only allows what the world imports itself
+    let allows = |owner: &ImportOwner| match (mode, owner) {
+        (VerifyMode::Pure, ImportOwner::Interface(_)) | (VerifyMode::Imports, _) => {
+            owner.enabled(features, has_io, world_imports)
+        }
+        (VerifyMode::Pure, _) => false,
+    };
+
+    let mut unexpected = BTreeSet::new();
+    for payload in Parser::new(0).parse_all(wasm) {
+        let Payload::ImportSection(reader) = payload? else {
+            continue;
+        };
+        for import in reader.into_imports() {
+            let import = import?;
+            if !matches!(import.ty, TypeRef::Func(_)) {
+                continue;
+            }
+            let (module, name) = (import.module, import.name);
+            let allowed = if module == "$root"
+                || module.starts_with("[export]")
+                || world_imports.contains(&canonical_interface(module))
+                || (mode == VerifyMode::Imports && policy.keeps(module, name))
+            {
+                true
+            } else if module == PREVIEW1 {
+                PREVIEW1_FUNCS
+                    .iter()
+                    .find(|(func, ..)| *func == name)
+                    .is_some_and(|(_, owner, _)| allows(owner))
+            } else {
+                let iface = module.split('@').next().unwrap_or(module);
+                match FEATURE_INTERFACES.iter().find(|(known, _)| *known == iface) {
+                    Some((_, owner)) => allows(owner),
+                    None => interface_feature(iface)
+                        .is_some_and(|feature| allows(&ImportOwner::Feature(feature))),
+                }
+            };
+            if !allowed {
+                unexpected.insert(format!("{module}#{name}"));
+            }
+        }
+    }
+
+    if !unexpected.is_empty() {
+        bail!(
+            "unexpected imports remain after stubbing:\n  {}",
+            unexpected.into_iter().collect::<Vec<_>>().join("\n  ")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A module importing a `func()` for each `(module, name)` of `imports`
+    fn importing(imports: &[(&str, &str)]) -> Vec<u8> {
+        let mut types = wasm_encoder::TypeSection::new();
+        types.ty().function([], []);
+        let mut section = wasm_encoder::ImportSection::new();
+        for (module, name) in imports {
+            section.import(module, name, wasm_encoder::EntityType::Function(0));
+        }
+        let mut wasm = wasm_encoder::Module::new();
+        wasm.section(&types).section(&section);
+        wasm.finish()
+    }
+
+    #[test]
+    fn canonical_interface_versions() {
+        assert_eq!(canonical_interface("wasi:cli/stdin"), "wasi:cli/stdin");
+        assert_eq!(
+            canonical_interface("wasi:cli/stdin@0.2.3"),
+            "wasi:cli/stdin@0.2"
+        );
+        assert_eq!(canonical_interface("local:a/b@1.4.2"), "local:a/b@1");
+        assert_eq!(canonical_interface("local:a/b@0.0.4"), "local:a/b@0.0.4");
+        assert_eq!(
+            canonical_interface("wasi:http/types@0.3.0-rc-2025-09-16"),
+            "wasi:http/types@0.3.0-rc-2025-09-16"
+        );
+        assert_eq!(canonical_interface("local:a/b@2.0.1+build"), "local:a/b@2");
+    }
+
+    #[test]
+    fn features_allow_their_interfaces() {
+        let wasm = importing(&[
+            ("wasi:cli/stdout@0.2.3", "get-stdout"),
+            ("wasi:io/streams@0.2.3", "[method]output-stream.write"),
+            (PREVIEW1, "fd_write"),
+        ]);
+        let policy = StubPolicy::default();
+        let world = HashSet::new();
+        verify_imports(
+            &wasm,
+            VerifyMode::Imports,
+            &policy,
+            &[Feature::Stdio],
+            true,
+            &world,
+        )
+        .unwrap();
+
+        let err = verify_imports(&wasm, VerifyMode::Imports, &policy, &[], false, &world)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("wasi:cli/stdout@0.2.3#get-stdout"), "{err}");
+        assert!(err.contains("wasi:io/streams@0.2.3#"), "{err}");
+        assert!(err.contains(&format!("{PREVIEW1}#fd_write")), "{err}");
+    }
+
+    #[test]
+    fn unknown_imports_are_reported() {
+        let wasm = importing(&[("wasi:random/random@0.2.3", "get-random-u64"), ("env", "f")]);
+        let err = verify_imports(
+            &wasm,
+            VerifyMode::Imports,
+            &StubPolicy::default(),
+            &[Feature::Random],
+            false,
+            &HashSet::new(),
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("env#f"), "{err}");
+        assert!(!err.contains("get-random-u64"), "{err}");
+    }
+
+    #[test]
+    fn world_and_policy_imports_are_allowed() {
+        let wasm = importing(&[
+            ("local:app/log@1.2.0", "log"),
+            ("$root", "f"),
+            ("wasi:filesystem/types@0.2.3", "[method]descriptor.stat"),
+        ]);
+        let world = HashSet::from(["local:app/log@1".to_string()]);
+        let policy =
+            StubPolicy::parse(r#"{ "imports": { "wasi:filesystem/*": "keep" } }"#).unwrap();
+        verify_imports(&wasm, VerifyMode::Imports, &policy, &[], false, &world).unwrap();
+
+        // pure mode ignores the kept imports
+        let err = verify_imports(&wasm, VerifyMode::Pure, &policy, &[], false, &world)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("[method]descriptor.stat"), "{err}");
+        assert!(!err.contains("local:app/log"), "{err}");
+    }
+
+    #[test]
+    fn pure_mode_rejects_features() {
+        let wasm = importing(&[]);
+        let err = verify_imports(
+            &wasm,
+            VerifyMode::Pure,
+            &StubPolicy::default(),
+            &[Feature::Clocks],
+            false,
+            &HashSet::new(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("pure mode"), "{err}");
+    }
+
+    #[test]
+    fn verify_mode_from_json() {
+        assert_eq!(
+            VerifyMode::from_json(&Value::from("pure")).unwrap(),
+            VerifyMode::Pure
+        );
+        assert_eq!(
+            VerifyMode::from_json(&Value::from("imports")).unwrap(),
+            VerifyMode::Imports
+        );
+        assert!(VerifyMode::from_json(&Value::from(true)).is_err());
+    }
+}
@@ -0,0 +1,1176 @@
+//! Embedded read-only virtual filesystem
+//!
+//! A host directory is captured at build time into an image stored in a passive
+//! data segment of the engine. The image is copied into linear memory on first
+//! use, and `wasi:filesystem/types` and `wasi:filesystem/preopens` imports are
+//! replaced with functions that serve it.
+//!
+//! The image layout is a `u32` node count, followed by a node table, followed by
+//! the names and contents of the nodes. Each node is six `u32` fields:
+//!
+//! | offset | field                                                     |
+//! |--------|-----------------------------------------------------------|
+//! | 0      | `descriptor-type` discriminant                            |
+//! | 4      | parent node index                                         |
+//! | 8      | name offset in the image                                  |
+//! | 12     | name length                                               |
+//! | 16     | content offset (files), first child node index (dirs)    |
+//! | 20     | content length (files), number of children (dirs)        |
+//!
+//! Node 0 is the preopened root directory, named after the preopen path. Nodes are
+//! laid out breadth-first so the children of a directory are contiguous.
+//!
+//! Descriptors are node indices plus one. File input streams are handles tagged
+//! with [`VFS_TAG`], which the wrapped `wasi:io/streams` functions dispatch on.
+
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context as _, Result, bail};
+use serde_json::Value;
+use wirm::ir::function::FunctionBuilder;
+use wirm::ir::id::{FunctionID, LocalID, MemoryID};
+use wirm::ir::types::BlockType;
+use wirm::module_builder::AddLocal;
+use wirm::{DataType, Module, Opcode};
+
+use crate::stub_policy::StubPolicy;
+use crate::stub_wasi::{
+    Dispatcher, HandleDispatch, VFS_TAG, build_image, build_memeq, emit_tag_address,
+    emit_untag_address, mem, stub_wasi_imports,
+};
+
+const NODE_SIZE: i32 = 24;
+const HEADER_SIZE: i32 = 4;
+
+// `descriptor-type` cases
+const DESCRIPTOR_TYPE_DIRECTORY: i32 = 3;
+const DESCRIPTOR_TYPE_REGULAR_FILE: i32 = 6;
+
+// `error-code` cases
+const ERROR_INVALID: i32 = 12;
+const ERROR_IS_DIRECTORY: i32 = 14;
+const ERROR_NO_ENTRY: i32 = 20;
+const ERROR_NOT_DIRECTORY: i32 = 24;
+const ERROR_READ_ONLY: i32 = 33;
+
+// results of the path lookup helper that are not node indices
+const LOOKUP_NO_ENTRY: i32 = -1;
+const LOOKUP_NOT_DIRECTORY: i32 = -2;
+
+/// Mutating descriptor functions, which fail with `read-only`, along with the
+/// index of their return pointer parameter and the offset of the error payload
+/// in their return area
+const READ_ONLY_FUNCS: [(&str, u32, u64); 12] = [
+    ("[method]descriptor.write-via-stream", 2, 4),
+    ("[method]descriptor.append-via-stream", 1, 4),
+    ("[method]descriptor.set-size", 2, 1),
+    ("[method]descriptor.set-times", 7, 1),
+    ("[method]descriptor.write", 4, 8),
+    ("[method]descriptor.create-directory-at", 3, 1),
+    ("[method]descriptor.set-times-at", 10, 1),
+    ("[method]descriptor.link-at", 7, 1),
+    ("[method]descriptor.remove-directory-at", 3, 1),
+    ("[method]descriptor.rename-at", 6, 1),
+    ("[method]descriptor.symlink-at", 5, 1),
+    ("[method]descriptor.unlink-file-at", 3, 1),
+];
+
+/// Descriptor functions that succeed without doing anything on a read-only tree,
+/// along with the index of their return pointer parameter
+const NOOP_FUNCS: [(&str, u32); 3] = [
+    ("[method]descriptor.advise", 4),
+    ("[method]descriptor.sync-data", 1),
+    ("[method]descriptor.sync", 1),
+];
+
+/// Virtual filesystem configuration, from the `virtual-fs` key of a stub policy
+#[derive(Debug)]
+pub struct VirtualFs {
+    /// Host directory to embed
+    pub dir: PathBuf,
+    /// Guest path under which the directory is preopened
+    pub preopen: String,
+}
+
+impl VirtualFs {
+    pub fn from_json(value: &Value) -> Result<Self> {
+        let Value::Object(config) = value else {
+            bail!("stub policy \"virtual-fs\" must be an object");
+        };
+        let mut dir = None;
+        let mut preopen = "/".to_string();
+        for (key, value) in config {
+            let Value::String(value) = value else {
+                bail!("stub policy \"virtual-fs.{key}\" must be a string");
+            };
+            match key.as_str() {
+                "dir" => dir = Some(PathBuf::from(value)),
+                "preopen" => preopen = value.clone(),
+                _ => bail!("unrecognized stub policy key [virtual-fs.{key}]"),
+            }
+        }
+        let Some(dir) = dir else {
+            bail!("stub policy \"virtual-fs\" requires a \"dir\"");
+        };
+        Ok(VirtualFs { dir, preopen })
+    }
+
+    /// Capture the directory into an image
+    pub fn image(&self) -> Result<Vec<u8>> {
+        struct Node {
+            kind: i32,
+            parent: usize,
+            name: Vec<u8>,
+            path: PathBuf,
+            first: usize,
+            count: usize,
+        }
+
+        let root = self
+            .dir
+            .canonicalize()
+            .with_context(|| format!("failed to read virtual-fs dir [{}]", self.dir.display()))?;
+        let mut nodes = vec![Node {
+            kind: DESCRIPTOR_TYPE_DIRECTORY,
+            parent: 0,
+            name: self.preopen.as_bytes().to_vec(),
+            path: root,
+            first: 0,
+            count: 0,
+        }];
+
+        let mut idx = 0;
+        while idx < nodes.len() {
+            if nodes[idx].kind == DESCRIPTOR_TYPE_DIRECTORY {
+                let mut entries = fs::read_dir(&nodes[idx].path)
+                    .with_context(|| format!("failed to read [{}]", nodes[idx].path.display()))?
+                    .collect::<std::io::Result<Vec<_>>>()?;
+                entries.sort_by_key(|entry| entry.file_name());
+
+                let first = nodes.len();
+                for entry in entries {
+                    let path = entry.path().canonicalize()?;
+                    let meta = fs::metadata(&path)?;
+                    let kind = if meta.is_dir() {
+                        // symlinks are followed, so guard against cycles
+                        let mut ancestor = idx;
+                        loop {
+                            if nodes[ancestor].path == path {
+                                bail!("symlink cycle at [{}]", entry.path().display());
+                            }
+                            if ancestor == 0 {
+                                break;
+                            }
+                            ancestor = nodes[ancestor].parent;
+                        }
+                        DESCRIPTOR_TYPE_DIRECTORY
+                    } else if meta.is_file() {
+                        DESCRIPTOR_TYPE_REGULAR_FILE
+                    } else {
+                        continue;
+                    };
+                    let Some(name) = entry.file_name().to_str().map(|n| n.as_bytes().to_vec())
+                    else {
+                        bail!("non UTF-8 file name at [{}]", entry.path().display());
+                    };
+                    nodes.push(Node {
+                        kind,
+                        parent: idx,
+                        name,
+                        path,
+                        first: 0,
+                        count: 0,
+                    });
+                }
+                nodes[idx].first = first;
+                nodes[idx].count = nodes.len() - first;
+            }
+            idx += 1;
+        }
+
+        let mut table = Vec::with_capacity(HEADER_SIZE as usize + nodes.len() * NODE_SIZE as usize);
+        let mut data = Vec::new();
+        let data_start = HEADER_SIZE as usize + nodes.len() * NODE_SIZE as usize;
+        let mut push_data = |bytes: &[u8]| {
+            let offset = data_start + data.len();
+            data.extend_from_slice(bytes);
+            u32::try_from(offset).context("virtual-fs image exceeds 4GiB")
+        };
+
+        table.extend_from_slice(&(nodes.len() as u32).to_le_bytes());
+        for node in &nodes {
+            let name_offset = push_data(&node.name)?;
+            let (data0, data1) = if node.kind == DESCRIPTOR_TYPE_DIRECTORY {
+                (node.first as u32, node.count as u32)
+            } else {
+                let contents = fs::read(&node.path)
+                    .with_context(|| format!("failed to read [{}]", node.path.display()))?;
+                (push_data(&contents)?, contents.len() as u32)
+            };
+            for field in [
+                node.kind as u32,
+                node.parent as u32,
+                name_offset,
+                node.name.len() as u32,
+                data0,
+                data1,
+            ] {
+                table.extend_from_slice(&field.to_le_bytes());
+            }
+        }
+        table.extend_from_slice(&data);
+        Ok(table)
+    }
+}
+
+/// Helper functions shared by the synthesized imports
+#[derive(Clone, Copy)]
+struct Vfs {
+    memory: MemoryID,
+    realloc: FunctionID,
+    /// `(node) -> i32`: address of a node table entry
+    node: FunctionID,
+    /// `(node, path_ptr, path_len) -> i32`: resolve a relative path
+    lookup: FunctionID,
+    /// `(offset, len) -> i32`: copy a range of the image into a new allocation
+    copy: FunctionID,
+    /// `(node, retptr)`: write an ok `descriptor-stat` result
+    stat: FunctionID,
+}
+
+impl Vfs {
+    fn new(module: &mut Module, image: Vec<u8>) -> Vfs {
+        let memory = module.get_memory_id().unwrap();
+        let realloc = module
+            .exports
+            .get_func_by_name("cabi_realloc".to_string())
+            .unwrap();
+        let base = build_image(module, image);
+
+        let mut body = FunctionBuilder::new(&[DataType::I32], &[DataType::I32]);
+        body.call(base);
+        body.local_get(LocalID(0));
+        body.i32_const(NODE_SIZE);
+        body.i32_mul();
+        body.i32_add();
+        body.i32_const(HEADER_SIZE);
+        body.i32_add();
+        let node = body.finish_module(module);
+
+        let copy = Self::build_copy(module, memory, realloc, base);
+        let memeq = build_memeq(module, memory);
+        let lookup = Self::build_lookup(module, memory, base, node, memeq);
+        let stat = Self::build_stat(module, memory, node);
+
+        Vfs {
+            memory,
+            realloc,
+            node,
+            lookup,
+            copy,
+            stat,
+        }
+    }
+
+    fn build_copy(
+        module: &mut Module,
+        memory: MemoryID,
+        realloc: FunctionID,
+        base: FunctionID,
+    ) -> FunctionID {
+        let (offset, len) = (LocalID(0), LocalID(1));
+        let mut body = FunctionBuilder::new(&[DataType::I32, DataType::I32], &[DataType::I32]);
+        let out = body.add_local(DataType::I32);
+        body.i32_const(0);
+        body.i32_const(0);
+        body.i32_const(1);
+        body.local_get(len);
+        body.call(realloc);
+        body.local_tee(out);
+        body.call(base);
+        body.local_get(offset);
+        body.i32_add();
+        body.local_get(len);
+        body.memory_copy(memory, memory);
+        body.local_get(out);
+        body.finish_module(module)
+    }
+
+    /// Resolve a path relative to a directory node, returning the node index or
+    /// one of the `LOOKUP_*` codes
+    fn build_lookup(
+        module: &mut Module,
+        memory: MemoryID,
+        base: FunctionID,
+        node_fn: FunctionID,
+        memeq: FunctionID,
+    ) -> FunctionID {
+        let (node, ptr, len) = (LocalID(0), LocalID(1), LocalID(2));
+        let mut body = FunctionBuilder::new(
+            &[DataType::I32, DataType::I32, DataType::I32],
+            &[DataType::I32],
+        );
+        let pos = body.add_local(DataType::I32);
+        let start = body.add_local(DataType::I32);
+        let seg_len = body.add_local(DataType::I32);
+        let child = body.add_local(DataType::I32);
+        let end = body.add_local(DataType::I32);
+
+        // loop over path segments
+        body.loop_stmt(BlockType::Empty);
+        body.local_get(pos);
+        body.local_get(len);
+        body.i32_ge_u();
+        body.if_stmt(BlockType::Empty);
+        body.local_get(node);
+        body.return_stmt();
+        body.end();
+
+        // scan to the next '/'
+        body.local_get(pos);
+        body.local_set(start);
+        body.block(BlockType::Empty);
+        body.loop_stmt(BlockType::Empty);
+        body.local_get(pos);
+        body.local_get(len);
+        body.i32_ge_u();
+        body.br_if(1);
+        body.local_get(ptr);
+        body.local_get(pos);
+        body.i32_add();
+        body.i32_load8_u(mem(memory, 0, 0));
+        body.i32_const(b'/' as i32);
+        body.i32_eq();
+        body.br_if(1);
+        body.local_get(pos);
+        body.i32_const(1);
+        body.i32_add();
+        body.local_set(pos);
+        body.br(0);
+        body.end();
+        body.end();
+        body.local_get(pos);
+        body.local_get(start);
+        body.i32_sub();
+        body.local_set(seg_len);
+        body.local_get(pos);
+        body.i32_const(1);
+        body.i32_add();
+        body.local_set(pos);
+
+        // skip empty and "." segments
+        body.local_get(seg_len);
+        body.i32_eqz();
+        body.br_if(0);
+        body.local_get(seg_len);
+        body.i32_const(1);
+        body.i32_eq();
+        body.local_get(ptr);
+        body.local_get(start);
+        body.i32_add();
+        body.i32_load8_u(mem(memory, 0, 0));
+        body.i32_const(b'.' as i32);
+        body.i32_eq();
+        body.i32_and();
+        body.br_if(0);
+
+        // ".." moves to the parent, which for the root is itself
+        body.local_get(seg_len);
+        body.i32_const(2);
+        body.i32_eq();
+        body.local_get(ptr);
+        body.local_get(start);
+        body.i32_add();
+        body.i32_load16_u(mem(memory, 0, 0));
+        body.i32_const(i32::from(u16::from_le_bytes(*b"..")));
+        body.i32_eq();
+        body.i32_and();
+        body.if_stmt(BlockType::Empty);
+        body.local_get(node);
+        body.call(node_fn);
+        body.i32_load(mem(memory, 2, 4));
+        body.local_set(node);
+        body.br(1);
+        body.end();
+
+        // otherwise search the children of the current directory
+        body.local_get(node);
+        body.call(node_fn);
+        body.i32_load(mem(memory, 2, 0));
+        body.i32_const(DESCRIPTOR_TYPE_DIRECTORY);
+        body.i32_ne();
+        body.if_stmt(BlockType::Empty);
+        body.i32_const(LOOKUP_NOT_DIRECTORY);
+        body.return_stmt();
+        body.end();
+        body.local_get(node);
+        body.call(node_fn);
+        body.i32_load(mem(memory, 2, 16));
+        body.local_tee(child);
+        body.local_get(node);
+        body.call(node_fn);
+        body.i32_load(mem(memory, 2, 20));
+        body.i32_add();
+        body.local_set(end);
+
+        body.loop_stmt(BlockType::Empty);
+        body.local_get(child);
+        body.local_get(end);
+        body.i32_ge_u();
+        body.if_stmt(BlockType::Empty);
+        body.i32_const(LOOKUP_NO_ENTRY);
+        body.return_stmt();
+        body.end();
+        body.local_get(child);
+        body.call(node_fn);
+        body.i32_load(mem(memory, 2, 12));
+        body.local_get(seg_len);
+        body.i32_eq();
+        body.if_stmt(BlockType::Empty);
+        body.call(base);
+        body.local_get(child);
+        body.call(node_fn);
+        body.i32_load(mem(memory, 2, 8));
+        body.i32_add();
+        body.local_get(ptr);
+        body.local_get(start);
+        body.i32_add();
+        body.local_get(seg_len);
+        body.call(memeq);
+        body.if_stmt(BlockType::Empty);
+        body.local_get(child);
+        body.local_set(node);
+        // continue with the next path segment
+        body.br(3);
+        body.end();
+        body.end();
+        body.local_get(child);
+        body.i32_const(1);
+        body.i32_add();
+        body.local_set(child);
+        body.br(0);
+        body.end();
+
+        body.end();
+        body.unreachable();
+        body.finish_module(module)
+    }
+
+    fn build_stat(module: &mut Module, memory: MemoryID, node_fn: FunctionID) -> FunctionID {
+        let (node, retptr) = (LocalID(0), LocalID(1));
+        let mut body = FunctionBuilder::new(&[DataType::I32, DataType::I32], &[]);
+        let addr = body.add_local(DataType::I32);
+        body.local_get(node);
+        body.call(node_fn);
+        body.local_set(addr);
+
+        // ok(descriptor-stat), with no timestamps
+        body.local_get(retptr);
+        body.i32_const(0);
+        body.i32_const(104);
+        body.memory_fill(memory);
+        body.local_get(retptr);
+        body.local_get(addr);
+        body.i32_load(mem(memory, 2, 0));
+        body.i32_store8(mem(memory, 0, 8));
+        body.local_get(retptr);
+        body.i64_const(1);
+        body.i64_store(mem(memory, 3, 16));
+        body.local_get(retptr);
+        body.local_get(addr);
+        body.i32_load(mem(memory, 2, 20));
+        body.i64_extend_i32_u();
+        body.i64_const(0);
+        body.local_get(addr);
+        body.i32_load(mem(memory, 2, 0));
+        body.i32_const(DESCRIPTOR_TYPE_REGULAR_FILE);
+        body.i32_eq();
+        body.select();
+        body.i64_store(mem(memory, 3, 24));
+        body.finish_module(module)
+    }
+
+    /// Push the address of a node from its descriptor handle
+    fn node_addr(self, body: &mut FunctionBuilder, handle: LocalID) {
+        body.local_get(handle);
+        body.i32_const(1);
+        body.i32_sub();
+        body.call(self.node);
+    }
+
+    fn store_err(self, body: &mut FunctionBuilder, retptr: LocalID, offset: u64, code: i32) {
+        body.local_get(retptr);
+        body.i32_const(1);
+        body.i32_store8(mem(self.memory, 0, 0));
+        body.local_get(retptr);
+        body.i32_const(code);
+        body.i32_store8(mem(self.memory, 0, offset));
+    }
+
+    fn store_ok(self, body: &mut FunctionBuilder, retptr: LocalID) {
+        body.local_get(retptr);
+        body.i32_const(0);
+        body.i32_store8(mem(self.memory, 0, 0));
+    }
+
+    /// Return with an error if the node of a descriptor is not of the given type
+    fn require_type(
+        self,
+        body: &mut FunctionBuilder,
+        handle: LocalID,
+        retptr: LocalID,
+        offset: u64,
+        ty: i32,
+        code: i32,
+    ) {
+        self.node_addr(body, handle);
+        body.i32_load(mem(self.memory, 2, 0));
+        body.i32_const(ty);
+        body.i32_ne();
+        body.if_stmt(BlockType::Empty);
+        self.store_err(body, retptr, offset, code);
+        body.return_stmt();
+        body.end();
+    }
+
+    /// Resolve a path into `node`, returning with an error if it doesn't exist
+    fn lookup_at(
+        self,
+        body: &mut FunctionBuilder,
+        handle: LocalID,
+        (ptr, len): (LocalID, LocalID),
+        retptr: LocalID,
+        offset: u64,
+        node: LocalID,
+    ) {
+        body.local_get(handle);
+        body.i32_const(1);
+        body.i32_sub();
+        body.local_get(ptr);
+        body.local_get(len);
+        body.call(self.lookup);
+        body.local_tee(node);
+        body.i32_const(LOOKUP_NO_ENTRY);
+        body.i32_eq();
+        body.if_stmt(BlockType::Empty);
+        self.store_err(body, retptr, offset, ERROR_NO_ENTRY);
+        body.return_stmt();
+        body.end();
+        body.local_get(node);
+        body.i32_const(LOOKUP_NOT_DIRECTORY);
+        body.i32_eq();
+        body.if_stmt(BlockType::Empty);
+        self.store_err(body, retptr, offset, ERROR_NOT_DIRECTORY);
+        body.return_stmt();
+        body.end();
+    }
+
+    /// Copy `min(len, size - pos)` bytes of a file node from `pos` into a new
+    /// list at `retptr + offset`, leaving the number of bytes copied in `n`
+    fn read_file(
+        self,
+        body: &mut FunctionBuilder,
+        addr: LocalID,
+        pos: LocalID,
+        len: LocalID,
+        retptr: LocalID,
+        offset: u64,
+        n: LocalID,
+    ) {
+        // n = min(len, size - pos)
+        body.local_get(addr);
+        body.i32_load(mem(self.memory, 2, 20));
+        body.local_get(pos);
+        body.i32_sub();
+        body.local_tee(n);
+        body.local_get(len);
+        body.i32_wrap_i64();
+        body.local_get(len);
+        body.local_get(n);
+        body.i64_extend_i32_u();
+        body.i64_gt_u();
+        body.select();
+        body.local_set(n);
+
+        body.local_get(retptr);
+        body.local_get(addr);
+        body.i32_load(mem(self.memory, 2, 16));
+        body.local_get(pos);
+        body.i32_add();
+        body.local_get(n);
+        body.call(self.copy);
+        body.i32_store(mem(self.memory, 2, offset));
+        body.local_get(retptr);
+        body.local_get(n);
+        body.i32_store(mem(self.memory, 2, offset + 4));
+    }
+}
+
+/// Replace the filesystem imports of the engine with functions serving an embedded
+/// image of a host directory
+///
+/// `has_io` indicates that `wasi:io/streams` remains available, in which case host
+/// streams are forwarded to it from the wrapped stream functions.
+pub(crate) fn synthesize_virtual_fs(
+    module: &mut Module,
+    dispatcher: &mut Dispatcher,
+    policy: &StubPolicy,
+    vfs: &VirtualFs,
+    has_io: bool,
+) -> Result<()> {
+    // imports must be added before any local function is synthesized
+    let streams = wrap_stream_imports(module, dispatcher, has_io)?;
+
+    let image = vfs.image()?;
+    let vfs = Vfs::new(module, image);
+    let memory = vfs.memory;
+
+    for (stream_fn, wrapper) in streams {
+        dispatcher.handle(module, wrapper, |body| stream_fn.build(vfs, body))?;
+    }
+
+    stub_wasi_imports(
+        module,
+        policy,
+        "wasi:filesystem/preopens",
+        "get-directories",
+        |body| {
+            let retptr = LocalID(0);
+            let list = body.add_local(DataType::I32);
+            body.i32_const(0);
+            body.i32_const(0);
+            body.i32_const(4);
+            body.i32_const(12);
+            body.call(vfs.realloc);
+            body.local_set(list);
+            body.local_get(list);
+            body.i32_const(1);
+            body.i32_store(mem(memory, 2, 0));
+            body.local_get(list);
+            body.i32_const(0);
+            body.call(vfs.node);
+            body.i32_load(mem(memory, 2, 8));
+            body.i32_const(0);
+            body.call(vfs.node);
+            body.i32_load(mem(memory, 2, 12));
+            body.call(vfs.copy);
+            body.i32_store(mem(memory, 2, 4));
+            body.local_get(list);
+            body.i32_const(0);
+            body.call(vfs.node);
+            body.i32_load(mem(memory, 2, 12));
+            body.i32_store(mem(memory, 2, 8));
+            body.local_get(retptr);
+            body.local_get(list);
+            body.i32_store(mem(memory, 2, 0));
+            body.local_get(retptr);
+            body.i32_const(1);
+            body.i32_store(mem(memory, 2, 4));
+            Ok(vec![])
+        },
+    )?;
+
+    let types = "wasi:filesystem/types";
+
+    stub_wasi_imports(
+        module,
+        policy,
+        types,
+        "[method]descriptor.open-at",
+        |body| {
+            let (handle, ptr, len, open_flags, flags, retptr) = (
+                LocalID(0),
+                LocalID(2),
+                LocalID(3),
+                LocalID(4),
+                LocalID(5),
+                LocalID(6),
+            );
+            let node = body.add_local(DataType::I32);
+            // create, exclusive or truncate open flags, or write or mutate-directory
+            // descriptor flags
+            body.local_get(open_flags);
+            body.i32_const(0b1101);
+            body.i32_and();
+            body.local_get(flags);
+            body.i32_const(0b10_0010);
+            body.i32_and();
+            body.i32_or();
+            body.if_stmt(BlockType::Empty);
+            vfs.store_err(body, retptr, 4, ERROR_READ_ONLY);
+            body.return_stmt();
+            body.end();
+            vfs.lookup_at(body, handle, (ptr, len), retptr, 4, node);
+            // the directory open flag
+            body.local_get(open_flags);
+            body.i32_const(1);
+            body.i32_shr_u();
+            body.i32_const(1);
+            body.i32_and();
+            body.local_get(node);
+            body.call(vfs.node);
+            body.i32_load(mem(memory, 2, 0));
+            body.i32_const(DESCRIPTOR_TYPE_DIRECTORY);
+            body.i32_ne();
+            body.i32_and();
+            body.if_stmt(BlockType::Empty);
+            vfs.store_err(body, retptr, 4, ERROR_NOT_DIRECTORY);
+            body.return_stmt();
+            body.end();
+            vfs.store_ok(body, retptr);
+            body.local_get(retptr);
+            body.local_get(node);
+            body.i32_const(1);
+            body.i32_add();
+            body.i32_store(mem(memory, 2, 4));
+            Ok(vec![])
+        },
+    )?;
+
+    stub_wasi_imports(module, policy, types, "[method]descriptor.stat", |body| {
+        let (handle, retptr) = (LocalID(0), LocalID(1));
+        body.local_get(handle);
+        body.i32_const(1);
+        body.i32_sub();
+        body.local_get(retptr);
+        body.call(vfs.stat);
+        Ok(vec![])
+    })?;
+
+    stub_wasi_imports(
+        module,
+        policy,
+        types,
+        "[method]descriptor.stat-at",
+        |body| {
+            let (handle, ptr, len, retptr) = (LocalID(0), LocalID(2), LocalID(3), LocalID(4));
+            let node = body.add_local(DataType::I32);
+            vfs.lookup_at(body, handle, (ptr, len), retptr, 8, node);
+            body.local_get(node);
+            body.local_get(retptr);
+            body.call(vfs.stat);
+            Ok(vec![])
+        },
+    )?;
+
+    stub_wasi_imports(
+        module,
+        policy,
+        types,
+        "[method]descriptor.get-type",
+        |body| {
+            let (handle, retptr) = (LocalID(0), LocalID(1));
+            vfs.store_ok(body, retptr);
+            body.local_get(retptr);
+            vfs.node_addr(body, handle);
+            body.i32_load(mem(memory, 2, 0));
+            body.i32_store8(mem(memory, 0, 1));
+            Ok(vec![])
+        },
+    )?;
+
+    stub_wasi_imports(
+        module,
+        policy,
+        types,
+        "[method]descriptor.get-flags",
+        |body| {
+            let retptr = LocalID(1);
+            vfs.store_ok(body, retptr);
+            // read
+            body.local_get(retptr);
+            body.i32_const(1);
+            body.i32_store8(mem(memory, 0, 1));
+            Ok(vec![])
+        },
+    )?;
+
+    stub_wasi_imports(
+        module,
+        policy,
+        types,
+        "[method]descriptor.read-via-stream",
+        |body| {
+            let (handle, offset, retptr) = (LocalID(0), LocalID(1), LocalID(2));
+            let state = body.add_local(DataType::I32);
+            vfs.require_type(
+                body,
+                handle,
+                retptr,
+                4,
+                DESCRIPTOR_TYPE_REGULAR_FILE,
+                ERROR_IS_DIRECTORY,
+            );
+            // stream state is the node index and read position
+            body.i32_const(0);
+            body.i32_const(0);
+            body.i32_const(4);
+            body.i32_const(8);
+            body.call(vfs.realloc);
+            body.local_tee(state);
+            body.local_get(handle);
+            body.i32_const(1);
+            body.i32_sub();
+            body.i32_store(mem(memory, 2, 0));
+            body.local_get(state);
+            vfs.node_addr(body, handle);
+            body.i32_load(mem(memory, 2, 20));
+            body.local_get(offset);
+            body.i32_wrap_i64();
+            body.local_get(offset);
+            vfs.node_addr(body, handle);
+            body.i32_load(mem(memory, 2, 20));
+            body.i64_extend_i32_u();
+            body.i64_gt_u();
+            body.select();
+            body.i32_store(mem(memory, 2, 4));
+            vfs.store_ok(body, retptr);
+            body.local_get(retptr);
+            body.local_get(state);
+            emit_tag_address(body, VFS_TAG);
+            body.i32_store(mem(memory, 2, 4));
+            Ok(vec![])
+        },
+    )?;
+
+    stub_wasi_imports(module, policy, types, "[method]descriptor.read", |body| {
+        let (handle, len, offset, retptr) = (LocalID(0), LocalID(1), LocalID(2), LocalID(3));
+        let addr = body.add_local(DataType::I32);
+        let pos = body.add_local(DataType::I32);
+        let n = body.add_local(DataType::I32);
+        vfs.require_type(
+            body,
+            handle,
+            retptr,
+            4,
+            DESCRIPTOR_TYPE_REGULAR_FILE,
+            ERROR_IS_DIRECTORY,
+        );
+        vfs.node_addr(body, handle);
+        body.local_set(addr);
+        // pos = min(offset, size)
+        body.local_get(addr);
+        body.i32_load(mem(memory, 2, 20));
+        body.local_get(offset);
+        body.i32_wrap_i64();
+        body.local_get(offset);
+        body.local_get(addr);
+        body.i32_load(mem(memory, 2, 20));
+        body.i64_extend_i32_u();
+        body.i64_gt_u();
+        body.select();
+        body.local_set(pos);
+        vfs.store_ok(body, retptr);
+        vfs.read_file(body, addr, pos, len, retptr, 4, n);
+        // end of stream once the read reaches the end of the file
+        body.local_get(retptr);
+        body.local_get(pos);
+        body.local_get(n);
+        body.i32_add();
+        body.local_get(addr);
+        body.i32_load(mem(memory, 2, 20));
+        body.i32_ge_u();
+        body.i32_store8(mem(memory, 0, 12));
+        Ok(vec![])
+    })?;
+
+    stub_wasi_imports(
+        module,
+        policy,
+        types,
+        "[method]descriptor.read-directory",
+        |body| {
+            let (handle, retptr) = (LocalID(0), LocalID(1));
+            let state = body.add_local(DataType::I32);
+            vfs.require_type(
+                body,
+                handle,
+                retptr,
+                4,
+                DESCRIPTOR_TYPE_DIRECTORY,
+                ERROR_NOT_DIRECTORY,
+            );
+            // entry stream state is the node index and the next child
+            body.i32_const(0);
+            body.i32_const(0);
+            body.i32_const(4);
+            body.i32_const(8);
+            body.call(vfs.realloc);
+            body.local_tee(state);
+            body.local_get(handle);
+            body.i32_const(1);
+            body.i32_sub();
+            body.i32_store(mem(memory, 2, 0));
+            body.local_get(state);
+            body.i32_const(0);
+            body.i32_store(mem(memory, 2, 4));
+            vfs.store_ok(body, retptr);
+            body.local_get(retptr);
+            body.local_get(state);
+            body.i32_store(mem(memory, 2, 4));
+            Ok(vec![])
+        },
+    )?;
+
+    stub_wasi_imports(
+        module,
+        policy,
+        types,
+        "[method]directory-entry-stream.read-directory-entry",
+        |body| {
+            let (state, retptr) = (LocalID(0), LocalID(1));
+            let addr = body.add_local(DataType::I32);
+            let child = body.add_local(DataType::I32);
+            vfs.store_ok(body, retptr);
+            body.local_get(state);
+            body.i32_load(mem(memory, 2, 0));
+            body.call(vfs.node);
+            body.local_set(addr);
+            // ok(none) once all children have been read
+            body.local_get(state);
+            body.i32_load(mem(memory, 2, 4));
+            body.local_get(addr);
+            body.i32_load(mem(memory, 2, 20));
+            body.i32_ge_u();
+            body.if_stmt(BlockType::Empty);
+            body.local_get(retptr);
+            body.i32_const(0);
+            body.i32_store8(mem(memory, 0, 4));
+            body.return_stmt();
+            body.end();
+            body.local_get(addr);
+            body.i32_load(mem(memory, 2, 16));
+            body.local_get(state);
+            body.i32_load(mem(memory, 2, 4));
+            body.i32_add();
+            body.call(vfs.node);
+            body.local_set(child);
+            body.local_get(state);
+            body.local_get(state);
+            body.i32_load(mem(memory, 2, 4));
+            body.i32_const(1);
+            body.i32_add();
+            body.i32_store(mem(memory, 2, 4));
+            // ok(some(directory-entry))
+            body.local_get(retptr);
+            body.i32_const(1);
+            body.i32_store8(mem(memory, 0, 4));
+            body.local_get(retptr);
+            body.local_get(child);
+            body.i32_load(mem(memory, 2, 0));
+            body.i32_store8(mem(memory, 0, 8));
+            body.local_get(retptr);
+            body.local_get(child);
+            body.i32_load(mem(memory, 2, 8));
+            body.local_get(child);
+            body.i32_load(mem(memory, 2, 12));
+            body.call(vfs.copy);
+            body.i32_store(mem(memory, 2, 12));
+            body.local_get(retptr);
+            body.local_get(child);
+            body.i32_load(mem(memory, 2, 12));
+            body.i32_store(mem(memory, 2, 16));
+            Ok(vec![])
+        },
+    )?;
+
+    stub_wasi_imports(
+        module,
+        policy,
+        types,
+        "[method]descriptor.is-same-object",
+        |body| {
+            body.local_get(LocalID(0));
+            body.local_get(LocalID(1));
+            body.i32_eq();
+            Ok(vec![])
+        },
+    )?;
+
+    stub_wasi_imports(
+        module,
+        policy,
+        types,
+        "[method]descriptor.metadata-hash",
+        |body| {
+            let (handle, retptr) = (LocalID(0), LocalID(1));
+            vfs.store_ok(body, retptr);
+            body.local_get(retptr);
+            body.local_get(handle);
+            body.i64_extend_i32_u();
+            body.i64_store(mem(memory, 3, 8));
+            body.local_get(retptr);
+            body.i64_const(0);
+            body.i64_store(mem(memory, 3, 16));
+            Ok(vec![])
+        },
+    )?;
+
+    stub_wasi_imports(
+        module,
+        policy,
+        types,
+        "[method]descriptor.metadata-hash-at",
+        |body| {
+            let (handle, ptr, len, retptr) = (LocalID(0), LocalID(2), LocalID(3), LocalID(4));
+            let node = body.add_local(DataType::I32);
+            vfs.lookup_at(body, handle, (ptr, len), retptr, 8, node);
+            vfs.store_ok(body, retptr);
+            body.local_get(retptr);
+            body.local_get(node);
+            body.i32_const(1);
+            body.i32_add();
+            body.i64_extend_i32_u();
+            body.i64_store(mem(memory, 3, 8));
+            body.local_get(retptr);
+            body.i64_const(0);
+            body.i64_store(mem(memory, 3, 16));
+            Ok(vec![])
+        },
+    )?;
+
+    stub_wasi_imports(
+        module,
+        policy,
+        types,
+        "[method]descriptor.readlink-at",
+        |body| {
+            // there are no symlinks in the image
+            let retptr = LocalID(3);
+            vfs.store_err(body, retptr, 4, ERROR_INVALID);
+            Ok(vec![])
+        },
+    )?;
+
+    stub_wasi_imports(module, policy, types, "filesystem-error-code", |body| {
+        // none
+        body.local_get(LocalID(1));
+        body.i32_const(0);
+        body.i32_store8(mem(memory, 0, 0));
+        Ok(vec![])
+    })?;
+
+    for name in [
+        "[resource-drop]descriptor",
+        "[resource-drop]directory-entry-stream",
+    ] {
+        stub_wasi_imports(module, policy, types, name, |_| Ok(vec![]))?;
+    }
+
+    for (name, retptr) in NOOP_FUNCS {
+        stub_wasi_imports(module, policy, types, name, |body| {
+            vfs.store_ok(body, LocalID(retptr));
+            Ok(vec![])
+        })?;
+    }
+
+    for (name, retptr, offset) in READ_ONLY_FUNCS {
+        stub_wasi_imports(module, policy, types, name, |body| {
+            vfs.store_err(body, LocalID(retptr), offset, ERROR_READ_ONLY);
+            Ok(vec![])
+        })?;
+    }
+
+    Ok(())
+}
+
+/// `wasi:io/streams` functions that need to handle file input streams
+#[derive(Clone, Copy)]
+enum StreamFn {
+    Read,
+    Skip,
+    Drop,
+}
+
+impl StreamFn {
+    /// Build the handling of a tagged stream handle
+    fn build(self, vfs: Vfs, body: &mut FunctionBuilder) {
+        let memory = vfs.memory;
+        let (handle, len, retptr) = (LocalID(0), LocalID(1), LocalID(2));
+        let (StreamFn::Read | StreamFn::Skip) = self else {
+            // stream state is not reclaimed
+            return;
+        };
+        // both return a `result<_, stream-error>`, with the error payload at the
+        // same offset as the ok payload
+        let offset = match self {
+            StreamFn::Read => 4,
+            _ => 8,
+        };
+        let state = body.add_local(DataType::I32);
+        let addr = body.add_local(DataType::I32);
+        let n = body.add_local(DataType::I32);
+        let pos = body.add_local(DataType::I32);
+        body.local_get(handle);
+        emit_untag_address(body);
+        body.local_tee(state);
+        body.i32_load(mem(memory, 2, 0));
+        body.call(vfs.node);
+        body.local_set(addr);
+        body.local_get(state);
+        body.i32_load(mem(memory, 2, 4));
+        body.local_set(pos);
+
+        // err(closed) at the end of the file
+        body.local_get(pos);
+        body.local_get(addr);
+        body.i32_load(mem(memory, 2, 20));
+        body.i32_ge_u();
+        body.if_stmt(BlockType::Empty);
+        vfs.store_err(body, retptr, offset, 1);
+        body.else_stmt();
+        vfs.store_ok(body, retptr);
+        match self {
+            StreamFn::Read => vfs.read_file(body, addr, pos, len, retptr, offset, n),
+            _ => {
+                // n = min(len, size - pos)
+                body.local_get(addr);
+                body.i32_load(mem(memory, 2, 20));
+                body.local_get(pos);
+                body.i32_sub();
+                body.local_tee(n);
+                body.local_get(len);
+                body.i32_wrap_i64();
+                body.local_get(len);
+                body.local_get(n);
+                body.i64_extend_i32_u();
+                body.i64_gt_u();
+                body.select();
+                body.local_set(n);
+                body.local_get(retptr);
+                body.local_get(n);
+                body.i64_extend_i32_u();
+                body.i64_store(mem(memory, 3, offset));
+            }
+        }
+        body.local_get(state);
+        body.local_get(pos);
+        body.local_get(n);
+        body.i32_add();
+        body.i32_store(mem(memory, 2, 4));
+        body.end();
+    }
+}
+
+fn wrap_stream_imports(
+    module: &mut Module,
+    dispatcher: &mut Dispatcher,
+    has_io: bool,
+) -> Result<Vec<(StreamFn, HandleDispatch)>> {
+    let mut wrappers = vec![];
+    for (stream_fn, name) in [
+        (StreamFn::Read, "[method]input-stream.read"),
+        (StreamFn::Read, "[method]input-stream.blocking-read"),
+        (StreamFn::Skip, "[method]input-stream.skip"),
+        (StreamFn::Skip, "[method]input-stream.blocking-skip"),
+        (StreamFn::Drop, "[resource-drop]input-stream"),
+    ] {
+        for dispatch in
+            dispatcher.dispatch_handles(module, VFS_TAG, "wasi:io/streams", name, has_io)?
+        {
+            wrappers.push((stream_fn, dispatch));
+        }
+    }
+    Ok(wrappers)
+}
@@ -17,6 +17,11 @@ impl std::str::FromStr for Feature {
             "random" => Ok(Feature::Random),
             "http" => Ok(Feature::Http),
             "fetch-event" => Ok(Feature::FetchEvent),
+            "filesystem" => Ok(Feature::Filesystem),
+            "sockets" => Ok(Feature::Sockets),
+            "environment" => Ok(Feature::Environment),
+            "exit" => Ok(Feature::Exit),
+            "terminal" => Ok(Feature::Terminal),
             _ => bail!("unrecognized feature string [{s}]"),
         }
     }
@@ -0,0 +1,413 @@
+//! Compatibility of JS sources across changes to the world they implement
+//!
+//! The binding surfaces of both worlds are generated by `componentize_bindgen`,
+//! so that export names, interface aliases and built-in module members follow
+//! the same naming rules as the bindings themselves. Interfaces whose ID only
+//! differs by version, or which share an alias, are reported as renamed.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use anyhow::Result;
+use serde_json::{Value, json};
+use wit_parser::{Resolve, WorldId};
+
+use crate::bindgen::{BindingSurface, ExportSurface, componentize_bindgen};
+use crate::wit::exports::local::spidermonkey_embedding_splicer::splicer::Feature;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Renamed { from: String },
+}
+
+#[derive(Debug, Clone)]
+pub struct Change {
+    /// Export, built-in module specifier, or `#`-separated member of either
+    pub path: String,
+    pub kind: ChangeKind,
+    /// Whether JS sources written against the old world may no longer bind
+    pub breaking: bool,
+}
+
+/// Changes to the binding surface between two worlds
+#[derive(Debug, Default)]
+pub struct WorldDiff {
+    /// Changes to the exports the JS source must provide
+    pub exports: Vec<Change>,
+    /// Changes to the built-in modules the JS source can import
+    pub modules: Vec<Change>,
+}
+
+impl WorldDiff {
+    /// Whether JS sources written against the old world still bind
+    pub fn compatible(&self) -> bool {
+        !self
+            .exports
+            .iter()
+            .chain(&self.modules)
+            .any(|change| change.breaking)
+    }
+
+    pub fn to_json(&self) -> Value {
+        let changes = |changes: &[Change]| {
+            changes
+                .iter()
+                .map(|change| {
+                    let mut value = json!({
+                        "path": change.path,
+                        "change": match change.kind {
+                            ChangeKind::Added => "added",
+                            ChangeKind::Removed => "removed",
+                            ChangeKind::Renamed { .. } => "renamed",
+                        },
+                        "breaking": change.breaking,
+                    });
+                    if let ChangeKind::Renamed { from } = &change.kind {
+                        value["from"] = json!(from);
+                    }
+                    value
+                })
+                .collect::<Vec<_>>()
+        };
+        json!({
+            "compatible": self.compatible(),
+            "exports": changes(&self.exports),
+            "modules": changes(&self.modules),
+        })
+    }
+}
+
+impl fmt::Display for WorldDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (title, changes) in [("exports", &self.exports), ("modules", &self.modules)] {
+            if changes.is_empty() {
+                writeln!(f, "no changes to {title}")?;
+                continue;
+            }
+            writeln!(f, "{title}")?;
+            for change in changes {
+                let breaking = if change.breaking { " (breaking)" } else { "" };
+                match &change.kind {
+                    ChangeKind::Added => writeln!(f, "  + {}{breaking}", change.path)?,
+                    ChangeKind::Removed => writeln!(f, "  - {}{breaking}", change.path)?,
+                    ChangeKind::Renamed { from } => {
+                        writeln!(f, "  ~ {} (renamed from {from}){breaking}", change.path)?
+                    }
+                }
+            }
+        }
+        writeln!(f)?;
+        if self.compatible() {
+            writeln!(f, "existing JS sources still bind")
+        } else {
+            writeln!(f, "existing JS sources may no longer bind")
+        }
+    }
+}
+
+/// Compare the binding surfaces of the `old` and `new` worlds
+pub fn diff_worlds(
+    old_resolve: &Resolve,
+    old: WorldId,
+    new_resolve: &Resolve,
+    new: WorldId,
+    features: &[Feature],
+) -> Result<WorldDiff> {
+    let features = features.to_vec();
+    let old = componentize_bindgen(old_resolve, old, &features, None)?.surface;
+    let new = componentize_bindgen(new_resolve, new, &features, None)?.surface;
+    Ok(diff_surfaces(&old, &new))
+}
+
+/// Compare two binding surfaces
+///
+/// New exports and export members are breaking, since existing sources do not
+/// provide them, as are removed built-in modules and members, which existing
+/// sources may import.
+///
+/// Functions and members are matched by name only, as JS sources bind them, so
+/// a renamed function is reported as removed and added, the addition of an
+/// export or the removal of an import being breaking. Changes to the signature
+/// of a function are not reported, since the source still binds to it.
+pub fn diff_surfaces(old: &BindingSurface, new: &BindingSurface) -> WorldDiff {
+    let mut diff = WorldDiff::default();
+
+    let mut renamed = BTreeSet::new();
+    for (name, item) in &new.exports {
+        if let Some(old_item) = old.exports.get(name) {
+            diff_export(&mut diff.exports, name, old_item, item);
+            continue;
+        }
+        let previous = old.exports.iter().find(|(old_name, old_item)| {
+            !new.exports.contains_key(*old_name) && same_interface(old_name, old_item, name, item)
+        });
+        match previous {
+            Some((old_name, old_item)) => {
+                // sources may export the interface by its ID or by its alias
+                let accepted = [Some(name), alias(item)];
+                let breaking = [Some(old_name), alias(old_item)]
+                    .into_iter()
+                    .flatten()
+                    .any(|old_name| !accepted.contains(&Some(old_name)));
+                diff.exports.push(Change {
+                    path: name.clone(),
+                    kind: ChangeKind::Renamed {
+                        from: old_name.clone(),
+                    },
+                    breaking,
+                });
+                renamed.insert(old_name);
+                diff_export(&mut diff.exports, name, old_item, item);
+            }
+            None => diff.exports.push(Change {
+                path: name.clone(),
+                kind: ChangeKind::Added,
+                breaking: true,
+            }),
+        }
+    }
+    for name in old.exports.keys() {
+        if !new.exports.contains_key(name) && !renamed.contains(name) {
+            diff.exports.push(Change {
+                path: name.clone(),
+                kind: ChangeKind::Removed,
+                breaking: false,
+            });
+        }
+    }
+
+    let mut renamed = BTreeSet::new();
+    for (specifier, members) in &new.modules {
+        if let Some(old_members) = old.modules.get(specifier) {
+            diff_members(&mut diff.modules, specifier, old_members, members, false);
+            continue;
+        }
+        let previous = old.modules.iter().find(|(old_specifier, _)| {
+            !new.modules.contains_key(*old_specifier)
+                && unversioned(old_specifier) == unversioned(specifier)
+        });
+        match previous {
+            Some((old_specifier, old_members)) => {
+                diff.modules.push(Change {
+                    path: specifier.clone(),
+                    kind: ChangeKind::Renamed {
+                        from: old_specifier.clone(),
+                    },
+                    breaking: true,
+                });
+                renamed.insert(old_specifier);
+                diff_members(&mut diff.modules, specifier, old_members, members, false);
+            }
+            None => diff.modules.push(Change {
+                path: specifier.clone(),
+                kind: ChangeKind::Added,
+                breaking: false,
+            }),
+        }
+    }
+    for specifier in old.modules.keys() {
+        if !new.modules.contains_key(specifier) && !renamed.contains(specifier) {
+            diff.modules.push(Change {
+                path: specifier.clone(),
+                kind: ChangeKind::Removed,
+                breaking: true,
+            });
+        }
+    }
+
+    diff
+}
+
+fn diff_export(changes: &mut Vec<Change>, name: &str, old: &ExportSurface, new: &ExportSurface) {
+    match (old, new) {
+        (
+            ExportSurface::Interface {
+                members: old_members,
+                ..
+            },
+            ExportSurface::Interface { members, .. },
+        ) => diff_members(changes, name, old_members, members, true),
+        (ExportSurface::Interface { .. }, _) | (_, ExportSurface::Interface { .. }) => {
+            changes.push(Change {
+                path: name.to_string(),
+                kind: ChangeKind::Removed,
+                breaking: false,
+            });
+            changes.push(Change {
+                path: name.to_string(),
+                kind: ChangeKind::Added,
+                breaking: true,
+            });
+        }
+        // functions and resources are both bound as functions
+        _ => {}
+    }
+}
+
+/// Members added or removed, where added members are breaking for exports and
+/// removed members are breaking for built-in modules
+fn diff_members(
+    changes: &mut Vec<Change>,
+    parent: &str,
+    old: &BTreeSet<String>,
+    new: &BTreeSet<String>,
+    export: bool,
+) {
+    let members = old.union(new).collect::<BTreeSet<_>>();
+    for member in members {
+        let kind = match (old.contains(member), new.contains(member)) {
+            (false, true) => ChangeKind::Added,
+            (true, false) => ChangeKind::Removed,
+            _ => continue,
+        };
+        let breaking = (kind == ChangeKind::Added) == export;
+        changes.push(Change {
+            path: format!("{parent}#{member}"),
+            kind,
+            breaking,
+        });
+    }
+}
+
+fn alias(item: &ExportSurface) -> Option<&String> {
+    match item {
+        ExportSurface::Interface { alias, .. } => alias.as_ref(),
+        _ => None,
+    }
+}
+
+/// Whether two exported interfaces are the same interface under another
+/// version or another ID with the same alias
+fn same_interface(
+    old_name: &str,
+    old: &ExportSurface,
+    new_name: &str,
+    new: &ExportSurface,
+) -> bool {
+    let (ExportSurface::Interface { .. }, ExportSurface::Interface { .. }) = (old, new) else {
+        return false;
+    };
+    (old_name.contains(':') && unversioned(old_name) == unversioned(new_name))
+        || alias(old).is_some_and(|old_alias| alias(new) == Some(old_alias))
+}
+
+fn unversioned(name: &str) -> &str {
+    name.split_once('@').map_or(name, |(name, _)| name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diff(old: &str, new: &str) -> WorldDiff {
+        let world = |wit: &str| {
+            let mut resolve = Resolve::default();
+            let pkg = resolve.push_str("world.wit", wit).unwrap();
+            let world = resolve.select_world(&[pkg], None).unwrap();
+            (resolve, world)
+        };
+        let (old_resolve, old) = world(old);
+        let (new_resolve, new) = world(new);
+        diff_worlds(&old_resolve, old, &new_resolve, new, &[]).unwrap()
+    }
+
+    fn changes(changes: &[Change]) -> Vec<(&str, &ChangeKind, bool)> {
+        changes
+            .iter()
+            .map(|change| (change.path.as_str(), &change.kind, change.breaking))
+            .collect()
+    }
+
+    #[test]
+    fn added_exports_are_breaking() {
+        let diff = diff(
+            "package local:t; world w { export run: func(); }",
+            "package local:t; world w { export run: func(); export stop: func(); }",
+        );
+        assert_eq!(changes(&diff.exports), [("stop", &ChangeKind::Added, true)]);
+        assert!(diff.modules.is_empty());
+        assert!(!diff.compatible());
+    }
+
+    #[test]
+    fn removed_exports_are_compatible() {
+        let diff = diff(
+            "package local:t; world w { export run: func(); export stop: func(); }",
+            "package local:t; world w { export run: func(); }",
+        );
+        assert_eq!(
+            changes(&diff.exports),
+            [("stop", &ChangeKind::Removed, false)]
+        );
+        assert!(diff.compatible());
+    }
+
+    #[test]
+    fn removed_imports_are_breaking() {
+        let diff = diff(
+            "package local:t; interface host { log: func(); now: func() -> u64; } world w { import host; }",
+            "package local:t; interface host { log: func(); } world w { import host; import random: func() -> u64; }",
+        );
+        assert_eq!(
+            changes(&diff.modules),
+            [
+                ("local:t/host#now", &ChangeKind::Removed, true),
+                ("random", &ChangeKind::Added, false),
+            ]
+        );
+        assert!(!diff.compatible());
+    }
+
+    #[test]
+    fn changed_signatures_are_not_reported() {
+        let diff = diff(
+            "package local:t; world w { import log: func(msg: string); export run: func(n: u32); }",
+            "package local:t; world w { import log: func(level: u8, msg: string); export run: func(s: string) -> u32; }",
+        );
+        assert!(diff.exports.is_empty());
+        assert!(diff.modules.is_empty());
+        assert!(diff.compatible());
+    }
+
+    #[test]
+    fn renamed_functions_are_removed_and_added() {
+        let diff = diff(
+            "package local:t; world w { export run: func(); }",
+            "package local:t; world w { export start: func(); }",
+        );
+        assert_eq!(
+            changes(&diff.exports),
+            [
+                ("start", &ChangeKind::Added, true),
+                ("run", &ChangeKind::Removed, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn versioned_interfaces_are_renamed() {
+        let diff = diff(
+            "package local:t@0.1.0; interface api { run: func(); } world w { import api; export api; }",
+            "package local:t@0.2.0; interface api { run: func(); stop: func(); } world w { import api; export api; }",
+        );
+        let renamed = ChangeKind::Renamed {
+            from: "local:t/api@0.1.0".to_string(),
+        };
+        assert_eq!(
+            changes(&diff.exports),
+            [
+                ("local:t/api@0.2.0", &renamed, true),
+                ("local:t/api@0.2.0#stop", &ChangeKind::Added, true),
+            ]
+        );
+        assert_eq!(
+            changes(&diff.modules),
+            [
+                ("local:t/api@0.2.0", &renamed, true),
+                ("local:t/api@0.2.0#stop", &ChangeKind::Added, false),
+            ]
+        );
+    }
+}
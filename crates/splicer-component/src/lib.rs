@@ -1,19 +1,51 @@
+use spidermonkey_embedding_splicer::engine;
 use spidermonkey_embedding_splicer::stub_wasi::stub_wasi;
 use spidermonkey_embedding_splicer::wit::{self, export};
-use spidermonkey_embedding_splicer::wit::exports::local::spidermonkey_embedding_splicer::splicer::{Feature, Guest, SpliceResult};
+use spidermonkey_embedding_splicer::wit::exports::local::spidermonkey_embedding_splicer::splicer::{BindingsOptions, BindingsResult, Feature, Guest, GuestEngine, SpliceOptions, SpliceResult};
 use spidermonkey_embedding_splicer::splice;
 
 struct SpidermonkeyEmbeddingSplicerComponent;
 
+struct Engine(engine::Engine);
+
+impl GuestEngine for Engine {
+    fn new(engine: Vec<u8>) -> Result<Self, String> {
+        engine::Engine::parse(engine)
+            .map(Engine)
+            .map_err(|e| format!("{e:?}"))
+    }
+
+    fn splice_bindings(
+        &self,
+        features: Vec<Feature>,
+        wit_source: Option<String>,
+        wit_path: Option<String>,
+        world_name: Option<String>,
+        debug: bool,
+        options: SpliceOptions,
+    ) -> Result<SpliceResult, String> {
+        splice::splice_engine_bindings(
+            &self.0, features, wit_source, wit_path, world_name, debug, options,
+        )
+    }
+}
+
 impl Guest for SpidermonkeyEmbeddingSplicerComponent {
+    type Engine = Engine;
+
     fn stub_wasi(
         wasm: Vec<u8>,
         features: Vec<Feature>,
         wit_source: Option<String>,
         wit_path: Option<String>,
         world_name: Option<String>,
+        policy: Option<String>,
+        policy_dir: Option<String>,
     ) -> Result<Vec<u8>, String> {
-        stub_wasi(wasm, features, wit_source, wit_path, world_name).map_err(|e| e.to_string())
+        stub_wasi(
+            wasm, features, wit_source, wit_path, world_name, policy, policy_dir,
+        )
+        .map_err(|e| e.to_string())
     }
 
     fn splice_bindings(
@@ -23,8 +55,21 @@ impl Guest for SpidermonkeyEmbeddingSplicerComponent {
         wit_path: Option<String>,
         world_name: Option<String>,
         debug: bool,
+        options: SpliceOptions,
     ) -> Result<SpliceResult, String> {
-        splice::splice_bindings(engine, features, wit_source, wit_path, world_name, debug)
+        splice::splice_bindings(
+            engine, features, wit_source, wit_path, world_name, debug, options,
+        )
+    }
+
+    fn generate_bindings(
+        wit_source: Option<String>,
+        wit_path: Option<String>,
+        world_name: Option<String>,
+        features: Vec<Feature>,
+        options: BindingsOptions,
+    ) -> Result<BindingsResult, String> {
+        splice::generate_bindings(wit_source, wit_path, world_name, features, options)
     }
 }
 
@@ -0,0 +1,175 @@
+//! Native harness for the bindings test cases of `test/cases`
+//!
+//! Each case is componentized with the native componentization of the splicer,
+//! as `test/bindings.js` does with `componentize.js`, and run with Wasmtime
+//! against host implementations of the imports the case implements in JS.
+//! Componentizing fails when the engine has not been built, unless
+//! `SPLICER_TESTS_SKIP=1` is set to skip the cases.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, bail};
+use spidermonkey_embedding_splicer::componentize::componentize;
+use spidermonkey_embedding_splicer::resolve_wit;
+use spidermonkey_embedding_splicer::wit::exports::local::spidermonkey_embedding_splicer::splicer::{
+    Feature, SpliceOptions,
+};
+use wasmtime::component::{Component, Linker, ResourceTable};
+use wasmtime::{Engine, Store};
+use wasmtime_wasi::{WasiCtx, WasiCtxView, WasiView};
+
+/// Engine built by `make release`, unless `ENGINE_WASM_PATH` is set
+const DEFAULT_ENGINE_PATH: &str = "../../lib/starlingmonkey_embedding.wasm";
+
+/// Features `test/bindings.js` uses for cases that are not WASI targets
+pub const FEATURES: [Feature; 2] = [Feature::Http, Feature::FetchEvent];
+
+/// Features `test/bindings.js` uses for WASI targets, the cases with a `wit`
+/// directory instead of a `world.wit` file
+pub const WASI_FEATURES: [Feature; 5] = [
+    Feature::Stdio,
+    Feature::Random,
+    Feature::Clocks,
+    Feature::Http,
+    Feature::FetchEvent,
+];
+
+/// Cases whose `test.js` sets the `reportError` option, with its value
+const REPORT_ERROR: [(&str, &str); 1] = [("report-error", "report-error")];
+
+/// The `reportError` option `test/bindings.js` componentizes the `name` case with
+pub fn report_error(name: &str) -> Option<String> {
+    REPORT_ERROR
+        .iter()
+        .find(|(case, _)| *case == name)
+        .map(|(_, import)| import.to_string())
+}
+
+fn manifest_path(path: impl AsRef<Path>) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join(path)
+}
+
+/// Path of the engine to componentize with
+pub fn engine_path() -> PathBuf {
+    std::env::var("ENGINE_WASM_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| manifest_path(DEFAULT_ENGINE_PATH))
+}
+
+fn case_path(name: &str) -> PathBuf {
+    manifest_path("../../test/cases").join(name)
+}
+
+/// Componentize the `name` case, or `None` when skipping the cases
+pub fn componentize_case(name: &str) -> Result<Option<Vec<u8>>> {
+    let features = if case_path(name).join("world.wit").exists() {
+        FEATURES.to_vec()
+    } else {
+        WASI_FEATURES.to_vec()
+    };
+    componentize_case_with_options(
+        name,
+        features,
+        SpliceOptions {
+            diagnostics: false,
+            report_error: report_error(name),
+        },
+    )
+}
+
+/// [`componentize_case`] with other features than [`FEATURES`], and splice
+/// `options`
+pub fn componentize_case_with_options(
+    name: &str,
+    features: Vec<Feature>,
+    options: SpliceOptions,
+) -> Result<Option<Vec<u8>>> {
+    if std::env::var("SPLICER_TESTS_SKIP").is_ok_and(|skip| skip == "1") {
+        eprintln!("skipping case {name}, SPLICER_TESTS_SKIP is set");
+        return Ok(None);
+    }
+    let engine_path = engine_path();
+    let Ok(engine) = std::fs::read(&engine_path) else {
+        bail!(
+            "no engine at {}, build it with `make release` or set ENGINE_WASM_PATH, \
+             or set SPLICER_TESTS_SKIP=1 to skip the cases",
+            engine_path.display()
+        );
+    };
+
+    let case = case_path(name);
+    let world = case.join("world.wit");
+    let wit = if world.exists() {
+        world
+    } else {
+        case.join("wit")
+    };
+    let (resolve, id) = resolve_wit(None, &[wit])?;
+    componentize(
+        engine,
+        &case.join("source.js"),
+        &resolve,
+        id,
+        None,
+        features,
+        None,
+        None,
+        None,
+        false,
+        options,
+    )
+    .map(Some)
+}
+
+/// Store data of a case, `imports` implementing the imports of its world
+pub struct Host<T> {
+    wasi: WasiCtx,
+    table: ResourceTable,
+    pub imports: T,
+}
+
+impl<T: Send> WasiView for Host<T> {
+    fn ctx(&mut self) -> WasiCtxView<'_> {
+        WasiCtxView {
+            ctx: &mut self.wasi,
+            table: &mut self.table,
+        }
+    }
+}
+
+/// A case component along with a store and a linker providing WASI to it
+pub struct Runtime<T: 'static> {
+    pub component: Component,
+    pub linker: Linker<Host<T>>,
+    pub store: Store<Host<T>>,
+}
+
+impl<T: Send + 'static> Runtime<T> {
+    pub fn new(component: &[u8], imports: T) -> Result<Self> {
+        let engine = Engine::default();
+        let component = Component::new(&engine, component)?;
+        let mut linker = Linker::new(&engine);
+        wasmtime_wasi::p2::add_to_linker_sync(&mut linker)?;
+        let store = Store::new(
+            &engine,
+            Host {
+                wasi: WasiCtx::builder().inherit_stderr().build(),
+                table: ResourceTable::new(),
+                imports,
+            },
+        );
+        Ok(Self {
+            component,
+            linker,
+            store,
+        })
+    }
+
+    /// Trap in the imports that have not been linked, such as the stubbed
+    /// WASI imports of disabled features
+    pub fn trap_unknown_imports(&mut self) -> Result<()> {
+        self.linker
+            .define_unknown_imports_as_traps(&self.component)
+            .map_err(Into::into)
+    }
+}
@@ -0,0 +1,52 @@
+//! Snapshots of the JS bindings and core signatures generated for the world of
+//! each case of `test/cases`, to be reviewed with `cargo insta review` when the
+//! generated bindings change
+//!
+//! The intrinsics the bindings of all worlds share are left out of the JS
+//! snapshots, so that they only hold the bindings of each world.
+
+use spidermonkey_embedding_splicer::bindgen::componentize_bindgen;
+use spidermonkey_embedding_splicer::resolve_wit;
+use spidermonkey_embedding_splicer::splice::generate_world_bindings;
+use spidermonkey_embedding_splicer::wit::exports::local::spidermonkey_embedding_splicer::splicer::BindingsOptions;
+use splicer_tests::{FEATURES, report_error};
+
+/// Stands in for the intrinsics in the JS snapshots
+const INTRINSICS: &str = "/* intrinsics */\n";
+
+#[test]
+fn bindings() {
+    insta::glob!("../../../test/cases", "*/world.wit", |path| {
+        let (resolve, id) = resolve_wit(None, &[path.to_path_buf()]).unwrap();
+        let case = path.parent().unwrap().file_name().unwrap();
+        let report_error = report_error(&case.to_string_lossy());
+        let bindings = generate_world_bindings(
+            &resolve,
+            id,
+            None,
+            FEATURES.to_vec(),
+            BindingsOptions {
+                manifest: true,
+                report_error: report_error.clone(),
+            },
+        );
+        match bindings {
+            Ok(bindings) => {
+                let world = resolve.select_world(&[id], None).unwrap();
+                let intrinsics = componentize_bindgen(
+                    &resolve,
+                    world,
+                    &FEATURES.to_vec(),
+                    report_error.as_deref(),
+                )
+                .unwrap()
+                .intrinsics;
+                assert!(bindings.js_bindings.contains(&intrinsics));
+                let js = bindings.js_bindings.replacen(&intrinsics, INTRINSICS, 1);
+                insta::assert_snapshot!("js", js);
+                insta::assert_snapshot!("manifest", bindings.manifest.unwrap());
+            }
+            Err(err) => insta::assert_snapshot!("error", err),
+        }
+    });
+}
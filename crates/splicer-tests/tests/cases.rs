@@ -0,0 +1,1678 @@
+//! The cases of `test/bindings.js`, asserting the same outcomes with the JS
+//! import implementations of each case ported to host implementations
+//!
+//! The resource cases are in `resource_cases.rs`. Left out are:
+//!
+//! - `fetch-event-server`, `http-request` and `http-server`, which target the
+//!   worlds of `test/wit` and need a `wasi:http` host serving or making
+//!   requests, with network access for `http-request`
+//! - `keywords`, whose `type` parameter `bindgen!` cannot generate bindings for
+
+use anyhow::Result;
+use spidermonkey_embedding_splicer::wit::exports::local::spidermonkey_embedding_splicer::splicer::{
+    Feature, SpliceOptions,
+};
+use splicer_tests::{FEATURES, Host, Runtime, componentize_case, componentize_case_with_options};
+use wasmtime::component::{HasSelf, Val};
+
+mod hello {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/hello/world.wit",
+        world: "hello",
+    });
+
+    #[derive(Default)]
+    pub struct Imports;
+
+    impl get_num::Host for Imports {
+        fn get_num(&mut self, text: String) -> String {
+            text.len().to_string()
+        }
+    }
+}
+
+#[test]
+fn hello() -> Result<()> {
+    let Some(component) = componentize_case("hello")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, hello::Imports)?;
+    hello::Hello::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| {
+        &mut host.imports
+    })?;
+    rt.trap_unknown_imports()?;
+    let instance = hello::Hello::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    let exports = instance.exports();
+    assert_eq!(exports.call_hello(&mut rt.store, "hmm")?, "world hmm (5)");
+    Ok(())
+}
+
+#[test]
+fn diagnostics() -> Result<()> {
+    let features = [&FEATURES[..], &[Feature::Clocks]].concat();
+    let options = SpliceOptions {
+        diagnostics: true,
+        report_error: None,
+    };
+    let Some(component) = componentize_case_with_options("hello", features, options)? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, hello::Imports)?;
+    hello::Hello::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| {
+        &mut host.imports
+    })?;
+    rt.trap_unknown_imports()?;
+    let instance = rt.linker.instantiate(&mut rt.store, &rt.component)?;
+    let bindings = hello::Hello::new(&mut rt.store, &instance)?;
+    let exports = bindings.exports();
+    exports.call_hello(&mut rt.store, "one")?;
+    exports.call_hello(&mut rt.store, "two")?;
+
+    let interface = instance
+        .get_export_index(&mut rt.store, None, "componentize-js:diagnostics/exports")
+        .expect("missing diagnostics export");
+    let stats = instance
+        .get_export_index(&mut rt.store, Some(&interface), "stats")
+        .and_then(|stats| instance.get_func(&mut rt.store, stats))
+        .expect("missing stats function");
+    let mut results = [Val::Bool(false)];
+    stats.call(&mut rt.store, &[], &mut results)?;
+    let [Val::List(stats)] = results else {
+        panic!("unexpected stats {results:?}");
+    };
+    let [Val::Record(fields)] = stats.as_slice() else {
+        panic!("unexpected stats {stats:?}");
+    };
+    let field = |name: &str| &fields.iter().find(|(field, _)| field == name).unwrap().1;
+    assert_eq!(field("name"), &Val::String("exports#hello".into()));
+    assert_eq!(field("calls"), &Val::U64(2));
+    assert!(matches!(field("ticks"), Val::U64(ticks) if *ticks > 0));
+    Ok(())
+}
+
+mod simple_functions {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/simple-functions/world.wit",
+        world: "the-world",
+    });
+
+    #[derive(Default)]
+    pub struct Imports {
+        z: u32,
+    }
+
+    impl local::simple_functions::simple::Host for Imports {
+        fn f1(&mut self) {
+            self.z = 5;
+        }
+        fn f2(&mut self, a: u32) {
+            self.z += a;
+        }
+        fn f3(&mut self, a: u32, b: u32) {
+            self.z += a + b;
+        }
+        fn f4(&mut self) -> u32 {
+            self.z
+        }
+        fn f5(&mut self) -> (u32, u32) {
+            (self.z, self.z + 2)
+        }
+        fn f6(&mut self, a: u32, b: u32, c: u32) -> (u32, u32, u32) {
+            (self.z + a, self.z + b, self.z + c)
+        }
+    }
+}
+
+#[test]
+fn simple_functions() -> Result<()> {
+    use simple_functions::TheWorld;
+
+    let Some(component) = componentize_case("simple-functions")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, simple_functions::Imports::default())?;
+    TheWorld::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| {
+        &mut host.imports
+    })?;
+    rt.trap_unknown_imports()?;
+    let instance = TheWorld::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    let simple = instance.local_simple_functions_simple();
+    let store = &mut rt.store;
+    simple.call_f1(&mut *store)?;
+    simple.call_f2(&mut *store, 1)?;
+    simple.call_f3(&mut *store, 2, 3)?;
+    assert_eq!(simple.call_f4(&mut *store)?, 11);
+    assert_eq!(simple.call_f5(&mut *store)?, (11, 13));
+    assert_eq!(simple.call_f6(&mut *store, 1, 2, 3)?, (12, 13, 14));
+    Ok(())
+}
+
+mod integers {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/integers/world.wit",
+        world: "the-world",
+    });
+
+    #[derive(Default)]
+    pub struct Imports {
+        i1: u8,
+        i2: i8,
+        i3: u16,
+        i4: i16,
+        i5: u32,
+        i6: i32,
+        i7: u64,
+        i8: i64,
+    }
+
+    impl local::integers::integers::Host for Imports {
+        fn a1(&mut self, x: u8) {
+            self.i1 = x;
+        }
+        fn a2(&mut self, x: i8) {
+            self.i2 = x;
+        }
+        fn a3(&mut self, x: u16) {
+            self.i3 = x;
+        }
+        fn a4(&mut self, x: i16) {
+            self.i4 = x;
+        }
+        fn a5(&mut self, x: u32) {
+            self.i5 = x;
+        }
+        fn a6(&mut self, x: i32) {
+            self.i6 = x;
+        }
+        fn a7(&mut self, x: u64) {
+            self.i7 = x;
+        }
+        fn a8(&mut self, x: i64) {
+            self.i8 = x;
+        }
+        fn a9(
+            &mut self,
+            _p1: u8,
+            _p2: i8,
+            _p3: u16,
+            _p4: i16,
+            _p5: u32,
+            _p6: i32,
+            _p7: u64,
+            _p8: i64,
+        ) {
+        }
+        fn r1(&mut self) -> u8 {
+            self.i1
+        }
+        fn r2(&mut self) -> i8 {
+            self.i2
+        }
+        fn r3(&mut self) -> u16 {
+            self.i3
+        }
+        fn r4(&mut self) -> i16 {
+            self.i4
+        }
+        fn r5(&mut self) -> u32 {
+            self.i5
+        }
+        fn r6(&mut self) -> i32 {
+            self.i6
+        }
+        fn r7(&mut self) -> u64 {
+            self.i7
+        }
+        fn r8(&mut self) -> i64 {
+            self.i8
+        }
+        fn pair_ret(&mut self) -> (i64, u8) {
+            (-999, 1)
+        }
+    }
+}
+
+#[test]
+fn integers() -> Result<()> {
+    use integers::TheWorld;
+
+    let Some(component) = componentize_case("integers")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, integers::Imports::default())?;
+    TheWorld::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| {
+        &mut host.imports
+    })?;
+    rt.trap_unknown_imports()?;
+    let instance = TheWorld::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    let integers = instance.local_integers_integers();
+    let store = &mut rt.store;
+    integers.call_a1(&mut *store, 5)?;
+    assert_eq!(integers.call_r1(&mut *store)?, 5);
+    integers.call_a2(&mut *store, -5)?;
+    assert_eq!(integers.call_r2(&mut *store)?, -5);
+    integers.call_a3(&mut *store, 100)?;
+    assert_eq!(integers.call_r3(&mut *store)?, 100);
+    integers.call_a4(&mut *store, -100)?;
+    assert_eq!(integers.call_r4(&mut *store)?, -100);
+    integers.call_a5(&mut *store, 5000)?;
+    assert_eq!(integers.call_r5(&mut *store)?, 5000);
+    integers.call_a6(&mut *store, -5000)?;
+    assert_eq!(integers.call_r6(&mut *store)?, -5000);
+    integers.call_a7(&mut *store, 500000)?;
+    assert_eq!(integers.call_r7(&mut *store)?, 500000);
+    Ok(())
+}
+
+mod strings {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/strings/world.wit",
+        world: "the-world",
+    });
+
+    #[derive(Default)]
+    pub struct Imports {
+        z: String,
+    }
+
+    impl local::strings::strings::Host for Imports {
+        fn a(&mut self, x: String) {
+            self.z = x;
+        }
+        fn b(&mut self) -> String {
+            self.z.clone()
+        }
+        fn c(&mut self, a: String, b: String) -> String {
+            a + &b
+        }
+    }
+}
+
+#[test]
+fn strings() -> Result<()> {
+    use strings::TheWorld;
+
+    let Some(component) = componentize_case("strings")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, strings::Imports::default())?;
+    TheWorld::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| {
+        &mut host.imports
+    })?;
+    rt.trap_unknown_imports()?;
+    let instance = TheWorld::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    let strings = instance.local_strings_strings();
+    let store = &mut rt.store;
+    strings.call_a(&mut *store, "test")?;
+    assert_eq!(strings.call_b(&mut *store)?, "test");
+    assert_eq!(strings.call_c(&mut *store, "😀", "😀")?, "😀😀");
+
+    let long_string = "long string\n".repeat(5000);
+    strings.call_a(&mut *store, &long_string)?;
+    assert_eq!(strings.call_b(&mut *store)?, long_string);
+    Ok(())
+}
+
+mod report_error {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/report-error/world.wit",
+        world: "report-error",
+    });
+
+    #[derive(Default)]
+    pub struct Imports {
+        pub reports: Vec<(String, String)>,
+    }
+
+    impl ReportErrorImports for Imports {
+        fn report_error(&mut self, message: String, stack: String) {
+            self.reports.push((message, stack));
+        }
+    }
+}
+
+#[test]
+fn report_error() -> Result<()> {
+    use report_error::ReportError;
+
+    let Some(component) = componentize_case("report-error")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, report_error::Imports::default())?;
+    ReportError::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| {
+        &mut host.imports
+    })?;
+    rt.trap_unknown_imports()?;
+    let instance = ReportError::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    assert_eq!(instance.call_succeed(&mut rt.store, "ok")?, "ok");
+    assert!(rt.store.data().imports.reports.is_empty());
+
+    assert!(instance.call_fail(&mut rt.store, "boom").is_err());
+    let [(message, stack)] = rt.store.data().imports.reports.as_slice() else {
+        panic!("expected a single report");
+    };
+    assert_eq!(message, "Error: boom");
+    assert!(stack.contains("fail"), "{stack}");
+    Ok(())
+}
+
+#[test]
+fn missing_export() -> Result<()> {
+    let err = match componentize_case("missing-export") {
+        Ok(None) => return Ok(()),
+        Ok(Some(_)) => panic!("componentized without the expected export"),
+        Err(err) => format!("{err:?}"),
+    };
+    assert!(
+        err.contains(r#""missing-export.js" does not export a "expected" function"#),
+        "{err}"
+    );
+    assert!(err.contains("Try defining it:"), "{err}");
+    assert!(err.contains("export function expected() {};"), "{err}");
+    Ok(())
+}
+
+#[test]
+fn bad_binding() -> Result<()> {
+    let err = match componentize_case("bad-binding") {
+        Ok(None) => return Ok(()),
+        Ok(Some(_)) => panic!("componentized importing an undefined module"),
+        Err(err) => format!("{err:?}"),
+    };
+    assert!(
+        err.contains(r#"ReferenceError: Error loading module "not:world-defined""#),
+        "{err}"
+    );
+    Ok(())
+}
+
+#[test]
+fn args() -> Result<()> {
+    mod args {
+        wasmtime::component::bindgen!({
+            path: "../../test/cases/args/world.wit",
+            world: "count",
+        });
+    }
+    use args::Count;
+
+    let Some(component) = componentize_case("args")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, ())?;
+    rt.trap_unknown_imports()?;
+    let instance = Count::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    let count = instance
+        .exports()
+        .call_count_a(&mut rt.store, "how many a's are there in this string?")?;
+    assert_eq!(count, 3);
+    Ok(())
+}
+
+mod char {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/char/world.wit",
+        world: "the-world",
+    });
+
+    pub struct Imports {
+        char: char,
+    }
+
+    impl Default for Imports {
+        fn default() -> Self {
+            Self { char: 'F' }
+        }
+    }
+
+    impl local::char::chars::Host for Imports {
+        fn take_char(&mut self, x: char) {
+            self.char = x;
+        }
+        fn return_char(&mut self) -> char {
+            self.char
+        }
+    }
+}
+
+#[test]
+fn char() -> Result<()> {
+    use char::TheWorld;
+
+    let Some(component) = componentize_case("char")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, char::Imports::default())?;
+    TheWorld::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| {
+        &mut host.imports
+    })?;
+    rt.trap_unknown_imports()?;
+    let instance = TheWorld::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    let chars = instance.local_char_chars();
+    chars.call_take_char(&mut rt.store, 'p')?;
+    assert_eq!(chars.call_return_char(&mut rt.store)?, 'p');
+    Ok(())
+}
+
+mod conventions {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/conventions/world.wit",
+        world: "the-world",
+    });
+
+    use local::conventions::conventions::LudicrousSpeed;
+
+    #[derive(Default)]
+    pub struct Imports {
+        pub x: Option<LudicrousSpeed>,
+    }
+
+    impl local::conventions::conventions::Host for Imports {
+        fn foo(&mut self, x: LudicrousSpeed) {
+            self.x = Some(x);
+        }
+    }
+}
+
+#[test]
+fn conventions() -> Result<()> {
+    use conventions::TheWorld;
+    use conventions::exports::local::conventions::conventions::LudicrousSpeed;
+
+    let Some(component) = componentize_case("conventions")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, conventions::Imports::default())?;
+    TheWorld::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| {
+        &mut host.imports
+    })?;
+    rt.trap_unknown_imports()?;
+    let instance = TheWorld::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    let val = LudicrousSpeed {
+        how_fast_are_you_going: 999,
+        i_am_going_extremely_slow: 999999,
+    };
+    instance
+        .local_conventions_conventions()
+        .call_foo(&mut rt.store, val)?;
+    let x = rt
+        .store
+        .data()
+        .imports
+        .x
+        .as_ref()
+        .expect("foo was not called");
+    assert_eq!(x.how_fast_are_you_going, 999);
+    assert_eq!(x.i_am_going_extremely_slow, 999999);
+    Ok(())
+}
+
+mod empty {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/empty/world.wit",
+        world: "empty",
+    });
+}
+
+#[test]
+fn empty() -> Result<()> {
+    let Some(component) = componentize_case("empty")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, ())?;
+    rt.trap_unknown_imports()?;
+    empty::Empty::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+    Ok(())
+}
+
+mod flags {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/flags/world.wit",
+        world: "the-flags",
+    });
+
+    use local::flags::flags::{Flag1, Flag2, Flag4, Flag8, Flag16, Flag32};
+
+    #[derive(Default)]
+    pub struct Imports;
+
+    impl local::flags::flags::Host for Imports {
+        fn roundtrip_flag1(&mut self, x: Flag1) -> Flag1 {
+            x | Flag1::B0
+        }
+        fn roundtrip_flag2(&mut self, x: Flag2) -> Flag2 {
+            x | Flag2::B1
+        }
+        fn roundtrip_flag4(&mut self, x: Flag4) -> Flag4 {
+            x | Flag4::B3
+        }
+        fn roundtrip_flag8(&mut self, x: Flag8) -> Flag8 {
+            x | Flag8::B7
+        }
+        fn roundtrip_flag16(&mut self, x: Flag16) -> Flag16 {
+            x | Flag16::B15
+        }
+        fn roundtrip_flag32(&mut self, x: Flag32) -> Flag32 {
+            x | Flag32::B31
+        }
+    }
+}
+
+#[test]
+fn flags() -> Result<()> {
+    use flags::TheFlags;
+    use flags::exports::local::flags::flags::{Flag1, Flag2, Flag4, Flag8, Flag16, Flag32};
+
+    let Some(component) = componentize_case("flags")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, flags::Imports)?;
+    TheFlags::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| {
+        &mut host.imports
+    })?;
+    rt.trap_unknown_imports()?;
+    let instance = TheFlags::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    let flags = instance.local_flags_flags();
+    let store = &mut rt.store;
+    assert_eq!(
+        flags.call_roundtrip_flag1(&mut *store, Flag1::empty())?,
+        Flag1::B0
+    );
+    assert_eq!(
+        flags.call_roundtrip_flag2(&mut *store, Flag2::B0)?,
+        Flag2::B0 | Flag2::B1
+    );
+    assert_eq!(
+        flags.call_roundtrip_flag4(&mut *store, Flag4::B0)?,
+        Flag4::B0 | Flag4::B3
+    );
+    assert_eq!(
+        flags.call_roundtrip_flag8(&mut *store, Flag8::B0)?,
+        Flag8::B0 | Flag8::B7
+    );
+    assert_eq!(
+        flags.call_roundtrip_flag16(&mut *store, Flag16::B0)?,
+        Flag16::B0 | Flag16::B15
+    );
+    assert_eq!(
+        flags.call_roundtrip_flag32(&mut *store, Flag32::B0)?,
+        Flag32::B0 | Flag32::B31
+    );
+    Ok(())
+}
+
+mod floats {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/floats/world.wit",
+        world: "the-world",
+    });
+
+    #[derive(Default)]
+    pub struct Imports {
+        float32: f32,
+        float64: f64,
+    }
+
+    impl local::floats::floats::Host for Imports {
+        fn float32_param(&mut self, x: f32) {
+            self.float32 = x;
+        }
+        fn float64_param(&mut self, x: f64) {
+            self.float64 = x;
+        }
+        fn float32_result(&mut self) -> f32 {
+            self.float32
+        }
+        fn float64_result(&mut self) -> f64 {
+            self.float64
+        }
+        fn float64_result2(&mut self) -> f64 {
+            3.0
+        }
+        fn float32_result2(&mut self) -> f32 {
+            3.0
+        }
+    }
+}
+
+#[test]
+fn floats() -> Result<()> {
+    use floats::TheWorld;
+
+    let Some(component) = componentize_case("floats")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, floats::Imports::default())?;
+    TheWorld::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| {
+        &mut host.imports
+    })?;
+    rt.trap_unknown_imports()?;
+    let instance = TheWorld::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    let floats = instance.local_floats_floats();
+    let store = &mut rt.store;
+    floats.call_float32_param(&mut *store, 1.5)?;
+    floats.call_float64_param(&mut *store, 1.51111111111111)?;
+    assert_eq!(floats.call_float32_result(&mut *store)?, 1.5);
+    assert_eq!(floats.call_float64_result(&mut *store)?, 1.51111111111111);
+    floats.call_float32_param(&mut *store, 3.0)?;
+    floats.call_float64_param(&mut *store, 3.0)?;
+    assert_eq!(floats.call_float32_result(&mut *store)?, 3.0);
+    assert_eq!(floats.call_float64_result(&mut *store)?, 3.0);
+    assert_eq!(floats.call_float64_result2(&mut *store)?, 3.0);
+    assert_eq!(floats.call_float32_result2(&mut *store)?, 3.0);
+    Ok(())
+}
+
+mod import_func {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/import-func/world.wit",
+        world: "foo",
+    });
+
+    #[derive(Default)]
+    pub struct Imports;
+
+    impl FooImports for Imports {
+        fn foo(&mut self) {}
+        fn foo1(&mut self) -> String {
+            String::new()
+        }
+        fn foo2(&mut self, _x: String) {}
+        fn foo3(&mut self, _x: Vec<u8>) -> Result<Option<u32>, String> {
+            Ok(None)
+        }
+    }
+}
+
+#[test]
+fn import_func() -> Result<()> {
+    use import_func::Foo;
+
+    // the source throws during initialization unless the imports are functions
+    let Some(component) = componentize_case("import-func")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, import_func::Imports)?;
+    Foo::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| &mut host.imports)?;
+    rt.trap_unknown_imports()?;
+    Foo::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+    Ok(())
+}
+
+mod import_duplicated_interface {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/import-duplicated-interface/wit",
+        world: "hello",
+    });
+
+    #[derive(Default)]
+    pub struct Imports;
+
+    impl local::hello::hello::Host for Imports {
+        fn hello(&mut self, name: String) -> String {
+            format!("Hello 1.0.0, {name}")
+        }
+    }
+
+    impl local::hello_second::hello::Host for Imports {
+        fn hello(&mut self, name: Option<String>) -> Option<String> {
+            name.map(|name| format!("Hello 2.0.0, {name}"))
+        }
+    }
+}
+
+#[test]
+fn import_duplicated_interface() -> Result<()> {
+    use import_duplicated_interface::Hello;
+
+    let Some(component) = componentize_case("import-duplicated-interface")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, import_duplicated_interface::Imports)?;
+    Hello::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| &mut host.imports)?;
+    rt.trap_unknown_imports()?;
+    let instance = Hello::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    let exports = instance.exports();
+    let store = &mut rt.store;
+    assert_eq!(
+        exports.call_hello(&mut *store, "hello")?,
+        "world hello (Hello 1.0.0, world)"
+    );
+    assert_eq!(
+        exports.call_hello(&mut *store, "hello-second")?,
+        "world hello-second (Hello 2.0.0, world)"
+    );
+    assert_eq!(
+        exports.call_hello(&mut *store, "unknown")?,
+        "world unknown unknown"
+    );
+    Ok(())
+}
+
+mod kebab_fn_impt {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/kebab-fn-impt/world.wit",
+        world: "my-world",
+    });
+
+    #[derive(Default)]
+    pub struct Imports {
+        pub log: Option<String>,
+    }
+
+    impl MyWorldImports for Imports {
+        fn print(&mut self, msg: String) {
+            self.log = Some(msg);
+        }
+    }
+}
+
+#[test]
+fn kebab_fn_impt() -> Result<()> {
+    use kebab_fn_impt::MyWorld;
+
+    let Some(component) = componentize_case("kebab-fn-impt")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, kebab_fn_impt::Imports::default())?;
+    MyWorld::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| {
+        &mut host.imports
+    })?;
+    rt.trap_unknown_imports()?;
+    let instance = MyWorld::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    instance.call_run(&mut rt.store)?;
+    assert_eq!(rt.store.data().imports.log.as_deref(), Some("hi"));
+    Ok(())
+}
+
+mod lists {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/lists/world.wit",
+        world: "the-lists",
+    });
+}
+
+#[test]
+fn lists() -> Result<()> {
+    use lists::TheLists;
+
+    let Some(component) = componentize_case("lists")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, ())?;
+    // the exports of the case do not call its imports
+    rt.trap_unknown_imports()?;
+    let instance = TheLists::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    instance
+        .local_lists_lists()
+        .call_list_u8_param(&mut rt.store, &[1, 2, 3])?;
+    Ok(())
+}
+
+mod many_arguments {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/many-arguments/world.wit",
+        world: "the-world",
+    });
+
+    use local::many_arguments::manyarg::BigStruct;
+
+    #[derive(Default)]
+    pub struct Imports {
+        pub args: Vec<u64>,
+        pub big: Option<BigStruct>,
+    }
+
+    impl local::many_arguments::manyarg::Host for Imports {
+        fn many_args(
+            &mut self,
+            a1: u64,
+            a2: u64,
+            a3: u64,
+            a4: u64,
+            a5: u64,
+            a6: u64,
+            a7: u64,
+            a8: u64,
+            a9: u64,
+            a10: u64,
+            a11: u64,
+            a12: u64,
+            a13: u64,
+            a14: u64,
+            a15: u64,
+            a16: u64,
+        ) {
+            self.args = vec![
+                a1, a2, a3, a4, a5, a6, a7, a8, a9, a10, a11, a12, a13, a14, a15, a16,
+            ];
+        }
+        fn big_argument(&mut self, x: BigStruct) {
+            self.big = Some(x);
+        }
+    }
+}
+
+#[test]
+fn many_arguments() -> Result<()> {
+    use many_arguments::TheWorld;
+    use many_arguments::exports::local::many_arguments::manyarg::BigStruct;
+
+    let Some(component) = componentize_case("many-arguments")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, many_arguments::Imports::default())?;
+    TheWorld::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| {
+        &mut host.imports
+    })?;
+    rt.trap_unknown_imports()?;
+    let instance = TheWorld::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    let manyarg = instance.local_many_arguments_manyarg();
+    manyarg.call_many_args(
+        &mut rt.store,
+        1,
+        2,
+        3,
+        4,
+        5,
+        6,
+        7,
+        8,
+        9,
+        10,
+        11,
+        12,
+        13,
+        14,
+        15,
+        16,
+    )?;
+    assert_eq!(rt.store.data().imports.args, (1..=16).collect::<Vec<_>>());
+
+    let field = |n: u32| format!("{n}n");
+    let big = BigStruct {
+        a1: field(1),
+        a2: field(2),
+        a3: field(3),
+        a4: field(4),
+        a5: field(5),
+        a6: field(6),
+        a7: field(7),
+        a8: field(8),
+        a9: field(9),
+        a10: field(10),
+        a11: field(11),
+        a12: field(12),
+        a13: field(13),
+        a14: field(14),
+        a15: field(15),
+        a16: field(16),
+        a17: field(17),
+        a18: field(18),
+        a19: field(19),
+        a20: field(20),
+    };
+    manyarg.call_big_argument(&mut rt.store, &big)?;
+    let received = rt.store.data().imports.big.as_ref().unwrap();
+    assert_eq!(received.a1, "1n");
+    assert_eq!(received.a10, "10n");
+    assert_eq!(received.a20, "20n");
+    Ok(())
+}
+
+mod records {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/records/world.wit",
+        world: "the-world",
+    });
+
+    use local::records::records::{Aggregates, Empty, ReallyFlags, Scalars, TupleTypedef2};
+
+    #[derive(Default)]
+    pub struct Imports {
+        tuple: Option<(char, u32)>,
+        empty: Option<Empty>,
+        scalars: Option<Scalars>,
+        flags: Option<ReallyFlags>,
+        aggregates: Option<Aggregates>,
+    }
+
+    impl local::records::records::Host for Imports {
+        fn tuple_arg(&mut self, x: (char, u32)) {
+            self.tuple = Some(x);
+        }
+        fn tuple_result(&mut self) -> (char, u32) {
+            self.tuple.unwrap()
+        }
+        fn empty_arg(&mut self, x: Empty) {
+            self.empty = Some(x);
+        }
+        fn empty_result(&mut self) -> Empty {
+            self.empty.unwrap()
+        }
+        fn scalar_arg(&mut self, x: Scalars) {
+            self.scalars = Some(x);
+        }
+        fn scalar_result(&mut self) -> Scalars {
+            self.scalars.unwrap()
+        }
+        fn flags_arg(&mut self, x: ReallyFlags) {
+            self.flags = Some(x);
+        }
+        fn flags_result(&mut self) -> ReallyFlags {
+            self.flags.unwrap()
+        }
+        fn aggregate_arg(&mut self, x: Aggregates) {
+            self.aggregates = Some(x);
+        }
+        fn aggregate_result(&mut self) -> Aggregates {
+            self.aggregates.clone().unwrap()
+        }
+        fn typedef_inout(&mut self, e: TupleTypedef2) -> i32 {
+            e.0
+        }
+    }
+}
+
+#[test]
+fn records() -> Result<()> {
+    use records::TheWorld;
+
+    let Some(component) = componentize_case("records")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, records::Imports::default())?;
+    TheWorld::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| {
+        &mut host.imports
+    })?;
+    rt.trap_unknown_imports()?;
+    let instance = TheWorld::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    let records = instance.local_records_records();
+    records.call_tuple_arg(&mut rt.store, ('z', 23))?;
+    assert_eq!(records.call_tuple_result(&mut rt.store)?, ('z', 23));
+    Ok(())
+}
+
+mod rename_interface {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/rename-interface/world.wit",
+        world: "the-world",
+    });
+
+    #[derive(Default)]
+    pub struct Imports;
+
+    impl local::rename_interface::foo::Host for Imports {}
+
+    impl other_name::Host for Imports {
+        fn a(&mut self) -> other_name::Bar {
+            other_name::Bar { dummy: false }
+        }
+    }
+}
+
+#[test]
+fn rename_interface() -> Result<()> {
+    use rename_interface::TheWorld;
+
+    let Some(component) = componentize_case("rename-interface")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, rename_interface::Imports)?;
+    TheWorld::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| {
+        &mut host.imports
+    })?;
+    rt.trap_unknown_imports()?;
+    TheWorld::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+    Ok(())
+}
+
+mod repeated_calls {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/repeated-calls/world.wit",
+        world: "hello",
+    });
+}
+
+#[test]
+fn repeated_calls() -> Result<()> {
+    use repeated_calls::Hello;
+
+    let Some(component) = componentize_case("repeated-calls")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, ())?;
+    rt.trap_unknown_imports()?;
+    let instance = Hello::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    let exports = instance.exports();
+    for attempt in 0..2000 {
+        let hello = exports.call_hello(&mut rt.store)?;
+        assert_eq!(hello, "hello", "failed on attempt [{attempt}]");
+    }
+    Ok(())
+}
+
+mod simple_lists {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/simple-lists/world.wit",
+        world: "my-world",
+    });
+
+    #[derive(Default)]
+    pub struct Imports {
+        list: Vec<u32>,
+    }
+
+    impl local::simple_lists::simple_lists::Host for Imports {
+        fn simple_list1(&mut self, l: Vec<u32>) {
+            self.list = l;
+        }
+        fn simple_list2(&mut self) -> Vec<u32> {
+            self.list.clone()
+        }
+        fn simple_list4(&mut self, mut l: Vec<Vec<u32>>) -> Vec<Vec<u32>> {
+            l[0] = vec![0];
+            l
+        }
+    }
+}
+
+#[test]
+fn simple_lists() -> Result<()> {
+    use simple_lists::MyWorld;
+
+    let Some(component) = componentize_case("simple-lists")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, simple_lists::Imports::default())?;
+    MyWorld::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| {
+        &mut host.imports
+    })?;
+    rt.trap_unknown_imports()?;
+    let instance = MyWorld::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    let lists = instance.local_simple_lists_simple_lists();
+    let store = &mut rt.store;
+    lists.call_simple_list1(&mut *store, &[1, 2, 3])?;
+    assert_eq!(lists.call_simple_list2(&mut *store)?, [1, 2, 3]);
+    assert_eq!(
+        lists.call_simple_list4(&mut *store, &[vec![1, 2, 3], vec![2, 3, 4]])?,
+        [vec![0], vec![2, 3, 4]]
+    );
+    Ok(())
+}
+
+mod small_anonymous {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/small-anonymous/world.wit",
+        world: "the-world",
+    });
+
+    use local::small_anonymous::anon::Error;
+
+    #[derive(Default)]
+    pub struct Imports {
+        ran: bool,
+    }
+
+    impl local::small_anonymous::anon::Host for Imports {
+        fn option_test(&mut self) -> Result<Option<String>, Error> {
+            if !self.ran {
+                self.ran = true;
+                Err(Error::Success)
+            } else {
+                Ok(Some("outer".into()))
+            }
+        }
+    }
+}
+
+#[test]
+fn small_anonymous() -> Result<()> {
+    use small_anonymous::TheWorld;
+    use small_anonymous::exports::local::small_anonymous::anon::Error;
+
+    let Some(component) = componentize_case("small-anonymous")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, small_anonymous::Imports::default())?;
+    TheWorld::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| {
+        &mut host.imports
+    })?;
+    rt.trap_unknown_imports()?;
+    let instance = TheWorld::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    let anon = instance.local_small_anonymous_anon();
+    let store = &mut rt.store;
+    assert_eq!(anon.call_option_test(&mut *store)?, Err(Error::Failure));
+    assert_eq!(anon.call_option_test(&mut *store)?, Err(Error::Success));
+    assert_eq!(
+        anon.call_option_test(&mut *store)?,
+        Ok(Some("outer".into()))
+    );
+    assert_eq!(anon.call_option_test(&mut *store)?, Ok(Some("yay".into())));
+    Ok(())
+}
+
+mod smoke_default {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/smoke-default/world.wit",
+        world: "the-world",
+    });
+}
+
+#[test]
+fn smoke_default() -> Result<()> {
+    use smoke_default::TheWorld;
+
+    let Some(component) = componentize_case("smoke-default")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, ())?;
+    rt.trap_unknown_imports()?;
+    let instance = TheWorld::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    instance.call_y(&mut rt.store)?;
+    Ok(())
+}
+
+mod smoke_export {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/smoke-export/world.wit",
+        world: "the-world",
+    });
+}
+
+#[test]
+fn smoke_export() -> Result<()> {
+    use smoke_export::TheWorld;
+
+    let Some(component) = componentize_case("smoke-export")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, ())?;
+    rt.trap_unknown_imports()?;
+    let instance = TheWorld::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    instance.the_name().call_y(&mut rt.store)?;
+    Ok(())
+}
+
+mod type_imports {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/type-imports/wit",
+        world: "type-imports",
+    });
+
+    #[derive(Default)]
+    pub struct Imports;
+
+    impl local::b::foo::Host for Imports {}
+}
+
+#[test]
+fn type_imports() -> Result<()> {
+    use type_imports::TypeImports;
+
+    let Some(component) = componentize_case("type-imports")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, type_imports::Imports)?;
+    TypeImports::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| {
+        &mut host.imports
+    })?;
+    rt.trap_unknown_imports()?;
+    let instance = TypeImports::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    assert_eq!(instance.call_test(&mut rt.store)?.thing, 5);
+    Ok(())
+}
+
+mod use_across_interfaces {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/use-across-interfaces/world.wit",
+        world: "baz",
+    });
+
+    use local::use_across_interfaces::foo::A;
+
+    #[derive(Default)]
+    pub struct Imports;
+
+    impl local::use_across_interfaces::foo::Host for Imports {
+        fn x(&mut self) -> A {
+            A { dummy: false }
+        }
+    }
+
+    impl local::use_across_interfaces::bar::Host for Imports {
+        fn x(&mut self) -> A {
+            A { dummy: false }
+        }
+    }
+
+    impl baz::Host for Imports {
+        fn x(&mut self) -> A {
+            A { dummy: false }
+        }
+    }
+}
+
+#[test]
+fn use_across_interfaces() -> Result<()> {
+    use use_across_interfaces::Baz;
+
+    let Some(component) = componentize_case("use-across-interfaces")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, use_across_interfaces::Imports)?;
+    Baz::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| &mut host.imports)?;
+    rt.trap_unknown_imports()?;
+    Baz::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+    Ok(())
+}
+
+mod versions {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/versions/wit",
+        world: "hello",
+    });
+
+    #[derive(Default)]
+    pub struct Imports;
+
+    impl local::hello1_0_0::hello::Host for Imports {
+        fn hello(&mut self, name: String) -> String {
+            format!("Hello 1.0.0, {name}")
+        }
+    }
+
+    impl local::hello2_0_0::hello::Host for Imports {
+        fn hello(&mut self, name: Option<String>) -> Option<String> {
+            name.map(|name| format!("Hello 2.0.0, {name}"))
+        }
+    }
+}
+
+#[test]
+fn versions() -> Result<()> {
+    use versions::Hello;
+
+    let Some(component) = componentize_case("versions")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, versions::Imports)?;
+    Hello::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| &mut host.imports)?;
+    rt.trap_unknown_imports()?;
+    let instance = Hello::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    let store = &mut rt.store;
+    assert_eq!(
+        instance
+            .local_hello1_0_0_hello()
+            .call_hello(&mut *store, "foo")?,
+        "Hello 1.0.0, foo"
+    );
+    let hello2 = instance.local_hello2_0_0_hello();
+    assert_eq!(
+        hello2.call_hello(&mut *store, Some("bar"))?.as_deref(),
+        Some("Hello 2.0.0, bar")
+    );
+    assert_eq!(hello2.call_hello(&mut *store, None)?, None);
+    Ok(())
+}
+
+mod wall_clock {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/wall-clock/world.wit",
+        world: "wall-clock-world",
+    });
+
+    use std::time::SystemTime;
+
+    use local::wall_clock::wall_clock_t::Datetime;
+
+    #[derive(Default)]
+    pub struct Imports;
+
+    impl local::wall_clock::wall_clock_t::Host for Imports {
+        fn now(&mut self) -> Datetime {
+            let now = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap();
+            Datetime {
+                seconds: now.as_secs(),
+                nanoseconds: now.subsec_nanos(),
+            }
+        }
+    }
+
+    impl local::wall_clock::random_t::Host for Imports {
+        fn get_random_bytes(&mut self, len: u64) -> Vec<u8> {
+            vec![4; len as usize]
+        }
+        fn get_random_u64(&mut self) -> u64 {
+            4
+        }
+        fn insecure_random(&mut self) -> (u64, u64) {
+            (4, 4)
+        }
+    }
+}
+
+#[test]
+fn wall_clock() -> Result<()> {
+    use std::time::SystemTime;
+
+    use wall_clock::WallClockWorld;
+
+    let Some(component) = componentize_case("wall-clock")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, wall_clock::Imports)?;
+    WallClockWorld::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| {
+        &mut host.imports
+    })?;
+    rt.trap_unknown_imports()?;
+    let instance = WallClockWorld::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_secs();
+    let result = instance.call_test(&mut rt.store)?;
+    assert_eq!(result[..10], format!("NOW: {now}")[..10]);
+    Ok(())
+}
+
+mod variants {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/variants/world.wit",
+        world: "my-world",
+    });
+
+    use local::variants::variants::{
+        Casts1, Casts2, Casts3, Casts4, Casts5, Casts6, E1, IsClone, MyErrno, V1,
+    };
+
+    type Options = (
+        Option<bool>,
+        Option<(bool,)>,
+        Option<u32>,
+        Option<E1>,
+        Option<f32>,
+        Option<E1>,
+        Option<Option<bool>>,
+    );
+    type Results = (
+        Result<(), ()>,
+        Result<(), E1>,
+        Result<E1, ()>,
+        Result<(bool,), (bool,)>,
+        Result<u32, V1>,
+        Result<String, Vec<u8>>,
+    );
+
+    #[derive(Default)]
+    pub struct Imports {
+        e1: Option<E1>,
+        v1: Option<V1>,
+        bool: bool,
+        options: Option<Options>,
+        results: Option<Results>,
+        is_clone: Option<IsClone>,
+    }
+
+    impl local::variants::variants::Host for Imports {
+        fn e1_arg(&mut self, x: E1) {
+            self.e1 = Some(x);
+        }
+        fn e1_result(&mut self) -> E1 {
+            self.e1.unwrap()
+        }
+        fn v1_arg(&mut self, x: V1) {
+            self.v1 = Some(x);
+        }
+        fn v1_result(&mut self) -> V1 {
+            self.v1.clone().unwrap()
+        }
+        fn bool_arg(&mut self, x: bool) {
+            self.bool = x;
+        }
+        fn bool_result(&mut self) -> bool {
+            self.bool
+        }
+        fn option_arg(
+            &mut self,
+            a: Option<bool>,
+            b: Option<(bool,)>,
+            c: Option<u32>,
+            d: Option<E1>,
+            e: Option<f32>,
+            f: Option<E1>,
+            g: Option<Option<bool>>,
+        ) {
+            self.options = Some((a, b, c, d, e, f, g));
+        }
+        fn option_result(&mut self) -> Options {
+            self.options.unwrap()
+        }
+        fn casts(
+            &mut self,
+            a: Casts1,
+            b: Casts2,
+            c: Casts3,
+            d: Casts4,
+            e: Casts5,
+            f: Casts6,
+        ) -> (Casts1, Casts2, Casts3, Casts4, Casts5, Casts6) {
+            (a, b, c, d, e, f)
+        }
+        fn result_arg(
+            &mut self,
+            a: Result<(), ()>,
+            b: Result<(), E1>,
+            c: Result<E1, ()>,
+            d: Result<(bool,), (bool,)>,
+            e: Result<u32, V1>,
+            f: Result<String, Vec<u8>>,
+        ) {
+            self.results = Some((a, b, c, d, e, f));
+        }
+        fn result_result(&mut self) -> Results {
+            self.results.clone().unwrap()
+        }
+        fn return_result_sugar(&mut self) -> Result<i32, MyErrno> {
+            Err(MyErrno::Bad1)
+        }
+        fn return_result_sugar2(&mut self) -> Result<(), MyErrno> {
+            Err(MyErrno::Bad2)
+        }
+        fn return_result_sugar3(&mut self) -> Result<MyErrno, MyErrno> {
+            Err(MyErrno::Bad1)
+        }
+        fn return_result_sugar4(&mut self) -> Result<(i32, u32), MyErrno> {
+            Err(MyErrno::Bad2)
+        }
+        fn return_option_sugar(&mut self) -> Option<i32> {
+            None
+        }
+        fn return_option_sugar2(&mut self) -> Option<MyErrno> {
+            None
+        }
+        fn result_simple(&mut self) -> Result<u32, i32> {
+            Ok(1)
+        }
+        fn is_clone_arg(&mut self, a: IsClone) {
+            self.is_clone = Some(a);
+        }
+        fn is_clone_return(&mut self) -> IsClone {
+            self.is_clone.clone().unwrap()
+        }
+        fn return_named_option(&mut self) -> Option<u8> {
+            Some(22)
+        }
+        fn return_named_result(&mut self) -> Result<u8, MyErrno> {
+            Ok(22)
+        }
+    }
+}
+
+#[test]
+fn variants() -> Result<()> {
+    use variants::MyWorld;
+    use variants::exports::local::variants::variants::E1;
+
+    let Some(component) = componentize_case("variants")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, variants::Imports::default())?;
+    MyWorld::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| {
+        &mut host.imports
+    })?;
+    rt.trap_unknown_imports()?;
+    let instance = MyWorld::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    let variants = instance.local_variants_variants();
+    variants.call_e1_arg(&mut rt.store, E1::A)?;
+    assert_eq!(variants.call_e1_result(&mut rt.store)?, E1::A);
+    Ok(())
+}
+
+mod flavorful {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/flavorful/world.wit",
+        world: "flavorful",
+    });
+
+    use local::flavorful::test::{
+        ListInAlias, ListInRecord1, ListInRecord2, ListInRecord3, ListInVariant1V1,
+        ListInVariant1V2, ListInVariant2, ListInVariant3, MyErrno,
+    };
+
+    #[derive(Default)]
+    pub struct Imports {
+        record: String,
+        variant: Option<(ListInVariant1V1, ListInVariant1V2)>,
+    }
+
+    fn json_string(s: &str) -> String {
+        format!("{s:?}")
+    }
+
+    impl local::flavorful::test::Host for Imports {
+        fn f_list_in_record1(&mut self, a: ListInRecord1) {
+            self.record = a.a;
+        }
+        fn f_list_in_record2(&mut self) -> ListInRecord2 {
+            ListInRecord2 {
+                a: self.record.clone(),
+            }
+        }
+        fn f_list_in_record3(&mut self, a: ListInRecord3) -> ListInRecord3 {
+            ListInRecord3 {
+                a: a.a + &self.record,
+            }
+        }
+        fn f_list_in_record4(&mut self, a: ListInAlias) -> ListInAlias {
+            ListInAlias {
+                a: a.a + &self.record,
+            }
+        }
+        fn f_list_in_variant1(&mut self, a: ListInVariant1V1, b: ListInVariant1V2) {
+            self.variant = Some((a, b));
+        }
+        // the JS import returns the arguments of `f-list-in-variant1` as JSON
+        fn f_list_in_variant2(&mut self) -> ListInVariant2 {
+            let (a, b) = self.variant.as_ref()?;
+            let a = a.as_deref().map_or("null".into(), json_string);
+            let b = match b {
+                Ok(()) => r#"{"tag":"ok"}"#.into(),
+                Err(e) => format!(r#"{{"tag":"err","val":{}}}"#, json_string(e)),
+            };
+            Some(format!("[{a},{b}]"))
+        }
+        fn f_list_in_variant3(&mut self, a: ListInVariant3) -> ListInVariant3 {
+            a
+        }
+        fn errno_result(&mut self) -> Result<(), MyErrno> {
+            Err(MyErrno::B)
+        }
+        fn list_typedefs(&mut self, a: String, c: Vec<String>) -> (Vec<u8>, Vec<String>) {
+            (vec![1, 2, 3], [vec![a], c].concat())
+        }
+        fn list_of_variants(
+            &mut self,
+            a: Vec<bool>,
+            b: Vec<Result<(), ()>>,
+            c: Vec<MyErrno>,
+        ) -> (Vec<bool>, Vec<Result<(), ()>>, Vec<MyErrno>) {
+            ([vec![false], a].concat(), b, c)
+        }
+    }
+}
+
+#[test]
+fn flavorful() -> Result<()> {
+    use flavorful::Flavorful;
+    use flavorful::exports::local::flavorful::test::{
+        ListInAlias, ListInRecord1, ListInRecord3, MyErrno,
+    };
+
+    let Some(component) = componentize_case("flavorful")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, flavorful::Imports::default())?;
+    Flavorful::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| {
+        &mut host.imports
+    })?;
+    rt.trap_unknown_imports()?;
+    let instance = Flavorful::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    let test = instance.local_flavorful_test();
+    let store = &mut rt.store;
+    test.call_f_list_in_record1(&mut *store, &ListInRecord1 { a: "a".into() })?;
+    assert_eq!(test.call_f_list_in_record2(&mut *store)?.a, "a");
+    let record3 = ListInRecord3 { a: "b".into() };
+    assert_eq!(test.call_f_list_in_record3(&mut *store, &record3)?.a, "ba");
+    let record4 = ListInAlias { a: "c".into() };
+    assert_eq!(test.call_f_list_in_record4(&mut *store, &record4)?.a, "ca");
+
+    test.call_f_list_in_variant1(&mut *store, &None, &Ok(()))?;
+    assert_eq!(
+        test.call_f_list_in_variant2(&mut *store)?.as_deref(),
+        Some(r#"[null,{"tag":"ok"}]"#)
+    );
+    assert_eq!(
+        test.call_f_list_in_variant3(&mut *store, &Some("test".into()))?
+            .as_deref(),
+        Some("test")
+    );
+
+    assert_eq!(test.call_errno_result(&mut *store)?, Err(MyErrno::B));
+
+    let strings = vec!["some".into(), "strings".into()];
+    let (bytes, strings) = test.call_list_typedefs(&mut *store, &"test".into(), &strings)?;
+    assert_eq!(bytes, [1, 2, 3]);
+    assert_eq!(strings, ["test", "some", "strings"]);
+    let (a, b, c) = test.call_list_of_variants(&mut *store, &[], &[], &[])?;
+    assert_eq!(a, [false]);
+    assert!(b.is_empty());
+    assert!(c.is_empty());
+    Ok(())
+}
@@ -0,0 +1,822 @@
+//! The resource cases of `test/bindings.js`, with the JS classes of imported
+//! resources ported to host resources kept in a [`ResourceTable`] of the
+//! imports, and exported resources driven through their guest handles
+//!
+//! [`ResourceTable`]: wasmtime::component::ResourceTable
+
+use anyhow::Result;
+use splicer_tests::{Host, Runtime, componentize_case};
+use wasmtime::component::{HasSelf, Resource, ResourceAny};
+
+mod resource_top_level {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/resource-top-level/world.wit",
+        world: "test",
+        with: { "thing": crate::resource_top_level::host::Thing },
+    });
+
+    use wasmtime::component::{Resource, ResourceTable};
+
+    pub mod host {
+        pub struct Thing {
+            pub v: u32,
+        }
+    }
+
+    #[derive(Default)]
+    pub struct Imports {
+        pub table: ResourceTable,
+    }
+
+    impl HostThing for Imports {
+        fn new(&mut self, v: u32) -> Resource<Thing> {
+            self.table.push(Thing { v }).unwrap()
+        }
+        fn get(&mut self, thing: Resource<Thing>) -> u32 {
+            self.table.get(&thing).unwrap().v
+        }
+        fn set(&mut self, thing: Resource<Thing>, v: u32) {
+            self.table.get_mut(&thing).unwrap().v = v;
+        }
+        fn drop(&mut self, thing: Resource<Thing>) -> wasmtime::Result<()> {
+            self.table.delete(thing)?;
+            Ok(())
+        }
+    }
+
+    impl TestImports for Imports {}
+}
+
+#[test]
+fn resource_top_level() -> Result<()> {
+    use resource_top_level::{Test, Thing};
+
+    let Some(component) = componentize_case("resource-top-level")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, resource_top_level::Imports::default())?;
+    Test::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| &mut host.imports)?;
+    rt.trap_unknown_imports()?;
+    let instance = Test::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    let thing = rt.store.data_mut().imports.table.push(Thing { v: 5 })?;
+    instance.call_f(&mut rt.store, Resource::new_borrow(thing.rep()))?;
+    assert_eq!(rt.store.data().imports.table.get(&thing)?.v, 6);
+    Ok(())
+}
+
+mod resource_borrow_export {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/resource-borrow-export/world.wit",
+        world: "test",
+    });
+}
+
+#[test]
+fn resource_borrow_export() -> Result<()> {
+    use resource_borrow_export::Test;
+
+    let Some(component) = componentize_case("resource-borrow-export")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, ())?;
+    rt.trap_unknown_imports()?;
+    let instance = Test::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    let exports = instance.test_test_resource_borrow_export();
+    let thing = exports.thing().call_constructor(&mut rt.store, 42)?;
+    assert_eq!(exports.call_foo(&mut rt.store, thing)?, 42 + 1 + 2);
+    Ok(())
+}
+
+mod resource_borrow_import {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/resource-borrow-import/world.wit",
+        world: "test",
+        with: { "test:test/resource-borrow-import.thing": crate::resource_borrow_import::host::Thing },
+    });
+
+    use test::test::resource_borrow_import::{Host, HostThing};
+    use wasmtime::component::{Resource, ResourceTable};
+
+    pub mod host {
+        pub struct Thing {
+            pub value: u32,
+        }
+    }
+
+    #[derive(Default)]
+    pub struct Imports {
+        pub table: ResourceTable,
+    }
+
+    impl HostThing for Imports {
+        fn new(&mut self, v: u32) -> Resource<Thing> {
+            self.table.push(Thing { value: v + 2 }).unwrap()
+        }
+        fn drop(&mut self, thing: Resource<Thing>) -> wasmtime::Result<()> {
+            self.table.delete(thing)?;
+            Ok(())
+        }
+    }
+
+    impl Host for Imports {
+        fn foo(&mut self, thing: Resource<Thing>) -> u32 {
+            self.table.get(&thing).unwrap().value + 3
+        }
+    }
+}
+
+#[test]
+fn resource_borrow_import() -> Result<()> {
+    use resource_borrow_import::{Test, Thing};
+
+    let Some(component) = componentize_case("resource-borrow-import")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, resource_borrow_import::Imports::default())?;
+    Test::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| &mut host.imports)?;
+    rt.trap_unknown_imports()?;
+    let instance = Test::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    assert_eq!(instance.call_test(&mut rt.store, 42)?, 42 + 1 + 2 + 3 + 4);
+
+    let thing = rt
+        .store
+        .data_mut()
+        .imports
+        .table
+        .push(Thing { value: 42 + 2 })?;
+    assert_eq!(
+        instance.call_test_borrow(&mut rt.store, Resource::new_borrow(thing.rep()))?,
+        42 + 2 + 3 + 6
+    );
+    assert_eq!(
+        instance.call_test_borrow_early_drop(&mut rt.store, Resource::new_borrow(thing.rep()))?,
+        42 + 2 + 3 + 8
+    );
+    Ok(())
+}
+
+mod resource_import_and_export {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/resource-import-and-export/world.wit",
+        world: "test",
+        with: { "test:test/resource-import-and-export.thing": crate::resource_import_and_export::Thing },
+    });
+
+    use test::test::resource_import_and_export::{Host, HostThing};
+    use wasmtime::component::{Resource, ResourceTable};
+
+    pub struct Thing {
+        value: u32,
+    }
+
+    #[derive(Default)]
+    pub struct Imports {
+        table: ResourceTable,
+    }
+
+    impl HostThing for Imports {
+        fn new(&mut self, v: u32) -> Resource<Thing> {
+            self.table.push(Thing { value: v + 1 }).unwrap()
+        }
+        fn foo(&mut self, thing: Resource<Thing>) -> u32 {
+            self.table.get(&thing).unwrap().value + 2
+        }
+        fn bar(&mut self, thing: Resource<Thing>, v: u32) {
+            self.table.get_mut(&thing).unwrap().value = v + 3;
+        }
+        fn baz(&mut self, a: Resource<Thing>, b: Resource<Thing>) -> Resource<Thing> {
+            let v = self.foo(a) + self.foo(b) + 4;
+            self.new(v)
+        }
+        fn drop(&mut self, thing: Resource<Thing>) -> wasmtime::Result<()> {
+            self.table.delete(thing)?;
+            Ok(())
+        }
+    }
+
+    impl Host for Imports {}
+}
+
+#[test]
+fn resource_import_and_export() -> Result<()> {
+    use resource_import_and_export::Test;
+
+    let Some(component) = componentize_case("resource-import-and-export")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, resource_import_and_export::Imports::default())?;
+    Test::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| &mut host.imports)?;
+    rt.trap_unknown_imports()?;
+    let instance = Test::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    let thing = instance.test_test_resource_import_and_export().thing();
+    let store = &mut rt.store;
+    let thing1 = thing.call_constructor(&mut *store, 42)?;
+    assert_eq!(thing.call_foo(&mut *store, thing1)?, 42 + 1 + 1 + 2 + 2);
+    thing.call_bar(&mut *store, thing1, 33)?;
+    assert_eq!(thing.call_foo(&mut *store, thing1)?, 33 + 3 + 3 + 2 + 2);
+
+    let thing2 = thing.call_constructor(&mut *store, 81)?;
+    let thing3 = thing.call_baz(&mut *store, thing1, thing2)?;
+    assert_eq!(
+        thing.call_foo(&mut *store, thing3)?,
+        33 + 3 + 3 + 81 + 1 + 1 + 2 + 2 + 4 + 1 + 2 + 4 + 1 + 1 + 2 + 2
+    );
+    Ok(())
+}
+
+mod resource_with_lists {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/resource-with-lists/world.wit",
+        world: "test",
+        with: { "test:test/resource-with-lists.thing": crate::resource_with_lists::Thing },
+    });
+
+    use test::test::resource_with_lists::{Host, HostThing};
+    use wasmtime::component::{Resource, ResourceTable};
+
+    pub struct Thing {
+        value: Vec<u8>,
+    }
+
+    #[derive(Default)]
+    pub struct Imports {
+        table: ResourceTable,
+    }
+
+    fn with_suffix(mut list: Vec<u8>, suffix: &str) -> Vec<u8> {
+        list.extend_from_slice(suffix.as_bytes());
+        list
+    }
+
+    impl HostThing for Imports {
+        fn new(&mut self, l: Vec<u8>) -> Resource<Thing> {
+            let value = with_suffix(l, " HostThing");
+            self.table.push(Thing { value }).unwrap()
+        }
+        fn foo(&mut self, thing: Resource<Thing>) -> Vec<u8> {
+            let value = self.table.get(&thing).unwrap().value.clone();
+            with_suffix(value, " HostThing.foo")
+        }
+        fn bar(&mut self, thing: Resource<Thing>, l: Vec<u8>) {
+            self.table.get_mut(&thing).unwrap().value = with_suffix(l, " HostThing.bar");
+        }
+        fn baz(&mut self, l: Vec<u8>) -> Vec<u8> {
+            with_suffix(l, " HostThing.baz")
+        }
+        fn drop(&mut self, thing: Resource<Thing>) -> wasmtime::Result<()> {
+            self.table.delete(thing)?;
+            Ok(())
+        }
+    }
+
+    impl Host for Imports {}
+}
+
+#[test]
+fn resource_with_lists() -> Result<()> {
+    use resource_with_lists::Test;
+
+    let Some(component) = componentize_case("resource-with-lists")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, resource_with_lists::Imports::default())?;
+    Test::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| &mut host.imports)?;
+    rt.trap_unknown_imports()?;
+    let instance = Test::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    let thing = instance.test_test_resource_with_lists().thing();
+    let store = &mut rt.store;
+    let thing1 = thing.call_constructor(&mut *store, b"Hi")?;
+    assert_eq!(
+        thing.call_foo(&mut *store, thing1)?,
+        b"Hi Thing HostThing HostThing.foo Thing.foo"
+    );
+    thing.call_bar(&mut *store, thing1, b"Hola")?;
+    assert_eq!(
+        thing.call_foo(&mut *store, thing1)?,
+        b"Hola Thing.bar HostThing.bar HostThing.foo Thing.foo"
+    );
+    assert_eq!(
+        thing.call_baz(&mut *store, b"Ohayo Gozaimas")?,
+        b"Ohayo Gozaimas Thing.baz HostThing.baz Thing.baz again"
+    );
+    Ok(())
+}
+
+mod resource_borrow_in_record {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/resource-borrow-in-record/world.wit",
+        world: "test",
+        with: { "test:test/resource-borrow-in-record.thing": crate::resource_borrow_in_record::Thing },
+    });
+
+    use test::test::resource_borrow_in_record::{Foo, Host, HostThing};
+    use wasmtime::component::{Resource, ResourceTable};
+
+    pub struct Thing {
+        value: String,
+    }
+
+    #[derive(Default)]
+    pub struct Imports {
+        table: ResourceTable,
+    }
+
+    impl HostThing for Imports {
+        fn new(&mut self, s: String) -> Resource<Thing> {
+            let value = s + " HostThing";
+            self.table.push(Thing { value }).unwrap()
+        }
+        fn get(&mut self, thing: Resource<Thing>) -> String {
+            self.table.get(&thing).unwrap().value.clone() + " HostThing.get"
+        }
+        fn drop(&mut self, thing: Resource<Thing>) -> wasmtime::Result<()> {
+            self.table.delete(thing)?;
+            Ok(())
+        }
+    }
+
+    impl Host for Imports {
+        fn test(&mut self, a: Vec<Foo>) -> Vec<Resource<Thing>> {
+            a.into_iter()
+                .map(|record| {
+                    let value = self.table.get(&record.thing).unwrap().value.clone();
+                    self.new(value + " test")
+                })
+                .collect()
+        }
+    }
+}
+
+#[test]
+fn resource_borrow_in_record() -> Result<()> {
+    use resource_borrow_in_record::Test;
+    use resource_borrow_in_record::exports::test::test::resource_borrow_in_record::Foo;
+
+    let Some(component) = componentize_case("resource-borrow-in-record")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, resource_borrow_in_record::Imports::default())?;
+    Test::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| &mut host.imports)?;
+    rt.trap_unknown_imports()?;
+    let instance = Test::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    let exports = instance.test_test_resource_borrow_in_record();
+    let thing = exports.thing();
+    let store = &mut rt.store;
+    let thing1 = thing.call_constructor(&mut *store, "Bonjour")?;
+    let thing2 = thing.call_constructor(&mut *store, "mon cher")?;
+    let things = exports.call_test(&mut *store, &[Foo { thing: thing1 }, Foo { thing: thing2 }])?;
+    let values = things
+        .into_iter()
+        .map(|x| thing.call_get(&mut *store, x))
+        .collect::<wasmtime::Result<Vec<_>>>()?;
+    assert_eq!(
+        values,
+        [
+            "Bonjour Thing HostThing test HostThing HostThing.get Thing.get",
+            "mon cher Thing HostThing test HostThing HostThing.get Thing.get",
+        ]
+    );
+    Ok(())
+}
+
+mod resource_floats {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/resource-floats/world.wit",
+        world: "test",
+        with: {
+            "test:test/resource-floats.my-float": crate::resource_floats::host::MyFloat,
+            "resource-floats-imports.my-float": crate::resource_floats::ImportsMyFloat,
+        },
+    });
+
+    use wasmtime::component::{Resource, ResourceTable};
+
+    pub mod host {
+        pub struct MyFloat {
+            pub value: f64,
+        }
+    }
+
+    pub struct ImportsMyFloat {
+        value: f64,
+    }
+
+    #[derive(Default)]
+    pub struct Imports {
+        pub table: ResourceTable,
+    }
+
+    impl test::test::resource_floats::HostMyFloat for Imports {
+        fn new(&mut self, v: f64) -> Resource<MyFloat> {
+            self.table.push(MyFloat { value: v + 1.0 }).unwrap()
+        }
+        fn get(&mut self, float: Resource<MyFloat>) -> f64 {
+            self.table.get(&float).unwrap().value + 3.0
+        }
+        fn drop(&mut self, float: Resource<MyFloat>) -> wasmtime::Result<()> {
+            self.table.delete(float)?;
+            Ok(())
+        }
+    }
+
+    impl test::test::resource_floats::Host for Imports {}
+
+    impl resource_floats_imports::HostMyFloat for Imports {
+        fn new(&mut self, v: f64) -> Resource<ImportsMyFloat> {
+            self.table.push(ImportsMyFloat { value: v + 2.0 }).unwrap()
+        }
+        fn get(&mut self, float: Resource<ImportsMyFloat>) -> f64 {
+            self.table.get(&float).unwrap().value + 4.0
+        }
+        fn add(&mut self, a: Resource<ImportsMyFloat>, b: f64) -> Resource<ImportsMyFloat> {
+            let a = self.table.delete(a).unwrap();
+            self.new(a.value + b + 6.0)
+        }
+        fn drop(&mut self, float: Resource<ImportsMyFloat>) -> wasmtime::Result<()> {
+            self.table.delete(float)?;
+            Ok(())
+        }
+    }
+
+    impl resource_floats_imports::Host for Imports {}
+}
+
+#[test]
+fn resource_floats() -> Result<()> {
+    use resource_floats::{MyFloat, Test};
+
+    let Some(component) = componentize_case("resource-floats")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, resource_floats::Imports::default())?;
+    Test::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| &mut host.imports)?;
+    rt.trap_unknown_imports()?;
+    let instance = Test::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    let table = &mut rt.store.data_mut().imports.table;
+    let float1 = table.push(MyFloat { value: 42.0 + 1.0 })?;
+    let float2 = table.push(MyFloat { value: 55.0 + 1.0 })?;
+    let sum = instance.call_add(
+        &mut rt.store,
+        Resource::new_borrow(float1.rep()),
+        Resource::new_borrow(float2.rep()),
+    )?;
+    assert_eq!(
+        rt.store.data().imports.table.get(&sum)?.value,
+        42.0 + 1.0 + 3.0 + 55.0 + 1.0 + 3.0 + 5.0 + 1.0
+    );
+
+    let my_float = instance.resource_floats_exports().my_float();
+    let store = &mut rt.store;
+    let float3 = my_float.call_constructor(&mut *store, 22.0)?;
+    assert_eq!(
+        my_float.call_get(&mut *store, float3)?,
+        22.0 + 1.0 + 2.0 + 4.0 + 3.0
+    );
+    let result = my_float.call_add(&mut *store, float3, 7.0)?;
+    assert_eq!(
+        my_float.call_get(&mut *store, result)?,
+        22.0 + 1.0 + 2.0 + 7.0 + 6.0 + 2.0 + 4.0 + 5.0 + 1.0 + 2.0 + 4.0 + 3.0
+    );
+    Ok(())
+}
+
+mod resource_alias_redux {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/resource-alias-redux/world.wit",
+        world: "test",
+        with: { "test:test/resource-alias1.thing": crate::resource_alias_redux::host::Thing },
+    });
+
+    use test::test::{resource_alias1, resource_alias2};
+    use wasmtime::component::{Resource, ResourceTable};
+
+    pub mod host {
+        pub struct Thing {
+            pub value: String,
+        }
+    }
+
+    #[derive(Default)]
+    pub struct Imports {
+        pub table: ResourceTable,
+    }
+
+    impl resource_alias1::HostThing for Imports {
+        fn new(&mut self, s: String) -> Resource<Thing> {
+            let value = s + " HostThing";
+            self.table.push(Thing { value }).unwrap()
+        }
+        fn get(&mut self, thing: Resource<Thing>) -> String {
+            self.table.get(&thing).unwrap().value.clone() + " HostThing.get"
+        }
+        fn drop(&mut self, thing: Resource<Thing>) -> wasmtime::Result<()> {
+            self.table.delete(thing)?;
+            Ok(())
+        }
+    }
+
+    impl resource_alias1::Host for Imports {
+        fn a(&mut self, f: resource_alias1::Foo) -> Vec<Resource<Thing>> {
+            vec![f.thing]
+        }
+    }
+
+    impl resource_alias2::Host for Imports {
+        fn b(&mut self, f: resource_alias2::Foo, g: resource_alias2::Bar) -> Vec<Resource<Thing>> {
+            vec![f.thing, g.thing]
+        }
+    }
+}
+
+#[test]
+fn resource_alias_redux() -> Result<()> {
+    use resource_alias_redux::exports::test::test::{resource_alias1, resource_alias2};
+    use resource_alias_redux::{Test, Thing};
+
+    let Some(component) = componentize_case("resource-alias-redux")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, resource_alias_redux::Imports::default())?;
+    Test::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| &mut host.imports)?;
+    rt.trap_unknown_imports()?;
+    let instance = Test::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    let thing1 = rt.store.data_mut().imports.table.push(Thing {
+        value: "Ni Hao HostThing".into(),
+    })?;
+    let things = instance.call_test(&mut rt.store, &[thing1])?;
+    let table = &rt.store.data().imports.table;
+    let values = things
+        .iter()
+        .map(|thing| Ok(table.get(thing)?.value.clone()))
+        .collect::<wasmtime::Result<Vec<_>>>()?;
+    assert_eq!(values, ["Ni Hao HostThing"]);
+
+    let alias1 = instance.test_test_resource_alias1();
+    let thing = alias1.thing();
+    let store = &mut rt.store;
+    let get = |store: &mut _, things: Vec<ResourceAny>| {
+        things
+            .into_iter()
+            .map(|x| thing.call_get(&mut *store, x))
+            .collect::<wasmtime::Result<Vec<_>>>()
+    };
+
+    let thing2 = thing.call_constructor(&mut *store, "Ciao")?;
+    let things = alias1.call_a(&mut *store, resource_alias1::Foo { thing: thing2 })?;
+    assert_eq!(
+        get(&mut *store, things)?,
+        ["Ciao Thing HostThing HostThing.get Thing.get"]
+    );
+
+    let thing3 = thing.call_constructor(&mut *store, "Ciao")?;
+    let thing4 = thing.call_constructor(&mut *store, "Aloha")?;
+    let things = instance.test_test_resource_alias2().call_b(
+        &mut *store,
+        resource_alias2::Foo { thing: thing3 },
+        resource_alias1::Foo { thing: thing4 },
+    )?;
+    assert_eq!(
+        get(&mut *store, things)?,
+        [
+            "Ciao Thing HostThing HostThing.get Thing.get",
+            "Aloha Thing HostThing HostThing.get Thing.get",
+        ]
+    );
+    Ok(())
+}
+
+mod resource_alias {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/resource-alias/world.wit",
+        world: "resources",
+    });
+}
+
+#[test]
+fn resource_alias() -> Result<()> {
+    use resource_alias::Resources;
+    use resource_alias::exports::my::resources::{e1, e2};
+
+    let Some(component) = componentize_case("resource-alias")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, ())?;
+    rt.trap_unknown_imports()?;
+    let instance = Resources::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    // the JS test compares the returned handles as objects, which only checks
+    // how many are returned
+    let exports = instance.my_resources_e1();
+    let x = exports.x();
+    let store = &mut rt.store;
+    let x1 = x.call_constructor(&mut *store, 42)?;
+    assert_eq!(exports.call_a(&mut *store, e1::Foo { x: x1 })?.len(), 1);
+
+    let x2 = x.call_constructor(&mut *store, 7)?;
+    let x3 = x.call_constructor(&mut *store, 8)?;
+    let xs =
+        instance
+            .my_resources_e2()
+            .call_a(&mut *store, e2::Foo { x: x2 }, e1::Foo { x: x3 })?;
+    assert_eq!(xs.len(), 2);
+    Ok(())
+}
+
+mod resource_aggregates {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/resource-aggregates/world.wit",
+        world: "test",
+        with: { "test:test/resource-aggregates.thing": crate::resource_aggregates::Thing },
+    });
+
+    use test::test::resource_aggregates::{Host, HostThing, L1, L2, R1, R2, R3, T1, T2, V1, V2};
+    use wasmtime::component::{Resource, ResourceTable};
+
+    pub struct Thing {
+        value: u32,
+    }
+
+    #[derive(Default)]
+    pub struct Imports {
+        table: ResourceTable,
+    }
+
+    impl Imports {
+        fn value(&self, thing: &Resource<Thing>) -> u32 {
+            self.table.get(thing).unwrap().value
+        }
+    }
+
+    impl HostThing for Imports {
+        fn new(&mut self, v: u32) -> Resource<Thing> {
+            self.table.push(Thing { value: v + 2 }).unwrap()
+        }
+        fn drop(&mut self, thing: Resource<Thing>) -> wasmtime::Result<()> {
+            self.table.delete(thing)?;
+            Ok(())
+        }
+    }
+
+    impl Host for Imports {
+        fn foo(
+            &mut self,
+            r1: R1,
+            r2: R2,
+            r3: R3,
+            t1: T1,
+            t2: T2,
+            v1: V1,
+            v2: V2,
+            l1: L1,
+            l2: L2,
+            o1: Option<Resource<Thing>>,
+            o2: Option<Resource<Thing>>,
+            result1: Result<Resource<Thing>, ()>,
+            result2: Result<Resource<Thing>, ()>,
+        ) -> u32 {
+            let V1::Thing(v1) = v1;
+            let V2::Thing(v2) = v2;
+            let things = [
+                r1.thing, r2.thing, r3.thing1, r3.thing2, t1.0, t1.1.thing, t2.0, v1, v2,
+            ]
+            .into_iter()
+            .chain(l1)
+            .chain(l2)
+            .chain(o1)
+            .chain(o2)
+            .chain(result1)
+            .chain(result2);
+            things.map(|thing| self.value(&thing)).sum::<u32>() + 3
+        }
+    }
+}
+
+#[test]
+fn resource_aggregates() -> Result<()> {
+    use resource_aggregates::Test;
+    use resource_aggregates::exports::test::test::resource_aggregates::{R1, R2, R3, V1, V2};
+
+    let Some(component) = componentize_case("resource-aggregates")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, resource_aggregates::Imports::default())?;
+    Test::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| &mut host.imports)?;
+    rt.trap_unknown_imports()?;
+    let instance = Test::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    let exports = instance.test_test_resource_aggregates();
+    let store = &mut rt.store;
+    let mut things = Vec::new();
+    let mut expected = 0;
+    for i in 1..18 {
+        things.push(exports.thing().call_constructor(&mut *store, i)?);
+        expected += i + 1 + 2;
+    }
+    expected += 3 + 4;
+
+    let sum = exports.call_foo(
+        &mut *store,
+        R1 { thing: things[0] },
+        R2 { thing: things[1] },
+        R3 {
+            thing1: things[2],
+            thing2: things[3],
+        },
+        (things[4], R1 { thing: things[5] }),
+        (things[6],),
+        V1::Thing(things[7]),
+        V2::Thing(things[8]),
+        &vec![things[9], things[10]],
+        &vec![things[11], things[12]],
+        Some(things[13]),
+        Some(things[14]),
+        Ok(things[15]),
+        Ok(things[16]),
+    )?;
+    assert_eq!(sum, expected);
+    Ok(())
+}
+
+mod resources {
+    wasmtime::component::bindgen!({
+        path: "../../test/cases/resources/world.wit",
+        world: "resources",
+        with: { "imports.y": crate::resources::Y },
+    });
+
+    use wasmtime::component::{Resource, ResourceTable};
+
+    pub struct Y {
+        a: i32,
+    }
+
+    #[derive(Default)]
+    pub struct Imports {
+        table: ResourceTable,
+    }
+
+    impl imports::HostY for Imports {
+        fn new(&mut self, a: i32) -> Resource<Y> {
+            self.table.push(Y { a }).unwrap()
+        }
+        fn get_a(&mut self, y: Resource<Y>) -> i32 {
+            self.table.get(&y).unwrap().a
+        }
+        fn set_a(&mut self, y: Resource<Y>, a: i32) {
+            self.table.get_mut(&y).unwrap().a = a;
+        }
+        fn add(&mut self, y: Resource<Y>, a: i32) -> Resource<Y> {
+            self.table.get_mut(&y).unwrap().a += a;
+            y
+        }
+        fn drop(&mut self, y: Resource<Y>) -> wasmtime::Result<()> {
+            self.table.delete(y)?;
+            Ok(())
+        }
+    }
+
+    impl imports::Host for Imports {}
+}
+
+#[test]
+fn resources() -> Result<()> {
+    use resources::Resources;
+
+    let Some(component) = componentize_case("resources")? else {
+        return Ok(());
+    };
+    let mut rt = Runtime::new(&component, resources::Imports::default())?;
+    Resources::add_to_linker::<_, HasSelf<_>>(&mut rt.linker, |host: &mut Host<_>| {
+        &mut host.imports
+    })?;
+    rt.trap_unknown_imports()?;
+    let instance = Resources::instantiate(&mut rt.store, &rt.component, &rt.linker)?;
+
+    let exports = instance.exports();
+    let (x, z) = (exports.x(), exports.z());
+    let store = &mut rt.store;
+    let one = z.call_constructor(&mut *store, 1)?;
+    let two = z.call_constructor(&mut *store, 2)?;
+    let sum = exports.call_add(&mut *store, one, two)?;
+    assert_eq!(z.call_get_a(&mut *store, sum)?, 3);
+
+    let x1 = x.call_constructor(&mut *store, 3)?;
+    let x1 = x.call_add(&mut *store, x1, 4)?;
+    assert_eq!(x.call_get_a(&mut *store, x1)?, 7);
+
+    let x2 = x.call_constructor(&mut *store, 3)?;
+    x.call_set_a(&mut *store, x2, 5)?;
+    let x2 = x.call_add(&mut *store, x2, 4)?;
+    assert_eq!(x.call_get_a(&mut *store, x2)?, 9);
+
+    assert_eq!(exports.call_test_imports(&mut *store)?, Ok(()));
+    Ok(())
+}
@@ -0,0 +1,734 @@
+//! Behavior of the imports synthesized by `stub_wasi` from a stub policy
+//!
+//! Each test stubs a stand-in engine, a core module importing the WASI
+//! functions under test with their lowered signatures and exporting a wrapper
+//! calling each, then runs the stubbed module with Wasmtime without linking
+//! any import and inspects the results the stubs write to memory.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde_json::json;
+use spidermonkey_embedding_splicer::stub_wasi::stub_wasi;
+use wasmtime::{
+    Caller, Engine, Instance, Linker, Memory, Module, Store, Trap, WasmParams, WasmResults,
+};
+
+/// Memory, heap and `cabi_realloc` of the stand-in engines, a bump allocator
+/// starting after the scratch space the tests use for arguments and results
+const PRELUDE: &str = r#"
+  (memory (export "memory") 2)
+  (global $heap (mut i32) (i32.const 4096))
+  (func (export "cabi_realloc") (param i32 i32 i32 i32) (result i32)
+    (local $ptr i32)
+    global.get $heap
+    local.get 2
+    i32.add
+    i32.const 1
+    i32.sub
+    i32.const 0
+    local.get 2
+    i32.sub
+    i32.and
+    local.tee $ptr
+    local.get 3
+    i32.add
+    global.set $heap
+    local.get $ptr)
+"#;
+
+/// Import of every engine, which stubbing random requires
+const RANDOM: &str = r#"
+  (import "wasi:random/random@0.2.3" "get-random-u64" (func (result i64)))
+"#;
+
+const WORLD: &str = "package local:stubs;\nworld stubs {}";
+
+/// A stand-in engine with the `imports`, as `(module, name, signature)`, each
+/// called by an export of the same name
+fn engine(imports: &[(&str, &str, &str)]) -> Result<Vec<u8>> {
+    let (mut imported, mut funcs) = (String::new(), String::new());
+    for (idx, (module, name, signature)) in imports.iter().enumerate() {
+        let params = signature.split("(result").next().unwrap();
+        let args: String = (0..params.matches(" i").count())
+            .map(|arg| format!("local.get {arg} "))
+            .collect();
+        imported += &format!("(import \"{module}\" \"{name}\" (func $import{idx} {signature}))\n");
+        funcs += &format!("(func (export \"{name}\") {signature} {args}call $import{idx})\n");
+    }
+    wat::parse_str(format!("(module {RANDOM} {imported} {PRELUDE} {funcs})")).map_err(Into::into)
+}
+
+/// A fresh directory with the `files`, as `(path, contents)`
+fn fixture(name: &str, files: &[(&str, &str)]) -> Result<PathBuf> {
+    let dir = std::env::temp_dir().join(format!("splicer-stubs-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    for (path, contents) in files {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(path, contents)?;
+    }
+    Ok(dir)
+}
+
+/// Calls of a logging function of the target world, as `(level, context, message)`
+type Logs = Vec<(i32, String, String)>;
+
+/// A stubbed stand-in engine, instantiated with only the target world imports
+struct Stubbed {
+    store: Store<Logs>,
+    instance: Instance,
+    memory: Memory,
+}
+
+impl Stubbed {
+    /// Stub for an empty target world, whose stubs replace every import
+    fn new(wasm: Vec<u8>, policy: serde_json::Value) -> Result<Self> {
+        Self::with_world(wasm, WORLD, policy, |_| Ok(()))
+    }
+
+    fn with_world(
+        wasm: Vec<u8>,
+        world: &str,
+        policy: serde_json::Value,
+        link: impl FnOnce(&mut Linker<Logs>) -> Result<()>,
+    ) -> Result<Self> {
+        let wasm = stub_wasi(
+            wasm,
+            vec![],
+            Some(world.to_string()),
+            None,
+            None,
+            Some(policy.to_string()),
+            None,
+        )?;
+        let engine = Engine::default();
+        let module = Module::new(&engine, wasm)?;
+        let mut store = Store::new(&engine, Logs::new());
+        let mut linker = Linker::new(&engine);
+        link(&mut linker)?;
+        let instance = linker.instantiate(&mut store, &module)?;
+        let memory = instance.get_memory(&mut store, "memory").unwrap();
+        Ok(Self {
+            store,
+            instance,
+            memory,
+        })
+    }
+
+    fn call<P: WasmParams, R: WasmResults>(&mut self, name: &str, params: P) -> Result<R> {
+        let func = self
+            .instance
+            .get_typed_func::<P, R>(&mut self.store, name)?;
+        Ok(func.call(&mut self.store, params)?)
+    }
+
+    fn write(&mut self, addr: u32, bytes: &[u8]) {
+        self.memory.data_mut(&mut self.store)[addr as usize..][..bytes.len()]
+            .copy_from_slice(bytes);
+    }
+
+    fn bytes(&self, addr: u32, len: u32) -> Vec<u8> {
+        self.memory.data(&self.store)[addr as usize..][..len as usize].to_vec()
+    }
+
+    fn u8(&self, addr: u32) -> u8 {
+        self.memory.data(&self.store)[addr as usize]
+    }
+
+    fn u32(&self, addr: u32) -> u32 {
+        u32::from_le_bytes(self.bytes(addr, 4).try_into().unwrap())
+    }
+
+    fn u64(&self, addr: u32) -> u64 {
+        u64::from_le_bytes(self.bytes(addr, 8).try_into().unwrap())
+    }
+
+    /// Whether calling `name` traps on an `unreachable` stub
+    fn traps<P: WasmParams, R: WasmResults>(&mut self, name: &str, params: P) -> bool {
+        let func = self.instance.get_typed_func::<P, R>(&mut self.store, name);
+        let Err(err) = func.unwrap().call(&mut self.store, params) else {
+            return false;
+        };
+        err.downcast_ref::<Trap>() == Some(&Trap::UnreachableCodeReached)
+    }
+
+    /// The `list<u8>` or `string` whose pointer and length are at `addr`
+    fn list(&self, addr: u32) -> Vec<u8> {
+        self.bytes(self.u32(addr), self.u32(addr + 4))
+    }
+}
+
+const PREVIEW1: &str = "wasi_snapshot_preview1";
+const ENVIRONMENT: &str = "wasi:cli/environment@0.2.3";
+const PREOPENS: &str = "wasi:filesystem/preopens@0.2.3";
+const FILESYSTEM: &str = "wasi:filesystem/types@0.2.3";
+const STREAMS: &str = "wasi:io/streams@0.2.3";
+const HTTP: &str = "wasi:http/types@0.2.3";
+const MONOTONIC_CLOCK: &str = "wasi:clocks/monotonic-clock@0.2.3";
+const POLL: &str = "wasi:io/poll@0.2.3";
+
+/// Scratch addresses for arguments and return areas
+const ARG: u32 = 512;
+const RET: u32 = 1024;
+
+// `descriptor-type` and `error-code` cases of `wasi:filesystem/types`
+const DESCRIPTOR_TYPE_REGULAR_FILE: u8 = 6;
+const ERROR_NO_ENTRY: u8 = 20;
+const ERROR_READ_ONLY: u8 = 33;
+
+// `method` and `error-code` cases of `wasi:http/types`
+const METHOD_POST: u32 = 2;
+const ERROR_HTTP_REQUEST_DENIED: u8 = 15;
+
+// `stream-error` case of `wasi:io/streams`
+const STREAM_ERROR_CLOSED: u8 = 1;
+
+#[test]
+fn virtual_fs() -> Result<()> {
+    let dir = fixture(
+        "virtual-fs",
+        &[("hello.txt", "hello world"), ("sub/nested.txt", "nested")],
+    )?;
+    let wasm = engine(&[
+        (PREOPENS, "get-directories", "(param i32)"),
+        (
+            FILESYSTEM,
+            "[method]descriptor.open-at",
+            "(param i32 i32 i32 i32 i32 i32 i32)",
+        ),
+        (
+            FILESYSTEM,
+            "[method]descriptor.read",
+            "(param i32 i64 i64 i32)",
+        ),
+        (
+            FILESYSTEM,
+            "[method]descriptor.write",
+            "(param i32 i32 i32 i64 i32)",
+        ),
+        (FILESYSTEM, "[method]descriptor.stat", "(param i32 i32)"),
+        (
+            FILESYSTEM,
+            "[method]descriptor.read-via-stream",
+            "(param i32 i64 i32)",
+        ),
+        (STREAMS, "[method]input-stream.read", "(param i32 i64 i32)"),
+    ])?;
+    let policy = json!({ "virtual-fs": { "dir": dir, "preopen": "/data" } });
+    let mut fs = Stubbed::new(wasm, policy)?;
+
+    // a single preopen, named after the preopen path
+    fs.call::<_, ()>("get-directories", RET)?;
+    assert_eq!(fs.u32(RET + 4), 1);
+    let preopen = fs.u32(RET);
+    let root = fs.u32(preopen);
+    assert_eq!(fs.list(preopen + 4), b"/data");
+
+    let open = |fs: &mut Stubbed, path: &str, open_flags: u32, flags: u32| -> Result<()> {
+        fs.write(ARG, path.as_bytes());
+        let len = path.len() as u32;
+        fs.call(
+            "[method]descriptor.open-at",
+            (root, 0u32, ARG, len, open_flags, flags, RET),
+        )
+    };
+
+    // read a nested file to its end
+    open(&mut fs, "sub/../sub/./nested.txt", 0, 1)?;
+    assert_eq!(fs.u8(RET), 0);
+    let file = fs.u32(RET + 4);
+    fs.call::<_, ()>("[method]descriptor.read", (file, 100u64, 0u64, RET))?;
+    assert_eq!(fs.u8(RET), 0);
+    assert_eq!(fs.list(RET + 4), b"nested");
+    assert_eq!(fs.u8(RET + 12), 1, "a read to the end reports the end");
+
+    // read from an offset
+    open(&mut fs, "hello.txt", 0, 1)?;
+    let file = fs.u32(RET + 4);
+    fs.call::<_, ()>("[method]descriptor.read", (file, 3u64, 6u64, RET))?;
+    assert_eq!(fs.list(RET + 4), b"wor");
+    assert_eq!(fs.u8(RET + 12), 0);
+
+    // the type and size of a file, in a `descriptor-stat` at offset 8
+    fs.call::<_, ()>("[method]descriptor.stat", (file, RET))?;
+    assert_eq!(fs.u8(RET), 0);
+    assert_eq!(fs.u8(RET + 8), DESCRIPTOR_TYPE_REGULAR_FILE);
+    assert_eq!(fs.u64(RET + 24), 11);
+
+    // read through a stream until it is closed
+    fs.call::<_, ()>("[method]descriptor.read-via-stream", (file, 6u64, RET))?;
+    assert_eq!(fs.u8(RET), 0);
+    let stream = fs.u32(RET + 4);
+    fs.call::<_, ()>("[method]input-stream.read", (stream, 2u64, RET))?;
+    assert_eq!((fs.u8(RET), fs.list(RET + 4)), (0, b"wo".to_vec()));
+    fs.call::<_, ()>("[method]input-stream.read", (stream, 100u64, RET))?;
+    assert_eq!((fs.u8(RET), fs.list(RET + 4)), (0, b"rld".to_vec()));
+    fs.call::<_, ()>("[method]input-stream.read", (stream, 100u64, RET))?;
+    assert_eq!((fs.u8(RET), fs.u8(RET + 4)), (1, STREAM_ERROR_CLOSED));
+
+    open(&mut fs, "missing.txt", 0, 1)?;
+    assert_eq!((fs.u8(RET), fs.u8(RET + 4)), (1, ERROR_NO_ENTRY));
+
+    // creating files and writing are refused
+    open(&mut fs, "new.txt", 1, 1)?;
+    assert_eq!((fs.u8(RET), fs.u8(RET + 4)), (1, ERROR_READ_ONLY));
+    fs.write(ARG, b"data");
+    fs.call::<_, ()>("[method]descriptor.write", (file, ARG, 4u32, 0u64, RET))?;
+    assert_eq!((fs.u8(RET), fs.u8(RET + 8)), (1, ERROR_READ_ONLY));
+    Ok(())
+}
+
+#[test]
+fn virtual_fs_relative_dir() -> Result<()> {
+    let dir = fixture("relative", &[("hello.txt", "hello world")])?;
+    let wasm = engine(&[(PREOPENS, "get-directories", "(param i32)")])?;
+    let name = dir.file_name().unwrap().to_str().unwrap();
+    let policy = format!("[virtual-fs]\ndir = \"{name}\"\npreopen = \"/data\"");
+    let stub = |policy_dir: Option<&Path>| {
+        stub_wasi(
+            wasm.clone(),
+            vec![],
+            Some(WORLD.to_string()),
+            None,
+            None,
+            Some(policy.clone()),
+            policy_dir.map(|dir| dir.display().to_string()),
+        )
+    };
+
+    // a relative directory of a TOML policy is the one of the policy directory
+    assert!(stub(dir.parent()).is_ok());
+    assert!(stub(None).is_err());
+    Ok(())
+}
+
+#[test]
+fn environment() -> Result<()> {
+    let wasm = engine(&[
+        (ENVIRONMENT, "get-environment", "(param i32)"),
+        (ENVIRONMENT, "get-arguments", "(param i32)"),
+        (ENVIRONMENT, "initial-cwd", "(param i32)"),
+        (
+            PREVIEW1,
+            "environ_sizes_get",
+            "(param i32 i32) (result i32)",
+        ),
+        (PREVIEW1, "environ_get", "(param i32 i32) (result i32)"),
+    ])?;
+    let policy = json!({
+        "environment": {
+            "vars": { "MODE": "production", "LANG": "C" },
+            "args": ["app", "--verbose"],
+            "cwd": "/srv",
+        }
+    });
+    let mut env = Stubbed::new(wasm, policy)?;
+
+    // `list<tuple<string, string>>`
+    env.call::<_, ()>("get-environment", RET)?;
+    let (vars, len) = (env.u32(RET), env.u32(RET + 4));
+    let vars: Vec<_> = (0..len)
+        .map(|idx| (env.list(vars + idx * 16), env.list(vars + idx * 16 + 8)))
+        .collect();
+    assert_eq!(
+        vars,
+        [
+            (b"LANG".to_vec(), b"C".to_vec()),
+            (b"MODE".to_vec(), b"production".to_vec()),
+        ]
+    );
+
+    // `list<string>`
+    env.call::<_, ()>("get-arguments", RET)?;
+    let (args, len) = (env.u32(RET), env.u32(RET + 4));
+    let args: Vec<_> = (0..len).map(|idx| env.list(args + idx * 8)).collect();
+    assert_eq!(args, [b"app".to_vec(), b"--verbose".to_vec()]);
+
+    // `option<string>`
+    env.call::<_, ()>("initial-cwd", RET)?;
+    assert_eq!((env.u8(RET), env.list(RET + 4)), (1, b"/srv".to_vec()));
+
+    // preview1 NUL-terminated `name=value` strings in a caller provided buffer
+    assert_eq!(env.call::<_, i32>("environ_sizes_get", (RET, RET + 4))?, 0);
+    assert_eq!(env.u32(RET), 2);
+    let size = env.u32(RET + 4);
+    assert_eq!(env.call::<_, i32>("environ_get", (RET, ARG))?, 0);
+    assert_eq!(env.u32(RET), ARG);
+    assert_eq!(
+        env.bytes(ARG, size),
+        b"LANG=C\0MODE=production\0",
+        "the strings are laid out contiguously"
+    );
+    assert_eq!(env.u32(RET + 4), ARG + 7);
+    Ok(())
+}
+
+/// Writes to stdout through `wasi:cli/stdout`, and to stderr through preview1
+const STDIO_IMPORTS: [(&str, &str, &str); 3] = [
+    ("wasi:cli/stdout@0.2.3", "get-stdout", "(result i32)"),
+    (
+        STREAMS,
+        "[method]output-stream.blocking-write-and-flush",
+        "(param i32 i32 i32 i32)",
+    ),
+    (PREVIEW1, "fd_write", "(param i32 i32 i32 i32) (result i32)"),
+];
+
+impl Stubbed {
+    /// Write `text` to stdout
+    fn print(&mut self, text: &str) -> Result<()> {
+        let stdout = self.call::<_, u32>("get-stdout", ())?;
+        self.write(ARG, text.as_bytes());
+        let len = text.len() as u32;
+        let write = "[method]output-stream.blocking-write-and-flush";
+        self.call::<_, ()>(write, (stdout, ARG, len, RET))?;
+        assert_eq!(self.u8(RET), 0);
+        Ok(())
+    }
+
+    /// Write `text` to stderr, with a single iovec
+    fn eprint(&mut self, text: &str) -> Result<()> {
+        self.write(ARG, text.as_bytes());
+        self.write(RET, &ARG.to_le_bytes());
+        self.write(RET + 4, &(text.len() as u32).to_le_bytes());
+        assert_eq!(self.call::<_, i32>("fd_write", (2, RET, 1, RET + 8))?, 0);
+        assert_eq!(self.u32(RET + 8), text.len() as u32);
+        Ok(())
+    }
+}
+
+#[test]
+fn stdio_buffer() -> Result<()> {
+    let wasm = engine(&STDIO_IMPORTS)?;
+    let policy = json!({ "stdio-buffer": { "capacity": 8 } });
+    let mut stdio = Stubbed::new(wasm, policy)?;
+    let drain = |stdio: &mut Stubbed| -> Result<Vec<u8>> {
+        let retptr = stdio.call::<_, u32>("componentize-js:stdio/buffer#drain", ())?;
+        Ok(stdio.list(retptr))
+    };
+
+    assert_eq!(drain(&mut stdio)?, b"");
+    stdio.print("out ")?;
+    stdio.eprint("err")?;
+    assert_eq!(drain(&mut stdio)?, b"out err");
+    assert_eq!(drain(&mut stdio)?, b"", "draining empties the buffer");
+
+    // the oldest output is dropped, across the end of the ring
+    stdio.print("abcde")?;
+    stdio.eprint("fghij")?;
+    assert_eq!(drain(&mut stdio)?, b"cdefghij");
+    stdio.print("0123456789")?;
+    assert_eq!(drain(&mut stdio)?, b"23456789");
+    Ok(())
+}
+
+const LOGGING_WORLD: &str = r#"
+package local:stubs;
+
+interface logging {
+  enum level { debug, info, warn, error }
+  log: func(level: level, context: string, message: string);
+}
+
+world stubs {
+  import logging;
+}
+"#;
+
+#[test]
+fn stdio_log() -> Result<()> {
+    let wasm = engine(&STDIO_IMPORTS)?;
+    let policy = json!({
+        "stdio-log": { "function": "local:stubs/logging#log", "stdout": "debug", "stderr": "warn" }
+    });
+    let mut stdio = Stubbed::with_world(wasm, LOGGING_WORLD, policy, |linker| {
+        linker.func_wrap(
+            "local:stubs/logging",
+            "log",
+            |mut caller: Caller<'_, Logs>,
+             level: i32,
+             context: u32,
+             context_len: u32,
+             message: u32,
+             message_len: u32| {
+                let memory = caller.get_export("memory").unwrap().into_memory().unwrap();
+                let data = memory.data(&caller);
+                let string = |ptr: u32, len: u32| {
+                    String::from_utf8(data[ptr as usize..][..len as usize].to_vec()).unwrap()
+                };
+                let log = (
+                    level,
+                    string(context, context_len),
+                    string(message, message_len),
+                );
+                caller.data_mut().push(log);
+            },
+        )?;
+        Ok(())
+    })?;
+
+    // one call per line, each stream with its own pending line
+    stdio.print("first ")?;
+    stdio.eprint("oops\n")?;
+    stdio.print("line\nsecond line\n")?;
+    stdio.print("partial")?;
+    let logs = |logs: &[(i32, &str, &str)]| -> Logs {
+        logs.iter()
+            .map(|(level, context, message)| (*level, context.to_string(), message.to_string()))
+            .collect()
+    };
+    assert_eq!(
+        *stdio.store.data(),
+        logs(&[
+            (2, "stderr", "oops"),
+            (0, "stdout", "first line"),
+            (0, "stdout", "second line"),
+        ])
+    );
+    Ok(())
+}
+
+#[test]
+fn http_mock() -> Result<()> {
+    let wasm = engine(&[
+        (HTTP, "[constructor]fields", "(result i32)"),
+        (HTTP, "[method]fields.get", "(param i32 i32 i32 i32)"),
+        (
+            HTTP,
+            "[constructor]outgoing-request",
+            "(param i32) (result i32)",
+        ),
+        (
+            HTTP,
+            "[method]outgoing-request.set-method",
+            "(param i32 i32 i32 i32) (result i32)",
+        ),
+        (
+            HTTP,
+            "[method]outgoing-request.set-authority",
+            "(param i32 i32 i32 i32) (result i32)",
+        ),
+        (
+            HTTP,
+            "[method]outgoing-request.set-path-with-query",
+            "(param i32 i32 i32 i32) (result i32)",
+        ),
+        (
+            "wasi:http/outgoing-handler@0.2.3",
+            "handle",
+            "(param i32 i32 i32 i32)",
+        ),
+        (
+            HTTP,
+            "[method]future-incoming-response.get",
+            "(param i32 i32)",
+        ),
+        (
+            HTTP,
+            "[method]incoming-response.status",
+            "(param i32) (result i32)",
+        ),
+        (
+            HTTP,
+            "[method]incoming-response.headers",
+            "(param i32) (result i32)",
+        ),
+        (HTTP, "[method]incoming-response.consume", "(param i32 i32)"),
+        (HTTP, "[method]incoming-body.stream", "(param i32 i32)"),
+        (STREAMS, "[method]input-stream.read", "(param i32 i64 i32)"),
+    ])?;
+    let policy = json!({
+        "http-mock": [
+            {
+                "method": "GET",
+                "url": "https://api.example.com/users/1",
+                "headers": { "Content-Type": "application/json" },
+                "body": "{\"id\":1}",
+            },
+            { "url": "https://api.example.com/*", "status": 404, "body": "not found" },
+        ]
+    });
+    let mut http = Stubbed::new(wasm, policy)?;
+
+    // handle a request, returning the `result<future-incoming-response, error-code>`
+    // left at `RET`
+    let fetch = |http: &mut Stubbed, method: u32, authority: &str, path: &str| -> Result<()> {
+        let fields = http.call::<_, u32>("[constructor]fields", ())?;
+        let request = http.call::<_, u32>("[constructor]outgoing-request", fields)?;
+        let set = "[method]outgoing-request.set-method";
+        assert_eq!(http.call::<_, i32>(set, (request, method, 0u32, 0u32))?, 0);
+        for (name, value) in [("authority", authority), ("path-with-query", path)] {
+            http.write(ARG, value.as_bytes());
+            let set = format!("[method]outgoing-request.set-{name}");
+            let params = (request, 1u32, ARG, value.len() as u32);
+            assert_eq!(http.call::<_, i32>(&set, params)?, 0);
+        }
+        http.call("handle", (request, 0u32, 0u32, RET))
+    };
+
+    // the `incoming-response` of the handled request, with its status
+    let respond = |http: &mut Stubbed| -> Result<(u32, i32)> {
+        assert_eq!(http.u8(RET), 0);
+        let future = http.u32(RET + 8);
+        http.call::<_, ()>("[method]future-incoming-response.get", (future, RET))?;
+        assert_eq!(
+            (http.u8(RET), http.u8(RET + 8), http.u8(RET + 16)),
+            (1, 0, 0)
+        );
+        let response = http.u32(RET + 24);
+        let status = http.call::<_, i32>("[method]incoming-response.status", response)?;
+        Ok((response, status))
+    };
+
+    // the whole body of a response
+    let body = |http: &mut Stubbed, response: u32| -> Result<Vec<u8>> {
+        http.call::<_, ()>("[method]incoming-response.consume", (response, RET))?;
+        assert_eq!(http.u8(RET), 0);
+        let incoming = http.u32(RET + 4);
+        http.call::<_, ()>("[method]incoming-body.stream", (incoming, RET))?;
+        let stream = http.u32(RET + 4);
+        let mut bytes = vec![];
+        loop {
+            http.call::<_, ()>("[method]input-stream.read", (stream, 4u64, RET))?;
+            if http.u8(RET) != 0 {
+                assert_eq!(http.u8(RET + 4), STREAM_ERROR_CLOSED);
+                return Ok(bytes);
+            }
+            bytes.extend(http.list(RET + 4));
+        }
+    };
+
+    fetch(&mut http, 0, "api.example.com", "/users/1")?;
+    let (response, status) = respond(&mut http)?;
+    assert_eq!(status, 200);
+    let headers = http.call::<_, u32>("[method]incoming-response.headers", response)?;
+    http.write(ARG, b"Content-Type");
+    http.call::<_, ()>("[method]fields.get", (headers, ARG, 12u32, RET))?;
+    assert_eq!(http.u32(RET + 4), 1);
+    assert_eq!(http.list(http.u32(RET)), b"application/json");
+    assert_eq!(body(&mut http, response)?, b"{\"id\":1}");
+
+    // the method is matched before the prefix entry
+    fetch(&mut http, METHOD_POST, "api.example.com", "/users/1")?;
+    let (response, status) = respond(&mut http)?;
+    assert_eq!(status, 404);
+    assert_eq!(body(&mut http, response)?, b"not found");
+
+    fetch(&mut http, 0, "other.example.com", "/")?;
+    assert_eq!(
+        (http.u8(RET), http.u8(RET + 8)),
+        (1, ERROR_HTTP_REQUEST_DENIED)
+    );
+    Ok(())
+}
+
+const CLOCK_IMPORTS: [(&str, &str, &str); 6] = [
+    (MONOTONIC_CLOCK, "now", "(result i64)"),
+    (
+        MONOTONIC_CLOCK,
+        "subscribe-duration",
+        "(param i64) (result i32)",
+    ),
+    (
+        MONOTONIC_CLOCK,
+        "subscribe-instant",
+        "(param i64) (result i32)",
+    ),
+    (POLL, "[method]pollable.ready", "(param i32) (result i32)"),
+    (POLL, "[method]pollable.block", "(param i32)"),
+    (POLL, "poll", "(param i32 i32 i32)"),
+];
+
+#[test]
+fn virtual_time() -> Result<()> {
+    let wasm = engine(&CLOCK_IMPORTS)?;
+    let mut clocks = Stubbed::new(wasm, json!({ "virtual-time": true }))?;
+    let now = |clocks: &mut Stubbed| clocks.call::<_, u64>("now", ());
+    let ready = |clocks: &mut Stubbed, pollable: u32| -> Result<bool> {
+        Ok(clocks.call::<_, i32>("[method]pollable.ready", pollable)? != 0)
+    };
+
+    let start = now(&mut clocks)?;
+    let late = clocks.call::<_, u32>("subscribe-duration", 1000u64)?;
+    let early = clocks.call::<_, u32>("subscribe-duration", 500u64)?;
+    let past = clocks.call::<_, u32>("subscribe-instant", start)?;
+    assert!(ready(&mut clocks, past)?);
+    assert!(!ready(&mut clocks, late)?);
+    assert_eq!(now(&mut clocks)?, start, "time only passes while waiting");
+
+    // polling advances to the earliest deadline
+    clocks.write(ARG, &late.to_le_bytes());
+    clocks.write(ARG + 4, &early.to_le_bytes());
+    clocks.call::<_, ()>("poll", (ARG, 2u32, RET))?;
+    assert_eq!(clocks.u32(RET + 4), 1);
+    assert_eq!(
+        clocks.u32(clocks.u32(RET)),
+        1,
+        "the index of the early timer"
+    );
+    assert_eq!(now(&mut clocks)?, start + 500);
+    assert!(ready(&mut clocks, early)?);
+    assert!(!ready(&mut clocks, late)?);
+
+    // blocking advances to the deadline
+    clocks.call::<_, ()>("[method]pollable.block", late)?;
+    assert_eq!(now(&mut clocks)?, start + 1000);
+    assert!(ready(&mut clocks, late)?);
+    Ok(())
+}
+
+#[test]
+fn fixed_time() -> Result<()> {
+    let wasm = engine(&CLOCK_IMPORTS)?;
+    let mut clocks = Stubbed::new(wasm, json!({}))?;
+    let start = clocks.call::<_, u64>("now", ())?;
+    assert_eq!(clocks.call::<_, u64>("now", ())?, start);
+    assert!(
+        clocks.traps::<_, u32>("subscribe-duration", 1000u64),
+        "timers trap without virtual time"
+    );
+    Ok(())
+}
+
+#[test]
+fn preview1() -> Result<()> {
+    let wasm = engine(&[
+        (PREVIEW1, "sched_yield", "(result i32)"),
+        (PREVIEW1, "fd_read", "(param i32 i32 i32 i32) (result i32)"),
+        (
+            PREVIEW1,
+            "path_open",
+            "(param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)",
+        ),
+    ])?;
+    // every preview1 import is stubbed without any feature
+    let mut preview1 = Stubbed::new(wasm, json!({}))?;
+    assert_eq!(preview1.call::<_, i32>("sched_yield", ())?, 0);
+    assert!(preview1.traps::<_, i32>("fd_read", (0, ARG, 1, RET)));
+    let params = (3, 0, ARG, 4, 0, 0i64, 0i64, 0, RET);
+    assert!(preview1.traps::<_, i32>("path_open", params));
+    Ok(())
+}
+
+#[test]
+fn preview1_zero() -> Result<()> {
+    let wasm = engine(&[
+        (PREVIEW1, "fd_write", "(param i32 i32 i32 i32) (result i32)"),
+        (PREVIEW1, "fd_seek", "(param i32 i64 i32 i32) (result i32)"),
+    ])?;
+    let policy = json!({ "imports": { "wasi_snapshot_preview1": "zero" } });
+    let mut preview1 = Stubbed::new(wasm, policy)?;
+
+    // a zero fd_write succeeds without writing anything
+    preview1.write(ARG, &[0xff; 8]);
+    preview1.write(RET, &[0xff; 8]);
+    assert_eq!(preview1.call::<_, i32>("fd_write", (1, ARG, 1, RET))?, 0);
+    assert_eq!(preview1.u32(RET), 0);
+    assert_eq!(preview1.u32(RET + 4), u32::MAX);
+
+    preview1.write(RET, &[0xff; 8]);
+    assert_eq!(preview1.call::<_, i32>("fd_seek", (3, 10i64, 0, RET))?, 0);
+    assert_eq!(preview1.u64(RET), 0);
+    Ok(())
+}
@@ -0,0 +1,117 @@
+//! Differential fuzzing of the lifting and lowering of the spliced bindings
+//!
+//! A random world of echo functions is componentized with JS implementations
+//! returning their arguments, and each function is called from Wasmtime with
+//! random arguments, which must come back unchanged.
+
+#![no_main]
+
+use std::sync::OnceLock;
+
+use libfuzzer_sys::arbitrary::{Result, Unstructured};
+use libfuzzer_sys::fuzz_target;
+use spidermonkey_embedding_splicer::componentize::componentize;
+use spidermonkey_embedding_splicer::resolve_wit;
+use spidermonkey_embedding_splicer::wit::exports::local::spidermonkey_embedding_splicer::splicer::SpliceOptions;
+use splicer_fuzz::{World, resources_as_reps};
+use splicer_tests::{FEATURES, Runtime, engine_path};
+use wasmtime::component::{Resource, ResourceAny, ResourceType, Val};
+
+/// Host resource of the `types` interface
+struct Token;
+
+fn engine() -> Vec<u8> {
+    static ENGINE: OnceLock<Vec<u8>> = OnceLock::new();
+    ENGINE
+        .get_or_init(|| {
+            let path = engine_path();
+            std::fs::read(&path).unwrap_or_else(|err| {
+                panic!(
+                    "no engine at {}, set ENGINE_WASM_PATH: {err}",
+                    path.display()
+                )
+            })
+        })
+        .clone()
+}
+
+fn echo(u: &mut Unstructured<'_>) -> Result<()> {
+    let world = World::arbitrary(u)?;
+    let (wit, js) = (world.wit(), world.js());
+    let context = format!("world:\n{wit}\nsource:\n{js}");
+
+    let source_path = std::env::temp_dir().join(format!("echo-{}.js", std::process::id()));
+    std::fs::write(&source_path, &js).unwrap();
+    let (resolve, id) = resolve_wit(Some(&wit), &[]).unwrap();
+    let component = componentize(
+        engine(),
+        &source_path,
+        &resolve,
+        id,
+        None,
+        FEATURES.to_vec(),
+        None,
+        None,
+        None,
+        false,
+        SpliceOptions {
+            diagnostics: false,
+            report_error: None,
+        },
+    )
+    .unwrap_or_else(|err| panic!("failed to componentize {context}\n{err:?}"));
+
+    let mut rt = Runtime::new(&component, ()).unwrap();
+    rt.linker
+        .instance("local:fuzz/types")
+        .unwrap()
+        .resource("r", ResourceType::host::<Token>(), |_, _| Ok(()))
+        .unwrap();
+    rt.trap_unknown_imports().unwrap();
+    let instance = rt
+        .linker
+        .instantiate(&mut rt.store, &rt.component)
+        .unwrap_or_else(|err| panic!("failed to instantiate {context}\n{err:?}"));
+    let interface = instance
+        .get_export_index(&mut rt.store, None, "local:fuzz/echo")
+        .unwrap();
+
+    let mut next_rep = 0;
+    for func_idx in 0..world.funcs.len() {
+        let name = format!("f{func_idx}");
+        let export = instance
+            .get_export_index(&mut rt.store, Some(&interface), &name)
+            .unwrap();
+        let func = instance.get_func(&mut rt.store, export).unwrap();
+
+        let store = &mut rt.store;
+        let (args, expected) = world.args(u, func_idx, &mut || {
+            next_rep += 1;
+            let resource = Resource::<Token>::new_own(next_rep);
+            let resource = ResourceAny::try_from_resource(resource, &mut *store).unwrap();
+            (Val::Resource(resource), next_rep)
+        })?;
+
+        let mut results = [Val::Bool(false)];
+        func.call(&mut rt.store, &args, &mut results)
+            .unwrap_or_else(|err| {
+                panic!("failed to call {name} with {args:?} in {context}\n{err:?}")
+            });
+        let [result] = results;
+        let received = resources_as_reps(result, &mut |val| {
+            let Val::Resource(resource) = val else {
+                unreachable!()
+            };
+            resource
+                .try_into_resource::<Token>(&mut rt.store)
+                .unwrap()
+                .rep()
+        });
+        assert_eq!(expected, received, "{name} echoed {args:?} in {context}");
+    }
+    Ok(())
+}
+
+fuzz_target!(|data: &[u8]| {
+    let _ = echo(&mut Unstructured::new(data));
+});
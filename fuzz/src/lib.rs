@@ -0,0 +1,420 @@
+//! Random worlds of echo functions, whose JS implementations return their
+//! arguments, and random values of their types
+//!
+//! Every world exports an `echo` interface of functions over types defined in
+//! an imported `types` interface, which also holds the `r` resource of the
+//! host. Values are generated alongside the value the host expects back, in
+//! which resources are replaced by their representation.
+
+use std::fmt::Write as _;
+
+use arbitrary::{Result, Unstructured};
+use wasmtime::component::Val;
+
+const MAX_DEPTH: u32 = 3;
+const MAX_TYPES: usize = 4;
+const MAX_FUNCS: usize = 4;
+const MAX_PARAMS: usize = 3;
+const MAX_CASES: usize = 4;
+const MAX_FLAGS: usize = 8;
+const MAX_LEN: usize = 4;
+
+#[derive(Debug, Clone)]
+pub enum Ty {
+    Bool,
+    S8,
+    U8,
+    S16,
+    U16,
+    S32,
+    U32,
+    S64,
+    U64,
+    F32,
+    F64,
+    Char,
+    String,
+    List(Box<Ty>),
+    Option(Box<Ty>),
+    Result {
+        ok: Option<Box<Ty>>,
+        err: Option<Box<Ty>>,
+    },
+    Tuple(Vec<Ty>),
+    /// Type of the `types` interface, by index
+    Named(usize),
+    /// Owned handle of the host resource
+    Resource,
+}
+
+#[derive(Debug)]
+pub enum TypeDef {
+    Record(Vec<Ty>),
+    Variant(Vec<Option<Ty>>),
+    Enum(usize),
+    Flags(usize),
+}
+
+#[derive(Debug)]
+pub struct World {
+    pub types: Vec<TypeDef>,
+    /// Parameters of each function, which returns them as a tuple unless it
+    /// takes a single one
+    pub funcs: Vec<Vec<Ty>>,
+}
+
+impl World {
+    pub fn arbitrary(u: &mut Unstructured<'_>) -> Result<Self> {
+        let mut types = Vec::new();
+        for _ in 0..u.int_in_range(0..=MAX_TYPES)? {
+            // types only refer to the ones defined before them
+            let named = types.len();
+            let def = match u.int_in_range(0..=3)? {
+                0 => TypeDef::Record(
+                    (0..u.int_in_range(1..=MAX_CASES)?)
+                        .map(|_| Ty::arbitrary(u, named, MAX_DEPTH - 1))
+                        .collect::<Result<_>>()?,
+                ),
+                1 => TypeDef::Variant(
+                    (0..u.int_in_range(1..=MAX_CASES)?)
+                        .map(|_| {
+                            u.arbitrary::<bool>()?
+                                .then(|| Ty::arbitrary(u, named, MAX_DEPTH - 1))
+                                .transpose()
+                        })
+                        .collect::<Result<_>>()?,
+                ),
+                2 => TypeDef::Enum(u.int_in_range(1..=MAX_CASES)?),
+                _ => TypeDef::Flags(u.int_in_range(1..=MAX_FLAGS)?),
+            };
+            types.push(def);
+        }
+        let funcs = (0..u.int_in_range(1..=MAX_FUNCS)?)
+            .map(|_| {
+                (0..u.int_in_range(1..=MAX_PARAMS)?)
+                    .map(|_| Ty::arbitrary(u, types.len(), MAX_DEPTH))
+                    .collect()
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { types, funcs })
+    }
+
+    pub fn wit(&self) -> String {
+        let mut wit = String::from("package local:fuzz;\n\ninterface types {\n  resource r;\n");
+        for (idx, def) in self.types.iter().enumerate() {
+            match def {
+                TypeDef::Record(fields) => {
+                    let fields = fields
+                        .iter()
+                        .enumerate()
+                        .map(|(idx, ty)| format!("f{idx}: {}", ty.wit()))
+                        .collect::<Vec<_>>();
+                    writeln!(wit, "  record t{idx} {{ {} }}", fields.join(", ")).unwrap();
+                }
+                TypeDef::Variant(cases) => {
+                    let cases = cases
+                        .iter()
+                        .enumerate()
+                        .map(|(idx, ty)| match ty {
+                            Some(ty) => format!("c{idx}({})", ty.wit()),
+                            None => format!("c{idx}"),
+                        })
+                        .collect::<Vec<_>>();
+                    writeln!(wit, "  variant t{idx} {{ {} }}", cases.join(", ")).unwrap();
+                }
+                TypeDef::Enum(cases) => {
+                    let cases = (0..*cases).map(|idx| format!("e{idx}")).collect::<Vec<_>>();
+                    writeln!(wit, "  enum t{idx} {{ {} }}", cases.join(", ")).unwrap();
+                }
+                TypeDef::Flags(flags) => {
+                    let flags = (0..*flags).map(|idx| format!("b{idx}")).collect::<Vec<_>>();
+                    writeln!(wit, "  flags t{idx} {{ {} }}", flags.join(", ")).unwrap();
+                }
+            }
+        }
+
+        let uses = std::iter::once("r".to_string())
+            .chain((0..self.types.len()).map(|idx| format!("t{idx}")))
+            .collect::<Vec<_>>();
+        writeln!(
+            wit,
+            "}}\n\ninterface echo {{\n  use types.{{{}}};",
+            uses.join(", ")
+        )
+        .unwrap();
+        for (idx, params) in self.funcs.iter().enumerate() {
+            let args = params
+                .iter()
+                .enumerate()
+                .map(|(idx, ty)| format!("p{idx}: {}", ty.wit()))
+                .collect::<Vec<_>>();
+            let ret = match params.as_slice() {
+                [ty] => ty.wit(),
+                params => Ty::Tuple(params.to_vec()).wit(),
+            };
+            writeln!(wit, "  f{idx}: func({}) -> {ret};", args.join(", ")).unwrap();
+        }
+        wit.push_str("}\n\nworld fuzz {\n  export echo;\n}\n");
+        wit
+    }
+
+    /// JS source implementing the `echo` interface
+    pub fn js(&self) -> String {
+        let mut js = String::from("export const echo = {\n");
+        for (idx, params) in self.funcs.iter().enumerate() {
+            let args = (0..params.len())
+                .map(|idx| format!("p{idx}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let body = match params.as_slice() {
+                // results returned directly are returned as their ok value,
+                // their err value being thrown instead
+                [Ty::Result { .. }] => {
+                    "if (p0.tag === 'err') throw { payload: p0.val };\n    return p0.val;".into()
+                }
+                [_] => "return p0;".to_string(),
+                _ => format!("return [{args}];"),
+            };
+            writeln!(js, "  f{idx}({args}) {{\n    {body}\n  }},").unwrap();
+        }
+        js.push_str("};\n");
+        js
+    }
+
+    /// Arguments of the `func` function, along with the value it is expected
+    /// to return, `resource` creating the resources of the arguments and
+    /// returning their representation
+    pub fn args(
+        &self,
+        u: &mut Unstructured<'_>,
+        func: usize,
+        resource: &mut dyn FnMut() -> (Val, u32),
+    ) -> Result<(Vec<Val>, Val)> {
+        let (args, expected): (Vec<_>, Vec<_>) = self.funcs[func]
+            .iter()
+            .map(|ty| self.val(u, ty, resource))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .unzip();
+        let expected = match <[Val; 1]>::try_from(expected) {
+            Ok([expected]) => expected,
+            Err(expected) => Val::Tuple(expected),
+        };
+        Ok((args, expected))
+    }
+
+    /// Value of type `ty` along with its expected echo
+    fn val(
+        &self,
+        u: &mut Unstructured<'_>,
+        ty: &Ty,
+        resource: &mut dyn FnMut() -> (Val, u32),
+    ) -> Result<(Val, Val)> {
+        let same = |val: Val| (val.clone(), val);
+        Ok(match ty {
+            Ty::Bool => same(Val::Bool(u.arbitrary()?)),
+            Ty::S8 => same(Val::S8(u.arbitrary()?)),
+            Ty::U8 => same(Val::U8(u.arbitrary()?)),
+            Ty::S16 => same(Val::S16(u.arbitrary()?)),
+            Ty::U16 => same(Val::U16(u.arbitrary()?)),
+            Ty::S32 => same(Val::S32(u.arbitrary()?)),
+            Ty::U32 => same(Val::U32(u.arbitrary()?)),
+            Ty::S64 => same(Val::S64(u.arbitrary()?)),
+            Ty::U64 => same(Val::U64(u.arbitrary()?)),
+            Ty::F32 => same(Val::Float32(u.arbitrary()?)),
+            Ty::F64 => same(Val::Float64(u.arbitrary()?)),
+            Ty::Char => same(Val::Char(u.arbitrary()?)),
+            Ty::String => same(Val::String(u.arbitrary()?)),
+            Ty::List(ty) => {
+                let (vals, expected) = (0..u.int_in_range(0..=MAX_LEN)?)
+                    .map(|_| self.val(u, ty, resource))
+                    .collect::<Result<Vec<_>>>()?
+                    .into_iter()
+                    .unzip();
+                (Val::List(vals), Val::List(expected))
+            }
+            Ty::Option(ty) => match u.arbitrary::<bool>()? {
+                true => {
+                    let (val, expected) = self.val(u, ty, resource)?;
+                    (
+                        Val::Option(Some(Box::new(val))),
+                        Val::Option(Some(Box::new(expected))),
+                    )
+                }
+                false => same(Val::Option(None)),
+            },
+            Ty::Result { ok, err } => {
+                let is_ok = u.arbitrary::<bool>()?;
+                let payload = if is_ok { ok } else { err };
+                let (val, expected) = match payload {
+                    Some(ty) => {
+                        let (val, expected) = self.val(u, ty, resource)?;
+                        (Some(Box::new(val)), Some(Box::new(expected)))
+                    }
+                    None => (None, None),
+                };
+                match is_ok {
+                    true => (Val::Result(Ok(val)), Val::Result(Ok(expected))),
+                    false => (Val::Result(Err(val)), Val::Result(Err(expected))),
+                }
+            }
+            Ty::Tuple(tys) => {
+                let (vals, expected) = tys
+                    .iter()
+                    .map(|ty| self.val(u, ty, resource))
+                    .collect::<Result<Vec<_>>>()?
+                    .into_iter()
+                    .unzip();
+                (Val::Tuple(vals), Val::Tuple(expected))
+            }
+            Ty::Named(idx) => match &self.types[*idx] {
+                TypeDef::Record(fields) => {
+                    let (vals, expected) = fields
+                        .iter()
+                        .enumerate()
+                        .map(|(idx, ty)| {
+                            let (val, expected) = self.val(u, ty, resource)?;
+                            Ok(((format!("f{idx}"), val), (format!("f{idx}"), expected)))
+                        })
+                        .collect::<Result<Vec<_>>>()?
+                        .into_iter()
+                        .unzip();
+                    (Val::Record(vals), Val::Record(expected))
+                }
+                TypeDef::Variant(cases) => {
+                    let case = u.choose_index(cases.len())?;
+                    let (val, expected) = match &cases[case] {
+                        Some(ty) => {
+                            let (val, expected) = self.val(u, ty, resource)?;
+                            (Some(Box::new(val)), Some(Box::new(expected)))
+                        }
+                        None => (None, None),
+                    };
+                    (
+                        Val::Variant(format!("c{case}"), val),
+                        Val::Variant(format!("c{case}"), expected),
+                    )
+                }
+                TypeDef::Enum(cases) => same(Val::Enum(format!("e{}", u.choose_index(*cases)?))),
+                TypeDef::Flags(flags) => {
+                    let mut set = Vec::new();
+                    for idx in 0..*flags {
+                        if u.arbitrary()? {
+                            set.push(format!("b{idx}"));
+                        }
+                    }
+                    same(Val::Flags(set))
+                }
+            },
+            Ty::Resource => {
+                let (val, rep) = resource();
+                (val, Val::U32(rep))
+            }
+        })
+    }
+}
+
+impl Ty {
+    fn arbitrary(u: &mut Unstructured<'_>, named: usize, depth: u32) -> Result<Self> {
+        const SCALARS: [Ty; 13] = [
+            Ty::Bool,
+            Ty::S8,
+            Ty::U8,
+            Ty::S16,
+            Ty::U16,
+            Ty::S32,
+            Ty::U32,
+            Ty::S64,
+            Ty::U64,
+            Ty::F32,
+            Ty::F64,
+            Ty::Char,
+            Ty::String,
+        ];
+        let composites = if depth == 0 { 0 } else { 4 };
+        let choice = u.choose_index(SCALARS.len() + composites + 2)?;
+        let inner = |u: &mut Unstructured<'_>| Ty::arbitrary(u, named, depth - 1).map(Box::new);
+        Ok(match choice.checked_sub(SCALARS.len()) {
+            None => SCALARS[choice].clone(),
+            Some(idx) if idx < composites => match idx {
+                0 => Ty::List(inner(u)?),
+                1 => Ty::Option(inner(u)?),
+                2 => Ty::Result {
+                    ok: u.arbitrary::<bool>()?.then(|| inner(u)).transpose()?,
+                    err: u.arbitrary::<bool>()?.then(|| inner(u)).transpose()?,
+                },
+                _ => Ty::Tuple(
+                    (0..u.int_in_range(1..=MAX_PARAMS)?)
+                        .map(|_| Ty::arbitrary(u, named, depth - 1))
+                        .collect::<Result<_>>()?,
+                ),
+            },
+            Some(idx) if idx == composites && named > 0 => Ty::Named(u.choose_index(named)?),
+            Some(_) => Ty::Resource,
+        })
+    }
+
+    fn wit(&self) -> String {
+        match self {
+            Ty::Bool => "bool".into(),
+            Ty::S8 => "s8".into(),
+            Ty::U8 => "u8".into(),
+            Ty::S16 => "s16".into(),
+            Ty::U16 => "u16".into(),
+            Ty::S32 => "s32".into(),
+            Ty::U32 => "u32".into(),
+            Ty::S64 => "s64".into(),
+            Ty::U64 => "u64".into(),
+            Ty::F32 => "f32".into(),
+            Ty::F64 => "f64".into(),
+            Ty::Char => "char".into(),
+            Ty::String => "string".into(),
+            Ty::List(ty) => format!("list<{}>", ty.wit()),
+            Ty::Option(ty) => format!("option<{}>", ty.wit()),
+            Ty::Result { ok, err } => match (ok, err) {
+                (Some(ok), Some(err)) => format!("result<{}, {}>", ok.wit(), err.wit()),
+                (Some(ok), None) => format!("result<{}>", ok.wit()),
+                (None, Some(err)) => format!("result<_, {}>", err.wit()),
+                (None, None) => "result".into(),
+            },
+            Ty::Tuple(tys) => {
+                let tys = tys.iter().map(Ty::wit).collect::<Vec<_>>();
+                format!("tuple<{}>", tys.join(", "))
+            }
+            Ty::Named(idx) => format!("t{idx}"),
+            Ty::Resource => "r".into(),
+        }
+    }
+}
+
+/// Replace the resources of a returned value by their representation, as in
+/// expected values
+pub fn resources_as_reps(val: Val, rep: &mut dyn FnMut(Val) -> u32) -> Val {
+    let boxed = |val: Option<Box<Val>>, rep: &mut dyn FnMut(Val) -> u32| {
+        val.map(|val| Box::new(resources_as_reps(*val, rep)))
+    };
+    match val {
+        Val::List(vals) => Val::List(
+            vals.into_iter()
+                .map(|v| resources_as_reps(v, rep))
+                .collect(),
+        ),
+        Val::Tuple(vals) => Val::Tuple(
+            vals.into_iter()
+                .map(|v| resources_as_reps(v, rep))
+                .collect(),
+        ),
+        Val::Record(fields) => Val::Record(
+            fields
+                .into_iter()
+                .map(|(name, v)| (name, resources_as_reps(v, rep)))
+                .collect(),
+        ),
+        Val::Variant(case, val) => Val::Variant(case, boxed(val, rep)),
+        Val::Option(val) => Val::Option(boxed(val, rep)),
+        Val::Result(Ok(val)) => Val::Result(Ok(boxed(val, rep))),
+        Val::Result(Err(val)) => Val::Result(Err(boxed(val, rep))),
+        val @ Val::Resource(_) => Val::U32(rep(val)),
+        val => val,
+    }
+}
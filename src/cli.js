@@ -1,7 +1,11 @@
 #! /usr/bin/env node
 
 import { program, Option } from 'commander';
-import { componentize, DEFAULT_FEATURES } from './componentize.js';
+import {
+  componentize,
+  DEFAULT_FEATURES,
+  OPTIONAL_FEATURES,
+} from './componentize.js';
 import { writeFile } from 'node:fs/promises';
 import { resolve } from 'node:path';
 
@@ -14,6 +18,7 @@ export async function componentizeCmd(jsSource, opts) {
     enableAot: opts.aot,
     engine: opts.engine,
     disableFeatures: opts.disable,
+    enableFeatures: opts.enable,
     preview2Adapter: opts.preview2Adapter,
     debugBindings: opts.debugBindings,
     debugBuild: opts.useDebugBuild,
@@ -44,6 +49,11 @@ program
       DEFAULT_FEATURES,
     ),
   )
+  .addOption(
+    new Option('-e, --enable <feature...>', 'enable WASI features').choices(
+      OPTIONAL_FEATURES,
+    ),
+  )
   .option(
     '--preview2-adapter <adapter>',
     'provide a custom preview2 adapter path',
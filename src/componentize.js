@@ -63,6 +63,15 @@ const DEFAULT_DEBUG_SETTINGS = {
 /** Features that are used by default if not explicitly disabled */
 export const DEFAULT_FEATURES = ['stdio', 'random', 'clocks', 'http', 'fetch-event'];
 
+/** Features that are only used when explicitly enabled */
+export const OPTIONAL_FEATURES = [
+  'filesystem',
+  'sockets',
+  'environment',
+  'exit',
+  'terminal',
+];
+
 export async function componentize(
   opts,
   _deprecatedWitWorldOrOpts = undefined,
@@ -102,6 +111,9 @@ export async function componentize(
     worldName,
     disableFeatures = [],
     enableFeatures = [],
+    stubPolicy,
+    diagnostics = false,
+    reportError,
 
     debug = { ...DEFAULT_DEBUG_SETTINGS },
     debugBuild = false,
@@ -127,6 +139,12 @@ export async function componentize(
       features.add(f);
     }
   }
+  for (let f of enableFeatures) {
+    if (!OPTIONAL_FEATURES.includes(f)) {
+      throw new Error(`unrecognized feature to enable [${f}]`);
+    }
+    features.add(f);
+  }
 
   if (!jsSource && sourcePath) {
     jsSource = await readFile(sourcePath, 'utf8');
@@ -136,13 +154,16 @@ export async function componentize(
     jsSource,
   );
 
-  // If there is an export of incomingHandler, there is likely to be a
-  // manual implementation of wasi:http/incoming-handler, so we should
-  // disable fetch-event
-  if (features.has('http') && detectedExports.has('incomingHandler')) {
+  // If the world exports wasi:http/incoming-handler (or wasi:http/handler for
+  // WASI 0.3) and there is an export of its handler, there is likely to be a
+  // manual implementation of it, so we should disable fetch-event
+  const handlerExport = features.has('http')
+    ? httpHandlerExport(witWorld, witPath, worldName)
+    : undefined;
+  if (handlerExport && detectedExports.has(handlerExport)) {
     if (debugBindings) {
       console.error(
-        'Detected `incomingHandler` export, disabling fetch-event...',
+        `Detected \`${handlerExport}\` export, disabling fetch-event...`,
       );
     }
     features.delete('fetch-event');
@@ -156,6 +177,7 @@ export async function componentize(
     maybeWindowsPath(witPath),
     worldName,
     false,
+    { diagnostics, reportError },
   );
 
   const inputWasmPath = join(workDir, 'in.wasm');
@@ -388,6 +410,23 @@ export async function componentize(
     getStderr,
   );
 
+  // The virtual filesystem directory is read by the splicer, so it must be an absolute path.
+  // TOML policies, which cannot start with `{`, are passed to the splicer as they are, which
+  // resolves their relative directory against the current directory passed along
+  let policy =
+    typeof stubPolicy === 'string' && stubPolicy.trimStart().startsWith('{')
+      ? JSON.parse(stubPolicy)
+      : stubPolicy;
+  if (policy?.['virtual-fs']?.dir) {
+    policy = {
+      ...policy,
+      'virtual-fs': {
+        ...policy['virtual-fs'],
+        dir: maybeWindowsPath(policy['virtual-fs'].dir),
+      },
+    };
+  }
+
   // After wizening, stub out the wasi imports depending on what features are enabled
   const finalBin = splicer.stubWasi(
     bin,
@@ -395,6 +434,8 @@ export async function componentize(
     witWorld,
     maybeWindowsPath(witPath),
     worldName,
+    typeof policy === 'string' ? policy : policy && JSON.stringify(policy),
+    maybeWindowsPath(cwd()),
   );
 
   if (debugBindings) {
@@ -617,6 +658,35 @@ async function handleCheckInitOutput(
   }
 }
 
+/**
+ * Name the source exports the WASI HTTP handler of the world under, if the
+ * world exports one
+ *
+ * @param {string | undefined} witWorld - inline WIT source
+ * @param {string | undefined} witPath - path to the WIT source
+ * @param {string | undefined} worldName - world to componentize
+ * @returns {'incomingHandler' | 'handler' | undefined} `incomingHandler` for
+ * `wasi:http/incoming-handler` of WASI 0.2, `handler` for `wasi:http/handler`
+ * of WASI 0.3
+ */
+function httpHandlerExport(witWorld, witPath, worldName) {
+  const { exportBindings } = splicer.generateBindings(
+    witWorld,
+    maybeWindowsPath(witPath),
+    worldName,
+    [],
+    { manifest: false },
+  );
+  for (const { worldKey } of exportBindings) {
+    if (/^wasi:http\/incoming-handler@0\.[23]\./.test(worldKey)) {
+      return 'incomingHandler';
+    }
+    if (/^wasi:http\/handler@0\.[23]\./.test(worldKey)) {
+      return 'handler';
+    }
+  }
+}
+
 /**
  * Detect known exports that correspond to certain interfaces
  *
@@ -79,6 +79,7 @@ suite('Bindings', async () => {
           worldName,
           enableFeatures,
           disableFeatures: maybeLogging(disableFeatures),
+          reportError: testcase.reportError,
           debugBuild: DEBUG_TEST_ENABLED,
           enableAot: WEVAL_TEST_ENABLED,
         });
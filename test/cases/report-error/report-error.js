@@ -0,0 +1,5 @@
+export const reports = [];
+
+export default function reportError(message, stack) {
+  reports.push({ message, stack });
+}
@@ -0,0 +1,7 @@
+export function fail(message) {
+  throw new Error(message);
+}
+
+export function succeed(message) {
+  return message;
+}
@@ -0,0 +1,15 @@
+import { ok, strictEqual, throws } from 'node:assert';
+
+import { reports } from './report-error.js';
+
+export const reportError = 'report-error';
+
+export function test(instance) {
+  strictEqual(instance.succeed('ok'), 'ok');
+  strictEqual(reports.length, 0);
+
+  throws(() => instance.fail('boom'));
+  strictEqual(reports.length, 1);
+  strictEqual(reports[0].message, 'Error: boom');
+  ok(reports[0].stack.includes('fail'), reports[0].stack);
+}
@@ -49,10 +49,32 @@ interface ComponentizeOptions {
    */
   disableFeatures?: ('stdio' | 'random' | 'clocks' | 'http' | 'fetch-event')[];
   /**
-   * Enable WASI features in the base engine
-   * (no experimental subsystems currently supported)
+   * Enable WASI features in the base engine, kept even when the target world does not import them
+   *
+   * - filesystem: `wasi:filesystem`
+   * - sockets: `wasi:sockets`
+   * - environment: `wasi:cli/environment`, for environment variables and arguments
+   * - exit: `wasi:cli/exit`
+   * - terminal: the `wasi:cli/terminal-*` interfaces
+   */
+  enableFeatures?: ('filesystem' | 'sockets' | 'environment' | 'exit' | 'terminal')[];
+  /**
+   * Stub policy refining how WASI imports of the engine are stubbed, as a JSON or TOML string or an object
+   *
+   * Maps import patterns (e.g. `wasi:filesystem/*` or `wasi:cli/environment#get-arguments`)
+   * to one of `'keep'`, `'trap'`, `'zero'` or `'error'`. See the README for details.
+   */
+  stubPolicy?: string | StubPolicy;
+  /**
+   * Count the calls of the JS exports, read through the `componentize-js:diagnostics/exports` export
+   */
+  diagnostics?: boolean;
+  /**
+   * Import of the target world to call with the message and stack of the uncaught exceptions of the exports, by
+   * function name (e.g. `report-error`) or as `<interface>#<function>`, which must be a
+   * `func(message: string, stack: string)`
    */
-  enableFeatures?: [];
+  reportError?: string;
   /**
    * Pass environment variables to the spawned Wizer or Weval Process
    * If set to true, all host environment variables are passed
@@ -120,6 +142,125 @@ export function componentize(
   opts?: ComponentizeOptions,
 ): Promise<ComponentizeOutput>;
 
+interface StubPolicy {
+  /**
+   * Features to use as the stubbing presets, replacing those derived from `disableFeatures`
+   */
+  features?: (
+    | 'stdio'
+    | 'random'
+    | 'clocks'
+    | 'http'
+    | 'fetch-event'
+    | 'filesystem'
+    | 'sockets'
+    | 'environment'
+    | 'exit'
+    | 'terminal'
+  )[];
+  /**
+   * Behavior for imports matching each pattern
+   */
+  imports?: Record<string, 'keep' | 'trap' | 'zero' | 'error'>;
+  /**
+   * Host directory to embed as a read-only `wasi:filesystem` preopen
+   */
+  'virtual-fs'?: {
+    /**
+     * Directory to embed, resolved against the current working directory
+     */
+    dir: string;
+    /**
+     * Guest path of the preopen (defaults to `/`)
+     */
+    preopen?: string;
+  };
+  /**
+   * Environment to return in place of `wasi:cli/environment`
+   */
+  environment?: {
+    /**
+     * Environment variables
+     */
+    vars?: Record<string, string>;
+    /**
+     * Command line arguments
+     */
+    args?: string[];
+    /**
+     * Initial working directory
+     */
+    cwd?: string;
+  };
+  /**
+   * Capture console output into a ring buffer drained through
+   * `componentize-js:stdio/buffer#drain`, when the `stdio` feature is disabled
+   */
+  'stdio-buffer'?:
+    | true
+    | {
+        /**
+         * Buffer size in bytes, the oldest output is dropped when it is full (defaults to 65536)
+         */
+        capacity?: number;
+      };
+  /**
+   * Send each line of console output to a logging function imported by the target world,
+   * when the `stdio` feature is disabled
+   */
+  'stdio-log'?:
+    | string
+    | {
+        /**
+         * Function as `<interface>#<name>`, or `<name>` for a function of the world itself
+         */
+        function: string;
+        /**
+         * Level enum case for stdout lines (defaults to `info`)
+         */
+        stdout?: string;
+        /**
+         * Level enum case for stderr lines (defaults to `error`)
+         */
+        stderr?: string;
+      };
+  /**
+   * Responses to answer outgoing HTTP requests with, when the `http` and `fetch-event`
+   * features are disabled
+   */
+  'http-mock'?: {
+    /**
+     * Method to match, any method when absent
+     */
+    method?: string;
+    /**
+     * URL to match, a trailing `*` matching any suffix
+     */
+    url: string;
+    /**
+     * Response status (defaults to 200)
+     */
+    status?: number;
+    /**
+     * Response headers
+     */
+    headers?: Record<string, string>;
+    /**
+     * Response body
+     */
+    body?: string;
+  }[];
+  /**
+   * Run timers in virtual time when the `clocks` feature is disabled, instead of trapping
+   */
+  'virtual-time'?: boolean;
+  /**
+   * Fail if imports remain beyond those of the target world and enabled features (`imports`),
+   * or beyond those of the target world alone with every feature disabled (`pure`)
+   */
+  verify?: 'imports' | 'pure';
+}
+
 interface ComponentizeOutput {
   /**
    * Component binary